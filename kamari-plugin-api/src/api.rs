@@ -1,4 +1,8 @@
 use crate::types::{PluginContext, Color};
+use rayon::prelude::*;
+
+// Filters below run on the current rayon thread pool; hosts choose the thread count by
+// calling them inside `ThreadPool::install`.
 
 /// Plugin context operations
 impl PluginContext {
@@ -7,9 +11,11 @@ impl PluginContext {
         let width = self.width();
         let height = self.height();
         let mut new_data = self.get_pixel_data().clone();
+        let source = &*self;
         
-        for y in 0..height {
-            for x in 0..width {
+        // Rows only read from the unchanged context, so they run in parallel
+        new_data.par_iter_mut().enumerate().for_each(|(y, row)| {
+            for x in 0..width.min(row.len()) {
                 let mut r_sum = 0.0;
                 let mut g_sum = 0.0;
                 let mut b_sum = 0.0;
@@ -23,7 +29,7 @@ impl PluginContext {
                         let ny = y as i32 + dy;
                         
                         if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
-                            if let Some(pixel) = self.get_pixel(nx as usize, ny as usize) {
+                            if let Some(pixel) = source.get_pixel(nx as usize, ny as usize) {
                                 r_sum += pixel.r as f32;
                                 g_sum += pixel.g as f32;
                                 b_sum += pixel.b as f32;
@@ -35,7 +41,7 @@ impl PluginContext {
                 }
                 
                 if count > 0 {
-                    new_data[y][x] = Color::new(
+                    row[x] = Color::new(
                         (r_sum / count as f32) as u8,
                         (g_sum / count as f32) as u8,
                         (b_sum / count as f32) as u8,
@@ -43,7 +49,7 @@ impl PluginContext {
                     );
                 }
             }
-        }
+        });
         
         self.set_pixel_data(new_data);
     }
@@ -53,12 +59,10 @@ impl PluginContext {
         use std::collections::hash_map::DefaultHasher;
         use std::hash::{Hash, Hasher};
         
-        let width = self.width();
-        let height = self.height();
+        let mut new_data = self.get_pixel_data().clone();
         
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = self.get_pixel(x, y).unwrap_or(Color::TRANSPARENT);
+        new_data.par_iter_mut().enumerate().for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate() {
                 if pixel.a > 0 {
                     // Simple pseudo-random noise based on position
                     let mut hasher = DefaultHasher::new();
@@ -70,10 +74,12 @@ impl PluginContext {
                     let g = (pixel.g as f32 + noise).clamp(0.0, 255.0) as u8;
                     let b = (pixel.b as f32 + noise).clamp(0.0, 255.0) as u8;
                     
-                    self.set_pixel(x, y, Color::new(r, g, b, pixel.a));
+                    *pixel = Color::new(r, g, b, pixel.a);
                 }
             }
-        }
+        });
+        
+        self.set_pixel_data(new_data);
     }
     
     /// Apply outline effect
@@ -81,37 +87,43 @@ impl PluginContext {
         let width = self.width();
         let height = self.height();
         let mut new_data = self.get_pixel_data().clone();
+        let source = &*self;
+        let reach = thickness as i32;
         
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = self.get_pixel(x, y).unwrap_or(Color::TRANSPARENT);
+        // Each transparent pixel looks for a filled neighbour within the outline distance,
+        // so every row only writes to itself
+        new_data.par_iter_mut().enumerate().for_each(|(y, row)| {
+            for x in 0..width.min(row.len()) {
+                let pixel = source.get_pixel(x, y).unwrap_or(Color::TRANSPARENT);
                 if pixel.a > 0 {
-                    // Check surrounding pixels
-                    for dy in -(thickness as i32)..=(thickness as i32) {
-                        for dx in -(thickness as i32)..=(thickness as i32) {
-                            if dx == 0 && dy == 0 { continue; }
-                            
-                            let nx = x as i32 + dx;
-                            let ny = y as i32 + dy;
-                            
-                            if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
-                                let nx = nx as usize;
-                                let ny = ny as usize;
-                                
-                                let neighbor = self.get_pixel(nx, ny).unwrap_or(Color::TRANSPARENT);
-                                if neighbor.a == 0 {
-                                    // Distance check for circular outline
-                                    let dist = ((dx * dx + dy * dy) as f32).sqrt();
-                                    if dist <= thickness as f32 {
-                                        new_data[ny][nx] = color;
-                                    }
-                                }
+                    continue;
+                }
+                
+                let mut near_filled = false;
+                'search: for dy in -reach..=reach {
+                    for dx in -reach..=reach {
+                        if dx == 0 && dy == 0 { continue; }
+                        
+                        let nx = x as i32 + dx;
+                        let ny = y as i32 + dy;
+                        
+                        if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
+                            let neighbor = source.get_pixel(nx as usize, ny as usize).unwrap_or(Color::TRANSPARENT);
+                            // Distance check for circular outline
+                            let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                            if neighbor.a > 0 && dist <= thickness as f32 {
+                                near_filled = true;
+                                break 'search;
                             }
                         }
                     }
                 }
+                
+                if near_filled {
+                    row[x] = color;
+                }
             }
-        }
+        });
         
         self.set_pixel_data(new_data);
     }
@@ -119,10 +131,11 @@ impl PluginContext {
     /// Apply pixelate effect
     pub fn apply_pixelate(&mut self, block_size: usize) {
         let width = self.width();
-        let height = self.height();
+        let block_size = block_size.max(1);
         let mut new_data = self.get_pixel_data().clone();
         
-        for y in (0..height).step_by(block_size) {
+        // One chunk of rows per row of blocks
+        new_data.par_chunks_mut(block_size).for_each(|rows| {
             for x in (0..width).step_by(block_size) {
                 // Calculate average color of the block
                 let mut r_sum = 0u32;
@@ -131,60 +144,53 @@ impl PluginContext {
                 let mut a_sum = 0u32;
                 let mut count = 0;
                 
-                for by in y..std::cmp::min(y + block_size, height) {
-                    for bx in x..std::cmp::min(x + block_size, width) {
-                        if let Some(pixel) = self.get_pixel(bx, by) {
-                            r_sum += pixel.r as u32;
-                            g_sum += pixel.g as u32;
-                            b_sum += pixel.b as u32;
-                            a_sum += pixel.a as u32;
-                            count += 1;
-                        }
+                for row in rows.iter() {
+                    for pixel in row.iter().skip(x).take(block_size) {
+                        r_sum += pixel.r as u32;
+                        g_sum += pixel.g as u32;
+                        b_sum += pixel.b as u32;
+                        a_sum += pixel.a as u32;
+                        count += 1;
                     }
                 }
                 
-                if count > 0 {
-                    let avg_color = Color::new(
-                        (r_sum / count) as u8,
-                        (g_sum / count) as u8,
-                        (b_sum / count) as u8,
-                        (a_sum / count) as u8,
-                    );
+                let average = |sum: u32| sum.checked_div(count).map(|value| value as u8);
+                if let (Some(r), Some(g), Some(b), Some(a)) = (average(r_sum), average(g_sum), average(b_sum), average(a_sum)) {
+                    let avg_color = Color::new(r, g, b, a);
                     
                     // Apply average color to the entire block
-                    for by in y..std::cmp::min(y + block_size, height) {
-                        for bx in x..std::cmp::min(x + block_size, width) {
-                            new_data[by][bx] = avg_color;
+                    for row in rows.iter_mut() {
+                        for pixel in row.iter_mut().skip(x).take(block_size) {
+                            *pixel = avg_color;
                         }
                     }
                 }
             }
-        }
+        });
         
         self.set_pixel_data(new_data);
     }
     
     /// Replace colors
     pub fn replace_color(&mut self, from_color: Color, to_color: Color, tolerance: u8) {
-        let width = self.width();
-        let height = self.height();
+        let mut new_data = self.get_pixel_data().clone();
         
-        for y in 0..height {
-            for x in 0..width {
-                if let Some(pixel) = self.get_pixel(x, y) {
-                    // Check if pixel matches the from_color within tolerance
-                    let r_diff = (pixel.r as i32 - from_color.r as i32).abs();
-                    let g_diff = (pixel.g as i32 - from_color.g as i32).abs();
-                    let b_diff = (pixel.b as i32 - from_color.b as i32).abs();
-                    let a_diff = (pixel.a as i32 - from_color.a as i32).abs();
-                    
-                    if r_diff <= tolerance as i32 && g_diff <= tolerance as i32 && 
-                       b_diff <= tolerance as i32 && a_diff <= tolerance as i32 {
-                        self.set_pixel(x, y, to_color);
-                    }
+        new_data.par_iter_mut().for_each(|row| {
+            for pixel in row.iter_mut() {
+                // Check if pixel matches the from_color within tolerance
+                let r_diff = (pixel.r as i32 - from_color.r as i32).abs();
+                let g_diff = (pixel.g as i32 - from_color.g as i32).abs();
+                let b_diff = (pixel.b as i32 - from_color.b as i32).abs();
+                let a_diff = (pixel.a as i32 - from_color.a as i32).abs();
+                
+                if r_diff <= tolerance as i32 && g_diff <= tolerance as i32 && 
+                   b_diff <= tolerance as i32 && a_diff <= tolerance as i32 {
+                    *pixel = to_color;
                 }
             }
-        }
+        });
+        
+        self.set_pixel_data(new_data);
     }
     
     /// Fill connected area with color (bucket fill)
@@ -8,12 +8,12 @@ impl eframe::App for PixelArtEditor {
         self.update_tool_animations(dt);
         
         // Handle animation
-        if self.animation_playing && self.frames.len() > 1 {
+        if self.animation_playing {
             let current_time = ctx.input(|i| i.time);
-            if current_time - self.last_animation_time > 1.0 / self.animation_speed as f64 {
-                self.animation_frame = (self.animation_frame + 1) % self.frames.len();
-                self.last_animation_time = current_time;
-                ctx.request_repaint();
+            self.advance_playback(current_time);
+            if self.animation_playing {
+                let wait = self.time_until_next_frame(current_time);
+                ctx.request_repaint_after(std::time::Duration::from_secs_f64(wait));
             }
         }
 
@@ -45,6 +45,10 @@ impl eframe::App for PixelArtEditor {
             self.show_export_dialog(ctx);
         }
 
+        if self.show_nine_slice_preview {
+            self.show_nine_slice_preview_dialog(ctx);
+        }
+
         // Show plugin dialogs
         self.plugin_manager.show_plugin_manager(ctx);
         self.plugin_manager.show_plugin_config(ctx);
@@ -76,6 +80,16 @@ impl eframe::App for PixelArtEditor {
                             ui.separator();
                         }
 
+                        if self.show_brush_panel {
+                            self.show_brush_panel(ui);
+                            ui.separator();
+                        }
+
+                        if self.show_tileset_panel && self.active_tileset().is_some() {
+                            self.show_tileset_panel(ui);
+                            ui.separator();
+                        }
+
                         if self.show_frames_panel {
                             self.show_frames_panel(ctx, ui);
                         }
@@ -110,9 +124,61 @@ impl eframe::App for PixelArtEditor {
             self.reset_zoom();
         }
         
+        // Selection shortcuts, left to text fields while one has focus
+        if !ctx.wants_keyboard_input() {
+            if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::A)) {
+                self.select_all();
+            } else if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::D)) {
+                self.deselect();
+            } else if ctx.input(|i| i.modifiers.ctrl && i.modifiers.shift && i.key_pressed(egui::Key::I)) {
+                self.invert_selection();
+            } else if ctx.input(|i| i.events.contains(&egui::Event::Copy)) {
+                self.copy();
+            } else if ctx.input(|i| i.events.contains(&egui::Event::Cut)) {
+                self.cut();
+            } else if self.paste_requested(ctx) {
+                if ctx.input(|i| i.modifiers.shift) {
+                    self.paste_as_new_layer();
+                } else {
+                    self.paste();
+                }
+            } else if ctx.input(|i| i.key_pressed(egui::Key::Delete)) {
+                if self.floating.is_some() {
+                    self.delete_floating();
+                } else {
+                    self.clear_selection();
+                }
+            } else if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+                self.commit_floating();
+            } else if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.cancel_floating();
+            }
+        }
+        
         // Canvas navigation shortcuts
         if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::Home)) {
             self.center_canvas();
         }
     }
 }
+
+impl PixelArtEditor {
+    /// Whether Ctrl+V was pressed. egui turns it into a Paste event, but only when the
+    /// system clipboard holds text, so releasing Ctrl+V without one counts as well.
+    fn paste_requested(&mut self, ctx: &egui::Context) -> bool {
+        let (pasted, released) = ctx.input(|i| {
+            let pasted = i.events.iter().any(|e| matches!(e, egui::Event::Paste(_)));
+            let released = i.events.iter().find_map(|e| match e {
+                egui::Event::Key { key: egui::Key::V, pressed: false, modifiers, .. } => Some(modifiers.ctrl),
+                _ => None,
+            });
+            (pasted, released)
+        });
+        let requested = match released {
+            Some(ctrl) => (ctrl || pasted) && !self.paste_event_seen,
+            None => pasted && !self.paste_event_seen,
+        };
+        self.paste_event_seen = released.is_none() && (self.paste_event_seen || pasted);
+        requested
+    }
+}
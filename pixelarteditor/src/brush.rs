@@ -0,0 +1,126 @@
+use eframe::egui::Color32;
+use crate::editor::stroke::line_points;
+use crate::pixel_buffer::PixelBuffer;
+
+/// Tip the pencil and eraser stamp at every step of a stroke
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrushShape {
+    Circle,
+    Square,
+    Line,
+    Custom, // The brush picked in the brush library
+}
+
+impl BrushShape {
+    pub const ALL: [BrushShape; 4] = [BrushShape::Circle, BrushShape::Square, BrushShape::Line, BrushShape::Custom];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BrushShape::Circle => "Circle",
+            BrushShape::Square => "Square",
+            BrushShape::Line => "Line",
+            BrushShape::Custom => "Custom",
+        }
+    }
+
+    /// Whether the brush angle changes this shape
+    pub fn has_angle(&self) -> bool {
+        matches!(self, BrushShape::Square | BrushShape::Line)
+    }
+
+    /// Offsets from the dab centre a tip of this shape covers. `angle` turns square and
+    /// line tips clockwise, in degrees. Custom tips have no offsets of their own.
+    pub fn tip(&self, size: usize, angle: f32) -> Vec<(isize, isize)> {
+        let size = size.max(1);
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut offsets = Vec::new();
+        match self {
+            BrushShape::Circle => {
+                let radius = (size / 2) as isize;
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        if dx * dx + dy * dy <= radius * radius {
+                            offsets.push((dx, dy));
+                        }
+                    }
+                }
+            }
+            BrushShape::Square => {
+                // Even sizes put the centre on the corner below and right of the dab pixel
+                let half = size as f32 / 2.0;
+                let shift = if size.is_multiple_of(2) { 0.5 } else { 0.0 };
+                let reach = size as isize;
+                for dy in -reach..=reach {
+                    for dx in -reach..=reach {
+                        let (px, py) = (dx as f32 - shift, dy as f32 - shift);
+                        let (u, v) = (px * cos + py * sin, -px * sin + py * cos);
+                        if u.abs() < half && v.abs() < half {
+                            offsets.push((dx, dy));
+                        }
+                    }
+                }
+            }
+            BrushShape::Line => {
+                // Steps of one pixel along the longer axis, so the line is `size` pixels
+                // long at any angle
+                let step = cos.abs().max(sin.abs());
+                let (dx, dy) = (cos / step, sin / step);
+                let from = -(((size - 1) / 2) as f32);
+                let to = from + (size - 1) as f32;
+                let end = |t: f32| ((t * dx).round() as isize, (t * dy).round() as isize);
+                offsets = line_points(end(from), end(to));
+            }
+            BrushShape::Custom => {}
+        }
+        offsets
+    }
+}
+
+/// How the pencil's dabs change the pixels under them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ink {
+    Simple,  // Paint the colour
+    Shading, // Step each pixel one colour lighter or darker along the shading ramp
+}
+
+impl Ink {
+    pub const ALL: [Ink; 2] = [Ink::Simple, Ink::Shading];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Ink::Simple => "Simple",
+            Ink::Shading => "Shading",
+        }
+    }
+}
+
+/// A brush captured from the canvas, kept in the brush library
+#[derive(Clone, PartialEq)]
+pub struct CustomBrush {
+    pub name: String,
+    pub pixels: PixelBuffer,
+}
+
+impl CustomBrush {
+    /// Pixels one dab centred on (x, y) paints. Own colours keep the brush's pixels as
+    /// they are; otherwise every opaque pixel takes `color`. Aligned to the grid, each
+    /// canvas pixel takes the brush pixel its position falls on when the brush is tiled
+    /// from the canvas corner, so overlapping dabs continue one pattern instead of
+    /// stacking copies.
+    pub fn dab(&self, x: isize, y: isize, color: Color32, own_colors: bool, aligned: bool) -> Vec<(isize, isize, Color32)> {
+        let (width, height) = (self.pixels.width() as isize, self.pixels.height() as isize);
+        let (left, top) = (x - width / 2, y - height / 2);
+        let mut pixels = Vec::new();
+        for by in 0..height {
+            for bx in 0..width {
+                let (cx, cy) = (left + bx, top + by);
+                let (sx, sy) = if aligned { (cx.rem_euclid(width), cy.rem_euclid(height)) } else { (bx, by) };
+                let brush = self.pixels.get_pixel(sx as usize, sy as usize);
+                if brush.a() > 0 {
+                    pixels.push((cx, cy, if own_colors { brush } else { color }));
+                }
+            }
+        }
+        pixels
+    }
+}
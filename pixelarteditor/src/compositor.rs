@@ -0,0 +1,365 @@
+use eframe::egui::Color32;
+use rayon::prelude::*;
+use crate::pixel_buffer::{PixelBuffer, RowBand};
+use crate::tiled_buffer::TILE_SIZE;
+use crate::types::{BlendMode, DirtyRect, Frame, Layer};
+
+// Shared layer compositor. The canvas, export and the frame/layer save paths all go
+// through here, so a sprite looks the same everywhere it ends up.
+//
+// Blending follows the W3C compositing model: the blend function mixes source and
+// backdrop colours, then the result is composited source-over using the layer's alpha.
+
+/// Composite `source` over `backdrop` with a blend mode and an extra opacity factor
+pub fn blend(mode: BlendMode, backdrop: Color32, source: Color32, opacity: f32) -> Color32 {
+    let alpha_s = source.a() as f32 / 255.0 * opacity.clamp(0.0, 1.0);
+    if alpha_s <= 0.0 {
+        return backdrop;
+    }
+    if mode == BlendMode::Normal && alpha_s >= 1.0 {
+        return source;
+    }
+
+    let alpha_b = backdrop.a() as f32 / 255.0;
+    let cs = to_unit(source);
+    let cb = to_unit(backdrop);
+    let blended = blend_rgb(mode, cb, cs);
+
+    let alpha_o = alpha_s + alpha_b * (1.0 - alpha_s);
+    if alpha_o <= 0.0 {
+        return Color32::TRANSPARENT;
+    }
+
+    let mut out = [0u8; 3];
+    for i in 0..3 {
+        // Where the backdrop is transparent the source shows through unblended
+        let mixed = (1.0 - alpha_b) * cs[i] + alpha_b * blended[i];
+        let c = (alpha_s * mixed + alpha_b * cb[i] * (1.0 - alpha_s)) / alpha_o;
+        out[i] = (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    Color32::from_rgba_unmultiplied(out[0], out[1], out[2], (alpha_o * 255.0).round() as u8)
+}
+
+fn to_unit(color: Color32) -> [f32; 3] {
+    let [r, g, b, _] = color.to_srgba_unmultiplied();
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]
+}
+
+fn blend_rgb(mode: BlendMode, cb: [f32; 3], cs: [f32; 3]) -> [f32; 3] {
+    match mode {
+        BlendMode::Hue => set_lum(set_sat(cs, sat(cb)), lum(cb)),
+        BlendMode::Saturation => set_lum(set_sat(cb, sat(cs)), lum(cb)),
+        BlendMode::Color => set_lum(cs, lum(cb)),
+        BlendMode::Luminosity => set_lum(cb, lum(cs)),
+        _ => [
+            blend_channel(mode, cb[0], cs[0]),
+            blend_channel(mode, cb[1], cs[1]),
+            blend_channel(mode, cb[2], cs[2]),
+        ],
+    }
+}
+
+fn blend_channel(mode: BlendMode, cb: f32, cs: f32) -> f32 {
+    match mode {
+        BlendMode::Normal => cs,
+        BlendMode::Multiply => cb * cs,
+        BlendMode::Screen => cb + cs - cb * cs,
+        BlendMode::Overlay => hard_light(cs, cb),
+        BlendMode::Darken => cb.min(cs),
+        BlendMode::Lighten => cb.max(cs),
+        BlendMode::ColorDodge => {
+            if cb <= 0.0 {
+                0.0
+            } else if cs >= 1.0 {
+                1.0
+            } else {
+                (cb / (1.0 - cs)).min(1.0)
+            }
+        }
+        BlendMode::ColorBurn => {
+            if cb >= 1.0 {
+                1.0
+            } else if cs <= 0.0 {
+                0.0
+            } else {
+                1.0 - ((1.0 - cb) / cs).min(1.0)
+            }
+        }
+        BlendMode::HardLight => hard_light(cb, cs),
+        BlendMode::SoftLight => {
+            if cs <= 0.5 {
+                cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+            } else {
+                let d = if cb <= 0.25 {
+                    ((16.0 * cb - 12.0) * cb + 4.0) * cb
+                } else {
+                    cb.sqrt()
+                };
+                cb + (2.0 * cs - 1.0) * (d - cb)
+            }
+        }
+        BlendMode::Difference => (cb - cs).abs(),
+        BlendMode::Exclusion => cb + cs - 2.0 * cb * cs,
+        BlendMode::Addition => (cb + cs).min(1.0),
+        BlendMode::Subtract => (cb - cs).max(0.0),
+        // Non-separable modes are handled per pixel in blend_rgb
+        BlendMode::Hue | BlendMode::Saturation | BlendMode::Color | BlendMode::Luminosity => cs,
+    }
+}
+
+fn hard_light(cb: f32, cs: f32) -> f32 {
+    if cs <= 0.5 {
+        cb * 2.0 * cs
+    } else {
+        let s = 2.0 * cs - 1.0;
+        cb + s - cb * s
+    }
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn clip_color(c: [f32; 3]) -> [f32; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    let mut out = c;
+    for v in &mut out {
+        if n < 0.0 && l - n > 0.0 {
+            *v = l + (*v - l) * l / (l - n);
+        }
+        if x > 1.0 && x - l > 0.0 {
+            *v = l + (*v - l) * (1.0 - l) / (x - l);
+        }
+    }
+    out
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    clip_color([c[0] + d, c[1] + d, c[2] + d])
+}
+
+fn sat(c: [f32; 3]) -> f32 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+    let max = c[0].max(c[1]).max(c[2]);
+    let min = c[0].min(c[1]).min(c[2]);
+    if max <= min {
+        return [0.0; 3];
+    }
+    c.map(|v| (v - min) * s / (max - min))
+}
+
+/// Pixels a group of layers can be composited onto
+trait Target {
+    fn get_pixel(&self, x: usize, y: usize) -> Color32;
+    fn set(&mut self, x: usize, y: usize, color: Color32);
+}
+
+impl Target for RowBand<'_> {
+    fn get_pixel(&self, x: usize, y: usize) -> Color32 {
+        RowBand::get_pixel(self, x, y)
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: Color32) {
+        RowBand::set(self, x, y, color);
+    }
+}
+
+/// Transparent buffer covering one region, where a group's children are composited
+/// before the group itself is blended
+struct GroupBuffer {
+    pixels: PixelBuffer,
+    region: DirtyRect,
+}
+
+impl Target for GroupBuffer {
+    fn get_pixel(&self, x: usize, y: usize) -> Color32 {
+        self.pixels.get_pixel(x - self.region.min_x, y - self.region.min_y)
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: Color32) {
+        self.pixels.set(x - self.region.min_x, y - self.region.min_y, color);
+    }
+}
+
+/// Which layers a composite is made from, besides being visible
+type LayerFilter = fn(&Layer) -> bool;
+
+fn any_layer(_: &Layer) -> bool {
+    true
+}
+
+/// Composite the visible layers, bottom to top, over what's already in the band.
+/// Reference layers are included; this is what the canvas shows.
+pub fn compose_layers(frame: &Frame, region: DirtyRect, target: &mut RowBand) {
+    compose_range(frame, 0..frame.layers.len(), region, target, any_layer);
+}
+
+/// Colour of one pixel of a frame as the eyedropper sees it, leaving out reference
+/// layers it isn't allowed to pick from
+pub fn compose_pixel(frame: &Frame, x: usize, y: usize) -> Color32 {
+    let region = DirtyRect::pixel(x, y);
+    let mut pixel = GroupBuffer { pixels: PixelBuffer::new(1, 1), region };
+    compose_range(frame, 0..frame.layers.len(), region, &mut pixel, Layer::is_pickable);
+    pixel.pixels.get_pixel(0, 0)
+}
+
+fn compose_range(frame: &Frame, range: std::ops::Range<usize>, region: DirtyRect, target: &mut impl Target, filter: LayerFilter) {
+    for (start, end) in frame.top_level(range) {
+        let layer = &frame.layers[end];
+        if !layer.visible || layer.opacity <= 0.0 || !filter(layer) {
+            continue;
+        }
+        if layer.is_group() {
+            // Groups are isolated: children blend with each other first, then the
+            // result is blended with the group's own mode and opacity
+            let mut group = GroupBuffer { pixels: PixelBuffer::new(region.width(), region.height()), region };
+            compose_range(frame, start..end, region, &mut group, filter);
+            for (x, y, color) in group.pixels.pixels() {
+                if color.a() > 0 {
+                    let (x, y) = (x + region.min_x, y + region.min_y);
+                    let backdrop = target.get_pixel(x, y);
+                    target.set(x, y, blend(layer.blend_mode, backdrop, color, layer.opacity));
+                }
+            }
+            continue;
+        }
+        // Only allocated tiles are visited, so empty areas of a layer cost nothing
+        layer.grid.for_each_visible_pixel(region, |x, y, color| {
+            let color = layer.masked(x, y, color);
+            let backdrop = target.get_pixel(x, y);
+            target.set(x, y, blend(layer.blend_mode, backdrop, color, layer.opacity));
+        });
+    }
+}
+
+/// Flatten a range of layers into a new image, one band of rows per worker thread.
+/// Runs on whichever pool calls it, so the editor wraps calls in its own pool.
+/// Reference layers are left out unless they are marked for export.
+fn compose_range_to_buffer(frame: &Frame, range: std::ops::Range<usize>) -> PixelBuffer {
+    let Some(first) = frame.layers.first() else {
+        return PixelBuffer::default();
+    };
+    let mut composed = PixelBuffer::new(first.width(), first.height());
+    let full = DirtyRect::full(composed.width(), composed.height());
+    composed.row_bands_mut(full, TILE_SIZE).into_par_iter().for_each(|mut band| {
+        if let Some(region) = band.clip(full) {
+            compose_range(frame, range.clone(), region, &mut band, Layer::is_exported);
+        }
+    });
+    composed
+}
+
+/// Flatten one frame into a new image
+pub fn compose_frame(frame: &Frame) -> PixelBuffer {
+    compose_range_to_buffer(frame, 0..frame.layers.len())
+}
+
+/// Copy of a frame where every top-level group is merged into a single image layer
+/// that keeps the group's name, visibility, opacity and blend mode
+pub fn flatten_groups(frame: &Frame) -> Frame {
+    let layers = frame.top_level(0..frame.layers.len()).into_iter().map(|(start, end)| {
+        let layer = &frame.layers[end];
+        if !layer.is_group() {
+            return layer.clone();
+        }
+        let mut flat = Layer::new(layer.name.clone(), layer.width(), layer.height(), Color32::TRANSPARENT);
+        flat.visible = layer.visible;
+        flat.opacity = layer.opacity;
+        flat.blend_mode = layer.blend_mode;
+        flat.set_pixels(&compose_range_to_buffer(frame, start..end));
+        flat
+    }).collect();
+    Frame { layers, duration_ms: frame.duration_ms }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Color32 {
+        Color32::from_rgb(r, g, b)
+    }
+
+    #[test]
+    fn separable_modes_follow_the_w3c_formulas() {
+        let (cb, cs) = (0.25, 0.75);
+        assert_eq!(blend_channel(BlendMode::Multiply, cb, cs), 0.1875);
+        assert_eq!(blend_channel(BlendMode::Screen, cb, cs), 0.8125);
+        assert_eq!(blend_channel(BlendMode::Darken, cb, cs), 0.25);
+        assert_eq!(blend_channel(BlendMode::Lighten, cb, cs), 0.75);
+        assert_eq!(blend_channel(BlendMode::Difference, cb, cs), 0.5);
+        assert_eq!(blend_channel(BlendMode::Exclusion, cb, cs), 0.625);
+        assert_eq!(blend_channel(BlendMode::Addition, cb, cs), 1.0);
+        assert_eq!(blend_channel(BlendMode::Subtract, cb, cs), 0.0);
+        // Overlay is hard light with the layers swapped
+        assert_eq!(blend_channel(BlendMode::Overlay, cb, cs), hard_light(cs, cb));
+        assert_eq!(blend_channel(BlendMode::HardLight, cb, cs), 0.625);
+        assert_eq!(blend_channel(BlendMode::Overlay, cb, cs), 0.375);
+    }
+
+    #[test]
+    fn dodge_and_burn_keep_to_their_edge_cases() {
+        assert_eq!(blend_channel(BlendMode::ColorDodge, 0.0, 1.0), 0.0);
+        assert_eq!(blend_channel(BlendMode::ColorDodge, 0.5, 1.0), 1.0);
+        assert_eq!(blend_channel(BlendMode::ColorDodge, 0.25, 0.5), 0.5);
+        assert_eq!(blend_channel(BlendMode::ColorBurn, 1.0, 0.0), 1.0);
+        assert_eq!(blend_channel(BlendMode::ColorBurn, 0.5, 0.0), 0.0);
+        assert_eq!(blend_channel(BlendMode::ColorBurn, 0.75, 0.5), 0.5);
+    }
+
+    #[test]
+    fn soft_light_leaves_the_backdrop_under_mid_grey() {
+        for cb in [0.0, 0.2, 0.5, 0.9, 1.0] {
+            assert!((blend_channel(BlendMode::SoftLight, cb, 0.5) - cb).abs() < 1e-6);
+        }
+        assert!(blend_channel(BlendMode::SoftLight, 0.5, 1.0) > 0.5);
+        assert!(blend_channel(BlendMode::SoftLight, 0.5, 0.0) < 0.5);
+    }
+
+    #[test]
+    fn luminosity_takes_the_source_brightness() {
+        let (cb, cs) = ([0.8, 0.2, 0.2], [0.5, 0.5, 0.5]);
+        let out = blend_rgb(BlendMode::Luminosity, cb, cs);
+        assert!((lum(out) - lum(cs)).abs() < 1e-5);
+        // The colour keeps the backdrop's hue: red stays the strongest channel
+        assert!(out[0] > out[1] && (out[1] - out[2]).abs() < 1e-6);
+        let out = blend_rgb(BlendMode::Color, cs, cb);
+        assert!((lum(out) - lum(cs)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn grey_has_no_saturation_to_give() {
+        let out = blend_rgb(BlendMode::Saturation, [0.8, 0.2, 0.2], [0.5, 0.5, 0.5]);
+        assert!((out[0] - out[1]).abs() < 1e-6 && (out[1] - out[2]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn opaque_normal_replaces_and_transparent_leaves_the_backdrop() {
+        let (backdrop, source) = (rgb(10, 20, 30), rgb(200, 100, 50));
+        assert_eq!(blend(BlendMode::Normal, backdrop, source, 1.0), source);
+        assert_eq!(blend(BlendMode::Multiply, backdrop, source, 0.0), backdrop);
+        assert_eq!(blend(BlendMode::Screen, backdrop, Color32::TRANSPARENT, 1.0), backdrop);
+    }
+
+    #[test]
+    fn blending_onto_nothing_shows_the_source_unblended() {
+        let source = rgb(200, 100, 50);
+        assert_eq!(blend(BlendMode::Multiply, Color32::TRANSPARENT, source, 1.0), source);
+        assert_eq!(blend(BlendMode::Difference, Color32::TRANSPARENT, source, 1.0), source);
+    }
+
+    #[test]
+    fn opacity_mixes_the_blend_into_the_backdrop() {
+        let out = blend(BlendMode::Normal, rgb(0, 0, 0), rgb(255, 255, 255), 0.5);
+        assert_eq!(out.a(), 255);
+        assert!((127..=128).contains(&out.r()));
+        let out = blend(BlendMode::Multiply, rgb(255, 255, 255), rgb(128, 64, 0), 1.0);
+        assert_eq!(out.to_srgba_unmultiplied(), [128, 64, 0, 255]);
+    }
+}
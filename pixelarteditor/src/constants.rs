@@ -1,36 +1,15 @@
 use eframe::egui;
 
 pub const PIXEL_SIZE: f32 = 24.0;
+// Layers are stored as sparse tiles, so these limits don't depend on canvas size
 pub const MAX_LAYERS: usize = 64;
 pub const MAX_FRAMES: usize = 64;
+// Frame timing; Aseprite and GIF store durations in 16 bits
+pub const DEFAULT_FRAME_DURATION_MS: u32 = 100;
+pub const MAX_FRAME_DURATION_MS: u32 = 65535;
 
-// Performance limits
-pub const PERFORMANCE_WARNING_SIZE: usize = 200;
-pub const PERFORMANCE_CRITICAL_SIZE: usize = 500;
-pub const MAX_SAFE_CANVAS_SIZE: usize = 1024;
-
-// Dynamic limits based on canvas size
-pub fn get_max_layers_for_size(width: usize, height: usize) -> usize {
-    let total_pixels = width * height;
-    if total_pixels > 250_000 { // 500x500
-        16
-    } else if total_pixels > 100_000 { // ~316x316
-        32
-    } else {
-        MAX_LAYERS
-    }
-}
-
-pub fn get_max_frames_for_size(width: usize, height: usize) -> usize {
-    let total_pixels = width * height;
-    if total_pixels > 250_000 { // 500x500
-        8
-    } else if total_pixels > 100_000 { // ~316x316
-        16
-    } else {
-        MAX_FRAMES
-    }
-}
+// Canvas size limits (the canvas is drawn as a single texture, so size only costs memory)
+pub const MAX_CANVAS_SIZE: usize = 4096;
 
 pub fn get_default_palette() -> Vec<egui::Color32> {
     vec![
@@ -0,0 +1,176 @@
+/// Ordered-dither threshold map, tiled over the canvas from its top-left corner.
+///
+/// Each cell holds a level from 0 to one less than the cell count. A pixel whose
+/// coverage passes its cell's threshold takes the second of the two colours being mixed,
+/// so levels in a good order spread any coverage evenly over the tile.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ThresholdMap {
+    width: usize,
+    height: usize,
+    levels: Vec<u8>,
+}
+
+impl ThresholdMap {
+    /// Wrap levels given row by row; returns None if the count doesn't match the size
+    pub fn new(width: usize, height: usize, levels: Vec<u8>) -> Option<Self> {
+        (width > 0 && height > 0 && levels.len() == width * height).then_some(Self { width, height, levels })
+    }
+
+    /// Bayer matrix of a power-of-two size, e.g. 2, 4 or 8
+    pub fn bayer(size: usize) -> Self {
+        let mut map = Self { width: 1, height: 1, levels: vec![0] };
+        while map.width < size {
+            // Each step quarters every cell: M(2n) = [4M, 4M+2; 4M+3, 4M+1]
+            let n = map.width;
+            let mut levels = vec![0; 4 * n * n];
+            for y in 0..2 * n {
+                for x in 0..2 * n {
+                    let offset = [[0, 2], [3, 1]][y / n][x / n];
+                    levels[y * 2 * n + x] = 4 * map.levels[(y % n) * n + x % n] + offset;
+                }
+            }
+            map = Self { width: 2 * n, height: 2 * n, levels };
+        }
+        map
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Number of distinct coverage steps the map can show
+    pub fn count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level(&self, x: usize, y: usize) -> u8 {
+        self.levels[(y % self.height) * self.width + x % self.width]
+    }
+
+    pub fn set_level(&mut self, x: usize, y: usize, level: u8) {
+        if x < self.width && y < self.height {
+            self.levels[y * self.width + x] = level;
+        }
+    }
+
+    /// Threshold between 0 and 1 for a canvas pixel
+    pub fn threshold(&self, x: isize, y: isize) -> f32 {
+        let level = self.level(x.rem_euclid(self.width as isize) as usize, y.rem_euclid(self.height as isize) as usize);
+        (level as f32 + 0.5) / self.count() as f32
+    }
+}
+
+/// Pattern the dither tool paints, lined up with the canvas rather than the pointer
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DitherPattern {
+    Bayer2,
+    Bayer4,
+    Bayer8,
+    Checkerboard,
+    HorizontalLines,
+    VerticalLines,
+    DiagonalLines,
+    Crosshatch,
+    Custom, // The user's own threshold map
+}
+
+impl DitherPattern {
+    pub const ALL: [DitherPattern; 9] = [
+        DitherPattern::Bayer2,
+        DitherPattern::Bayer4,
+        DitherPattern::Bayer8,
+        DitherPattern::Checkerboard,
+        DitherPattern::HorizontalLines,
+        DitherPattern::VerticalLines,
+        DitherPattern::DiagonalLines,
+        DitherPattern::Crosshatch,
+        DitherPattern::Custom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DitherPattern::Bayer2 => "Bayer 2×2",
+            DitherPattern::Bayer4 => "Bayer 4×4",
+            DitherPattern::Bayer8 => "Bayer 8×8",
+            DitherPattern::Checkerboard => "Checkerboard",
+            DitherPattern::HorizontalLines => "Horizontal Lines",
+            DitherPattern::VerticalLines => "Vertical Lines",
+            DitherPattern::DiagonalLines => "Diagonal Lines",
+            DitherPattern::Crosshatch => "Crosshatch",
+            DitherPattern::Custom => "Custom",
+        }
+    }
+
+    /// Whether the density setting changes this pattern; the others are fixed shapes
+    pub fn has_density(&self) -> bool {
+        matches!(self, DitherPattern::Bayer2 | DitherPattern::Bayer4 | DitherPattern::Bayer8 | DitherPattern::Custom)
+    }
+
+    /// Threshold map of a pattern that has a density; None for the fixed shapes
+    pub fn map(&self, custom: &ThresholdMap) -> Option<ThresholdMap> {
+        match self {
+            DitherPattern::Bayer2 => Some(ThresholdMap::bayer(2)),
+            DitherPattern::Bayer4 => Some(ThresholdMap::bayer(4)),
+            DitherPattern::Bayer8 => Some(ThresholdMap::bayer(8)),
+            DitherPattern::Custom => Some(custom.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the pattern covers a canvas pixel. `map` is the pattern's threshold map,
+    /// which covers the share of pixels given by `density`, from 0 to 1.
+    pub fn covers(&self, x: isize, y: isize, density: f32, map: Option<&ThresholdMap>) -> bool {
+        if let Some(map) = map {
+            return map.threshold(x, y) < density;
+        }
+        match self {
+            DitherPattern::Checkerboard => (x + y).rem_euclid(2) == 0,
+            DitherPattern::HorizontalLines => y.rem_euclid(2) == 0,
+            DitherPattern::VerticalLines => x.rem_euclid(2) == 0,
+            DitherPattern::DiagonalLines => (x + y).rem_euclid(4) == 0,
+            DitherPattern::Crosshatch => (x + y).rem_euclid(4) == 0 || (x - y).rem_euclid(4) == 0,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_bayer_matrices_match_the_standard_ones() {
+        assert_eq!(ThresholdMap::bayer(2), ThresholdMap::new(2, 2, vec![0, 2, 3, 1]).unwrap());
+        let four = vec![0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
+        assert_eq!(ThresholdMap::bayer(4), ThresholdMap::new(4, 4, four).unwrap());
+    }
+
+    #[test]
+    fn every_level_appears_once() {
+        for size in [1, 2, 4, 8, 16] {
+            let map = ThresholdMap::bayer(size);
+            assert_eq!((map.width(), map.height()), (size, size));
+            let mut levels: Vec<usize> = (0..size * size).map(|i| map.level(i % size, i / size) as usize).collect();
+            levels.sort_unstable();
+            assert_eq!(levels, (0..size * size).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn thresholds_tile_across_the_canvas() {
+        let map = ThresholdMap::bayer(4);
+        assert_eq!(map.threshold(0, 0), 0.5 / 16.0);
+        assert_eq!(map.threshold(-4, 8), map.threshold(0, 0));
+        assert_eq!(map.threshold(-1, -1), map.threshold(3, 3));
+    }
+
+    #[test]
+    fn levels_must_fill_the_size() {
+        assert_eq!(ThresholdMap::new(2, 2, vec![0, 1, 2]), None);
+        assert_eq!(ThresholdMap::new(0, 0, Vec::new()), None);
+    }
+}
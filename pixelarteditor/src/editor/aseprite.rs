@@ -0,0 +1,1020 @@
+use crate::constants::{DEFAULT_FRAME_DURATION_MS, MAX_FRAME_DURATION_MS};
+use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use crate::tiled_buffer::TiledBuffer;
+use crate::types::{AnimationDirection, AnimationTag, BlendMode, ColorMode, Frame, Layer, LayerKind, ReferenceImage, Slice, SliceKey, SliceRect};
+use eframe::egui;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+// Aseprite (.ase/.aseprite) reader and writer.
+// Format reference: https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md
+
+const HEADER_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+const HEADER_SIZE: usize = 128;
+const FRAME_HEADER_SIZE: usize = 16;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_COLOR_PROFILE: u16 = 0x2007;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_USER_DATA: u16 = 0x2020;
+const CHUNK_SLICE: u16 = 0x2022;
+const CHUNK_PALETTE: u16 = 0x2019;
+
+const HEADER_FLAG_LAYER_OPACITY: u32 = 1;
+
+const LAYER_FLAG_VISIBLE: u16 = 1;
+const LAYER_FLAG_EDITABLE: u16 = 2;
+const LAYER_FLAG_COLLAPSED: u16 = 32;
+const LAYER_FLAG_REFERENCE: u16 = 64;
+
+const LAYER_TYPE_IMAGE: u16 = 0;
+const LAYER_TYPE_GROUP: u16 = 1;
+
+const CEL_RAW: u16 = 0;
+const CEL_LINKED: u16 = 1;
+const CEL_COMPRESSED: u16 = 2;
+
+const SLICE_FLAG_NINE_PATCH: u32 = 1;
+const SLICE_FLAG_PIVOT: u32 = 2;
+
+const USER_DATA_FLAG_COLOR: u32 = 2;
+
+fn direction_to_ase(direction: AnimationDirection) -> u8 {
+    match direction {
+        AnimationDirection::Forward => 0,
+        AnimationDirection::Reverse => 1,
+        AnimationDirection::PingPong => 2,
+        AnimationDirection::PingPongReverse => 3,
+    }
+}
+
+fn direction_from_ase(direction: u8) -> AnimationDirection {
+    match direction {
+        1 => AnimationDirection::Reverse,
+        2 => AnimationDirection::PingPong,
+        3 => AnimationDirection::PingPongReverse,
+        _ => AnimationDirection::Forward,
+    }
+}
+
+fn blend_mode_to_ase(mode: BlendMode) -> u16 {
+    match mode {
+        BlendMode::Normal => 0,
+        BlendMode::Multiply => 1,
+        BlendMode::Screen => 2,
+        BlendMode::Overlay => 3,
+        BlendMode::Darken => 4,
+        BlendMode::Lighten => 5,
+        BlendMode::ColorDodge => 6,
+        BlendMode::ColorBurn => 7,
+        BlendMode::HardLight => 8,
+        BlendMode::SoftLight => 9,
+        BlendMode::Difference => 10,
+        BlendMode::Exclusion => 11,
+        BlendMode::Hue => 12,
+        BlendMode::Saturation => 13,
+        BlendMode::Color => 14,
+        BlendMode::Luminosity => 15,
+        BlendMode::Addition => 16,
+        BlendMode::Subtract => 17,
+    }
+}
+
+fn blend_mode_from_ase(id: u16) -> BlendMode {
+    // Aseprite's "Divide" (18) has no equivalent here and falls back to normal
+    match id {
+        1 => BlendMode::Multiply,
+        2 => BlendMode::Screen,
+        3 => BlendMode::Overlay,
+        4 => BlendMode::Darken,
+        5 => BlendMode::Lighten,
+        6 => BlendMode::ColorDodge,
+        7 => BlendMode::ColorBurn,
+        8 => BlendMode::HardLight,
+        9 => BlendMode::SoftLight,
+        10 => BlendMode::Difference,
+        11 => BlendMode::Exclusion,
+        12 => BlendMode::Hue,
+        13 => BlendMode::Saturation,
+        14 => BlendMode::Color,
+        15 => BlendMode::Luminosity,
+        16 => BlendMode::Addition,
+        17 => BlendMode::Subtract,
+        _ => BlendMode::Normal,
+    }
+}
+
+/// Little-endian writer for the Aseprite primitive types
+#[derive(Default)]
+struct AseWriter {
+    buf: Vec<u8>,
+}
+
+impl AseWriter {
+    fn byte(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn word(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn short(&mut self, v: i16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn dword(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn zeros(&mut self, n: usize) {
+        self.buf.resize(self.buf.len() + n, 0);
+    }
+
+    fn string(&mut self, s: &str) {
+        self.word(s.len() as u16);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn bytes(&mut self, b: &[u8]) {
+        self.buf.extend_from_slice(b);
+    }
+
+    /// Append a chunk: size and type header followed by the body
+    fn chunk(&mut self, chunk_type: u16, body: AseWriter) {
+        self.dword((body.buf.len() + 6) as u32);
+        self.word(chunk_type);
+        self.bytes(&body.buf);
+    }
+}
+
+/// Little-endian reader over a whole file or one chunk body
+struct AseReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> AseReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|end| *end <= self.data.len())
+            .ok_or_else(|| "Unexpected end of Aseprite data".to_string())?;
+        let slice = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), String> {
+        self.take(n).map(|_| ())
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn word(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn short(&mut self) -> Result<i16, String> {
+        Ok(self.word()? as i16)
+    }
+
+    fn dword(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.word()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let slice = &self.data[self.pos..];
+        self.pos = self.data.len();
+        slice
+    }
+}
+
+fn zlib_compress(data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn zlib_decompress(data: &[u8], expected_len: usize) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut out = Vec::with_capacity(expected_len);
+    flate2::read::ZlibDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/// Colour model of the pixels stored in a file
+#[derive(Clone, Copy, PartialEq)]
+enum ColorDepth {
+    Rgba,
+    Grayscale,
+    Indexed,
+}
+
+impl ColorDepth {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            ColorDepth::Rgba => 4,
+            ColorDepth::Grayscale => 2,
+            ColorDepth::Indexed => 1,
+        }
+    }
+}
+
+/// Layer description read from a layer chunk
+struct LayerEntry {
+    name: String,
+    visible: bool,
+    opacity: f32,
+    blend_mode: BlendMode,
+    layer_type: u16,
+    child_level: usize,
+    collapsed: bool,
+    reference: bool,
+}
+
+/// Layer indices of `range` in Aseprite order: bottom to top, each group before its children
+fn groups_first_order(stack: &Frame, range: std::ops::Range<usize>, out: &mut Vec<usize>) {
+    for (start, end) in stack.top_level(range) {
+        out.push(end);
+        if stack.layers[end].is_group() {
+            groups_first_order(stack, start..end, out);
+        }
+    }
+}
+
+/// Cel read from a file, in RGBA
+struct DecodedCel {
+    layer_index: usize,
+    x: isize,
+    y: isize,
+    pixels: PixelBuffer,
+    indices: Option<Vec<u8>>, // The file's palette indices, row by row, when it is indexed
+    linked_to: Option<usize>, // First frame of the cel's link
+}
+
+/// Everything read from an Aseprite file
+struct AseDocument {
+    width: usize,
+    height: usize,
+    frames: Vec<Frame>,
+    palette: Vec<egui::Color32>,
+    color_mode: ColorMode,
+    transparent_index: u8,
+    tags: Vec<AnimationTag>,
+    slices: Vec<Slice>,
+}
+
+impl PixelArtEditor {
+    /// Save every frame and layer as an Aseprite file, optionally merging each layer group
+    pub fn save_aseprite_file(&self, filepath: &str, flatten_groups: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let flattened: Vec<Frame>;
+        let frames: Vec<&Frame> = if flatten_groups {
+            flattened = self.thread_pool.install(|| self.frames.iter().map(crate::compositor::flatten_groups).collect());
+            flattened.iter().collect()
+        } else {
+            self.frames.iter().collect()
+        };
+        let data = self.encode_aseprite(&frames)?;
+        std::fs::write(filepath, data)?;
+        println!("Aseprite file saved as: {}", filepath);
+        Ok(())
+    }
+
+    /// Replace the sprite with the contents of an Aseprite file
+    pub fn load_aseprite_file(&mut self, filepath: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let data = std::fs::read(filepath)?;
+        let document = decode_aseprite(&data)?;
+        if document.frames.is_empty() || document.frames.iter().any(|frame| frame.layers.is_empty()) {
+            return Err("Aseprite file has no image layers".into());
+        }
+        if !document.palette.is_empty() {
+            self.color_palette = document.palette;
+        }
+        self.frames = document.frames;
+        self.color_mode = document.color_mode;
+        self.transparent_index = document.transparent_index;
+        self.tags = document.tags;
+        self.slices = document.slices;
+        self.selected_slice = None;
+        // Aseprite files load as image layers, so no tilesets carry over
+        self.tilesets.clear();
+        self.selected_tile = None;
+        self.terrains.clear();
+        self.active_terrain = None;
+        self.deselect();
+        self.playback_tag = None;
+        self.stop_playback();
+        if self.color_mode != ColorMode::Rgba {
+            // Rebuild palette indices from the decoded colours
+            for layer in self.frames.iter_mut().flat_map(|frame| frame.layers.iter_mut()) {
+                layer.mark_all_dirty();
+            }
+        }
+        self.current_frame = 0;
+        self.current_layer = 0;
+        self.invalidate_cache();
+        println!("Aseprite file loaded from: {} ({}x{})", filepath, document.width, document.height);
+        Ok(())
+    }
+
+    fn encode_aseprite(&self, frames: &[&Frame]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let (width, height) = frames.first()
+            .and_then(|frame| frame.layers.first())
+            .map(|layer| (layer.width(), layer.height()))
+            .ok_or("No canvas data to export")?;
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err("Canvas is too large for an Aseprite file".into());
+        }
+
+        // Aseprite keeps one layer stack for the whole sprite; take each layer's
+        // settings from the first frame that has it
+        let layer_count = frames.iter().map(|frame| frame.layers.len()).max().unwrap_or(0);
+        let layer_stack = Frame {
+            layers: (0..layer_count)
+                .filter_map(|i| frames.iter().find_map(|frame| frame.layers.get(i)).cloned())
+                .collect(),
+            ..Frame::default()
+        };
+
+        // Aseprite lists each group before its children, so layer indices in the file differ from ours
+        let mut file_order = Vec::with_capacity(layer_count);
+        groups_first_order(&layer_stack, 0..layer_count, &mut file_order);
+        let mut file_index = vec![0u16; layer_count];
+        for (position, &i) in file_order.iter().enumerate() {
+            file_index[i] = position as u16;
+        }
+
+        let palette: Vec<egui::Color32> = self.color_palette.iter().take(256).copied().collect();
+
+        let mut frames_data = AseWriter::default();
+        for (frame_idx, frame) in frames.iter().enumerate() {
+            let mut chunks = AseWriter::default();
+            let mut chunk_count: u32 = 0;
+
+            if frame_idx == 0 {
+                // sRGB colour profile
+                let mut profile = AseWriter::default();
+                profile.word(1);
+                profile.word(0);
+                profile.dword(0);
+                profile.zeros(8);
+                chunks.chunk(CHUNK_COLOR_PROFILE, profile);
+                chunk_count += 1;
+
+                if !palette.is_empty() {
+                    let mut body = AseWriter::default();
+                    body.dword(palette.len() as u32);
+                    body.dword(0);
+                    body.dword(palette.len() as u32 - 1);
+                    body.zeros(8);
+                    for color in &palette {
+                        body.word(0);
+                        body.bytes(&color.to_srgba_unmultiplied());
+                    }
+                    chunks.chunk(CHUNK_PALETTE, body);
+                    chunk_count += 1;
+                }
+
+                for &i in &file_order {
+                    let layer = &layer_stack.layers[i];
+                    let mut body = AseWriter::default();
+                    let mut flags = LAYER_FLAG_EDITABLE;
+                    if layer.visible {
+                        flags |= LAYER_FLAG_VISIBLE;
+                    }
+                    if layer.collapsed {
+                        flags |= LAYER_FLAG_COLLAPSED;
+                    }
+                    // Aseprite leaves reference layers out of its own exports too
+                    if layer.is_reference() {
+                        flags |= LAYER_FLAG_REFERENCE;
+                    }
+                    body.word(flags);
+                    body.word(if layer.is_group() { LAYER_TYPE_GROUP } else { LAYER_TYPE_IMAGE });
+                    body.word(layer.depth as u16);
+                    body.word(0); // Default width/height (ignored)
+                    body.word(0);
+                    body.word(blend_mode_to_ase(layer.blend_mode));
+                    body.byte((layer.opacity.clamp(0.0, 1.0) * 255.0).round() as u8);
+                    body.zeros(3);
+                    body.string(&layer.name);
+                    chunks.chunk(CHUNK_LAYER, body);
+                    chunk_count += 1;
+                }
+
+                let tags: Vec<&AnimationTag> = self.tags.iter().filter(|tag| tag.from < frames.len()).collect();
+                if !tags.is_empty() {
+                    let mut body = AseWriter::default();
+                    body.word(tags.len() as u16);
+                    body.zeros(8);
+                    for tag in tags {
+                        body.word(tag.from as u16);
+                        body.word(tag.to.min(frames.len() - 1) as u16);
+                        body.byte(direction_to_ase(tag.direction));
+                        body.word(tag.repeat.min(u16::MAX as u32) as u16);
+                        body.zeros(6);
+                        let [r, g, b, _] = tag.color.to_srgba_unmultiplied();
+                        body.bytes(&[r, g, b, 0]);
+                        body.string(&tag.name);
+                    }
+                    chunks.chunk(CHUNK_TAGS, body);
+                    chunk_count += 1;
+                }
+
+                for slice in self.slices.iter().filter(|slice| !slice.keys.is_empty()) {
+                    // Flags apply to the whole slice, so keys without a centre or pivot store zeros
+                    let mut flags = 0;
+                    if slice.keys.iter().any(|key| key.center.is_some()) {
+                        flags |= SLICE_FLAG_NINE_PATCH;
+                    }
+                    if slice.keys.iter().any(|key| key.pivot.is_some()) {
+                        flags |= SLICE_FLAG_PIVOT;
+                    }
+                    let mut body = AseWriter::default();
+                    body.dword(slice.keys.len() as u32);
+                    body.dword(flags);
+                    body.dword(0);
+                    body.string(&slice.name);
+                    for key in &slice.keys {
+                        body.dword(key.frame as u32);
+                        write_slice_rect(&mut body, key.bounds);
+                        if flags & SLICE_FLAG_NINE_PATCH != 0 {
+                            write_slice_rect(&mut body, key.center.unwrap_or(SliceRect { x: 0, y: 0, width: 0, height: 0 }));
+                        }
+                        // The flags cover the whole slice, so keys without a pivot get one at 0,0
+                        if flags & SLICE_FLAG_PIVOT != 0 {
+                            let (x, y) = key.pivot.unwrap_or((0, 0));
+                            body.dword(x as i32 as u32);
+                            body.dword(y as i32 as u32);
+                        }
+                    }
+                    chunks.chunk(CHUNK_SLICE, body);
+
+                    // The slice colour goes in a user data chunk right after it
+                    let mut user_data = AseWriter::default();
+                    user_data.dword(USER_DATA_FLAG_COLOR);
+                    user_data.bytes(&slice.color.to_srgba_unmultiplied());
+                    chunks.chunk(CHUNK_USER_DATA, user_data);
+                    chunk_count += 2;
+                }
+            }
+
+            for (layer_idx, layer) in frame.layers.iter().enumerate() {
+                if layer.is_group() {
+                    continue;
+                }
+                // Cels hold only the used area, which may reach past the canvas edges
+                let Some((x, y, w, h)) = layer.grid.content_bounds() else {
+                    continue;
+                };
+                // Later cels of a link point back at the first frame that has it
+                let linked_frame = layer.link.and_then(|id| {
+                    frames[..frame_idx].iter().position(|f| f.layers.get(layer_idx).is_some_and(|l| l.link == Some(id)))
+                });
+                if let Some(linked_frame) = linked_frame {
+                    let mut body = AseWriter::default();
+                    body.word(file_index[layer_idx]);
+                    body.short(x.clamp(i16::MIN as isize, i16::MAX as isize) as i16);
+                    body.short(y.clamp(i16::MIN as isize, i16::MAX as isize) as i16);
+                    body.byte(255);
+                    body.word(CEL_LINKED);
+                    body.short(0); // Z-index
+                    body.zeros(5);
+                    body.word(linked_frame as u16);
+                    chunks.chunk(CHUNK_CEL, body);
+                    chunk_count += 1;
+                    continue;
+                }
+                let mut pixels = layer.grid.read_region(x, y, w, h);
+                // Aseprite has no layer masks, so they are baked into the cel
+                if layer.mask.is_some() {
+                    for (px, py, color) in pixels.clone().pixels() {
+                        let (cx, cy) = (x + px as isize, y + py as isize);
+                        if cx >= 0 && cy >= 0 {
+                            pixels.set(px, py, layer.masked(cx as usize, cy as usize, color));
+                        }
+                    }
+                }
+                let mut body = AseWriter::default();
+                body.word(file_index[layer_idx]);
+                body.short(x.clamp(i16::MIN as isize, i16::MAX as isize) as i16);
+                body.short(y.clamp(i16::MIN as isize, i16::MAX as isize) as i16);
+                body.byte(255);
+                body.word(CEL_COMPRESSED);
+                body.short(0); // Z-index
+                body.zeros(5);
+                body.word(w as u16);
+                body.word(h as u16);
+                let data = match self.color_mode {
+                    ColorMode::Rgba => pixels.as_bytes().to_vec(),
+                    ColorMode::Grayscale => pixels.as_bytes().chunks_exact(4).flat_map(|p| [p[0], p[3]]).collect(),
+                    ColorMode::Indexed => {
+                        // Prefer the stored indices so duplicate palette entries survive
+                        let mut indices = self.rgba_to_indices(pixels.as_bytes());
+                        for (i, index) in indices.iter_mut().enumerate() {
+                            if let Some(stored) = layer.indices.get(x + (i % w) as isize, y + (i / w) as isize) {
+                                *index = stored;
+                            }
+                        }
+                        indices
+                    }
+                };
+                body.bytes(&zlib_compress(&data)?);
+                chunks.chunk(CHUNK_CEL, body);
+                chunk_count += 1;
+            }
+
+            frames_data.dword((chunks.buf.len() + FRAME_HEADER_SIZE) as u32);
+            frames_data.word(FRAME_MAGIC);
+            frames_data.word(chunk_count.min(0xFFFF) as u16);
+            frames_data.word(frame.duration_ms.min(MAX_FRAME_DURATION_MS) as u16);
+            frames_data.zeros(2);
+            frames_data.dword(chunk_count);
+            frames_data.bytes(&chunks.buf);
+        }
+
+        let mut file = AseWriter::default();
+        file.dword((HEADER_SIZE + frames_data.buf.len()) as u32);
+        file.word(HEADER_MAGIC);
+        file.word(frames.len() as u16);
+        file.word(width as u16);
+        file.word(height as u16);
+        file.word(match self.color_mode {
+            ColorMode::Rgba => 32,
+            ColorMode::Grayscale => 16,
+            ColorMode::Indexed => 8,
+        });
+        file.dword(HEADER_FLAG_LAYER_OPACITY);
+        file.word(frames[0].duration_ms.min(MAX_FRAME_DURATION_MS) as u16); // Deprecated speed field
+        file.dword(0);
+        file.dword(0);
+        file.byte(self.transparent_index);
+        file.zeros(3);
+        file.word(palette.len() as u16);
+        file.byte(1); // Pixel ratio 1:1
+        file.byte(1);
+        file.short(0); // Grid position and size
+        file.short(0);
+        file.word(16);
+        file.word(16);
+        file.zeros(84);
+        file.bytes(&frames_data.buf);
+        Ok(file.buf)
+    }
+}
+
+fn decode_aseprite(data: &[u8]) -> Result<AseDocument, Box<dyn std::error::Error>> {
+    let mut header = AseReader::new(data);
+    header.dword()?; // File size
+    if header.word()? != HEADER_MAGIC {
+        return Err("Not an Aseprite file".into());
+    }
+    let frame_count = header.word()? as usize;
+    let width = header.word()? as usize;
+    let height = header.word()? as usize;
+    let depth = match header.word()? {
+        32 => ColorDepth::Rgba,
+        16 => ColorDepth::Grayscale,
+        8 => ColorDepth::Indexed,
+        other => return Err(format!("Unsupported Aseprite color depth: {}", other).into()),
+    };
+    let header_flags = header.dword()?;
+    header.skip(2 + 4 + 4)?;
+    let transparent_index = header.byte()?;
+
+    let mut reader = AseReader::new(data);
+    reader.skip(HEADER_SIZE)?;
+
+    let mut layers: Vec<LayerEntry> = Vec::new();
+    let mut palette: Vec<egui::Color32> = Vec::new();
+    // Raw cels per frame, kept so linked cels can refer back to them
+    let mut frame_cels: Vec<Vec<DecodedCel>> = Vec::new();
+    let mut durations: Vec<u32> = Vec::new();
+    let mut tags: Vec<AnimationTag> = Vec::new();
+    let mut slices: Vec<Slice> = Vec::new();
+
+    for _ in 0..frame_count {
+        let frame_start = reader.pos;
+        let frame_size = reader.dword()? as usize;
+        if reader.word()? != FRAME_MAGIC {
+            return Err("Corrupt Aseprite frame header".into());
+        }
+        let old_chunks = reader.word()? as u32;
+        durations.push(reader.word()? as u32);
+        reader.skip(2)?;
+        let new_chunks = reader.dword()?;
+        let chunk_count = if new_chunks == 0 { old_chunks } else { new_chunks };
+
+        let mut cels = Vec::new();
+        let mut previous_chunk = 0;
+        for _ in 0..chunk_count {
+            let chunk_size = reader.dword()? as usize;
+            let chunk_type = reader.word()?;
+            let body = reader.take(chunk_size.checked_sub(6).ok_or("Corrupt Aseprite chunk")?)?;
+            let mut chunk = AseReader::new(body);
+
+            match chunk_type {
+                CHUNK_LAYER => {
+                    let flags = chunk.word()?;
+                    let layer_type = chunk.word()?;
+                    let child_level = chunk.word()? as usize;
+                    chunk.skip(4)?;
+                    let blend_mode = blend_mode_from_ase(chunk.word()?);
+                    let opacity = chunk.byte()?;
+                    chunk.skip(3)?;
+                    let name = chunk.string()?;
+                    layers.push(LayerEntry {
+                        name,
+                        visible: flags & LAYER_FLAG_VISIBLE != 0,
+                        opacity: if header_flags & HEADER_FLAG_LAYER_OPACITY != 0 { opacity as f32 / 255.0 } else { 1.0 },
+                        blend_mode,
+                        layer_type,
+                        child_level,
+                        collapsed: flags & LAYER_FLAG_COLLAPSED != 0,
+                        reference: flags & LAYER_FLAG_REFERENCE != 0,
+                    });
+                    // Trailing fields (tileset index, UUID) aren't needed
+                }
+                CHUNK_CEL => {
+                    let layer_index = chunk.word()? as usize;
+                    let x = chunk.short()? as isize;
+                    let y = chunk.short()? as isize;
+                    let opacity = chunk.byte()?;
+                    let cel_type = chunk.word()?;
+                    chunk.short()?; // Z-index
+                    chunk.skip(5)?;
+
+                    let pixels = match cel_type {
+                        CEL_RAW | CEL_COMPRESSED => {
+                            let w = chunk.word()? as usize;
+                            let h = chunk.word()? as usize;
+                            let len = w * h * depth.bytes_per_pixel();
+                            let raw = if cel_type == CEL_RAW {
+                                chunk.take(len)?.to_vec()
+                            } else {
+                                zlib_decompress(chunk.rest(), len)?
+                            };
+                            if raw.len() < len {
+                                return Err("Aseprite cel is shorter than its size".into());
+                            }
+                            Some(DecodedCel {
+                                layer_index,
+                                x,
+                                y,
+                                pixels: to_rgba(&raw[..len], w, h, depth, &palette, transparent_index, opacity),
+                                indices: matches!(depth, ColorDepth::Indexed).then(|| raw[..len].to_vec()),
+                                linked_to: None,
+                            })
+                        }
+                        CEL_LINKED => {
+                            let linked_frame = chunk.word()? as usize;
+                            frame_cels.get(linked_frame)
+                                .and_then(|cels| cels.iter().find(|cel| cel.layer_index == layer_index))
+                                .map(|source| DecodedCel {
+                                    pixels: source.pixels.clone(),
+                                    indices: source.indices.clone(),
+                                    linked_to: Some(source.linked_to.unwrap_or(linked_frame)),
+                                    ..*source
+                                })
+                        }
+                        // Tilemap cels aren't supported yet
+                        _ => None,
+                    };
+                    cels.extend(pixels);
+                }
+                CHUNK_PALETTE => {
+                    let size = chunk.dword()? as usize;
+                    let first = chunk.dword()? as usize;
+                    let last = chunk.dword()? as usize;
+                    chunk.skip(8)?;
+                    palette.resize(size.max(palette.len()), egui::Color32::TRANSPARENT);
+                    for index in first..=last {
+                        let flags = chunk.word()?;
+                        let rgba = chunk.take(4)?;
+                        if flags & 1 != 0 {
+                            chunk.string()?;
+                        }
+                        if let Some(entry) = palette.get_mut(index) {
+                            *entry = egui::Color32::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3]);
+                        }
+                    }
+                }
+                CHUNK_TAGS => {
+                    let count = chunk.word()?;
+                    chunk.skip(8)?;
+                    for _ in 0..count {
+                        let from = chunk.word()? as usize;
+                        let to = chunk.word()? as usize;
+                        let direction = direction_from_ase(chunk.byte()?);
+                        let repeat = chunk.word()? as u32;
+                        chunk.skip(6)?;
+                        let rgb = chunk.take(3)?;
+                        let color = egui::Color32::from_rgb(rgb[0], rgb[1], rgb[2]);
+                        chunk.skip(1)?;
+                        let mut tag = AnimationTag::new(chunk.string()?, from, to);
+                        tag.color = color;
+                        tag.direction = direction;
+                        tag.repeat = repeat;
+                        tags.push(tag);
+                    }
+                }
+                CHUNK_OLD_PALETTE if palette.is_empty() => {
+                    let packets = chunk.word()?;
+                    let mut index = 0usize;
+                    for _ in 0..packets {
+                        index += chunk.byte()? as usize;
+                        let count = match chunk.byte()? {
+                            0 => 256,
+                            n => n as usize,
+                        };
+                        for _ in 0..count {
+                            let rgb = chunk.take(3)?;
+                            if palette.len() <= index {
+                                palette.resize(index + 1, egui::Color32::TRANSPARENT);
+                            }
+                            palette[index] = egui::Color32::from_rgb(rgb[0], rgb[1], rgb[2]);
+                            index += 1;
+                        }
+                    }
+                }
+                CHUNK_SLICE => {
+                    let key_count = chunk.dword()?;
+                    let flags = chunk.dword()?;
+                    chunk.dword()?;
+                    let name = chunk.string()?;
+                    let mut keys: Vec<SliceKey> = Vec::new();
+                    for _ in 0..key_count {
+                        let frame = chunk.dword()? as usize;
+                        let bounds = read_slice_rect(&mut chunk)?;
+                        let center = if flags & SLICE_FLAG_NINE_PATCH != 0 {
+                            Some(read_slice_rect(&mut chunk)?).filter(|c| c.width > 0 && c.height > 0)
+                        } else {
+                            None
+                        };
+                        let pivot = if flags & SLICE_FLAG_PIVOT != 0 {
+                            Some((chunk.dword()? as i32 as isize, chunk.dword()? as i32 as isize))
+                        } else {
+                            None
+                        };
+                        keys.push(SliceKey { frame, bounds, center, pivot });
+                    }
+                    keys.sort_by_key(|key| key.frame);
+                    slices.push(Slice { name, color: egui::Color32::from_rgb(0, 0, 255), keys });
+                }
+                CHUNK_USER_DATA if previous_chunk == CHUNK_SLICE && chunk.dword()? & USER_DATA_FLAG_COLOR != 0 => {
+                    let rgba = chunk.take(4)?;
+                    if let Some(slice) = slices.last_mut() {
+                        slice.color = egui::Color32::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3]);
+                    }
+                }
+                _ => {}
+            }
+            previous_chunk = chunk_type;
+        }
+
+        frame_cels.push(cels);
+        reader.pos = frame_start + frame_size;
+    }
+
+    // Image and group layers become editor layers, reordered so each group follows its children
+    let mut editor_layers: Vec<usize> = Vec::new();
+    let mut open_groups: Vec<usize> = Vec::new();
+    for (i, layer) in layers.iter().enumerate() {
+        if layer.layer_type != LAYER_TYPE_IMAGE && layer.layer_type != LAYER_TYPE_GROUP {
+            continue;
+        }
+        while open_groups.last().is_some_and(|&g| layers[g].child_level >= layer.child_level) {
+            editor_layers.extend(open_groups.pop());
+        }
+        if layer.layer_type == LAYER_TYPE_GROUP {
+            open_groups.push(i);
+        } else {
+            editor_layers.push(i);
+        }
+    }
+    editor_layers.extend(open_groups.into_iter().rev());
+
+    // Linked cels become editor links, one per (layer, first frame) pair
+    let mut link_ids: HashMap<(usize, usize), u64> = HashMap::new();
+    for cel in frame_cels.iter().flatten() {
+        if let Some(root) = cel.linked_to {
+            let next = link_ids.len() as u64 + 1;
+            link_ids.entry((cel.layer_index, root)).or_insert(next);
+        }
+    }
+
+    let frames = frame_cels.into_iter().enumerate().map(|(frame_index, cels)| {
+        let mut frame_layers: Vec<Layer> = editor_layers.iter().map(|&i| {
+            let entry = &layers[i];
+            let mut layer = if entry.layer_type == LAYER_TYPE_GROUP {
+                Layer::new_group(entry.name.clone(), width, height)
+            } else {
+                Layer::new(entry.name.clone(), width, height, egui::Color32::TRANSPARENT)
+            };
+            if entry.reference {
+                layer.kind = LayerKind::Reference;
+            }
+            layer.depth = entry.child_level;
+            layer.collapsed = entry.collapsed;
+            layer.visible = entry.visible;
+            layer.opacity = entry.opacity;
+            layer.blend_mode = entry.blend_mode;
+            layer
+        }).collect();
+
+        for cel in cels {
+            let DecodedCel { layer_index, x, y, pixels, indices, linked_to } = cel;
+            if layers.get(layer_index).is_none_or(|layer| layer.layer_type != LAYER_TYPE_IMAGE) {
+                continue;
+            }
+            if let Some(target) = editor_layers.iter().position(|&i| i == layer_index) {
+                let mut grid = TiledBuffer::new(width, height);
+                grid.paste(&pixels, x, y);
+                if layers[layer_index].reference {
+                    // The file only has the reference as placed on the canvas, so that becomes its image
+                    frame_layers[target].reference = Some(ReferenceImage { image: grid.clone(), x: 0.0, y: 0.0, scale: 1.0, export: false, pickable: false });
+                }
+                frame_layers[target].set_grid(grid);
+                // Keep the file's own indices, so duplicate palette entries stay apart
+                for (i, &index) in indices.iter().flatten().enumerate() {
+                    let (cx, cy) = (x + (i % pixels.width()) as isize, y + (i / pixels.width()) as isize);
+                    let on_canvas = (0..width as isize).contains(&cx) && (0..height as isize).contains(&cy);
+                    if on_canvas && index != transparent_index {
+                        frame_layers[target].indices.set(cx, cy, Some(index));
+                    }
+                }
+                frame_layers[target].link = link_ids.get(&(layer_index, linked_to.unwrap_or(frame_index))).copied();
+            }
+        }
+        // Files from very old versions leave the duration at 0
+        let duration_ms = match durations[frame_index] {
+            0 => DEFAULT_FRAME_DURATION_MS,
+            ms => ms,
+        };
+        Frame { layers: frame_layers, duration_ms }
+    }).collect();
+
+    let color_mode = match depth {
+        ColorDepth::Rgba => ColorMode::Rgba,
+        ColorDepth::Grayscale => ColorMode::Grayscale,
+        ColorDepth::Indexed => ColorMode::Indexed,
+    };
+    Ok(AseDocument { width, height, frames, palette, color_mode, transparent_index, tags, slices })
+}
+
+fn write_slice_rect(body: &mut AseWriter, rect: SliceRect) {
+    body.dword(rect.x as i32 as u32);
+    body.dword(rect.y as i32 as u32);
+    body.dword(rect.width as u32);
+    body.dword(rect.height as u32);
+}
+
+fn read_slice_rect(chunk: &mut AseReader) -> Result<SliceRect, String> {
+    Ok(SliceRect {
+        x: chunk.dword()? as i32 as isize,
+        y: chunk.dword()? as i32 as isize,
+        width: chunk.dword()? as usize,
+        height: chunk.dword()? as usize,
+    })
+}
+
+/// Convert cel pixels of any colour depth to RGBA, baking in the cel opacity
+fn to_rgba(raw: &[u8], width: usize, height: usize, depth: ColorDepth, palette: &[egui::Color32], transparent_index: u8, opacity: u8) -> PixelBuffer {
+    let mut data = Vec::with_capacity(width * height * 4);
+    for pixel in raw.chunks_exact(depth.bytes_per_pixel()) {
+        let rgba = match depth {
+            ColorDepth::Rgba => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            ColorDepth::Grayscale => [pixel[0], pixel[0], pixel[0], pixel[1]],
+            ColorDepth::Indexed => {
+                if pixel[0] == transparent_index {
+                    [0, 0, 0, 0]
+                } else {
+                    palette.get(pixel[0] as usize).map_or([0, 0, 0, 0], |c| c.to_srgba_unmultiplied())
+                }
+            }
+        };
+        let alpha = (rgba[3] as u32 * opacity as u32 / 255) as u8;
+        data.extend_from_slice(&[rgba[0], rgba[1], rgba[2], alpha]);
+    }
+    PixelBuffer::from_raw(width, height, data).unwrap_or_else(|| PixelBuffer::new(width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str, depth: usize, pixels: &[(usize, usize, egui::Color32)]) -> Layer {
+        let mut layer = Layer::new(name.to_owned(), 4, 3, egui::Color32::TRANSPARENT);
+        layer.depth = depth;
+        for &(x, y, color) in pixels {
+            layer.set_pixel(x, y, color);
+        }
+        layer
+    }
+
+    fn sprite() -> PixelArtEditor {
+        let mut editor = PixelArtEditor::default();
+        let mut shade = layer("Shade", 1, &[(1, 1, egui::Color32::from_rgb(40, 30, 20))]);
+        shade.blend_mode = BlendMode::Multiply;
+        shade.visible = false;
+        let mut group = Layer::new_group("Body".to_owned(), 4, 3);
+        group.opacity = 128.0 / 255.0;
+        // The background is one linked cel across both frames; the shade only looks the same
+        let mut back = layer("Back", 0, &[(0, 0, egui::Color32::RED), (3, 2, egui::Color32::BLUE)]);
+        back.link = Some(7);
+        let first = Frame { layers: vec![back.clone(), shade.clone(), group.clone()], duration_ms: 100 };
+        let second = Frame { layers: vec![back, shade, group], duration_ms: 250 };
+        editor.frames = vec![first, second];
+
+        let mut tag = AnimationTag::new("walk".to_owned(), 0, 1);
+        tag.direction = AnimationDirection::PingPong;
+        tag.repeat = 3;
+        tag.color = egui::Color32::from_rgb(10, 200, 30);
+        editor.tags = vec![tag];
+        editor.slices = vec![Slice {
+            name: "hit box".to_owned(),
+            color: egui::Color32::from_rgb(0, 0, 255),
+            keys: vec![SliceKey {
+                frame: 1,
+                bounds: SliceRect { x: 1, y: 0, width: 3, height: 2 },
+                center: Some(SliceRect { x: 1, y: 1, width: 1, height: 1 }),
+                pivot: Some((2, 1)),
+            }],
+        }];
+        editor
+    }
+
+    #[test]
+    fn sprites_survive_a_round_trip() {
+        let editor = sprite();
+        let frames: Vec<&Frame> = editor.frames.iter().collect();
+        let document = decode_aseprite(&editor.encode_aseprite(&frames).unwrap()).unwrap();
+        assert_eq!((document.width, document.height), (4, 3));
+        assert_eq!(document.frames.len(), 2);
+        for (saved, loaded) in editor.frames.iter().zip(&document.frames) {
+            assert_eq!(saved.duration_ms, loaded.duration_ms);
+            assert_eq!(saved.layers.len(), loaded.layers.len());
+            for (saved, loaded) in saved.layers.iter().zip(&loaded.layers) {
+                assert_eq!(saved.name, loaded.name);
+                assert_eq!(saved.visible, loaded.visible);
+                assert_eq!(saved.depth, loaded.depth);
+                assert_eq!(saved.is_group(), loaded.is_group());
+                assert_eq!(saved.blend_mode, loaded.blend_mode);
+                assert!((saved.opacity - loaded.opacity).abs() < 1e-3);
+                for y in 0..3 {
+                    for x in 0..4 {
+                        assert_eq!(saved.get_pixel(x, y), loaded.get_pixel(x, y), "{} at {},{}", saved.name, x, y);
+                    }
+                }
+            }
+        }
+        let (back, shade) = (&document.frames[1].layers[0], &document.frames[1].layers[1]);
+        assert!(back.link.is_some());
+        assert_eq!(document.frames[0].layers[0].link, back.link);
+        assert_eq!(document.frames[0].layers[0].grid.to_pixel_buffer(), back.grid.to_pixel_buffer());
+        assert_eq!((document.frames[0].layers[1].link, shade.link), (None, None));
+        assert_eq!(document.tags, editor.tags);
+        assert_eq!(document.slices, editor.slices);
+    }
+
+    #[test]
+    fn indexed_files_keep_their_indices() {
+        let mut editor = PixelArtEditor {
+            color_mode: ColorMode::Indexed,
+            transparent_index: 0,
+            color_palette: vec![egui::Color32::TRANSPARENT, egui::Color32::RED, egui::Color32::RED],
+            ..PixelArtEditor::default()
+        };
+        let mut layer = layer("Art", 0, &[(0, 0, egui::Color32::RED), (1, 0, egui::Color32::RED)]);
+        layer.indices.set(0, 0, Some(1));
+        layer.indices.set(1, 0, Some(2));
+        editor.frames = vec![Frame { layers: vec![layer], duration_ms: 100 }];
+        let frames: Vec<&Frame> = editor.frames.iter().collect();
+        let document = decode_aseprite(&editor.encode_aseprite(&frames).unwrap()).unwrap();
+        let loaded = &document.frames[0].layers[0];
+        assert_eq!(document.color_mode, ColorMode::Indexed);
+        assert_eq!((loaded.indices.get(0, 0), loaded.indices.get(1, 0)), (Some(1), Some(2)));
+        assert_eq!(loaded.indices.get(2, 0), None);
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert!(decode_aseprite(b"GIF89a").is_err());
+        assert!(decode_aseprite(&[0; 128]).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use crate::editor::core::PixelArtEditor;
+use crate::types::{AutotileKind, Terrain, TileRef};
+
+// Auto-tiling. Painting a terrain marks the cell under the pointer, then every cell
+// around it picks the terrain's tile for its new neighbour mask. Which cells belong to
+// a terrain is read back from the tiles placed in them, so there is nothing to keep in step.
+
+// Edge bits for 4-bit sets, with the neighbour each stands for: N, E, S, W
+pub const EDGES: [(u8, (isize, isize)); 4] = [(1, (0, -1)), (2, (1, 0)), (4, (0, 1)), (8, (-1, 0))];
+
+// Neighbour offsets clockwise from north, in blob bit order
+pub const BLOB_NEIGHBOURS: [(isize, isize); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+// Corner bits, for Wang corner sets, in Tiled's order: top right, bottom right,
+// bottom left, top left. Each goes with the corner's offset from the cell's top left.
+pub const CORNERS: [(u8, (isize, isize)); 4] = [(1, (1, 0)), (2, (1, 1)), (4, (0, 1)), (8, (0, 0))];
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TerrainFile {
+    terrains: Vec<Terrain>,
+}
+
+impl PixelArtEditor {
+    /// Add a terrain for the current tileset and make it the one painted
+    pub fn add_terrain(&mut self, kind: AutotileKind) {
+        let Some(tileset) = self.active_tileset() else {
+            return;
+        };
+        self.push_undo();
+        self.terrains.push(Terrain::new(format!("Terrain {}", self.terrains.len() + 1), kind, tileset));
+        self.active_terrain = Some(self.terrains.len() - 1);
+    }
+
+    pub fn remove_terrain(&mut self, index: usize) {
+        if index >= self.terrains.len() {
+            return;
+        }
+        self.push_undo();
+        self.terrains.remove(index);
+        self.active_terrain = match self.active_terrain {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            other => other,
+        };
+    }
+
+    /// Tile tool: paint the active terrain, or stamp the selected tile; `erase` takes
+    /// the terrain or tile away instead
+    pub fn use_tile_tool(&mut self, x: usize, y: usize, erase: bool) {
+        if self.active_terrain.is_some() {
+            self.paint_terrain(x, y, erase);
+        } else if erase {
+            self.set_tile_cell(x, y, None);
+        } else {
+            self.stamp_tile(x, y);
+        }
+    }
+
+    /// Add the active terrain to the cell under a canvas pixel, or take it away, and
+    /// retile the cells around it
+    pub fn paint_terrain(&mut self, x: usize, y: usize, erase: bool) {
+        let Some(terrain) = self.active_terrain.and_then(|i| self.terrains.get(i)).cloned() else {
+            return;
+        };
+        let (Some(tileset), Some((column, row))) = (self.active_tileset(), self.tile_cell_at(x, y)) else {
+            return;
+        };
+        if tileset != terrain.tileset {
+            return;
+        }
+        let Some(tilemap) = self.get_active_layer().tilemap.clone() else {
+            return;
+        };
+        let (tile_width, tile_height) = (self.tilesets[tileset].tile_width, self.tilesets[tileset].tile_height);
+        let (column, row) = (column as isize, row as isize);
+        let inside = |c: isize, r: isize| c >= 0 && r >= 0 && (c as usize) < tilemap.columns && (r as usize) < tilemap.rows;
+        let placed = |c: isize, r: isize| tilemap.get(c as usize, r as usize);
+
+        let mut changes: Vec<(isize, isize, Option<TileRef>)> = Vec::new();
+        match terrain.kind {
+            AutotileKind::Edge4 | AutotileKind::Blob8 => {
+                // Cells past the map edges count as the terrain, so filled areas have no border there
+                let member = |c: isize, r: isize| {
+                    if (c, r) == (column, row) {
+                        !erase
+                    } else {
+                        !inside(c, r) || placed(c, r).is_some_and(|tile| terrain.mask_of(tile).is_some())
+                    }
+                };
+                for (dc, dr) in std::iter::once((0, 0)).chain(BLOB_NEIGHBOURS) {
+                    let (c, r) = (column + dc, row + dr);
+                    if !inside(c, r) {
+                        continue;
+                    }
+                    if !member(c, r) {
+                        if (c, r) == (column, row) && placed(c, r).is_some_and(|tile| terrain.mask_of(tile).is_some()) {
+                            changes.push((c, r, None));
+                        }
+                        continue;
+                    }
+                    let mask = if terrain.kind == AutotileKind::Edge4 {
+                        EDGES.iter()
+                            .filter(|(_, (dx, dy))| member(c + dx, r + dy))
+                            .fold(0, |mask, (bit, _)| mask | bit)
+                    } else {
+                        BLOB_NEIGHBOURS.iter().enumerate()
+                            .filter(|(_, (dx, dy))| member(c + dx, r + dy))
+                            .fold(0, |mask, (bit, _)| mask | 1 << bit)
+                    };
+                    changes.push((c, r, terrain.tile_for(mask)));
+                }
+            }
+            AutotileKind::WangCorner => {
+                // The cell's four corners are shared with the cells around it
+                for (dc, dr) in std::iter::once((0, 0)).chain(BLOB_NEIGHBOURS) {
+                    let (c, r) = (column + dc, row + dr);
+                    if !inside(c, r) {
+                        continue;
+                    }
+                    let current = placed(c, r).and_then(|tile| terrain.mask_of(tile));
+                    let shared = CORNERS.iter()
+                        .filter(|(_, (vx, vy))| (0..=1).contains(&(dc + vx)) && (0..=1).contains(&(dr + vy)))
+                        .fold(0, |mask, (bit, _)| mask | bit);
+                    let old = current.unwrap_or(0);
+                    let new = if erase { old & !shared } else { old | shared };
+                    if new == 0 {
+                        // Only clear cells this terrain drew
+                        if current.is_some() {
+                            changes.push((c, r, None));
+                        }
+                    } else if current != Some(new) {
+                        changes.push((c, r, terrain.tile_for(new)));
+                    }
+                }
+            }
+        }
+
+        for (c, r, tile) in changes {
+            self.set_tile_cell(c as usize * tile_width, r as usize * tile_height, tile);
+        }
+    }
+
+    /// Save the terrains and their rules as JSON
+    pub fn save_terrains_json(&self, filepath: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file = TerrainFile { terrains: self.terrains.clone() };
+        std::fs::write(filepath, serde_json::to_string_pretty(&file)?)?;
+        println!("Terrains saved as: {}", filepath);
+        Ok(())
+    }
+
+    /// Add the terrains from a JSON file as one undo step. They are set up for the
+    /// current tilemap layer's tileset, since rule files are usually shared between
+    /// tilesets with the same layout, so a tilemap layer has to be active.
+    pub fn load_terrains_json(&mut self, filepath: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file: TerrainFile = serde_json::from_str(&std::fs::read_to_string(filepath)?)?;
+        let tileset = self.active_tileset().ok_or("Select a tilemap layer to load terrains for")?;
+        self.push_undo();
+        for mut terrain in file.terrains {
+            terrain.tileset = tileset;
+            self.terrains.push(terrain);
+        }
+        println!("Terrains loaded from: {}", filepath);
+        Ok(())
+    }
+}
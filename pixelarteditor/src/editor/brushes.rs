@@ -0,0 +1,88 @@
+use crate::brush::{BrushShape, CustomBrush};
+use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use std::path::PathBuf;
+
+// The brush library lives in the config directory as one PNG per brush, named after the
+// brush, so it survives restarts and brushes can be shared by copying files.
+
+/// Folder the brush library is kept in
+pub fn brush_library_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("kamari-tool")
+        .join("brushes")
+}
+
+impl PixelArtEditor {
+    /// Read the brushes saved in the brush library folder, in name order
+    pub fn load_brush_library(&mut self) {
+        let Ok(entries) = std::fs::read_dir(brush_library_dir()) else {
+            return;
+        };
+        let mut paths: Vec<PathBuf> = entries.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let Some(name) = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else {
+                continue;
+            };
+            match image::open(&path) {
+                Ok(image) => self.brushes.push(CustomBrush { name, pixels: PixelBuffer::from_rgba_image(image.into_rgba8()) }),
+                Err(e) => eprintln!("Failed to load brush {}: {}", path.display(), e),
+            }
+        }
+        self.brush_textures.clear();
+    }
+
+    fn save_brush(brush: &CustomBrush) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = brush_library_dir();
+        std::fs::create_dir_all(&dir)?;
+        brush.pixels.as_image().save(dir.join(format!("{}.png", brush.name)))?;
+        Ok(())
+    }
+
+    /// Make a brush from the selected pixels of the current layer, or the floating
+    /// pixels, add it to the library and paint with it
+    pub fn capture_brush(&mut self) {
+        let pixels = match &self.floating {
+            Some(floating) => Some(floating.render().0),
+            None => self.selected_pixels().map(|(pixels, _, _)| pixels),
+        };
+        let Some(pixels) = pixels.filter(|pixels| pixels.pixels().any(|(_, _, color)| color.a() > 0)) else {
+            return;
+        };
+        let name = (1..)
+            .map(|n| format!("Brush {}", n))
+            .find(|name| !self.brushes.iter().any(|brush| &brush.name == name))
+            .unwrap_or_default();
+        let brush = CustomBrush { name, pixels };
+        if let Err(e) = Self::save_brush(&brush) {
+            eprintln!("Failed to save brush: {}", e);
+        }
+        self.brushes.push(brush);
+        self.active_brush = Some(self.brushes.len() - 1);
+        self.brush_shape = BrushShape::Custom;
+        self.brush_textures.clear();
+    }
+
+    /// Take a brush out of the library and delete its file
+    pub fn remove_brush(&mut self, index: usize) {
+        if index >= self.brushes.len() {
+            return;
+        }
+        let brush = self.brushes.remove(index);
+        let path = brush_library_dir().join(format!("{}.png", brush.name));
+        if let Err(e) = std::fs::remove_file(&path) && path.exists() {
+            eprintln!("Failed to delete brush {}: {}", path.display(), e);
+        }
+        self.active_brush = match self.active_brush {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            other => other,
+        };
+        self.brush_textures.clear();
+    }
+}
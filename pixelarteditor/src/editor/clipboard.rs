@@ -0,0 +1,100 @@
+use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use crate::selection_mask::SelectionMask;
+use crate::tiled_buffer::TiledBuffer;
+use crate::types::Layer;
+use eframe::egui;
+
+// Copied pixels are kept in `selection_pixels` and, when the OS clipboard can be
+// reached, also put there as an RGBA image so other programs can paste them. Pasting
+// prefers an image on the OS clipboard, so pixels copied elsewhere come in too. When
+// there is no OS clipboard (e.g. headless Linux) the editor's own copy is all there is.
+
+impl PixelArtEditor {
+    /// The OS clipboard, opened the first time it is needed. After a failed attempt it
+    /// is not tried again.
+    fn system_clipboard(&mut self) -> Option<&mut arboard::Clipboard> {
+        if self.system_clipboard.is_none() && !self.system_clipboard_unavailable {
+            match arboard::Clipboard::new() {
+                Ok(clipboard) => self.system_clipboard = Some(clipboard),
+                Err(e) => {
+                    eprintln!("System clipboard unavailable, copying within the editor only: {}", e);
+                    self.system_clipboard_unavailable = true;
+                }
+            }
+        }
+        self.system_clipboard.as_mut()
+    }
+
+    /// Put the copied pixels on the OS clipboard as an image
+    pub fn copy_to_system_clipboard(&mut self) {
+        let Some(pixels) = self.selection_pixels.clone() else {
+            return;
+        };
+        let Some(clipboard) = self.system_clipboard() else {
+            return;
+        };
+        let image = arboard::ImageData {
+            width: pixels.width(),
+            height: pixels.height(),
+            bytes: pixels.as_bytes().into(),
+        };
+        if let Err(e) = clipboard.set_image(image) {
+            eprintln!("Failed to copy image to the system clipboard: {}", e);
+        }
+    }
+
+    /// Take an image from the OS clipboard as the copied pixels. An image that matches
+    /// the pixels copied here already keeps the position it was copied from; any other
+    /// image is placed in the top-left corner.
+    fn take_system_clipboard_image(&mut self) {
+        let Some(clipboard) = self.system_clipboard() else {
+            return;
+        };
+        // No image on the clipboard (text, or nothing) leaves the editor's own copy
+        let Ok(image) = clipboard.get_image() else {
+            return;
+        };
+        let Some(pixels) = PixelBuffer::from_raw(image.width, image.height, image.bytes.into_owned()) else {
+            return;
+        };
+        if self.selection_pixels.as_ref() != Some(&pixels) {
+            self.selection_pixels = Some(pixels);
+            self.selection_pixels_origin = (0, 0);
+        }
+    }
+
+    /// Paste from the OS clipboard, or the editor's own copy, as a floating selection
+    pub fn paste(&mut self) {
+        self.take_system_clipboard_image();
+        self.paste_clipboard();
+    }
+
+    /// Paste from the OS clipboard, or the editor's own copy, onto a new layer and
+    /// select the pasted pixels
+    pub fn paste_as_new_layer(&mut self) {
+        self.take_system_clipboard_image();
+        let Some(pixels) = self.selection_pixels.clone() else {
+            return;
+        };
+        self.commit_floating();
+        self.push_undo();
+        let (x, y) = self.paste_position();
+        let frame = &mut self.frames[self.current_frame];
+        let (width, height) = (frame.layers[0].width(), frame.layers[0].height());
+        let mut grid = TiledBuffer::new(width, height);
+        let mut selection = SelectionMask::new(width, height);
+        for (px, py, color) in pixels.pixels() {
+            if color.a() > 0 {
+                grid.set(x + px as isize, y + py as isize, color);
+                selection.set(x + px as isize, y + py as isize, true);
+            }
+        }
+        let mut layer = Layer::new(format!("Layer {}", frame.layers.len() + 1), width, height, egui::Color32::TRANSPARENT);
+        layer.set_grid(grid);
+        frame.layers.push(layer);
+        self.current_layer = frame.layers.len() - 1;
+        self.selection = (!selection.is_empty()).then_some(selection);
+        self.invalidate_cache();
+    }
+}
@@ -0,0 +1,282 @@
+use crate::editor::core::PixelArtEditor;
+use crate::palette;
+use crate::types::ColorMode;
+use eframe::egui;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io::Write;
+
+impl PixelArtEditor {
+    /// Switch the document colour mode; existing pixels are converted on the next redraw
+    pub fn set_color_mode(&mut self, mode: ColorMode) {
+        if mode == self.color_mode {
+            return;
+        }
+        self.push_undo();
+
+        if mode == ColorMode::Indexed {
+            self.color_palette.truncate(256);
+            // Indexed documents need an entry to stand for "no colour"
+            match self.color_palette.iter().position(|c| c.a() == 0) {
+                Some(i) => self.transparent_index = i as u8,
+                None if self.color_palette.len() < 256 => {
+                    self.color_palette.insert(0, egui::Color32::TRANSPARENT);
+                    self.transparent_index = 0;
+                }
+                None => self.transparent_index = 0,
+            }
+        }
+
+        self.color_mode = mode;
+        for layer in self.frames.iter_mut().flat_map(|frame| frame.layers.iter_mut()) {
+            layer.indices.clear();
+            if mode != ColorMode::Rgba {
+                layer.mark_all_dirty();
+            }
+        }
+        self.invalidate_cache();
+    }
+
+    /// Change a palette entry; in indexed mode every pixel using it is recoloured. Edits
+    /// to the same entry in a row, like dragging in the colour picker, share one undo step.
+    pub fn set_palette_color(&mut self, index: usize, color: egui::Color32) {
+        if self.color_palette.get(index).is_none_or(|&entry| entry == color) {
+            return;
+        }
+        if self.palette_undo_entry != Some(index) {
+            self.push_undo();
+            self.palette_undo_entry = Some(index);
+        }
+        self.color_palette[index] = color;
+        if self.color_mode == ColorMode::Indexed {
+            self.recolor_indexed_layers();
+        }
+    }
+
+    /// Choose which palette entry is transparent. Pixels keep their indices, so pixels
+    /// using the new entry disappear and pixels using the old one show its colour. The
+    /// caller takes the undo step, so a drag through several entries can be one step.
+    pub fn set_transparent_index(&mut self, index: u8) {
+        if index == self.transparent_index {
+            return;
+        }
+        self.transparent_index = index;
+        if self.color_mode == ColorMode::Indexed {
+            self.recolor_indexed_layers();
+        }
+    }
+
+    fn recolor_indexed_layers(&mut self) {
+        let palette = &self.color_palette;
+        let transparent = self.transparent_index;
+        self.thread_pool.install(|| {
+            self.frames.par_iter_mut().flat_map(|frame| frame.layers.par_iter_mut()).for_each(|layer| {
+                layer.recolor_from_palette(palette, transparent);
+            });
+        });
+        self.invalidate_cache();
+    }
+
+    /// Bring pixels painted since the last redraw in line with the colour mode
+    pub fn conform_dirty_layers(&mut self) {
+        if self.color_mode == ColorMode::Rgba {
+            return;
+        }
+        let mode = self.color_mode;
+        let palette = &self.color_palette;
+        let transparent = self.transparent_index;
+        self.thread_pool.install(|| {
+            self.frames.par_iter_mut().flat_map(|frame| frame.layers.par_iter_mut()).for_each(|layer| {
+                // References keep the imported image's own colours
+                let Some(rect) = layer.dirty.filter(|_| !layer.is_reference()) else {
+                    return;
+                };
+                match mode {
+                    ColorMode::Indexed => layer.conform_to_palette(rect, palette, transparent),
+                    ColorMode::Grayscale => layer.conform_to_grayscale(rect),
+                    ColorMode::Rgba => {}
+                }
+            });
+        });
+    }
+
+    /// Map flattened RGBA pixels to palette indices, e.g. after layers were blended
+    pub fn rgba_to_indices(&self, image_data: &[u8]) -> Vec<u8> {
+        let mut cache: HashMap<[u8; 4], u8> = HashMap::new();
+        image_data.chunks_exact(4).map(|p| {
+            let rgba = [p[0], p[1], p[2], p[3]];
+            if rgba[3] == 0 {
+                return self.transparent_index;
+            }
+            *cache.entry(rgba).or_insert_with(|| {
+                let color = egui::Color32::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3]);
+                palette::nearest_index(&self.color_palette, color, self.transparent_index)
+                    .unwrap_or(self.transparent_index)
+            })
+        }).collect()
+    }
+
+    /// Palette as RGB triples padded to `len` entries
+    pub fn palette_rgb(&self, len: usize) -> Vec<u8> {
+        let mut rgb: Vec<u8> = self.color_palette.iter()
+            .take(256)
+            .flat_map(|c| {
+                let [r, g, b, _] = c.to_srgba_unmultiplied();
+                [r, g, b]
+            })
+            .collect();
+        rgb.resize(len * 3, 0);
+        rgb
+    }
+
+    /// Save an image as PNG-8, GIF or PCX using the document palette
+    pub fn save_indexed_image(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
+        if image_data.len() != width as usize * height as usize * 4 {
+            return Err("Image data does not match its size".into());
+        }
+        let indices = self.rgba_to_indices(image_data);
+        let extension = std::path::Path::new(filepath)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "gif" => self.save_as_gif_indexed(filepath, &indices, width, height),
+            "pcx" => self.save_as_pcx_indexed(filepath, &indices, width, height),
+            _ => self.save_as_png8(filepath, &indices, width, height),
+        }
+    }
+
+    fn save_as_png8(&self, filepath: &str, indices: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let entries = self.color_palette.len().clamp(1, 256);
+        let alpha: Vec<u8> = (0..entries)
+            .map(|i| palette::index_color(&self.color_palette, i as u8, self.transparent_index).a())
+            .collect();
+
+        let file = std::fs::File::create(filepath)?;
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(self.palette_rgb(entries));
+        if alpha.iter().any(|&a| a < 255) {
+            encoder.set_trns(alpha);
+        }
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(indices)?;
+        Ok(())
+    }
+
+    fn save_as_gif_indexed(&self, filepath: &str, indices: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err("Image is too large for GIF".into());
+        }
+        // GIF colour tables hold a power of two entries
+        let entries = self.color_palette.len().clamp(2, 256).next_power_of_two();
+        let file = std::fs::File::create(filepath)?;
+        let mut encoder = gif::Encoder::new(std::io::BufWriter::new(file), width as u16, height as u16, &self.palette_rgb(entries))?;
+        let frame = gif::Frame {
+            width: width as u16,
+            height: height as u16,
+            buffer: std::borrow::Cow::Borrowed(indices),
+            transparent: Some(self.transparent_index),
+            ..gif::Frame::default()
+        };
+        encoder.write_frame(&frame)?;
+        Ok(())
+    }
+
+    fn save_as_pcx_indexed(&self, filepath: &str, indices: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
+        if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err("Image size is not supported by PCX".into());
+        }
+        // Scanlines are padded to an even number of bytes
+        let bytes_per_line = (width as usize).div_ceil(2) * 2;
+
+        let mut header = [0u8; 128];
+        header[0] = 10; // Manufacturer
+        header[1] = 5; // Version 3.0 with 256-colour palette
+        header[2] = 1; // RLE encoding
+        header[3] = 8; // Bits per pixel
+        header[8..10].copy_from_slice(&(width as u16 - 1).to_le_bytes());
+        header[10..12].copy_from_slice(&(height as u16 - 1).to_le_bytes());
+        header[12..14].copy_from_slice(&72u16.to_le_bytes());
+        header[14..16].copy_from_slice(&72u16.to_le_bytes());
+        header[65] = 1; // Colour planes
+        header[66..68].copy_from_slice(&(bytes_per_line as u16).to_le_bytes());
+        header[68] = 1; // Colour palette
+
+        let file = std::fs::File::create(filepath)?;
+        let mut writer = std::io::BufWriter::new(file);
+        writer.write_all(&header)?;
+
+        let mut line = vec![0u8; bytes_per_line];
+        for row in indices.chunks_exact(width as usize) {
+            line[..row.len()].copy_from_slice(row);
+            writer.write_all(&pcx_rle(&line))?;
+        }
+
+        writer.write_all(&[0x0C])?;
+        writer.write_all(&self.palette_rgb(256))?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// PCX run-length encoding of one scanline. Runs are at most 63 bytes long, and a single
+/// byte with its top two bits set has to be written as a run so it isn't read as a count.
+fn pcx_rle(line: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(line.len());
+    let mut x = 0;
+    while x < line.len() {
+        let value = line[x];
+        let run = line[x..].iter().take(63).take_while(|&&v| v == value).count();
+        if run > 1 || value >= 0xC0 {
+            encoded.extend([0xC0 | run as u8, value]);
+        } else {
+            encoded.push(value);
+        }
+        x += run;
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcx_unrle(encoded: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        let mut bytes = encoded.iter();
+        while let Some(&byte) = bytes.next() {
+            if byte >= 0xC0 {
+                let value = *bytes.next().expect("run without a value");
+                decoded.extend(std::iter::repeat_n(value, (byte & 0x3F) as usize));
+            } else {
+                decoded.push(byte);
+            }
+        }
+        decoded
+    }
+
+    #[test]
+    fn pcx_runs_are_counted() {
+        assert_eq!(pcx_rle(&[1, 2, 2, 2, 3]), vec![1, 0xC3, 2, 3]);
+        assert_eq!(pcx_rle(&[]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn pcx_bytes_that_look_like_counts_are_escaped() {
+        assert_eq!(pcx_rle(&[0xBF, 0xC0, 0xFF]), vec![0xBF, 0xC1, 0xC0, 0xC1, 0xFF]);
+    }
+
+    #[test]
+    fn long_pcx_runs_are_split() {
+        let line = vec![7; 130];
+        assert_eq!(pcx_rle(&line), vec![0xFF, 7, 0xFF, 7, 0xC4, 7]);
+    }
+
+    #[test]
+    fn pcx_lines_decode_back() {
+        let line: Vec<u8> = (0..600).map(|i| [0, 0, 0, 5, 200, 200, 255, 12][i % 8] ^ (i / 97) as u8).collect();
+        assert_eq!(pcx_unrle(&pcx_rle(&line)), line);
+    }
+}
@@ -1,10 +1,30 @@
 use eframe::egui;
-use crate::types::{Tool, Layer, Frame, ExportFormat, ToolAnimation, AnimationType};
+use crate::types::{Tool, Layer, Frame, ExportFormat, ToolAnimation, AnimationType, DirtyRect, ColorMode, AnimationTag, Slice, SliceRect, Tileset, TileRef, Terrain, AutotileKind};
+use crate::editor::rendering::{ComposedGrid, CompositeKey};
+use crate::editor::stroke::Stroke;
+use crate::editor::undo_redo::UndoState;
+use crate::editor::floating::{FloatingSelection, FloatHandle};
+use crate::pixel_buffer::PixelBuffer;
+use crate::selection_mask::{SelectionMask, SelectionMode};
+use crate::brush::{BrushShape, CustomBrush, Ink};
+use crate::dither::{DitherPattern, ThresholdMap};
+use crate::gradient::{GradientDither, GradientShape};
+use crate::shapes::CurveKind;
+use crate::tiled_buffer::TiledBuffer;
 use crate::constants::*;
 use crate::plugins::PluginManager;
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::Instant;
 
+fn build_thread_pool(count: usize) -> Result<Arc<rayon::ThreadPool>, rayon::ThreadPoolBuildError> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(count)
+        .thread_name(|i| format!("kamari-worker-{}", i))
+        .build()
+        .map(Arc::new)
+}
+
 /// Core editor state and data
 pub struct PixelArtEditor {
     // Core editing state
@@ -16,8 +36,8 @@ pub struct PixelArtEditor {
     pub tool: Tool,
     
     // Undo/Redo system
-    pub undo_stack: Vec<(Vec<Frame>, usize, usize)>,
-    pub redo_stack: Vec<(Vec<Frame>, usize, usize)>,
+    pub undo_stack: Vec<UndoState>,
+    pub redo_stack: Vec<UndoState>,
     pub last_state: Option<(Vec<Frame>, usize, usize)>, // Keep for compatibility
     
     // View settings
@@ -33,14 +53,37 @@ pub struct PixelArtEditor {
     
     // Tool settings
     pub brush_size: usize,
+    pub brush_shape: BrushShape,
+    pub ink: Ink,
+    pub shading_colors: Vec<egui::Color32>, // Palette colours the shading ramp runs through; none means the whole palette
+    pub brush_angle: f32, // Clockwise turn of square and line brushes, in degrees
+    pub brushes: Vec<CustomBrush>, // Brush library, saved in the config directory
+    pub active_brush: Option<usize>,
+    pub brush_own_colors: bool, // Custom brushes paint their own colours, not a foreground silhouette
+    pub brush_aligned: bool, // Custom brush patterns line up with the canvas instead of each dab
+    pub brush_textures: Vec<egui::TextureHandle>, // Brush library thumbnails, cleared when the library changes
     pub spray_size: usize,
     pub spray_intensity: f32,
-    pub dither_pattern: usize,
+    pub dither_pattern: DitherPattern,
+    pub dither_density: f32, // Share of pixels threshold patterns cover, from 0 to 1
+    pub dither_two_color: bool, // Fill the gaps in the pattern with the secondary colour
+    pub gradient_shape: GradientShape,
+    pub gradient_dither: GradientDither,
+    pub gradient_palette_ramp: bool, // Run through the palette from primary to secondary, not just the two colours
+    pub custom_dither: ThresholdMap, // User-defined pattern for dithered gradients
+    pub gradient_start: Option<(isize, isize)>, // Where the gradient being dragged begins
+    pub ellipse_start: Option<(isize, isize)>, // Corner the ellipse being dragged starts from
+    pub polygon_points: Vec<(isize, isize)>, // Corners of the polygon being clicked out
+    pub curve_kind: CurveKind,
+    pub curve_points: Vec<(isize, isize)>, // Ends and control points of the curve being shaped, in order
+    pub curve_start: Option<(isize, isize)>, // Where the curve being dragged out begins
+    pub curve_handle: Option<usize>, // Point of the curve being dragged
     pub fill_shape: bool,
-    pub fill_outline: bool,
+    pub isometric_lines: bool, // Keep lines to the 2:1 slopes of isometric tiles
     pub pixel_perfect_mode: bool,
     pub symmetry_mode: bool,
     pub symmetry_axis: (bool, bool), // (horizontal, vertical)
+    pub stroke: Option<Stroke>, // Pencil or eraser stroke being drawn
     
     // Color palette
     pub color_palette: Vec<egui::Color32>,
@@ -48,11 +91,43 @@ pub struct PixelArtEditor {
     pub active_palette: usize,
     pub palette_names: Vec<String>,
     
+    // Document colour mode
+    pub color_mode: ColorMode,
+    pub transparent_index: u8, // Palette entry shown as transparent in indexed mode
+    pub palette_undo_entry: Option<usize>, // Palette entry the last undo step was taken for, so one picker drag is one step
+    pub selected_palette_index: Option<usize>, // Palette entry being edited
+    
     // Animation settings
     pub animation_playing: bool,
     pub animation_frame: usize,
-    pub animation_speed: f32,
-    pub last_animation_time: f64,
+    pub last_animation_time: f64, // When the frame being played was shown
+    pub tags: Vec<AnimationTag>,
+    pub playback_tag: Option<usize>, // Tag that plays and exports; None plays every frame
+    pub playback_sequence: Vec<usize>,
+    pub playback_position: usize,
+    pub playback_loops: bool,
+    
+    // Slices
+    pub slices: Vec<Slice>,
+    pub selected_slice: Option<usize>,
+    pub show_slices: bool, // Draw slices on the canvas with any tool, not just the slice tool
+    pub slice_drag_start: Option<(isize, isize)>,
+    pub slice_drag_end: Option<(isize, isize)>,
+    pub slice_drag_original: Option<SliceRect>, // Bounds of the slice being moved
+    pub show_nine_slice_preview: bool,
+    pub nine_slice_preview_size: (usize, usize),
+
+    // Tilemaps
+    pub tilesets: Vec<Tileset>,
+    pub selected_tile: Option<TileRef>, // Tile the tile tool stamps, with its flips
+    pub new_tile_size: (usize, usize),
+    pub show_tile_grid: bool,
+    pub tileset_texture: Option<egui::TextureHandle>,
+    pub show_tileset_panel: bool,
+    pub show_brush_panel: bool,
+    pub terrains: Vec<Terrain>,
+    pub active_terrain: Option<usize>, // Terrain the tile tool paints instead of stamping
+    pub new_terrain_kind: AutotileKind,
     
     // Onion skinning
     pub onion_skinning: bool,
@@ -73,10 +148,11 @@ pub struct PixelArtEditor {
     pub export_format: ExportFormat,
     pub export_individual_layers: bool,
     pub export_all_frames: bool,
+    pub export_flatten_groups: bool, // Export each layer group as one merged layer
     
     // Tool-specific state
     pub move_drag_start: Option<(usize, usize)>,
-    pub move_layer_snapshot: Option<Vec<Vec<egui::Color32>>>,
+    pub move_layer_snapshot: Option<TiledBuffer>,
     pub move_last_offset: Option<(isize, isize)>,
     pub line_start: Option<(usize, usize)>,
     pub line_end: Option<(usize, usize)>,
@@ -85,24 +161,44 @@ pub struct PixelArtEditor {
     pub circle_start: Option<(usize, usize)>,
     pub circle_center: Option<(usize, usize)>,
     pub circle_radius: Option<usize>,
-    pub selection_start: Option<(usize, usize)>,
-    pub selection_rect: Option<(usize, usize, usize, usize)>,
-    pub selection_area: Option<(usize, usize, usize, usize)>,
-    pub selection_pixels: Option<Vec<Vec<egui::Color32>>>,
-    pub lasso_points: Vec<(usize, usize)>,
+    pub selection: Option<SelectionMask>, // Pixels tools may change; None leaves the whole canvas open
+    pub selection_mode: SelectionMode,
+    pub selection_start: Option<(isize, isize)>, // Corner a rectangle or ellipse selection is dragged from
+    pub selection_modify_amount: usize, // Pixels grow, shrink and border work by
+    pub wand_tolerance: u8,
+    pub wand_contiguous: bool,
+    pub selection_pixels: Option<PixelBuffer>, // Copied pixels Ctrl+V pastes
+    pub selection_pixels_origin: (isize, isize), // Where the copied pixels were taken from
+    pub floating: Option<FloatingSelection>,
+    pub floating_redo: Vec<UndoState>, // Redo history lifting or pasting cleared, back if it is cancelled
+    pub paste_event_seen: bool, // egui already sent a Paste event for the Ctrl+V being held
+    pub floating_drag: Option<(FloatHandle, FloatingSelection, (f32, f32))>, // Handle, selection and pointer when the drag began
+    pub system_clipboard: Option<arboard::Clipboard>, // Opened on first copy or paste
+    pub system_clipboard_unavailable: bool, // Opening it failed, e.g. no display server
+    pub lasso_points: Vec<(isize, isize)>,
     pub lasso_active: bool,
-    pub lasso_selection: Option<Vec<(usize, usize)>>,
-    pub preview_overlay: Option<Vec<Vec<egui::Color32>>>,
+    pub preview_overlay: Option<PixelBuffer>, // Pixels a tool would paint, shown over the canvas until applied
+    pub preview_bounds: Option<DirtyRect>, // Where the overlay sits on the canvas; None when it paints nothing
+    pub preview_source: Vec<(isize, isize, egui::Color32)>, // Pixels the overlay was made from
+    pub preview_changed: bool, // Preview differs from what was last uploaded to its texture
+    pub preview_texture: Option<egui::TextureHandle>,
+    pub frame_thumbnails: Vec<Option<egui::TextureHandle>>, // Frames panel previews; None until shown or after the frame changes
     
     // Layer management
     pub renaming_layer: Option<usize>,
     pub rename_text: String,
     
     // Performance optimization
-    pub render_cache: Option<Vec<Vec<egui::Color32>>>,
+    pub render_cache: Option<ComposedGrid>,
+    pub render_cache_key: Option<CompositeKey>,
     pub cache_dirty: bool,
     pub last_frame_time: f64,
     pub frame_skip: usize,
+    pub canvas_texture: Option<egui::TextureHandle>,
+    pub texture_dirty: Option<DirtyRect>, // Region of the composite not yet uploaded to the canvas texture
+    pub checker_texture: Option<egui::TextureHandle>,
+    pub thread_count: usize, // Worker threads for compositing, filters and export (0 = one per core)
+    pub thread_pool: Arc<rayon::ThreadPool>,
     
     // Plugin system
     pub plugin_manager: PluginManager,
@@ -140,14 +236,37 @@ impl Default for PixelArtEditor {
             canvas_center_on_start: true,
             
             brush_size: 1,
+            brush_shape: BrushShape::Circle,
+            ink: Ink::Simple,
+            shading_colors: Vec::new(),
+            brush_angle: 0.0,
+            brushes: Vec::new(),
+            active_brush: None,
+            brush_own_colors: true,
+            brush_aligned: false,
+            brush_textures: Vec::new(),
             spray_size: 3,
             spray_intensity: 0.5,
-            dither_pattern: 0,
+            dither_pattern: DitherPattern::Bayer4,
+            dither_density: 0.5,
+            dither_two_color: false,
+            gradient_shape: GradientShape::Linear,
+            gradient_dither: GradientDither::Bayer4,
+            gradient_palette_ramp: false,
+            custom_dither: ThresholdMap::bayer(4),
+            gradient_start: None,
+            ellipse_start: None,
+            polygon_points: Vec::new(),
+            curve_kind: CurveKind::Cubic,
+            curve_points: Vec::new(),
+            curve_start: None,
+            curve_handle: None,
             fill_shape: false,
-            fill_outline: false,
+            isometric_lines: false,
             pixel_perfect_mode: true,
             symmetry_mode: false,
             symmetry_axis: (false, false),
+            stroke: None,
             
             color_palette: get_default_palette(),
             custom_palettes: vec![
@@ -158,10 +277,38 @@ impl Default for PixelArtEditor {
             active_palette: 0,
             palette_names: vec!["Default".to_string(), "Grayscale".to_string(), "Primary".to_string()],
             
+            color_mode: ColorMode::Rgba,
+            transparent_index: 0,
+            palette_undo_entry: None,
+            selected_palette_index: None,
+            
             animation_playing: false,
             animation_frame: 0,
-            animation_speed: 5.0,
             last_animation_time: 0.0,
+            tags: Vec::new(),
+            playback_tag: None,
+            playback_sequence: Vec::new(),
+            playback_position: 0,
+            playback_loops: true,
+            
+            slices: Vec::new(),
+            selected_slice: None,
+            show_slices: true,
+            slice_drag_start: None,
+            slice_drag_end: None,
+            slice_drag_original: None,
+            show_nine_slice_preview: false,
+            nine_slice_preview_size: (48, 32),
+            tilesets: Vec::new(),
+            selected_tile: None,
+            new_tile_size: (16, 16),
+            show_tile_grid: true,
+            tileset_texture: None,
+            show_tileset_panel: true,
+            show_brush_panel: true,
+            terrains: Vec::new(),
+            active_terrain: None,
+            new_terrain_kind: AutotileKind::Edge4,
             
             onion_skinning: false,
             onion_prev_frames: 1,
@@ -180,6 +327,7 @@ impl Default for PixelArtEditor {
             export_format: ExportFormat::PNG,
             export_individual_layers: false,
             export_all_frames: false,
+            export_flatten_groups: false,
             
             move_drag_start: None,
             move_layer_snapshot: None,
@@ -191,22 +339,42 @@ impl Default for PixelArtEditor {
             circle_start: None,
             circle_center: None,
             circle_radius: None,
+            selection: None,
+            selection_mode: SelectionMode::Replace,
             selection_start: None,
-            selection_rect: None,
-            selection_area: None,
+            selection_modify_amount: 1,
+            wand_tolerance: 0,
+            wand_contiguous: true,
             selection_pixels: None,
+            selection_pixels_origin: (0, 0),
+            floating: None,
+            floating_redo: Vec::new(),
+            paste_event_seen: false,
+            floating_drag: None,
+            system_clipboard: None,
+            system_clipboard_unavailable: false,
             lasso_points: Vec::new(),
             lasso_active: false,
-            lasso_selection: None,
             preview_overlay: None,
+            frame_thumbnails: Vec::new(),
+            preview_bounds: None,
+            preview_source: Vec::new(),
+            preview_changed: false,
+            preview_texture: None,
             
             renaming_layer: None,
             rename_text: String::new(),
             
             render_cache: None,
+            render_cache_key: None,
             cache_dirty: true,
             last_frame_time: 0.0,
             frame_skip: 0,
+            canvas_texture: None,
+            texture_dirty: None,
+            checker_texture: None,
+            thread_count: 0,
+            thread_pool: build_thread_pool(0).expect("failed to start worker threads"),
             
             plugin_manager: PluginManager::new(),
             
@@ -224,6 +392,7 @@ impl PixelArtEditor {
     pub fn new() -> Self {
         let mut editor = Self::default();
         editor.plugin_manager.initialize();
+        editor.load_brush_library();
         editor.setup_tool_animations();
         editor
     }
@@ -240,11 +409,23 @@ impl PixelArtEditor {
         self.cache_dirty = true;
     }
 
-    pub fn update_cache(&mut self, composed: Vec<Vec<egui::Color32>>) {
-        self.render_cache = Some(composed);
+    pub fn update_cache(&mut self, composed: PixelBuffer) {
+        self.render_cache = Some(ComposedGrid::new(composed));
+        self.render_cache_key = Some(self.composite_key());
         self.cache_dirty = false;
     }
 
+    /// Change how many worker threads heavy operations use (0 = one per core)
+    pub fn set_thread_count(&mut self, count: usize) {
+        match build_thread_pool(count) {
+            Ok(pool) => {
+                self.thread_pool = pool;
+                self.thread_count = count;
+            }
+            Err(e) => eprintln!("Failed to resize worker pool: {}", e),
+        }
+    }
+
     pub fn center_canvas(&mut self) {
         self.canvas_center_on_start = true;
     }
@@ -252,7 +433,6 @@ impl PixelArtEditor {
     pub fn set_zoom(&mut self, zoom: f32) {
         // Remove upper limit for unlimited zoom, but keep reasonable lower limit
         self.zoom = zoom.max(0.01); // Minimum zoom to prevent division by zero
-        self.invalidate_cache();
     }
 
     pub fn zoom_in(&mut self) {
@@ -286,85 +466,6 @@ impl PixelArtEditor {
     pub fn reset_zoom(&mut self) {
         self.set_zoom(1.0);
     }
-
-    pub fn paint_brush(&mut self, center_x: usize, center_y: usize, color: egui::Color32) {
-        let brush_radius = (self.brush_size / 2) as isize;
-        let symmetry_mode = self.symmetry_mode;
-        let symmetry_axis = self.symmetry_axis;
-        
-        let layer = self.get_active_layer_mut();
-        let width = layer.width();
-        let height = layer.height();
-        
-        // Draw circular brush
-        for dy in -brush_radius..=brush_radius {
-            for dx in -brush_radius..=brush_radius {
-                // Check if the pixel is within the circular brush
-                if dx * dx + dy * dy <= brush_radius * brush_radius {
-                    let x = center_x as isize + dx;
-                    let y = center_y as isize + dy;
-                    
-                    if x >= 0 && x < width as isize && y >= 0 && y < height as isize {
-                        let x = x as usize;
-                        let y = y as usize;
-                        
-                        // Paint the main pixel
-                        layer.grid[y][x] = color;
-                        
-                        // Paint symmetry pixels if enabled
-                        if symmetry_mode {
-                            if symmetry_axis.0 { // Horizontal symmetry
-                                let sym_x = width - 1 - x;
-                                if sym_x < width && y < height {
-                                    layer.grid[y][sym_x] = color;
-                                }
-                            }
-                            
-                            if symmetry_axis.1 { // Vertical symmetry
-                                let sym_y = height - 1 - y;
-                                if x < width && sym_y < height {
-                                    layer.grid[sym_y][x] = color;
-                                }
-                            }
-                            
-                            if symmetry_axis.0 && symmetry_axis.1 { // Both axes
-                                let sym_x = width - 1 - x;
-                                let sym_y = height - 1 - y;
-                                if sym_x < width && sym_y < height {
-                                    layer.grid[sym_y][sym_x] = color;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    pub fn erase_brush(&mut self, center_x: usize, center_y: usize) {
-        let brush_radius = (self.brush_size / 2) as isize;
-        
-        let layer = self.get_active_layer_mut();
-        let width = layer.width();
-        let height = layer.height();
-        
-        // Draw circular brush for erasing
-        for dy in -brush_radius..=brush_radius {
-            for dx in -brush_radius..=brush_radius {
-                // Check if the pixel is within the circular brush
-                if dx * dx + dy * dy <= brush_radius * brush_radius {
-                    let x = center_x as isize + dx;
-                    let y = center_y as isize + dy;
-                    
-                    if x >= 0 && x < width as isize && y >= 0 && y < height as isize {
-                        let x = x as usize;
-                        let y = y as usize;
-                        layer.grid[y][x] = egui::Color32::TRANSPARENT;
-                    }
-                }
-            }
-        }
-    }
 }
 
 impl PixelArtEditor {
@@ -378,10 +479,16 @@ impl PixelArtEditor {
         self.tool_animations.insert(Tool::Line, ToolAnimation::new(Tool::Line, AnimationType::Glow, 0.5));
         self.tool_animations.insert(Tool::Rectangle, ToolAnimation::new(Tool::Rectangle, AnimationType::Rotate, 0.8));
         self.tool_animations.insert(Tool::Circle, ToolAnimation::new(Tool::Circle, AnimationType::Sparkle, 1.0));
+        self.tool_animations.insert(Tool::Ellipse, ToolAnimation::new(Tool::Ellipse, AnimationType::Scale, 0.6));
+        self.tool_animations.insert(Tool::Polygon, ToolAnimation::new(Tool::Polygon, AnimationType::Rotate, 0.8));
+        self.tool_animations.insert(Tool::Curve, ToolAnimation::new(Tool::Curve, AnimationType::Wobble, 0.7));
         self.tool_animations.insert(Tool::Select, ToolAnimation::new(Tool::Select, AnimationType::Pulse, 0.4));
+        self.tool_animations.insert(Tool::EllipseSelect, ToolAnimation::new(Tool::EllipseSelect, AnimationType::Pulse, 0.4));
         self.tool_animations.insert(Tool::Lasso, ToolAnimation::new(Tool::Lasso, AnimationType::Shake, 0.3));
+        self.tool_animations.insert(Tool::MagicWand, ToolAnimation::new(Tool::MagicWand, AnimationType::Sparkle, 0.5));
         self.tool_animations.insert(Tool::Spray, ToolAnimation::new(Tool::Spray, AnimationType::Sparkle, 0.6));
         self.tool_animations.insert(Tool::Dither, ToolAnimation::new(Tool::Dither, AnimationType::Glow, 0.9));
+        self.tool_animations.insert(Tool::Gradient, ToolAnimation::new(Tool::Gradient, AnimationType::Glow, 0.6));
     }
     
     pub fn start_tool_animation(&mut self, tool: Tool) {
@@ -1,15 +1,63 @@
 use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use image::buffer::ConvertBuffer;
+use rayon::prelude::*;
+
+/// GIF frame with an exact palette when the image has few enough colours,
+/// otherwise a quantized one
+fn gif_frame_from_rgba(width: u16, height: u16, image_data: &[u8]) -> gif::Frame<'static> {
+    let mut palette: Vec<[u8; 3]> = Vec::new();
+    let mut lookup: std::collections::HashMap<[u8; 3], u8> = std::collections::HashMap::new();
+    let mut indices = Vec::with_capacity(image_data.len() / 4);
+    for p in image_data.chunks_exact(4) {
+        if p[3] == 0 {
+            indices.push(0);
+            continue;
+        }
+        let rgb = [p[0], p[1], p[2]];
+        // Index 0 is kept for transparency
+        let index = match lookup.get(&rgb) {
+            Some(&index) => index,
+            None if palette.len() < 255 => {
+                palette.push(rgb);
+                lookup.insert(rgb, palette.len() as u8);
+                palette.len() as u8
+            }
+            None => {
+                let mut data = image_data.to_vec();
+                return gif::Frame::from_rgba_speed(width, height, &mut data, 10);
+            }
+        };
+        indices.push(index);
+    }
+    let mut rgb_palette = vec![0u8; 3];
+    rgb_palette.extend(palette.iter().flatten());
+    gif::Frame {
+        width,
+        height,
+        buffer: std::borrow::Cow::Owned(indices),
+        palette: Some(rgb_palette),
+        transparent: Some(0),
+        ..gif::Frame::default()
+    }
+}
+
+/// Borrow tightly packed RGBA8 bytes as an image without copying them
+fn rgba_view(image_data: &[u8], width: u32, height: u32) -> Result<image::ImageBuffer<image::Rgba<u8>, &[u8]>, Box<dyn std::error::Error>> {
+    image::ImageBuffer::from_raw(width, height, image_data)
+        .ok_or_else(|| "Image data doesn't match its size".into())
+}
 
 impl PixelArtEditor {
     pub fn resize_canvas(&mut self, new_width: usize, new_height: usize, anchor: usize) {
+        self.commit_floating();
         self.push_undo();
         
+        let mut moved_by = (0, 0);
         for frame in &mut self.frames {
             for layer in &mut frame.layers {
                 let old_width = layer.width();
                 let old_height = layer.height();
-                let mut new_grid = vec![vec![eframe::egui::Color32::TRANSPARENT; new_width]; new_height];
-                
                 let (offset_x, offset_y) = match anchor {
                     0 => (0, 0), // Top-left
                     1 => { // Center
@@ -24,43 +72,36 @@ impl PixelArtEditor {
                     },
                     _ => (0, 0),
                 };
+                moved_by = (offset_x, offset_y);
                 
-                // Copy existing pixels to new grid
-                for y in 0..old_height.min(new_height) {
-                    for x in 0..old_width.min(new_width) {
-                        let new_x = x + offset_x;
-                        let new_y = y + offset_y;
-                        if new_x < new_width && new_y < new_height {
-                            new_grid[new_y][new_x] = layer.grid[y][x];
-                        }
-                    }
-                }
+                // Move existing pixels; anything past the new edges is kept off-canvas
+                let mut new_grid = layer.grid.shifted(offset_x as isize, offset_y as isize);
+                new_grid.set_size(new_width, new_height);
                 
-                layer.grid = new_grid;
+                layer.set_grid(new_grid);
+                if let Some(mask) = &mut layer.mask {
+                    *mask = mask.shifted(offset_x as isize, offset_y as isize);
+                    mask.set_size(new_width, new_height);
+                }
+                // References move with the art and are redrawn at the new size
+                if let Some(reference) = &mut layer.reference {
+                    reference.x += offset_x as f32;
+                    reference.y += offset_y as f32;
+                }
+                layer.render_reference();
             }
         }
+        self.resize_tilemaps(moved_by.0, moved_by.1);
+        self.drop_stale_selection();
+        self.invalidate_cache();
     }
 
     pub fn save_as_png(&self, filename: &str) {
         // Implementation for saving as PNG
-        let composed = self.get_composed_grid();
-        if let Some(first_row) = composed.first() {
-            let width = first_row.len();
-            let height = composed.len();
-            
-            // Create image buffer
-            let mut img = image::RgbaImage::new(width as u32, height as u32);
-            
-            for y in 0..height {
-                for x in 0..width {
-                    let pixel = composed[y][x];
-                    img.put_pixel(x as u32, y as u32, image::Rgba([pixel.r(), pixel.g(), pixel.b(), pixel.a()]));
-
-                }
-            }
-            
-            // Save the image
-            if let Err(e) = img.save(filename) {
+        let composed = self.compose_current_frame();
+        if composed.width() > 0 && composed.height() > 0 {
+            // The composite is already RGBA8, so it can be encoded in place
+            if let Err(e) = composed.as_image().save(filename) {
                 eprintln!("Failed to save PNG: {}", e);
             }
         }
@@ -107,6 +148,7 @@ impl PixelArtEditor {
                 ui.label("Export Options:");
                 ui.checkbox(&mut self.export_individual_layers, "Export individual layers");
                 ui.checkbox(&mut self.export_all_frames, "Export all frames");
+                ui.checkbox(&mut self.export_flatten_groups, "Flatten layer groups");
                 
                 if self.export_individual_layers {
                     ui.label("  └ Creates separate files for each layer");
@@ -114,6 +156,12 @@ impl PixelArtEditor {
                 if self.export_all_frames {
                     ui.label("  └ Creates separate files for each frame");
                 }
+                if self.export_flatten_groups {
+                    ui.label("  └ Each group is exported as one merged layer");
+                }
+                if self.export_format == crate::types::ExportFormat::GIF && self.frames.len() > 1 {
+                    ui.label("  └ GIF is animated with frame durations and the playback tag");
+                }
                 
                 ui.separator();
                 
@@ -202,69 +250,67 @@ impl PixelArtEditor {
         use std::fs::File;
         use std::io::Write;
         
-        // Get composed grid and convert to image data
-        let composed = self.get_composed_grid();
-        if composed.is_empty() {
+        // GIFs hold the whole animation, played the way the editor plays it
+        if self.export_format == crate::types::ExportFormat::GIF && self.frames.len() > 1 {
+            return self.save_animated_gif(filepath);
+        }
+        
+        // Get composed grid; its bytes are already the RGBA data the encoders expect
+        let composed = self.compose_current_frame();
+        if composed.width() == 0 || composed.height() == 0 {
             return Err("No canvas data to export".into());
         }
         
-        let width = composed[0].len() as u32;
-        let height = composed.len() as u32;
-        let mut image_data = vec![0u8; (width * height * 4) as usize];
+        let width = composed.width() as u32;
+        let height = composed.height() as u32;
+        let image_data = composed.as_bytes();
         
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = composed[y as usize][x as usize];
-                let idx = ((y * width + x) * 4) as usize;
-                image_data[idx] = pixel.r();     // R
-                image_data[idx + 1] = pixel.g(); // G
-                image_data[idx + 2] = pixel.b(); // B
-                image_data[idx + 3] = pixel.a(); // A
-            }
+        if let Some(result) = self.save_in_color_mode(filepath, image_data, width, height) {
+            return result;
         }
         
         match self.export_format {
             crate::types::ExportFormat::PNG => {
-                self.save_as_png_new(filepath, &image_data, width, height)?;
+                self.save_as_png_new(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::JPG | crate::types::ExportFormat::JPEG => {
-                self.save_as_jpg(filepath, &image_data, width, height)?;
+                self.save_as_jpg(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::BMP => {
-                self.save_as_bmp(filepath, &image_data, width, height)?;
+                self.save_as_bmp(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::GIF => {
-                self.save_as_gif(filepath, &image_data, width, height)?;
+                self.save_as_gif(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::ICO => {
-                self.save_as_ico(filepath, &image_data, width, height)?;
+                self.save_as_ico(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::PCX => {
-                self.save_as_pcx(filepath, &image_data, width, height)?;
+                self.save_as_pcx(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::POC => {
-                self.save_as_poc(filepath, &image_data, width, height)?;
+                self.save_as_poc(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::QOI => {
-                self.save_as_qoi(filepath, &image_data, width, height)?;
+                self.save_as_qoi(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::SVG => {
-                self.save_as_svg(filepath, &image_data, width, height)?;
+                self.save_as_svg(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::TGA => {
-                self.save_as_tga(filepath, &image_data, width, height)?;
+                self.save_as_tga(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::WEBP => {
-                self.save_as_webp(filepath, &image_data, width, height)?;
+                self.save_as_webp(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::ASE | crate::types::ExportFormat::ASEPRITE => {
-                self.save_as_aseprite(filepath, &image_data, width, height)?;
+                self.save_as_aseprite(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::CSS => {
-                self.save_as_css(filepath, &image_data, width, height)?;
+                self.save_as_css(filepath, image_data, width, height)?;
             }
             crate::types::ExportFormat::FLC | crate::types::ExportFormat::FLI => {
-                self.save_as_flic(filepath, &image_data, width, height)?;
+                self.save_as_flic(filepath, image_data, width, height)?;
             }
         }
         
@@ -272,49 +318,12 @@ impl PixelArtEditor {
     }
     
     fn save_as_png_new(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
-        // Use the existing PNG method but with new signature
-        let composed = self.get_composed_grid();
-        if let Some(first_row) = composed.first() {
-            let width = first_row.len();
-            let height = composed.len();
-            
-            // Create image buffer
-            let mut img = image::RgbaImage::new(width as u32, height as u32);
-            
-            for y in 0..height {
-                for x in 0..width {
-                    let pixel = composed[y][x];
-                    img.put_pixel(x as u32, y as u32, image::Rgba([pixel.r(), pixel.g(), pixel.b(), pixel.a()]));
-                }
-            }
-            
-            // Save the image
-            img.save(filepath)?;
-        }
-        Ok(())
+        self.save_as_png_data(filepath, image_data, width, height)
     }
     
     fn save_as_jpg(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
         // Convert RGBA to RGB (JPG doesn't support alpha)
-        let composed = self.get_composed_grid();
-        if let Some(first_row) = composed.first() {
-            let width = first_row.len();
-            let height = composed.len();
-            
-            // Create RGB image buffer
-            let mut img = image::RgbImage::new(width as u32, height as u32);
-            
-            for y in 0..height {
-                for x in 0..width {
-                    let pixel = composed[y][x];
-                    img.put_pixel(x as u32, y as u32, image::Rgb([pixel.r(), pixel.g(), pixel.b()]));
-                }
-            }
-            
-            // Save the image
-            img.save(filepath)?;
-        }
-        Ok(())
+        self.save_as_jpg_data(filepath, image_data, width, height)
     }
     
     fn save_as_bmp(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
@@ -358,6 +367,52 @@ impl PixelArtEditor {
         Ok(())
     }
     
+    /// Save the frames of the playback order as an animated GIF with each frame's duration
+    pub fn save_animated_gif(&self, filepath: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (order, loops) = self.playback_order();
+        let (width, height) = self.frames.first()
+            .and_then(|frame| frame.layers.first())
+            .map(|layer| (layer.width(), layer.height()))
+            .ok_or("No canvas data to export")?;
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err("Image is too large for GIF".into());
+        }
+
+        // Each frame is composed once, however often the order shows it
+        let composed: Vec<PixelBuffer> = self.thread_pool.install(|| {
+            self.frames.par_iter().map(crate::compositor::compose_frame).collect()
+        });
+        let indexed = self.color_mode == crate::types::ColorMode::Indexed;
+        let entries = self.color_palette.len().clamp(2, 256).next_power_of_two();
+        let global_palette = if indexed { self.palette_rgb(entries) } else { Vec::new() };
+
+        let file = std::fs::File::create(filepath)?;
+        let mut encoder = gif::Encoder::new(std::io::BufWriter::new(file), width as u16, height as u16, &global_palette)?;
+        // Finite repeats are already spelled out in the order, so it plays once
+        encoder.set_repeat(if loops { gif::Repeat::Infinite } else { gif::Repeat::Finite(0) })?;
+
+        for &frame_idx in &order {
+            let image = &composed[frame_idx];
+            let mut frame = if indexed {
+                gif::Frame {
+                    width: width as u16,
+                    height: height as u16,
+                    buffer: std::borrow::Cow::Owned(self.rgba_to_indices(image.as_bytes())),
+                    transparent: Some(self.transparent_index),
+                    ..gif::Frame::default()
+                }
+            } else {
+                gif_frame_from_rgba(width as u16, height as u16, image.as_bytes())
+            };
+            // GIF delays are in hundredths of a second
+            frame.delay = (self.frames[frame_idx].duration_ms.div_ceil(10)).min(u16::MAX as u32) as u16;
+            frame.dispose = gif::DisposalMethod::Background;
+            encoder.write_frame(&frame)?;
+        }
+        println!("Animated GIF saved as: {} ({} frames)", filepath, order.len());
+        Ok(())
+    }
+    
     fn save_as_ico(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
         // ICO format implementation (using fallback for now)
         self.write_raw_rgb(filepath, image_data, width, height)?;
@@ -425,10 +480,9 @@ impl PixelArtEditor {
         Ok(())
     }
     
-    fn save_as_aseprite(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
-        // Aseprite format implementation (using fallback for now)
-        self.write_raw_rgb(filepath, image_data, width, height)?;
-        Ok(())
+    fn save_as_aseprite(&self, filepath: &str, _image_data: &[u8], _width: u32, _height: u32) -> Result<(), Box<dyn std::error::Error>> {
+        // Aseprite files keep every frame and layer, not just the flattened image
+        self.save_aseprite_file(filepath, self.export_flatten_groups)
     }
     
     fn save_as_css(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
@@ -528,141 +582,107 @@ impl PixelArtEditor {
     
     /// Save all frames as separate files
     fn save_all_frames(&self, folder: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
-        for (frame_idx, frame) in self.frames.iter().enumerate() {
-            let filename = format!("frame_{:03}.{}", frame_idx + 1, self.get_file_extension());
-            let filepath = folder.join(filename);
-            
-            // Compose this frame
-            let composed = self.compose_frame(frame);
-            self.save_composed_image(&filepath, &composed)?;
-        }
+        // Frames are independent, so compose and encode them on the worker pool
+        self.thread_pool.install(|| {
+            self.frames.par_iter().enumerate().try_for_each(|(frame_idx, frame)| {
+                let filename = format!("frame_{:03}.{}", frame_idx + 1, self.get_file_extension());
+                let filepath = folder.join(filename);
+                
+                // Compose this frame
+                let composed = self.compose_frame(frame);
+                self.save_composed_image(&filepath, &composed).map_err(|e| e.to_string())
+            })
+        })?;
         Ok(())
     }
     
     /// Save all layers as separate files
     fn save_all_layers(&self, folder: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
-        for (frame_idx, frame) in self.frames.iter().enumerate() {
-            let frame_folder = folder.join(format!("frame_{:03}", frame_idx + 1));
-            std::fs::create_dir_all(&frame_folder)?;
-            
-            for (layer_idx, layer) in frame.layers.iter().enumerate() {
-                if !layer.visible {
-                    continue;
-                }
+        self.thread_pool.install(|| {
+            self.frames.par_iter().enumerate().try_for_each(|(frame_idx, frame)| {
+                let frame_folder = folder.join(format!("frame_{:03}", frame_idx + 1));
+                std::fs::create_dir_all(&frame_folder).map_err(|e| e.to_string())?;
                 
-                let layer_name = layer.name.replace(" ", "_").replace("/", "_").replace("\\", "_");
-                let filename = format!("layer_{:02}_{}.{}", layer_idx + 1, layer_name, self.get_file_extension());
-                let filepath = frame_folder.join(filename);
-                
-                self.save_layer_image(&filepath, layer)?;
-            }
-        }
+                let frame = self.export_layers(frame);
+                frame.layers.par_iter().enumerate().try_for_each(|(layer_idx, layer)| {
+                    // Groups have no pixels of their own; their children are saved instead
+                    if layer.is_group() || !frame.is_layer_visible(layer_idx) {
+                        return Ok(());
+                    }
+                    
+                    let layer_name = layer.name.replace(" ", "_").replace("/", "_").replace("\\", "_");
+                    let filename = format!("layer_{:02}_{}.{}", layer_idx + 1, layer_name, self.get_file_extension());
+                    let filepath = frame_folder.join(filename);
+                    
+                    self.save_layer_image(&filepath, layer).map_err(|e| e.to_string())
+                })
+            })
+        })?;
         Ok(())
     }
     
     /// Save all composed images (all layers combined)
     fn save_all_composed(&self, folder: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
-        for (frame_idx, frame) in self.frames.iter().enumerate() {
-            let filename = format!("composed_{:03}.{}", frame_idx + 1, self.get_file_extension());
-            let filepath = folder.join(filename);
-            
-            let composed = self.compose_frame(frame);
-            self.save_composed_image(&filepath, &composed)?;
-        }
+        self.thread_pool.install(|| {
+            self.frames.par_iter().enumerate().try_for_each(|(frame_idx, frame)| {
+                let filename = format!("composed_{:03}.{}", frame_idx + 1, self.get_file_extension());
+                let filepath = folder.join(filename);
+                
+                let composed = self.compose_frame(frame);
+                self.save_composed_image(&filepath, &composed).map_err(|e| e.to_string())
+            })
+        })?;
         Ok(())
     }
     
     /// Compose a single frame (combine all visible layers)
-    fn compose_frame(&self, frame: &crate::types::Frame) -> Vec<Vec<eframe::egui::Color32>> {
-        if frame.layers.is_empty() {
-            return vec![];
-        }
-        
-        let width = frame.layers[0].width();
-        let height = frame.layers[0].height();
-        let mut composed = vec![vec![eframe::egui::Color32::TRANSPARENT; width]; height];
-        
-        for layer in &frame.layers {
-            if !layer.visible {
-                continue;
-            }
-            
-            for y in 0..height {
-                for x in 0..width {
-                    let pixel = layer.grid[y][x];
-                    if pixel.a() > 0 {
-                        // Simple alpha blending
-                        if composed[y][x].a() == 0 {
-                            composed[y][x] = pixel;
-                        } else {
-                            // Blend with existing pixel
-                            let alpha = pixel.a() as f32 / 255.0;
-                            let inv_alpha = 1.0 - alpha;
-                            let existing = composed[y][x];
-                            
-                            let r = (pixel.r() as f32 * alpha + existing.r() as f32 * inv_alpha) as u8;
-                            let g = (pixel.g() as f32 * alpha + existing.g() as f32 * inv_alpha) as u8;
-                            let b = (pixel.b() as f32 * alpha + existing.b() as f32 * inv_alpha) as u8;
-                            let a = (pixel.a() as f32 + existing.a() as f32 * inv_alpha).min(255.0) as u8;
-                            
-                            composed[y][x] = eframe::egui::Color32::from_rgba_unmultiplied(r, g, b, a);
-                        }
-                    }
-                }
-            }
+    fn compose_frame(&self, frame: &crate::types::Frame) -> PixelBuffer {
+        self.thread_pool.install(|| crate::compositor::compose_frame(frame))
+    }
+
+    /// Layers of a frame as they should be exported, with groups merged if requested
+    /// and reference layers dropped unless they are marked for export
+    pub fn export_layers<'a>(&self, frame: &'a crate::types::Frame) -> std::borrow::Cow<'a, crate::types::Frame> {
+        let mut frame = if self.export_flatten_groups {
+            std::borrow::Cow::Owned(self.thread_pool.install(|| crate::compositor::flatten_groups(frame)))
+        } else {
+            std::borrow::Cow::Borrowed(frame)
+        };
+        if frame.layers.iter().any(|layer| !layer.is_exported()) {
+            frame.to_mut().layers.retain(|layer| layer.is_exported());
         }
-        
-        composed
+        frame
+    }
+
+    /// Flatten the frame being edited for export (no onion skins or other canvas overlays)
+    fn compose_current_frame(&self) -> PixelBuffer {
+        self.compose_frame(&self.frames[self.current_frame])
     }
     
     /// Save composed image data to file
-    fn save_composed_image(&self, filepath: &std::path::Path, composed: &Vec<Vec<eframe::egui::Color32>>) -> Result<(), Box<dyn std::error::Error>> {
-        if composed.is_empty() {
+    fn save_composed_image(&self, filepath: &std::path::Path, composed: &PixelBuffer) -> Result<(), Box<dyn std::error::Error>> {
+        if composed.width() == 0 || composed.height() == 0 {
             return Err("No image data to save".into());
         }
         
-        let width = composed[0].len() as u32;
-        let height = composed.len() as u32;
-        let mut image_data = vec![0u8; (width * height * 4) as usize];
-        
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = composed[y as usize][x as usize];
-                let idx = ((y * width + x) * 4) as usize;
-                image_data[idx] = pixel.r();
-                image_data[idx + 1] = pixel.g();
-                image_data[idx + 2] = pixel.b();
-                image_data[idx + 3] = pixel.a();
-            }
-        }
-        
         let filepath_str = filepath.to_string_lossy().to_string();
-        self.save_image_data(&filepath_str, &image_data, width, height)
+        self.save_image_data(&filepath_str, composed.as_bytes(), composed.width() as u32, composed.height() as u32)
     }
     
     /// Save single layer to file
     fn save_layer_image(&self, filepath: &std::path::Path, layer: &crate::types::Layer) -> Result<(), Box<dyn std::error::Error>> {
         let width = layer.width() as u32;
         let height = layer.height() as u32;
-        let mut image_data = vec![0u8; (width * height * 4) as usize];
-        
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = layer.grid[y as usize][x as usize];
-                let idx = ((y * width + x) * 4) as usize;
-                image_data[idx] = pixel.r();
-                image_data[idx + 1] = pixel.g();
-                image_data[idx + 2] = pixel.b();
-                image_data[idx + 3] = pixel.a();
-            }
-        }
         
         let filepath_str = filepath.to_string_lossy().to_string();
-        self.save_image_data(&filepath_str, &image_data, width, height)
+        self.save_image_data(&filepath_str, layer.masked_pixels().as_bytes(), width, height)
     }
     
     /// Save image data with current format
     fn save_image_data(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(result) = self.save_in_color_mode(filepath, image_data, width, height) {
+            return result;
+        }
         match self.export_format {
             crate::types::ExportFormat::PNG => {
                 self.save_as_png_data(filepath, image_data, width, height)?;
@@ -707,58 +727,43 @@ impl PixelArtEditor {
         }
     }
     
-    /// Save PNG with image data
-    fn save_as_png_data(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut img = image::RgbaImage::new(width, height);
-        
-        for y in 0..height {
-            for x in 0..width {
-                let idx = ((y * width + x) * 4) as usize;
-                let r = image_data[idx];
-                let g = image_data[idx + 1];
-                let b = image_data[idx + 2];
-                let a = image_data[idx + 3];
-                img.put_pixel(x, y, image::Rgba([r, g, b, a]));
+    /// Formats that can keep the document colour mode: PNG-8, GIF and PCX for indexed
+    /// documents and grey PNGs for grayscale ones. None means the regular encoder applies.
+    fn save_in_color_mode(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Option<Result<(), Box<dyn std::error::Error>>> {
+        use crate::types::{ColorMode, ExportFormat};
+        match (self.color_mode, self.export_format) {
+            (ColorMode::Indexed, ExportFormat::PNG | ExportFormat::GIF | ExportFormat::PCX) => {
+                Some(self.save_indexed_image(filepath, image_data, width, height))
             }
+            (ColorMode::Grayscale, ExportFormat::PNG) => Some(self.save_as_gray_png_data(filepath, image_data, width, height)),
+            _ => None,
         }
-        
+    }
+
+    /// Save PNG with image data
+    fn save_as_png_data(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
+        rgba_view(image_data, width, height)?.save(filepath)?;
+        Ok(())
+    }
+    
+    /// Save a grey PNG with alpha
+    fn save_as_gray_png_data(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let img: image::GrayAlphaImage = rgba_view(image_data, width, height)?.convert();
         img.save(filepath)?;
         Ok(())
     }
     
     /// Save JPG with image data
     fn save_as_jpg_data(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut img = image::RgbImage::new(width, height);
-        
-        for y in 0..height {
-            for x in 0..width {
-                let idx = ((y * width + x) * 4) as usize;
-                let r = image_data[idx];
-                let g = image_data[idx + 1];
-                let b = image_data[idx + 2];
-                // JPG doesn't support alpha, so we ignore it
-                img.put_pixel(x, y, image::Rgb([r, g, b]));
-            }
-        }
-        
+        // JPG doesn't support alpha, so we drop it
+        let img: image::RgbImage = rgba_view(image_data, width, height)?.convert();
         img.save(filepath)?;
         Ok(())
     }
     
     /// Save BMP with image data
     fn save_as_bmp_data(&self, filepath: &str, image_data: &[u8], width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
-        let mut img = image::RgbImage::new(width, height);
-        
-        for y in 0..height {
-            for x in 0..width {
-                let idx = ((y * width + x) * 4) as usize;
-                let r = image_data[idx];
-                let g = image_data[idx + 1];
-                let b = image_data[idx + 2];
-                img.put_pixel(x, y, image::Rgb([r, g, b]));
-            }
-        }
-        
+        let img: image::RgbImage = rgba_view(image_data, width, height)?.convert();
         img.save(filepath)?;
         Ok(())
     }
@@ -777,35 +782,32 @@ impl PixelArtEditor {
             .as_secs();
         
         let filename = format!("pixel_art_{}.png", timestamp);
-        let composed = self.get_composed_grid();
+        let composed = self.compose_current_frame();
         
-        if composed.is_empty() {
+        if composed.width() == 0 || composed.height() == 0 {
             return Err("No canvas data to save".into());
         }
         
-        let width = composed[0].len() as u32;
-        let height = composed.len() as u32;
-        let mut image_data = vec![0u8; (width * height * 4) as usize];
-        
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = composed[y as usize][x as usize];
-                let idx = ((y * width + x) * 4) as usize;
-                image_data[idx] = pixel.r();
-                image_data[idx + 1] = pixel.g();
-                image_data[idx + 2] = pixel.b();
-                image_data[idx + 3] = pixel.a();
-            }
-        }
-        
-        self.save_as_png_data(&filename, &image_data, width, height)?;
+        self.save_as_png_data(&filename, composed.as_bytes(), composed.width() as u32, composed.height() as u32)?;
         println!("Quick saved as: {}", filename);
         Ok(())
     }
     
     /// Save project file (all frames and layers)
     pub fn save_project_file(&self, filepath: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let project_data = serde_json::to_string_pretty(&self.frames)?;
+        let mut frames = self.frames.clone();
+        crate::editor::linked_cels::strip_linked_cels(&mut frames);
+        let project = crate::types::Project {
+            frames,
+            color_mode: self.color_mode,
+            palette: self.color_palette.clone(),
+            transparent_index: self.transparent_index,
+            tags: self.tags.clone(),
+            slices: self.slices.clone(),
+            tilesets: self.tilesets.clone(),
+            terrains: self.terrains.clone(),
+        };
+        let project_data = serde_json::to_string_pretty(&project)?;
         std::fs::write(filepath, project_data)?;
         println!("Project saved as: {}", filepath);
         Ok(())
@@ -814,9 +816,34 @@ impl PixelArtEditor {
     /// Load project file
     pub fn load_project_file(&mut self, filepath: &str) -> Result<(), Box<dyn std::error::Error>> {
         let project_data = std::fs::read_to_string(filepath)?;
-        self.frames = serde_json::from_str(&project_data)?;
+        let mut project = crate::types::Project::from_json(&project_data)?;
+        crate::editor::linked_cels::restore_linked_cels(&mut project.frames);
+        self.frames = project.frames;
+        self.color_mode = project.color_mode;
+        self.transparent_index = project.transparent_index;
+        self.tags = project.tags;
+        self.slices = project.slices;
+        self.selected_slice = None;
+        self.tilesets = project.tilesets;
+        self.selected_tile = None;
+        self.terrains = project.terrains;
+        self.active_terrain = None;
+        self.deselect();
+        self.playback_tag = None;
+        self.stop_playback();
+        if !project.palette.is_empty() {
+            self.color_palette = project.palette;
+        }
+        if self.color_mode != crate::types::ColorMode::Rgba {
+            // Stored indices are kept where they still match the colours; projects saved
+            // without them get them rebuilt from the colours
+            for layer in self.frames.iter_mut().flat_map(|frame| frame.layers.iter_mut()) {
+                layer.mark_all_dirty();
+            }
+        }
         self.current_frame = 0;
         self.current_layer = 0;
+        self.invalidate_cache();
         println!("Project loaded from: {}", filepath);
         Ok(())
     }
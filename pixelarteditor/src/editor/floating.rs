@@ -0,0 +1,311 @@
+use crate::compositor;
+use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use crate::selection_mask::SelectionMask;
+use crate::tiled_buffer::TiledBuffer;
+use crate::transform;
+use crate::types::BlendMode;
+use eframe::egui;
+
+// A floating selection is a block of pixels lifted off a layer or pasted, that can be
+// moved, scaled, flipped and rotated before it is dropped back. While it floats the
+// layer shows it on top of what it was lifted from, so it composites like any other
+// pixels. Lifting or pasting takes one undo step; committing keeps that step as the
+// whole transform, and cancelling (or undoing while floating) puts the layer back and
+// takes the step with it, along with the redo history it replaced. While a handle is
+// being dragged the pixels are rotated nearest-neighbour, and with RotSprite once it
+// is let go.
+
+/// Pixels lifted off a layer or pasted, waiting to be dropped back
+#[derive(Clone)]
+pub struct FloatingSelection {
+    pub source: PixelBuffer,  // Pixels as lifted, before any transform
+    pub x: isize,             // Top-left of the scaled box, before rotation
+    pub y: isize,
+    pub width: usize,         // Size the source is scaled to
+    pub height: usize,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub angle: f32,           // Clockwise rotation about the box centre, in degrees
+    pub frame: usize,
+    pub layer: usize,
+    pub under: TiledBuffer,   // What the layer holds beneath, with the lifted pixels gone
+    pub original: TiledBuffer, // The layer as it was before lifting
+    pub selection_before: Option<SelectionMask>,
+    pub rough: bool,          // Rotated nearest-neighbour while a handle is dragged
+}
+
+/// Part of a floating selection the Move tool drags
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FloatHandle {
+    Move,
+    Scale(i8, i8), // Sides dragged: -1 left/top, 1 right/bottom, 0 neither
+    Rotate,
+}
+
+impl FloatingSelection {
+    /// The transformed pixels and the canvas position of their top-left corner
+    pub fn render(&self) -> (PixelBuffer, isize, isize) {
+        let flipped = transform::flip(&self.source, self.flip_x, self.flip_y);
+        let scaled = transform::scale_nearest(&flipped, self.width.max(1), self.height.max(1));
+        let angle = self.angle.rem_euclid(360.0);
+        let rotated = if angle % 90.0 == 0.0 {
+            transform::rotate_quarter(&scaled, (angle / 90.0) as usize)
+        } else if self.rough {
+            transform::rotate_nearest(&scaled, angle)
+        } else {
+            transform::rotsprite(&scaled, angle)
+        };
+        let x = self.x + (self.width as isize - rotated.width() as isize) / 2;
+        let y = self.y + (self.height as isize - rotated.height() as isize) / 2;
+        (rotated, x, y)
+    }
+
+    /// Centre of the box in canvas pixels
+    pub fn center(&self) -> (f32, f32) {
+        (self.x as f32 + self.width as f32 / 2.0, self.y as f32 + self.height as f32 / 2.0)
+    }
+
+    /// The layer grid with the floating pixels dropped on what lies beneath, blended
+    /// over it so partly transparent pixels keep what shows through them
+    fn composite(&self) -> TiledBuffer {
+        let (pixels, x, y) = self.render();
+        let mut grid = self.under.clone();
+        for (px, py, color) in pixels.pixels() {
+            if color.a() > 0 {
+                let (cx, cy) = (x + px as isize, y + py as isize);
+                grid.set(cx, cy, compositor::blend(BlendMode::Normal, self.under.get(cx, cy), color, 1.0));
+            }
+        }
+        grid
+    }
+}
+
+impl PixelArtEditor {
+    /// Lift the selected pixels of the current layer so they can be transformed
+    pub fn lift_selection(&mut self) {
+        if self.floating.is_some() || !self.get_active_layer().is_editable() {
+            return;
+        }
+        let Some(selection) = self.selection.take() else {
+            return;
+        };
+        let Some(bounds) = selection.bounds() else {
+            return;
+        };
+        self.push_floating_undo();
+        let original = self.get_active_layer().target_grid().clone();
+        let mut source = original.read_region(bounds.min_x as isize, bounds.min_y as isize, bounds.width(), bounds.height());
+        let mut under = original.clone();
+        for y in 0..bounds.height() {
+            for x in 0..bounds.width() {
+                let (cx, cy) = ((bounds.min_x + x) as isize, (bounds.min_y + y) as isize);
+                if selection.contains(cx, cy) {
+                    under.set(cx, cy, egui::Color32::TRANSPARENT);
+                } else {
+                    source.set(x, y, egui::Color32::TRANSPARENT);
+                }
+            }
+        }
+        self.start_floating(source, bounds.min_x as isize, bounds.min_y as isize, under, original, Some(selection));
+    }
+
+    /// Paste the copied pixels as a floating selection where they were copied from
+    pub fn paste_clipboard(&mut self) {
+        let Some(pixels) = self.selection_pixels.clone() else {
+            return;
+        };
+        self.commit_floating();
+        if !self.get_active_layer().is_editable() {
+            return;
+        }
+        self.push_floating_undo();
+        let (x, y) = self.paste_position();
+        let original = self.get_active_layer().target_grid().clone();
+        let selection = self.selection.take();
+        self.start_floating(pixels, x, y, original.clone(), original, selection);
+        self.tool = crate::types::Tool::Move;
+    }
+
+    /// Where pasted pixels go: back where they were copied from, or in the corner when
+    /// that is off the current canvas
+    pub fn paste_position(&self) -> (isize, isize) {
+        let layer = self.get_active_layer();
+        let (x, y) = self.selection_pixels_origin;
+        let fits = x >= 0 && y >= 0 && x < layer.width() as isize && y < layer.height() as isize;
+        if fits { (x, y) } else { (0, 0) }
+    }
+
+    /// Take the undo step for lifting or pasting, keeping the redo history it clears
+    /// in case the floating selection is cancelled
+    fn push_floating_undo(&mut self) {
+        let redo = std::mem::take(&mut self.redo_stack);
+        self.push_undo();
+        self.floating_redo = redo;
+    }
+
+    fn start_floating(&mut self, source: PixelBuffer, x: isize, y: isize, under: TiledBuffer, original: TiledBuffer, selection_before: Option<SelectionMask>) {
+        self.floating = Some(FloatingSelection {
+            width: source.width(),
+            height: source.height(),
+            source,
+            x,
+            y,
+            flip_x: false,
+            flip_y: false,
+            angle: 0.0,
+            frame: self.current_frame,
+            layer: self.current_layer,
+            under,
+            original,
+            selection_before,
+            rough: false,
+        });
+        self.show_floating();
+    }
+
+    /// Change the floating selection's transform and redraw it
+    pub fn transform_floating(&mut self, edit: impl FnOnce(&mut FloatingSelection)) {
+        if let Some(floating) = &mut self.floating {
+            edit(floating);
+            self.show_floating();
+        }
+    }
+
+    fn show_floating(&mut self) {
+        let Some(floating) = &self.floating else {
+            return;
+        };
+        let grid = floating.composite();
+        if let Some(layer) = self.frames.get_mut(floating.frame).and_then(|frame| frame.layers.get_mut(floating.layer)) {
+            layer.set_target_grid(grid);
+        }
+        self.invalidate_cache();
+    }
+
+    /// Drop the floating pixels where they are and select them
+    pub fn commit_floating(&mut self) {
+        if self.floating.as_ref().is_some_and(|floating| floating.rough) {
+            self.transform_floating(|floating| floating.rough = false);
+        }
+        let Some(floating) = self.floating.take() else {
+            return;
+        };
+        self.floating_redo.clear();
+        let (pixels, x, y) = floating.render();
+        let (width, height) = (floating.under.width(), floating.under.height());
+        let mut selection = SelectionMask::new(width, height);
+        for (px, py, color) in pixels.pixels() {
+            if color.a() > 0 {
+                selection.set(x + px as isize, y + py as isize, true);
+            }
+        }
+        self.selection = (!selection.is_empty()).then_some(selection);
+    }
+
+    /// Put the layer and selection back as they were before lifting or pasting
+    pub fn cancel_floating(&mut self) {
+        let Some(floating) = self.floating.take() else {
+            return;
+        };
+        if let Some(layer) = self.frames.get_mut(floating.frame).and_then(|frame| frame.layers.get_mut(floating.layer)) {
+            layer.set_target_grid(floating.original);
+        }
+        self.selection = floating.selection_before;
+        self.undo_stack.pop();
+        self.redo_stack = std::mem::take(&mut self.floating_redo);
+        self.invalidate_cache();
+    }
+
+    /// Throw the floating pixels away, leaving the hole they were lifted from
+    pub fn delete_floating(&mut self) {
+        let Some(floating) = self.floating.take() else {
+            return;
+        };
+        if let Some(layer) = self.frames.get_mut(floating.frame).and_then(|frame| frame.layers.get_mut(floating.layer)) {
+            layer.set_target_grid(floating.under);
+        }
+        self.floating_redo.clear();
+        self.invalidate_cache();
+    }
+
+    /// Commit the floating selection once the user has moved on to another tool,
+    /// layer or frame
+    pub fn settle_floating(&mut self) {
+        let moved_on = self.floating.as_ref().is_some_and(|floating| {
+            self.tool != crate::types::Tool::Move || floating.frame != self.current_frame || floating.layer != self.current_layer
+        });
+        if moved_on {
+            self.commit_floating();
+        }
+    }
+
+    /// Copy the floating pixels as they look now, or else the selected pixels, here and
+    /// to the OS clipboard
+    pub fn copy(&mut self) {
+        match &self.floating {
+            Some(floating) => {
+                let (pixels, x, y) = floating.render();
+                self.selection_pixels = Some(pixels);
+                self.selection_pixels_origin = (x, y);
+            }
+            None => self.copy_selection(),
+        }
+        self.copy_to_system_clipboard();
+    }
+
+    pub fn cut(&mut self) {
+        self.copy();
+        if self.floating.is_some() {
+            self.delete_floating();
+        } else {
+            self.clear_selection();
+        }
+    }
+
+    /// Drag a handle of the floating selection. `start` is the selection as it was when
+    /// the drag began, `from` and `to` the pointer's canvas positions then and now.
+    pub fn drag_floating(&mut self, handle: FloatHandle, start: &FloatingSelection, from: (f32, f32), to: (f32, f32), snap: bool) {
+        let (dx, dy) = ((to.0 - from.0).round() as isize, (to.1 - from.1).round() as isize);
+        self.transform_floating(|floating| {
+            floating.rough = true;
+            match handle {
+                FloatHandle::Move => {
+                    floating.x = start.x + dx;
+                    floating.y = start.y + dy;
+                }
+                FloatHandle::Scale(sx, sy) => {
+                    let (mut left, mut right) = (start.x, start.x + start.width as isize);
+                    let (mut top, mut bottom) = (start.y, start.y + start.height as isize);
+                    match sx {
+                        -1 => left = (left + dx).min(right - 1),
+                        1 => right = (right + dx).max(left + 1),
+                        _ => {}
+                    }
+                    match sy {
+                        -1 => top = (top + dy).min(bottom - 1),
+                        1 => bottom = (bottom + dy).max(top + 1),
+                        _ => {}
+                    }
+                    floating.x = left;
+                    floating.y = top;
+                    floating.width = (right - left) as usize;
+                    floating.height = (bottom - top) as usize;
+                }
+                FloatHandle::Rotate => {
+                    let (cx, cy) = start.center();
+                    let turn = (to.1 - cy).atan2(to.0 - cx) - (from.1 - cy).atan2(from.0 - cx);
+                    let angle = start.angle + turn.to_degrees();
+                    // Snapping keeps to 15 degree steps
+                    floating.angle = if snap { (angle / 15.0).round() * 15.0 } else { angle.round() };
+                }
+            }
+        });
+    }
+
+    /// Redraw the floating selection with RotSprite once its handle is let go
+    pub fn end_floating_drag(&mut self) {
+        self.floating_drag = None;
+        self.transform_floating(|floating| floating.rough = false);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::editor::core::PixelArtEditor;
+use crate::gradient::{gradient_color, GradientDither};
+use eframe::egui;
+
+// The gradient tool fills the current layer, or the selection, between the two ends of
+// a drag. While dragging it shows the result through `preview_overlay` and only writes
+// to the layer when the drag ends.
+
+impl PixelArtEditor {
+    /// Colours the gradient runs through: the primary and secondary colours, or the
+    /// active palette's entries from the primary to the secondary. A palette that holds
+    /// only one of them, or neither, is used whole.
+    pub fn gradient_stops(&self) -> Vec<egui::Color32> {
+        if !self.gradient_palette_ramp {
+            return vec![self.selected_color, self.secondary_color];
+        }
+        let palette = &self.custom_palettes[self.active_palette];
+        let find = |color| palette.iter().position(|&c| c == color);
+        match (find(self.selected_color), find(self.secondary_color)) {
+            (Some(from), Some(to)) if from <= to => palette[from..=to].to_vec(),
+            (Some(from), Some(to)) => palette[to..=from].iter().rev().copied().collect(),
+            _ => palette.clone(),
+        }
+    }
+
+    /// Pixels of the gradient dragged between two canvas positions, inside the selection
+    pub fn gradient_pixels(&self, start: (isize, isize), end: (isize, isize)) -> Vec<(isize, isize, egui::Color32)> {
+        let stops = self.gradient_stops();
+        let map = self.gradient_dither.map(&self.custom_dither);
+        let center = |(x, y): (isize, isize)| (x as f32 + 0.5, y as f32 + 0.5);
+        let layer = self.get_active_layer();
+        let mut pixels = Vec::new();
+        for y in 0..layer.height() as isize {
+            for x in 0..layer.width() as isize {
+                if !self.is_selected(x, y) {
+                    continue;
+                }
+                let t = self.gradient_shape.position(center(start), center(end), center((x, y)));
+                let threshold = match &map {
+                    Some(map) => Some(map.threshold(x, y)),
+                    None if self.gradient_dither == GradientDither::Steps => Some(0.5),
+                    None => None,
+                };
+                pixels.push((x, y, gradient_color(&stops, t, threshold)));
+            }
+        }
+        pixels
+    }
+
+    /// Show the gradient a drag would make without touching the layer
+    pub fn preview_gradient(&mut self, start: (isize, isize), end: (isize, isize)) {
+        let pixels = self.gradient_pixels(start, end);
+        self.preview_pixels(&pixels);
+    }
+
+    /// Fill the gradient into the current layer as one undo step
+    pub fn apply_gradient(&mut self, start: (isize, isize), end: (isize, isize)) {
+        self.preview_overlay = None;
+        if !self.get_active_layer().is_editable() {
+            return;
+        }
+        self.push_undo();
+        let pixels = self.gradient_pixels(start, end);
+        self.put_pixels(&pixels);
+    }
+}
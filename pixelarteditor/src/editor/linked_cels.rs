@@ -0,0 +1,140 @@
+use crate::editor::core::PixelArtEditor;
+use crate::types::Frame;
+use crate::tiled_buffer::TiledBuffer;
+use std::collections::HashMap;
+
+// Linked cels: layers in different frames that show one shared image. Each linked
+// layer carries the same link id, and an edit to any of them is copied to the rest
+// before the canvas is recomposed. Tiles are shared, so copies cost almost nothing.
+
+impl PixelArtEditor {
+    fn new_link_id(&self) -> u64 {
+        self.frames.iter()
+            .flat_map(|frame| frame.layers.iter())
+            .filter_map(|layer| layer.link)
+            .max()
+            .map_or(1, |id| id + 1)
+    }
+
+    /// Whether the current layer exists in the previous frame and can be linked to it
+    pub fn can_link_cel_to_previous(&self) -> bool {
+        self.current_frame > 0
+            && self.frames[self.current_frame - 1].layers.get(self.current_layer).is_some_and(|layer| !layer.is_group())
+            && !self.get_active_layer().is_group()
+    }
+
+    /// Link the current cel to the same layer in the previous frame, taking its image
+    pub fn link_cel_to_previous(&mut self) {
+        if !self.can_link_cel_to_previous() {
+            return;
+        }
+        self.push_undo();
+        let (frame, layer) = (self.current_frame, self.current_layer);
+        let id = self.frames[frame - 1].layers[layer].link.unwrap_or_else(|| self.new_link_id());
+        let source = &mut self.frames[frame - 1].layers[layer];
+        source.link = Some(id);
+        let (grid, indices) = (source.grid.clone(), source.indices.clone());
+
+        let target = &mut self.frames[frame].layers[layer];
+        target.link = Some(id);
+        target.set_grid(grid);
+        target.indices = indices;
+        self.invalidate_cache();
+    }
+
+    /// Link the current layer in every frame to the current cel's image
+    pub fn link_cel_across_frames(&mut self) {
+        if self.get_active_layer().is_group() {
+            return;
+        }
+        self.push_undo();
+        let id = self.get_active_layer().link.unwrap_or_else(|| self.new_link_id());
+        let current_layer = self.current_layer;
+        let source = &mut self.frames[self.current_frame].layers[current_layer];
+        source.link = Some(id);
+        let (grid, indices) = (source.grid.clone(), source.indices.clone());
+
+        for frame in &mut self.frames {
+            if let Some(layer) = frame.layers.get_mut(current_layer).filter(|layer| !layer.is_group()) {
+                layer.link = Some(id);
+                layer.set_grid(grid.clone());
+                layer.indices = indices.clone();
+            }
+        }
+        self.invalidate_cache();
+    }
+
+    /// Give the current cel its own copy of the image again
+    pub fn unlink_cel(&mut self) {
+        if self.get_active_layer().link.is_none() {
+            return;
+        }
+        self.push_undo();
+        self.get_active_layer_mut().link = None;
+    }
+
+    /// Copy edits made to linked cels since the last redraw to every cel they are linked with
+    pub fn sync_linked_cels(&mut self) {
+        let mut edited: HashMap<u64, (usize, usize)> = HashMap::new();
+        for (f, frame) in self.frames.iter().enumerate() {
+            for (l, layer) in frame.layers.iter().enumerate() {
+                if let (Some(id), Some(_)) = (layer.link, layer.dirty) {
+                    edited.entry(id).or_insert((f, l));
+                }
+            }
+        }
+        if edited.is_empty() {
+            return;
+        }
+
+        let sources: HashMap<u64, _> = edited.into_iter().map(|(id, (f, l))| {
+            let layer = &self.frames[f].layers[l];
+            (id, ((f, l), layer.grid.clone(), layer.indices.clone(), layer.tilemap.clone(), layer.reference.clone(), layer.mask.clone(), layer.dirty))
+        }).collect();
+
+        for (f, frame) in self.frames.iter_mut().enumerate() {
+            for (l, layer) in frame.layers.iter_mut().enumerate() {
+                let Some((source, grid, indices, tilemap, reference, mask, dirty)) = layer.link.and_then(|id| sources.get(&id)) else {
+                    continue;
+                };
+                if *source == (f, l) {
+                    continue;
+                }
+                layer.grid = grid.clone();
+                layer.indices = indices.clone();
+                layer.tilemap = tilemap.clone();
+                layer.reference = reference.clone();
+                layer.mask = mask.clone();
+                if let Some(rect) = dirty {
+                    layer.mark_dirty(*rect);
+                }
+            }
+        }
+    }
+}
+
+/// Drop the pixels of every linked cel but the first of its link, so project files store
+/// shared images once
+pub fn strip_linked_cels(frames: &mut [Frame]) {
+    let mut seen = std::collections::HashSet::new();
+    for layer in frames.iter_mut().flat_map(|frame| frame.layers.iter_mut()) {
+        if layer.link.is_some_and(|id| !seen.insert(id)) {
+            layer.grid = TiledBuffer::new(layer.width(), layer.height());
+        }
+    }
+}
+
+/// Give every linked cel the image of the first cel of its link again
+pub fn restore_linked_cels(frames: &mut [Frame]) {
+    let mut images: HashMap<u64, TiledBuffer> = HashMap::new();
+    for layer in frames.iter_mut().flat_map(|frame| frame.layers.iter_mut()) {
+        if let Some(id) = layer.link {
+            match images.get(&id) {
+                Some(grid) => layer.set_grid(grid.clone()),
+                None => {
+                    images.insert(id, layer.grid.clone());
+                }
+            }
+        }
+    }
+}
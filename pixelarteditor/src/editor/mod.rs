@@ -3,6 +3,23 @@ pub mod tools;
 pub mod undo_redo;
 pub mod rendering;
 pub mod animation;
+pub mod playback;
 pub mod file_operations;
+pub mod aseprite;
+pub mod color_mode;
+pub mod linked_cels;
+pub mod slices;
+pub mod sprite_sheet;
+pub mod tilemap;
+pub mod tiled_export;
+pub mod autotile;
+pub mod reference;
+pub mod stroke;
+pub mod selection;
+pub mod floating;
+pub mod clipboard;
+pub mod brushes;
+pub mod gradient;
+pub mod shapes;
 
 pub use core::PixelArtEditor;
@@ -0,0 +1,128 @@
+use crate::editor::core::PixelArtEditor;
+use crate::types::AnimationTag;
+use eframe::egui;
+
+// Animation playback. Each frame is shown for its own duration; the selected tag
+// decides which frames play, in what order and how often. Exports use the same order.
+
+// Colours handed out to new tags in turn
+const TAG_COLORS: [egui::Color32; 6] = [
+    egui::Color32::from_rgb(230, 80, 80),
+    egui::Color32::from_rgb(80, 170, 230),
+    egui::Color32::from_rgb(110, 200, 90),
+    egui::Color32::from_rgb(240, 190, 60),
+    egui::Color32::from_rgb(170, 110, 220),
+    egui::Color32::from_rgb(240, 130, 200),
+];
+
+impl PixelArtEditor {
+    /// Frames in the order they play, and whether that order loops forever
+    pub fn playback_order(&self) -> (Vec<usize>, bool) {
+        match self.playback_tag.and_then(|i| self.tags.get(i)) {
+            Some(tag) => (tag.play_order(self.frames.len()), tag.repeat == 0),
+            None => ((0..self.frames.len()).collect(), true),
+        }
+    }
+
+    pub fn start_playback(&mut self, now: f64) {
+        let (order, loops) = self.playback_order();
+        let Some(&first) = order.first() else {
+            return;
+        };
+        self.playback_sequence = order;
+        self.playback_loops = loops;
+        self.playback_position = 0;
+        self.animation_frame = first;
+        self.last_animation_time = now;
+        self.animation_playing = true;
+    }
+
+    pub fn stop_playback(&mut self) {
+        self.animation_playing = false;
+        self.playback_sequence.clear();
+    }
+
+    fn frame_duration_secs(&self, frame: usize) -> f64 {
+        self.frames.get(frame).map_or(0.0, |f| f.duration_ms.max(1) as f64 / 1000.0)
+    }
+
+    /// Move playback on to the frame due at `now`; returns true if the shown frame changed
+    pub fn advance_playback(&mut self, now: f64) -> bool {
+        if !self.animation_playing || self.playback_sequence.is_empty() {
+            return false;
+        }
+        let start_frame = self.animation_frame;
+        // Catch up on every frame that was due, so slow redraws don't slow the animation down
+        loop {
+            let duration = self.frame_duration_secs(self.animation_frame);
+            if now - self.last_animation_time < duration {
+                break;
+            }
+            if self.playback_position + 1 < self.playback_sequence.len() {
+                self.playback_position += 1;
+            } else if self.playback_loops {
+                self.playback_position = 0;
+            } else {
+                // Finite repeats stop on the last frame played
+                self.animation_playing = false;
+                break;
+            }
+            self.last_animation_time += duration;
+            self.animation_frame = self.playback_sequence[self.playback_position].min(self.frames.len().saturating_sub(1));
+        }
+        self.animation_frame != start_frame
+    }
+
+    /// Seconds until playback shows its next frame
+    pub fn time_until_next_frame(&self, now: f64) -> f64 {
+        (self.last_animation_time + self.frame_duration_secs(self.animation_frame) - now).max(0.0)
+    }
+
+    /// Add a tag over the given frames and return its index
+    pub fn add_tag(&mut self, from: usize, to: usize) -> usize {
+        let mut tag = AnimationTag::new(format!("Tag {}", self.tags.len() + 1), from, to);
+        tag.color = TAG_COLORS[self.tags.len() % TAG_COLORS.len()];
+        self.tags.push(tag);
+        self.tags.len() - 1
+    }
+
+    pub fn remove_tag(&mut self, index: usize) {
+        if index >= self.tags.len() {
+            return;
+        }
+        self.tags.remove(index);
+        self.playback_tag = match self.playback_tag {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            other => other,
+        };
+    }
+
+    /// Keep tag ranges on the same frames after a frame was inserted at `index`
+    pub fn shift_tags_for_inserted_frame(&mut self, index: usize) {
+        for tag in &mut self.tags {
+            if tag.from >= index {
+                tag.from += 1;
+            }
+            if tag.to >= index {
+                tag.to += 1;
+            }
+        }
+    }
+
+    /// Keep tag ranges on the same frames after the frame at `index` was removed
+    pub fn shift_tags_for_removed_frame(&mut self, index: usize) {
+        // A tag whose only frame was removed goes with it
+        while let Some(i) = self.tags.iter().position(|tag| tag.from == index && tag.to == index) {
+            self.remove_tag(i);
+        }
+        for tag in &mut self.tags {
+            if tag.from > index {
+                tag.from -= 1;
+            }
+            if tag.to >= index {
+                tag.to -= 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use crate::tiled_buffer::TiledBuffer;
+use crate::types::{Layer, LayerKind, ReferenceImage};
+
+// Reference layers hold an image the artist works from. The layer's pixels show the image
+// placed on the canvas, so it stacks and blends like any other layer, but it can't be
+// painted on and stays out of exports and the eyedropper unless asked.
+
+impl PixelArtEditor {
+    pub fn import_reference_dialog(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .set_title("Import Reference Image")
+            .add_filter("Images", &["png", "jpg", "jpeg", "bmp", "gif", "tga", "tiff", "webp"])
+            .pick_file()
+            && let Err(e) = self.add_reference_layer(&path.to_string_lossy()) {
+            eprintln!("Failed to import reference image: {}", e);
+        }
+    }
+
+    /// Add a reference layer on top of the current frame showing an image file
+    pub fn add_reference_layer(&mut self, filepath: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let pixels = PixelBuffer::from_rgba_image(image::open(filepath)?.into_rgba8());
+        self.push_undo();
+        let frame = &mut self.frames[self.current_frame];
+        let (width, height) = (frame.layers[0].width(), frame.layers[0].height());
+        let name = std::path::Path::new(filepath).file_stem()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| format!("Reference {}", frame.layers.iter().filter(|l| l.kind == LayerKind::Reference).count() + 1));
+        let reference = ReferenceImage::fitted(TiledBuffer::from_pixel_buffer(&pixels), width, height);
+        frame.layers.push(Layer::new_reference(name, width, height, reference));
+        self.current_layer = frame.layers.len() - 1;
+        self.invalidate_cache();
+        Ok(())
+    }
+
+    /// Change the current reference layer's placement and redraw it
+    pub fn edit_reference(&mut self, edit: impl FnOnce(&mut ReferenceImage)) {
+        let layer = self.get_active_layer_mut();
+        if let Some(reference) = &mut layer.reference {
+            edit(reference);
+            layer.render_reference();
+        }
+    }
+
+    /// Move the current reference layer's image by whole canvas pixels
+    pub fn move_reference(&mut self, dx: isize, dy: isize) {
+        self.edit_reference(|reference| {
+            reference.x += dx as f32;
+            reference.y += dy as f32;
+        });
+    }
+
+    /// Colour the eyedropper picks at a canvas pixel: the flattened frame without
+    /// reference layers that aren't pickable
+    pub fn pick_color(&self, x: usize, y: usize) -> eframe::egui::Color32 {
+        crate::compositor::compose_pixel(&self.frames[self.current_frame], x, y)
+    }
+}
@@ -1,48 +1,169 @@
 use crate::editor::core::PixelArtEditor;
-use crate::types::Frame;
+use crate::types::{BlendMode, DirtyRect, Frame, union_dirty};
+use crate::compositor;
+use crate::pixel_buffer::{PixelBuffer, RowBand};
+use crate::tiled_buffer::TILE_SIZE;
+use rayon::prelude::*;
 use eframe::egui;
+use std::sync::Arc;
 
-fn blend_colors(bg: egui::Color32, fg: egui::Color32) -> egui::Color32 {
-    let bg_alpha = bg.a() as f32 / 255.0;
-    let fg_alpha = fg.a() as f32 / 255.0;
-    
-    if fg_alpha == 0.0 {
-        return bg;
-    }
-    
-    let out_alpha = fg_alpha + bg_alpha * (1.0 - fg_alpha);
-    
-    if out_alpha == 0.0 {
-        return egui::Color32::TRANSPARENT;
-    }
-    
-    let r = ((fg.r() as f32 * fg_alpha + bg.r() as f32 * bg_alpha * (1.0 - fg_alpha)) / out_alpha) as u8;
-    let g = ((fg.g() as f32 * fg_alpha + bg.g() as f32 * bg_alpha * (1.0 - fg_alpha)) / out_alpha) as u8;
-    let b = ((fg.b() as f32 * fg_alpha + bg.b() as f32 * bg_alpha * (1.0 - fg_alpha)) / out_alpha) as u8;
-    let a = (out_alpha * 255.0) as u8;
-    
-    egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+/// Composed canvas image shared between the render cache and its readers
+pub type ComposedGrid = Arc<PixelBuffer>;
+
+/// Inputs the cached composite was built from; any change forces a full recompose
+#[derive(Clone, Copy, PartialEq)]
+pub struct CompositeKey {
+    frame: usize,
+    width: usize,
+    height: usize,
+    onion: Option<(usize, usize, u32)>,
 }
 
 impl PixelArtEditor {
-    pub fn get_composed_grid(&self) -> Vec<Vec<egui::Color32>> {
-        // Use cache if available and not dirty
-        if let Some(ref cache) = self.render_cache {
-            if !self.cache_dirty {
-                return cache.clone();
+    /// Frame shown on the canvas (the playing frame during animation)
+    pub fn composite_frame_index(&self) -> usize {
+        if self.animation_playing {
+            self.animation_frame
+        } else {
+            self.current_frame
+        }
+    }
+
+    pub fn composite_key(&self) -> CompositeKey {
+        let frame = &self.frames[self.composite_frame_index()];
+        CompositeKey {
+            frame: self.composite_frame_index(),
+            width: frame.layers[0].width(),
+            height: frame.layers[0].height(),
+            onion: (self.onion_skinning && !self.animation_playing)
+                .then(|| (self.onion_prev_frames, self.onion_next_frames, self.onion_opacity.to_bits())),
+        }
+    }
+
+    fn has_pending_layer_changes(&self) -> bool {
+        self.frames.iter()
+            .flat_map(|frame| frame.layers.iter())
+            .any(|layer| layer.dirty.is_some())
+    }
+
+    /// Bring the render cache up to date, recomposing only the regions layers reported as changed
+    pub fn update_render_cache(&mut self) {
+        let key = self.composite_key();
+        self.sync_tilemaps();
+        self.conform_dirty_layers();
+        self.sync_linked_cels();
+
+        // Thumbnails of frames that changed are made again when next shown
+        if self.cache_dirty || self.frame_thumbnails.len() != self.frames.len() {
+            self.frame_thumbnails = vec![None; self.frames.len()];
+        }
+
+        // Collect everything the layers changed since the last composite
+        let mut region = None;
+        for (i, frame) in self.frames.iter_mut().enumerate() {
+            for layer in &mut frame.layers {
+                let dirty = layer.take_dirty();
+                if dirty.is_some() {
+                    self.frame_thumbnails[i] = None;
+                }
+                region = union_dirty(region, dirty);
             }
         }
 
-        let frame_idx = if self.animation_playing {
-            self.animation_frame
+        let full_recompose = self.cache_dirty
+            || self.render_cache.is_none()
+            || self.render_cache_key != Some(key);
+
+        if full_recompose {
+            let mut composed = PixelBuffer::new(key.width, key.height);
+            if let Some(full) = DirtyRect::full(key.width, key.height).clamp_to(key.width, key.height) {
+                self.compose_region(key.frame, full, &mut composed);
+            }
+            self.render_cache = Some(Arc::new(composed));
+            self.render_cache_key = Some(key);
+            self.cache_dirty = false;
+            self.texture_dirty = Some(DirtyRect::full(key.width, key.height));
+        } else if let Some(region) = region.and_then(|r| r.clamp_to(key.width, key.height)) {
+            if let Some(mut cache) = self.render_cache.take() {
+                // Copy-on-write: only clones if someone still holds the previous composite
+                let composed = Arc::make_mut(&mut cache);
+                self.compose_region(key.frame, region, composed);
+                self.render_cache = Some(cache);
+            }
+            self.texture_dirty = union_dirty(self.texture_dirty, Some(region));
+        }
+    }
+
+    /// Texture of a frame scaled down to fit in `size` pixels. It is kept until the frame
+    /// changes, and the frame on the canvas is taken from the render cache.
+    pub fn frame_thumbnail(&mut self, ctx: &egui::Context, index: usize, size: usize) -> egui::TextureId {
+        if let Some(Some(texture)) = self.frame_thumbnails.get(index) {
+            return texture.id();
+        }
+        let key = self.composite_key();
+        let composed = if index == key.frame && key.onion.is_none() {
+            self.get_composed_grid()
         } else {
-            self.current_frame
+            Arc::new(self.thread_pool.install(|| compositor::compose_frame(&self.frames[index])))
         };
+        let (width, height) = (composed.width(), composed.height());
+        let scale = (size as f32 / width.max(height) as f32).min(1.0);
+        let thumb_width = ((width as f32 * scale).round() as usize).max(1);
+        let thumb_height = ((height as f32 * scale).round() as usize).max(1);
+        let mut image = egui::ColorImage::new([thumb_width, thumb_height], egui::Color32::TRANSPARENT);
+        for y in 0..thumb_height {
+            for x in 0..thumb_width {
+                image.pixels[y * thumb_width + x] = composed.get_pixel(x * width / thumb_width, y * height / thumb_height);
+            }
+        }
+        let texture = ctx.load_texture(format!("frame_thumbnail_{}", index), image, egui::TextureOptions::NEAREST);
+        let id = texture.id();
+        if self.frame_thumbnails.len() < self.frames.len() {
+            self.frame_thumbnails.resize(self.frames.len(), None);
+        }
+        self.frame_thumbnails[index] = Some(texture);
+        id
+    }
+
+    /// Get the composed canvas image; shares the render cache when it is current
+    pub fn get_composed_grid(&self) -> ComposedGrid {
+        // Use cache if available and not dirty
+        if let Some(ref cache) = self.render_cache {
+            if !self.cache_dirty
+                && self.render_cache_key == Some(self.composite_key())
+                && !self.has_pending_layer_changes() {
+                return Arc::clone(cache);
+            }
+        }
+
+        let key = self.composite_key();
+        let mut composed = PixelBuffer::new(key.width, key.height);
+        if let Some(full) = DirtyRect::full(key.width, key.height).clamp_to(key.width, key.height) {
+            self.compose_region(key.frame, full, &mut composed);
+        }
+        Arc::new(composed)
+    }
+
+    /// Recompose one rectangle of the canvas from scratch, one band of rows per worker thread
+    fn compose_region(&self, frame_idx: usize, region: DirtyRect, composed: &mut PixelBuffer) {
+        let bands = composed.row_bands_mut(region, TILE_SIZE);
+        self.thread_pool.install(|| {
+            bands.into_par_iter().for_each(|mut band| {
+                if let Some(band_region) = band.clip(region) {
+                    self.compose_band(frame_idx, band_region, &mut band);
+                }
+            });
+        });
+    }
+
+    fn compose_band(&self, frame_idx: usize, region: DirtyRect, composed: &mut RowBand) {
         let frame = &self.frames[frame_idx];
 
-        let width = frame.layers[0].width();
-        let height = frame.layers[0].height();
-        let mut composed = vec![vec![egui::Color32::TRANSPARENT; width]; height];
+        for y in region.min_y..=region.max_y {
+            for x in region.min_x..=region.max_x {
+                composed.set(x, y, egui::Color32::TRANSPARENT);
+            }
+        }
 
         // Add onion skinning if enabled
         if self.onion_skinning && !self.animation_playing {
@@ -51,7 +172,7 @@ impl PixelArtEditor {
                 if frame_idx >= i {
                     let prev_frame = &self.frames[frame_idx - i];
                     let opacity = self.onion_opacity * (1.0 - (i as f32 * 0.2));
-                    self.compose_frame_with_opacity(prev_frame, &mut composed, opacity, egui::Color32::BLUE);
+                    Self::compose_frame_with_opacity(prev_frame, composed, region, opacity, egui::Color32::BLUE);
                 }
             }
             
@@ -60,67 +181,95 @@ impl PixelArtEditor {
                 if frame_idx + i < self.frames.len() {
                     let next_frame = &self.frames[frame_idx + i];
                     let opacity = self.onion_opacity * (1.0 - (i as f32 * 0.2));
-                    self.compose_frame_with_opacity(next_frame, &mut composed, opacity, egui::Color32::RED);
+                    Self::compose_frame_with_opacity(next_frame, composed, region, opacity, egui::Color32::RED);
                 }
             }
         }
 
         // Draw current frame
-        for layer in &frame.layers {
-            if !layer.visible {
+        compositor::compose_layers(frame, region, composed);
+    }
+
+    fn compose_frame_with_opacity(frame: &Frame, composed: &mut RowBand, region: DirtyRect, opacity: f32, tint: egui::Color32) {
+        for (i, layer) in frame.layers.iter().enumerate() {
+            if layer.is_group() || layer.is_reference() || !frame.is_layer_visible(i) {
                 continue;
             }
-            for y in 0..height {
-                for x in 0..width {
-                    let c = layer.grid[y][x];
-                    if c.a() > 0 {
-                        let new_color = if layer.opacity < 1.0 {
-                            let alpha = (c.a() as f32 * layer.opacity) as u8;
-                            egui::Color32::from_rgba_unmultiplied(c.r(), c.g(), c.b(), alpha)
-                        } else {
-                            c
-                        };
-
-                        if new_color.a() == 255 {
-                            composed[y][x] = new_color;
-                        } else if new_color.a() > 0 {
-                            let bg = composed[y][x];
-                            composed[y][x] = blend_colors(bg, new_color);
-                        }
-                    }
+            layer.grid.for_each_visible_pixel(region, |x, y, c| {
+                let c = layer.masked(x, y, c);
+                // Apply tint and opacity
+                let tinted = egui::Color32::from_rgba_unmultiplied(
+                    ((c.r() as f32 * 0.7) + (tint.r() as f32 * 0.3)) as u8,
+                    ((c.g() as f32 * 0.7) + (tint.g() as f32 * 0.3)) as u8,
+                    ((c.b() as f32 * 0.7) + (tint.b() as f32 * 0.3)) as u8,
+                    (c.a() as f32 * opacity) as u8
+                );
+                
+                if tinted.a() > 0 {
+                    let bg = composed.get_pixel(x, y);
+                    composed.set(x, y, compositor::blend(BlendMode::Normal, bg, tinted, 1.0));
                 }
-            }
+            });
         }
-        composed
     }
+}
 
-    fn compose_frame_with_opacity(&self, frame: &Frame, composed: &mut Vec<Vec<egui::Color32>>, opacity: f32, tint: egui::Color32) {
-        let width = composed[0].len();
-        let height = composed.len();
-        
-        for layer in &frame.layers {
-            if !layer.visible {
-                continue;
+impl PixelArtEditor {
+    /// Upload the composed image to the canvas texture, writing only the region that changed
+    pub fn sync_canvas_texture(&mut self, ctx: &egui::Context, composed: &PixelBuffer) -> egui::TextureId {
+        let (width, height) = (composed.width(), composed.height());
+
+        let size_changed = self.canvas_texture.as_ref().is_none_or(|texture| texture.size() != [width, height]);
+
+        if size_changed {
+            let image = egui::ColorImage::from_rgba_unmultiplied([width, height], composed.as_bytes());
+            self.canvas_texture = Some(ctx.load_texture("canvas", image, egui::TextureOptions::NEAREST));
+            self.texture_dirty = None;
+        } else if let Some(region) = self.texture_dirty.take().and_then(|r| r.clamp_to(width, height)) {
+            // Only send the changed rectangle to the GPU
+            let pixels: Vec<u8> = composed.region(region).flat_map(|(_, row)| row.iter().copied()).collect();
+            let image = egui::ColorImage::from_rgba_unmultiplied([region.width(), region.height()], &pixels);
+            if let Some(texture) = &mut self.canvas_texture {
+                texture.set_partial([region.min_x, region.min_y], image, egui::TextureOptions::NEAREST);
             }
-            for y in 0..height {
-                for x in 0..width {
-                    let c = layer.grid[y][x];
-                    if c.a() > 0 {
-                        // Apply tint and opacity
-                        let tinted = egui::Color32::from_rgba_unmultiplied(
-                            ((c.r() as f32 * 0.7) + (tint.r() as f32 * 0.3)) as u8,
-                            ((c.g() as f32 * 0.7) + (tint.g() as f32 * 0.3)) as u8,
-                            ((c.b() as f32 * 0.7) + (tint.b() as f32 * 0.3)) as u8,
-                            (c.a() as f32 * opacity) as u8
-                        );
-                        
-                        if tinted.a() > 0 {
-                            let bg = composed[y][x];
-                            composed[y][x] = blend_colors(bg, tinted);
-                        }
-                    }
-                }
+        }
+
+        self.canvas_texture.as_ref().map_or(egui::TextureId::default(), |texture| texture.id())
+    }
+
+    /// Texture of the preview overlay, if a tool has one, and the part of the canvas it
+    /// covers. It is only uploaded again after it changes.
+    pub fn sync_preview_texture(&mut self, ctx: &egui::Context) -> Option<(egui::TextureId, DirtyRect)> {
+        let (Some(preview), Some(bounds)) = (&self.preview_overlay, self.preview_bounds) else {
+            self.preview_texture = None;
+            return None;
+        };
+        if self.preview_changed || self.preview_texture.is_none() {
+            let image = egui::ColorImage::from_rgba_unmultiplied([preview.width(), preview.height()], preview.as_bytes());
+            match &mut self.preview_texture {
+                Some(texture) => texture.set(image, egui::TextureOptions::NEAREST),
+                None => self.preview_texture = Some(ctx.load_texture("preview", image, egui::TextureOptions::NEAREST)),
             }
+            self.preview_changed = false;
         }
+        self.preview_texture.as_ref().map(|texture| (texture.id(), bounds))
+    }
+
+    /// Get the 2x2 checkerboard texture drawn behind transparent pixels
+    pub fn checker_texture_id(&mut self, ctx: &egui::Context) -> egui::TextureId {
+        let texture = self.checker_texture.get_or_insert_with(|| {
+            let light = egui::Color32::from_gray(240);
+            let dark = egui::Color32::from_gray(220);
+            let image = egui::ColorImage {
+                size: [2, 2],
+                pixels: vec![dark, light, light, dark],
+            };
+            let options = egui::TextureOptions {
+                wrap_mode: egui::TextureWrapMode::Repeat,
+                ..egui::TextureOptions::NEAREST
+            };
+            ctx.load_texture("canvas_checker", image, options)
+        });
+        texture.id()
     }
 }
@@ -0,0 +1,171 @@
+use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use crate::selection_mask::{SelectionMask, SelectionMode};
+use crate::types::Tool;
+use eframe::egui;
+
+// The selection is a per-pixel mask over the canvas. Tools build a shape as a mask of
+// its own and merge it into the selection; every paint tool and filter then leaves the
+// pixels outside it alone. No selection means the whole canvas is open to editing.
+
+impl PixelArtEditor {
+    /// Whether tools may paint a canvas pixel
+    pub fn is_selected(&self, x: isize, y: isize) -> bool {
+        self.selection.as_ref().is_none_or(|selection| selection.contains(x, y))
+    }
+
+    /// Mode a selection tool uses: the held modifiers win over the one picked in the settings
+    pub fn selection_mode_for(&self, modifiers: egui::Modifiers) -> SelectionMode {
+        SelectionMode::from_modifiers(modifiers.shift, modifiers.alt).unwrap_or(self.selection_mode)
+    }
+
+    /// Merge a shape into the selection. A selection that ends up empty is dropped.
+    pub fn apply_selection(&mut self, shape: SelectionMask, mode: SelectionMode) {
+        let mut selection = match (mode, self.selection.take()) {
+            (SelectionMode::Replace, _) | (_, None) => SelectionMask::new(shape.width(), shape.height()),
+            (_, Some(selection)) => selection,
+        };
+        selection.combine(&shape, if mode == SelectionMode::Replace { SelectionMode::Add } else { mode });
+        self.selection = (!selection.is_empty()).then_some(selection);
+    }
+
+    /// Select pixels like the one at (x, y) on the current layer
+    pub fn magic_wand(&mut self, x: usize, y: usize, mode: SelectionMode) {
+        let pixels = self.get_active_layer().to_pixel_buffer();
+        let shape = SelectionMask::matching(&pixels, x, y, self.wand_tolerance, self.wand_contiguous);
+        self.apply_selection(shape, mode);
+    }
+
+    /// Shape the current selection tool makes when dragged between two canvas positions
+    pub fn selection_shape(&self, start: (isize, isize), end: (isize, isize)) -> SelectionMask {
+        let layer = self.get_active_layer();
+        match self.tool {
+            Tool::EllipseSelect => SelectionMask::ellipse(layer.width(), layer.height(), start, end),
+            _ => SelectionMask::rect(layer.width(), layer.height(), start, end),
+        }
+    }
+
+    /// Close the lasso outline drawn so far and merge what it encloses into the selection
+    pub fn close_lasso(&mut self, mode: SelectionMode) {
+        if !self.lasso_active {
+            return;
+        }
+        let points = std::mem::take(&mut self.lasso_points);
+        self.lasso_active = false;
+        let layer = self.get_active_layer();
+        let shape = SelectionMask::polygon(layer.width(), layer.height(), &points);
+        self.apply_selection(shape, mode);
+    }
+
+    pub fn select_all(&mut self) {
+        let layer = self.get_active_layer();
+        self.selection = Some(SelectionMask::all(layer.width(), layer.height()));
+    }
+
+    pub fn deselect(&mut self) {
+        self.commit_floating();
+        self.selection = None;
+        self.selection_start = None;
+        self.lasso_points.clear();
+        self.lasso_active = false;
+    }
+
+    pub fn invert_selection(&mut self) {
+        let selection = match &self.selection {
+            Some(selection) => selection.inverted(),
+            None => return self.select_all(),
+        };
+        self.selection = (!selection.is_empty()).then_some(selection);
+    }
+
+    pub fn grow_selection(&mut self) {
+        let amount = self.selection_modify_amount;
+        self.modify_selection(|selection| selection.grown(amount));
+    }
+
+    pub fn shrink_selection(&mut self) {
+        let amount = self.selection_modify_amount;
+        self.modify_selection(|selection| selection.shrunk(amount));
+    }
+
+    pub fn border_selection(&mut self) {
+        let amount = self.selection_modify_amount;
+        self.modify_selection(|selection| selection.border(amount));
+    }
+
+    fn modify_selection(&mut self, modify: impl FnOnce(&SelectionMask) -> SelectionMask) {
+        if let Some(selection) = &self.selection {
+            let selection = modify(selection);
+            self.selection = (!selection.is_empty()).then_some(selection);
+        }
+    }
+
+    /// Forget the selection if the canvas size no longer matches it, e.g. after a resize
+    pub fn drop_stale_selection(&mut self) {
+        let layer = self.get_active_layer();
+        let size = (layer.width(), layer.height());
+        if self.selection.as_ref().is_some_and(|selection| (selection.width(), selection.height()) != size) {
+            self.deselect();
+        }
+    }
+
+    /// Put back the current layer's pixels outside the selection, e.g. after a filter
+    /// ran over the whole layer. `before` is the layer as tools saw it beforehand.
+    pub fn clip_to_selection(&mut self, before: &PixelBuffer) {
+        let Some(selection) = &self.selection else {
+            return;
+        };
+        let layer = &mut self.frames[self.current_frame].layers[self.current_layer];
+        let mut pixels = layer.to_pixel_buffer();
+        let mut changed = false;
+        for (x, y, color) in before.pixels() {
+            if !selection.contains(x as isize, y as isize) && pixels.get_pixel(x, y) != color {
+                pixels.set(x, y, color);
+                changed = true;
+            }
+        }
+        if changed {
+            layer.set_pixels(&pixels);
+        }
+    }
+
+    /// The selected pixels of the current layer, cropped to the selection, and where
+    /// their top-left corner is on the canvas
+    pub fn selected_pixels(&self) -> Option<(PixelBuffer, isize, isize)> {
+        let selection = self.selection.as_ref()?;
+        let bounds = selection.bounds()?;
+        let layer = self.get_active_layer();
+        let mut pixels = layer.target_grid().read_region(bounds.min_x as isize, bounds.min_y as isize, bounds.width(), bounds.height());
+        for y in 0..bounds.height() {
+            for x in 0..bounds.width() {
+                if !selection.contains((bounds.min_x + x) as isize, (bounds.min_y + y) as isize) {
+                    pixels.set(x, y, egui::Color32::TRANSPARENT);
+                }
+            }
+        }
+        Some((pixels, bounds.min_x as isize, bounds.min_y as isize))
+    }
+
+    /// Copy the selected pixels of the current layer
+    pub fn copy_selection(&mut self) {
+        if let Some((pixels, x, y)) = self.selected_pixels() {
+            self.selection_pixels = Some(pixels);
+            self.selection_pixels_origin = (x, y);
+        }
+    }
+
+    /// Erase the selected pixels of the current layer
+    pub fn clear_selection(&mut self) {
+        let Some(bounds) = self.selection.as_ref().and_then(|selection| selection.bounds()) else {
+            return;
+        };
+        self.push_undo();
+        let mut pixels = Vec::new();
+        for y in bounds.min_y..=bounds.max_y {
+            for x in bounds.min_x..=bounds.max_x {
+                pixels.push((x as isize, y as isize, egui::Color32::TRANSPARENT));
+            }
+        }
+        self.put_pixels(&pixels);
+    }
+}
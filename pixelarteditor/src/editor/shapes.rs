@@ -0,0 +1,139 @@
+use crate::editor::core::PixelArtEditor;
+use crate::selection_mask::SelectionMask;
+use crate::editor::stroke::line_points;
+use crate::shapes::{bezier_points, ellipse_filled, ellipse_outline, polyline, snapped_line, CurveKind, ISOMETRIC_SLOPES, PIXEL_ART_SLOPES};
+
+// Line, rectangle, ellipse, polygon and curve tools. Each one shows the shape it would draw through
+// `preview_overlay` while it is being made, and draws it in the primary colour as one undo
+// step when finished.
+
+impl PixelArtEditor {
+    /// Pixels of a line from `start` towards `end`. Snapped lines keep to pixel-art slopes,
+    /// and isometric lines always keep to 2:1; centred ones reach as far back past `start`.
+    pub fn line_pixels(&self, start: (isize, isize), end: (isize, isize), snap: bool, centered: bool) -> Vec<(isize, isize)> {
+        if self.isometric_lines {
+            snapped_line(start, end, &ISOMETRIC_SLOPES, centered)
+        } else if snap {
+            snapped_line(start, end, &PIXEL_ART_SLOPES, centered)
+        } else if centered {
+            line_points((2 * start.0 - end.0, 2 * start.1 - end.1), end)
+        } else {
+            line_points(start, end)
+        }
+    }
+
+    /// Pixels of the rectangle between two corner pixels, filled when shapes are filled
+    pub fn rectangle_pixels(&self, a: (isize, isize), b: (isize, isize)) -> Vec<(isize, isize)> {
+        let (min_x, max_x) = (a.0.min(b.0), a.0.max(b.0));
+        let (min_y, max_y) = (a.1.min(b.1), a.1.max(b.1));
+        let mut pixels = Vec::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                // Outlines only take the edge pixels
+                if self.fill_shape || x == min_x || x == max_x || y == min_y || y == max_y {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    /// Pixels of the ellipse between two corner pixels, filled when shapes are filled
+    pub fn ellipse_pixels(&self, a: (isize, isize), b: (isize, isize)) -> Vec<(isize, isize)> {
+        if self.fill_shape { ellipse_filled(a, b) } else { ellipse_outline(a, b) }
+    }
+
+    /// Pixels of the circle about `center` passing through `edge`, filled when shapes are filled
+    pub fn circle_pixels(&self, center: (isize, isize), edge: (isize, isize)) -> Vec<(isize, isize)> {
+        // The circle's box is the centre grown by the radius each way
+        let radius = (((edge.0 - center.0).pow(2) + (edge.1 - center.1).pow(2)) as f32).sqrt() as isize;
+        self.ellipse_pixels((center.0 - radius, center.1 - radius), (center.0 + radius, center.1 + radius))
+    }
+
+    /// Pixels of a polygon through its corners; open polygons are left as a chain of lines
+    pub fn polygon_pixels(&self, corners: &[(isize, isize)], closed: bool) -> Vec<(isize, isize)> {
+        if !(closed && self.fill_shape) {
+            return polyline(corners, closed);
+        }
+        let layer = self.get_active_layer();
+        let mask = SelectionMask::polygon(layer.width(), layer.height(), corners);
+        let mut pixels = Vec::new();
+        for y in 0..layer.height() as isize {
+            for x in 0..layer.width() as isize {
+                if mask.contains(x, y) {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    /// Show shape pixels in the primary colour without touching the layer
+    pub fn preview_shape(&mut self, points: &[(isize, isize)]) {
+        let pixels: Vec<_> = points.iter().map(|&(x, y)| (x, y, self.selected_color)).collect();
+        self.preview_pixels(&pixels);
+    }
+
+    /// Draw shape pixels in the primary colour as one undo step
+    pub fn draw_shape(&mut self, points: &[(isize, isize)]) {
+        self.preview_overlay = None;
+        if points.is_empty() || !self.get_active_layer().is_editable() {
+            return;
+        }
+        self.push_undo();
+        let pixels: Vec<_> = points.iter().map(|&(x, y)| (x, y, self.selected_color)).collect();
+        self.put_pixels(&pixels);
+    }
+
+    /// Draw the polygon clicked out so far, closed back to its first corner
+    pub fn close_polygon(&mut self) {
+        let corners = std::mem::take(&mut self.polygon_points);
+        let pixels = self.polygon_pixels(&corners, true);
+        self.draw_shape(&pixels);
+    }
+
+    /// Start a straight curve of the current kind between two points, ready to be bent
+    pub fn begin_curve(&mut self, start: (isize, isize), end: (isize, isize)) {
+        self.curve_points = self.curve_kind.straight(start, end);
+        self.preview_shape(&bezier_points(&self.curve_points));
+    }
+
+    /// Move one of the curve's ends or control points
+    pub fn move_curve_point(&mut self, index: usize, point: (isize, isize)) {
+        if let Some(p) = self.curve_points.get_mut(index) {
+            *p = point;
+            self.preview_shape(&bezier_points(&self.curve_points));
+        }
+    }
+
+    /// Switch between quadratic and cubic curves, keeping the ends of the curve being shaped
+    pub fn set_curve_kind(&mut self, kind: CurveKind) {
+        self.curve_kind = kind;
+        if let (Some(&start), Some(&end)) = (self.curve_points.first(), self.curve_points.last()) {
+            self.begin_curve(start, end);
+        }
+    }
+
+    /// Draw the curve being shaped
+    pub fn commit_curve(&mut self) {
+        let points = std::mem::take(&mut self.curve_points);
+        self.curve_start = None;
+        self.curve_handle = None;
+        if !points.is_empty() {
+            self.draw_shape(&bezier_points(&points));
+        }
+    }
+
+    /// Drop an unfinished shape and its preview
+    pub fn cancel_shape(&mut self) {
+        self.line_start = None;
+        self.rectangle_start = None;
+        self.circle_start = None;
+        self.ellipse_start = None;
+        self.polygon_points.clear();
+        self.curve_points.clear();
+        self.curve_start = None;
+        self.curve_handle = None;
+        self.preview_overlay = None;
+    }
+}
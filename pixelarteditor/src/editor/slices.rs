@@ -0,0 +1,165 @@
+use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use crate::types::{Slice, SliceKey, SliceRect};
+use eframe::egui;
+
+// Slices: named rectangles with optional 9-slice centre and pivot. A slice is keyed
+// per frame like Aseprite's: each key holds from its frame until the next key, so
+// editing a slice on some frame adds a key there and leaves earlier frames alone.
+
+impl PixelArtEditor {
+    /// Add a slice starting on the current frame and return its index
+    pub fn add_slice(&mut self, bounds: SliceRect) -> usize {
+        self.slices.push(Slice {
+            name: format!("Slice {}", self.slices.len() + 1),
+            color: egui::Color32::from_rgb(0, 0, 255),
+            keys: vec![SliceKey { frame: self.current_frame, bounds, center: None, pivot: None }],
+        });
+        self.slices.len() - 1
+    }
+
+    pub fn remove_slice(&mut self, index: usize) {
+        if index >= self.slices.len() {
+            return;
+        }
+        self.slices.remove(index);
+        self.selected_slice = match self.selected_slice {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            other => other,
+        };
+    }
+
+    /// Topmost slice covering a pixel on `frame`
+    pub fn slice_at(&self, frame: usize, x: isize, y: isize) -> Option<usize> {
+        self.slices.iter().rposition(|slice| slice.key_at(frame).is_some_and(|key| key.bounds.contains(x, y)))
+    }
+
+    /// Change a slice as it is on `frame`, keying it there
+    pub fn edit_slice_key(&mut self, index: usize, frame: usize, edit: impl FnOnce(&mut SliceKey)) {
+        let Some(slice) = self.slices.get_mut(index) else {
+            return;
+        };
+        let Some(&current) = slice.key_at(frame) else {
+            return;
+        };
+        let mut key = SliceKey { frame, ..current };
+        edit(&mut key);
+        if key != current {
+            slice.set_key(key);
+        }
+    }
+
+    /// Slice tool press: grab the slice under the pointer or start drawing a new one
+    pub fn begin_slice_drag(&mut self, x: isize, y: isize) {
+        self.selected_slice = self.slice_at(self.current_frame, x, y);
+        self.slice_drag_original = self.selected_slice
+            .and_then(|i| self.slices[i].key_at(self.current_frame))
+            .map(|key| key.bounds);
+        self.slice_drag_start = Some((x, y));
+        self.slice_drag_end = Some((x, y));
+    }
+
+    pub fn update_slice_drag(&mut self, x: isize, y: isize) {
+        let Some((start_x, start_y)) = self.slice_drag_start else {
+            return;
+        };
+        self.slice_drag_end = Some((x, y));
+        if let (Some(index), Some(original)) = (self.selected_slice, self.slice_drag_original) {
+            let frame = self.current_frame;
+            self.edit_slice_key(index, frame, |key| {
+                key.bounds.x = original.x + x - start_x;
+                key.bounds.y = original.y + y - start_y;
+            });
+        }
+    }
+
+    pub fn end_slice_drag(&mut self) {
+        if let (Some(start), Some(end), None) = (self.slice_drag_start, self.slice_drag_end, self.slice_drag_original) {
+            // A plain click on empty canvas only clears the selection
+            if start != end {
+                self.selected_slice = Some(self.add_slice(SliceRect::from_corners(start, end)));
+            }
+        }
+        self.slice_drag_start = None;
+        self.slice_drag_end = None;
+        self.slice_drag_original = None;
+    }
+
+    /// Keep slice keys on the same frames after a frame was inserted at `index`
+    pub fn shift_slices_for_inserted_frame(&mut self, index: usize) {
+        for key in self.slices.iter_mut().flat_map(|slice| slice.keys.iter_mut()) {
+            if key.frame >= index {
+                key.frame += 1;
+            }
+        }
+    }
+
+    /// Keep slice keys on the same frames after the frame at `index` was removed
+    pub fn shift_slices_for_removed_frame(&mut self, index: usize) {
+        for slice in &mut self.slices {
+            // The first frame's key carries on from the frame that replaces it
+            if slice.keys.first().is_some_and(|key| key.frame == index)
+                && slice.keys.get(1).is_none_or(|key| key.frame > index + 1) {
+                slice.keys[0].frame += 1;
+            }
+            slice.keys.retain(|key| key.frame != index);
+            for key in &mut slice.keys {
+                if key.frame > index {
+                    key.frame -= 1;
+                }
+            }
+        }
+        // Slices with no frames left go too
+        let frame_count = self.frames.len();
+        for slice in &mut self.slices {
+            slice.keys.retain(|key| key.frame < frame_count);
+        }
+        while let Some(i) = self.slices.iter().position(|slice| slice.keys.is_empty()) {
+            self.remove_slice(i);
+        }
+    }
+
+    /// Composed pixels under a slice on `frame`
+    pub fn slice_image(&self, index: usize, frame: usize) -> Option<PixelBuffer> {
+        let key = self.slices.get(index)?.key_at(frame)?;
+        let frame = self.frames.get(frame)?;
+        let composed = self.thread_pool.install(|| crate::compositor::compose_frame(frame));
+        let mut image = PixelBuffer::new(key.bounds.width, key.bounds.height);
+        image.blit(&composed, -key.bounds.x, -key.bounds.y);
+        Some(image)
+    }
+}
+
+/// Resize an image as a 9-slice: corners stay as they are, edges stretch along
+/// their length and the centre stretches both ways
+pub fn nine_slice_stretch(source: &PixelBuffer, center: SliceRect, width: usize, height: usize) -> PixelBuffer {
+    let map_axis = |target: usize, size: usize, start: isize, length: usize| -> Vec<usize> {
+        let start = start.clamp(0, size as isize) as usize;
+        let end = (start + length).min(size);
+        let (head, tail) = (start, size - end);
+        let middle = target.saturating_sub(head + tail);
+        (0..target).map(|i| {
+            if i < head.min(target) {
+                i
+            } else if i >= head + middle {
+                // Borders that don't fit keep their outer pixels
+                size - (target - i).min(size)
+            } else {
+                start + (i - head) * (end - start) / middle.max(1)
+            }
+        }).collect()
+    };
+    let columns = map_axis(width, source.width(), center.x, center.width);
+    let rows = map_axis(height, source.height(), center.y, center.height);
+
+    let mut out = PixelBuffer::new(width, height);
+    for (y, &sy) in rows.iter().enumerate() {
+        for (x, &sx) in columns.iter().enumerate() {
+            if let Some(color) = source.get(sx, sy) {
+                out.set(x, y, color);
+            }
+        }
+    }
+    out
+}
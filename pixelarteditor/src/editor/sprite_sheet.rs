@@ -0,0 +1,102 @@
+use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use crate::types::{AnimationDirection, SliceRect};
+use eframe::egui;
+use rayon::prelude::*;
+use serde_json::json;
+
+// Sprite sheet export: every frame side by side in one PNG, plus a JSON file in the
+// layout Aseprite writes ("JSON Array"), so engines that read Aseprite sheets can
+// pick up frame durations, tags and slices.
+
+fn rect_json(rect: SliceRect) -> serde_json::Value {
+    json!({ "x": rect.x, "y": rect.y, "w": rect.width, "h": rect.height })
+}
+
+fn color_hex(color: egui::Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+}
+
+impl PixelArtEditor {
+    /// Save all frames as a horizontal strip PNG with a JSON file of the same name
+    pub fn save_sprite_sheet(&self, filepath: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+        let (width, height) = self.frames.first()
+            .and_then(|frame| frame.layers.first())
+            .map(|layer| (layer.width(), layer.height()))
+            .ok_or("No canvas data to export")?;
+
+        let composed: Vec<PixelBuffer> = self.thread_pool.install(|| {
+            self.frames.par_iter().map(crate::compositor::compose_frame).collect()
+        });
+        let mut sheet = PixelBuffer::new(width * composed.len(), height);
+        for (i, image) in composed.iter().enumerate() {
+            sheet.blit(image, (i * width) as isize, 0);
+        }
+        let (sheet_width, sheet_height) = (sheet.width(), sheet.height());
+        sheet.into_rgba_image().save_with_format(filepath, image::ImageFormat::Png)?;
+
+        let image_name = filepath.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let stem = filepath.file_stem().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let frames: Vec<serde_json::Value> = self.frames.iter().enumerate().map(|(i, frame)| {
+            json!({
+                "filename": format!("{} {}", stem, i),
+                "frame": { "x": i * width, "y": 0, "w": width, "h": height },
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize": { "x": 0, "y": 0, "w": width, "h": height },
+                "sourceSize": { "w": width, "h": height },
+                "duration": frame.duration_ms,
+            })
+        }).collect();
+
+        let tags: Vec<serde_json::Value> = self.tags.iter().map(|tag| {
+            let mut value = json!({
+                "name": tag.name,
+                "from": tag.from,
+                "to": tag.to,
+                "direction": match tag.direction {
+                    AnimationDirection::Forward => "forward",
+                    AnimationDirection::Reverse => "reverse",
+                    AnimationDirection::PingPong => "pingpong",
+                    AnimationDirection::PingPongReverse => "pingpong_reverse",
+                },
+                "color": color_hex(tag.color),
+            });
+            if tag.repeat > 0 {
+                value["repeat"] = json!(tag.repeat.to_string());
+            }
+            value
+        }).collect();
+
+        let slices: Vec<serde_json::Value> = self.slices.iter().map(|slice| {
+            let keys: Vec<serde_json::Value> = slice.keys.iter().map(|key| {
+                let mut value = json!({ "frame": key.frame, "bounds": rect_json(key.bounds) });
+                if let Some(center) = key.center {
+                    value["center"] = rect_json(center);
+                }
+                if let Some((x, y)) = key.pivot {
+                    value["pivot"] = json!({ "x": x, "y": y });
+                }
+                value
+            }).collect();
+            json!({ "name": slice.name, "color": color_hex(slice.color), "keys": keys })
+        }).collect();
+
+        let document = json!({
+            "frames": frames,
+            "meta": {
+                "app": "Kamari Pixel Art Editor",
+                "image": image_name,
+                "format": "RGBA8888",
+                "size": { "w": sheet_width, "h": sheet_height },
+                "scale": "1",
+                "frameTags": tags,
+                "slices": slices,
+            },
+        });
+        std::fs::write(filepath.with_extension("json"), serde_json::to_string_pretty(&document)?)?;
+        println!("Sprite sheet saved as: {:?}", filepath);
+        Ok(())
+    }
+}
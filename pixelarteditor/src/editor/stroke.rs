@@ -0,0 +1,250 @@
+use crate::brush::BrushShape;
+use crate::editor::core::PixelArtEditor;
+use crate::palette::shade_along;
+use crate::pixel_buffer::PixelBuffer;
+use crate::types::{DirtyRect, Layer};
+use eframe::egui;
+use std::collections::HashSet;
+
+// Freehand strokes. Pointer events arrive far apart on fast drags, so each new position is
+// joined to the last one with a Bresenham line and the brush is stamped at every step. In
+// pixel-perfect mode, a step that turns an L-shaped corner takes the corner's dab back out,
+// which keeps one-pixel lines free of doubled-up steps.
+
+/// Freehand stroke in progress
+#[derive(Clone)]
+pub struct Stroke {
+    pub color: egui::Color32,
+    pub path: Vec<(isize, isize)>, // Brush centres so far, each one next to the one before
+    pub before: Layer,             // The layer as it was when the stroke started
+    pub shade: Option<bool>,       // Shading ink: step pixels lighter (true) or darker
+}
+
+/// Pixels on a line between two pixels, both ends included
+pub fn line_points(from: (isize, isize), to: (isize, isize)) -> Vec<(isize, isize)> {
+    let (mut x, mut y) = from;
+    let (dx, dy) = ((to.0 - x).abs(), -(to.1 - y).abs());
+    let (sx, sy) = ((to.0 - x).signum(), (to.1 - y).signum());
+    let mut err = dx + dy;
+    let mut points = vec![(x, y)];
+    while (x, y) != to {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+        points.push((x, y));
+    }
+    points
+}
+
+/// Whether `b` is the corner of an L-shaped step from `a` to `c`
+pub fn is_corner(a: (isize, isize), b: (isize, isize), c: (isize, isize)) -> bool {
+    (a.0 - c.0).abs() == 1 && (a.1 - c.1).abs() == 1
+        && (b.0 == a.0 || b.0 == c.0)
+        && (b.1 == a.1 || b.1 == c.1)
+}
+
+impl PixelArtEditor {
+    /// Pixels one brush dab covers, symmetry copies included, with their colours
+    pub fn dab_pixels(&self, x: isize, y: isize, color: egui::Color32) -> Vec<(isize, isize, egui::Color32)> {
+        self.with_symmetry(self.brush_dab(x, y, color))
+    }
+
+    /// Pixels one brush dab covers, without its symmetry copies
+    pub fn brush_dab(&self, x: isize, y: isize, color: egui::Color32) -> Vec<(isize, isize, egui::Color32)> {
+        let custom = self.active_brush.and_then(|i| self.brushes.get(i)).filter(|_| self.brush_shape == BrushShape::Custom);
+        match custom {
+            // Erasing takes out the brush's shape rather than painting its colours
+            Some(brush) => brush.dab(x, y, color, self.brush_own_colors && color.a() > 0, self.brush_aligned),
+            None => {
+                let shape = if self.brush_shape == BrushShape::Custom { BrushShape::Circle } else { self.brush_shape };
+                shape.tip(self.brush_size, self.brush_angle).into_iter()
+                    .map(|(dx, dy)| (x + dx, y + dy, color))
+                    .collect()
+            }
+        }
+    }
+
+    /// Furthest a dab reaches from its centre in either direction
+    fn brush_reach(&self) -> isize {
+        let custom = self.active_brush.and_then(|i| self.brushes.get(i)).filter(|_| self.brush_shape == BrushShape::Custom);
+        match custom {
+            Some(brush) => brush.pixels.width().max(brush.pixels.height()) as isize,
+            None => self.brush_size.max(1) as isize,
+        }
+    }
+
+    /// Colours shading ink steps through: the ones picked from the active palette, in
+    /// palette order, or the whole palette when none are picked
+    pub fn shading_ramp(&self) -> Vec<egui::Color32> {
+        let palette = &self.custom_palettes[self.active_palette];
+        let ramp: Vec<_> = palette.iter().copied().filter(|color| self.shading_colors.contains(color)).collect();
+        if ramp.is_empty() { palette.clone() } else { ramp }
+    }
+
+    /// Add the mirror images of pixels for the enabled symmetry axes
+    pub fn with_symmetry(&self, pixels: Vec<(isize, isize, egui::Color32)>) -> Vec<(isize, isize, egui::Color32)> {
+        if !self.symmetry_mode {
+            return pixels;
+        }
+        let layer = self.get_active_layer();
+        let (width, height) = (layer.width() as isize, layer.height() as isize);
+        let (horizontal, vertical) = self.symmetry_axis;
+        let mut mirrored = pixels.clone();
+        for &(x, y, color) in &pixels {
+            if horizontal {
+                mirrored.push((width - 1 - x, y, color));
+            }
+            if vertical {
+                mirrored.push((x, height - 1 - y, color));
+            }
+            if horizontal && vertical {
+                mirrored.push((width - 1 - x, height - 1 - y, color));
+            }
+        }
+        mirrored
+    }
+
+    /// Write pixels to the current layer, skipping any off the canvas or outside the selection
+    pub fn put_pixels(&mut self, pixels: &[(isize, isize, egui::Color32)]) {
+        let selection = self.selection.as_ref();
+        let layer = &mut self.frames[self.current_frame].layers[self.current_layer];
+        let (width, height) = (layer.width() as isize, layer.height() as isize);
+        for &(x, y, color) in pixels {
+            let selected = selection.is_none_or(|selection| selection.contains(x, y));
+            if (0..width).contains(&x) && (0..height).contains(&y) && selected {
+                layer.set_pixel(x as usize, y as usize, color);
+            }
+        }
+    }
+
+    /// Show pixels in `preview_overlay` as `put_pixels` would write them, without
+    /// touching the layer. The overlay only covers the pixels' bounds, and is left alone
+    /// when the pixels are the same as last time.
+    pub fn preview_pixels(&mut self, pixels: &[(isize, isize, egui::Color32)]) {
+        if self.preview_overlay.is_some() && self.preview_source == pixels {
+            return;
+        }
+        let layer = self.get_active_layer();
+        if !layer.is_editable() {
+            self.preview_overlay = None;
+            return;
+        }
+        let (width, height) = (layer.width() as isize, layer.height() as isize);
+        let shown: Vec<_> = pixels.iter()
+            .filter(|&&(x, y, _)| (0..width).contains(&x) && (0..height).contains(&y) && self.is_selected(x, y))
+            .collect();
+        let bounds = shown.iter()
+            .map(|&&(x, y, _)| DirtyRect::pixel(x as usize, y as usize))
+            .reduce(DirtyRect::union);
+        let mut preview = PixelBuffer::new(bounds.map_or(0, |b| b.width()), bounds.map_or(0, |b| b.height()));
+        if let Some(bounds) = bounds {
+            for &&(x, y, color) in &shown {
+                preview.set(x as usize - bounds.min_x, y as usize - bounds.min_y, color);
+            }
+        }
+        self.preview_overlay = Some(preview);
+        self.preview_bounds = bounds;
+        self.preview_source = pixels.to_vec();
+        self.preview_changed = true;
+    }
+
+    /// Start a freehand stroke at a canvas position, which may be off the canvas
+    pub fn begin_stroke(&mut self, x: isize, y: isize, color: egui::Color32) {
+        self.start_stroke(x, y, color, None);
+    }
+
+    /// Start a freehand stroke that shades pixels one step lighter or darker along the
+    /// shading ramp instead of painting a colour
+    pub fn begin_shading_stroke(&mut self, x: isize, y: isize, lighter: bool) {
+        self.start_stroke(x, y, self.selected_color, Some(lighter));
+    }
+
+    fn start_stroke(&mut self, x: isize, y: isize, color: egui::Color32, shade: Option<bool>) {
+        self.push_undo();
+        let stroke = Stroke { color, path: vec![(x, y)], before: self.get_active_layer().clone(), shade };
+        self.put_pixels(&self.stroke_dab(&stroke, (x, y)));
+        self.stroke = Some(stroke);
+    }
+
+    /// Pixels a dab of the stroke paints. Shading works from the layer as it was when
+    /// the stroke began, so going over the same pixels again doesn't shade them twice,
+    /// and leaves pixels whose colour isn't on the ramp alone.
+    fn stroke_dab(&self, stroke: &Stroke, (x, y): (isize, isize)) -> Vec<(isize, isize, egui::Color32)> {
+        let pixels = self.dab_pixels(x, y, stroke.color);
+        let Some(lighter) = stroke.shade else {
+            return pixels;
+        };
+        let ramp = self.shading_ramp();
+        let (width, height) = (stroke.before.width() as isize, stroke.before.height() as isize);
+        pixels.into_iter()
+            .filter(|&(x, y, _)| (0..width).contains(&x) && (0..height).contains(&y))
+            .filter_map(|(x, y, _)| {
+                shade_along(&ramp, stroke.before.get_pixel(x as usize, y as usize), lighter).map(|color| (x, y, color))
+            })
+            .collect()
+    }
+
+    /// Carry the stroke on to a new pointer position, filling in the pixels between
+    pub fn continue_stroke(&mut self, x: isize, y: isize) {
+        let Some(last) = self.stroke.as_ref().and_then(|stroke| stroke.path.last().copied()) else {
+            return;
+        };
+        for point in line_points(last, (x, y)).into_iter().skip(1) {
+            self.add_stroke_point(point);
+        }
+    }
+
+    pub fn end_stroke(&mut self) {
+        self.stroke = None;
+    }
+
+    fn add_stroke_point(&mut self, point: (isize, isize)) {
+        let Some(stroke) = &mut self.stroke else {
+            return;
+        };
+        let corner = match stroke.path[..] {
+            [.., a, b] if self.pixel_perfect_mode && is_corner(a, b, point) => stroke.path.pop(),
+            _ => None,
+        };
+        stroke.path.push(point);
+        if let Some(corner) = corner {
+            self.remove_dab(corner);
+        }
+        let dab = self.stroke.as_ref().map(|stroke| self.stroke_dab(stroke, point)).unwrap_or_default();
+        self.put_pixels(&dab);
+    }
+
+    /// Take a dab back out of the stroke: put back what was under it before the stroke
+    /// and redraw the parts other dabs of the stroke cover
+    fn remove_dab(&mut self, (x, y): (isize, isize)) {
+        let Some(stroke) = self.stroke.take() else {
+            return;
+        };
+        let (width, height) = (stroke.before.width() as isize, stroke.before.height() as isize);
+        let covered: HashSet<(isize, isize)> = self.dab_pixels(x, y, stroke.color).into_iter()
+            .map(|(x, y, _)| (x, y))
+            .filter(|&(x, y)| (0..width).contains(&x) && (0..height).contains(&y))
+            .collect();
+        let restored: Vec<_> = covered.iter()
+            .map(|&(x, y)| (x, y, stroke.before.get_pixel(x as usize, y as usize)))
+            .collect();
+        self.put_pixels(&restored);
+        // Only dabs near the removed one, or near one of its mirror copies, can overlap it
+        let reach = 2 * self.brush_reach();
+        let centres = self.with_symmetry(vec![(x, y, stroke.color)]);
+        let near = |&&(px, py): &&(isize, isize)| centres.iter().any(|&(cx, cy, _)| (px - cx).abs() <= reach && (py - cy).abs() <= reach);
+        for &(px, py) in stroke.path.iter().filter(near) {
+            let overlap: Vec<_> = self.stroke_dab(&stroke, (px, py)).into_iter()
+                .filter(|&(x, y, _)| covered.contains(&(x, y)))
+                .collect();
+            self.put_pixels(&overlap);
+        }
+        self.stroke = Some(stroke);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::editor::core::PixelArtEditor;
+use crate::types::{Layer, TileRef, Tileset};
+use serde_json::json;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+// Tilemap export: Tiled maps (.tmx) with external tilesets (.tsx), or a plain JSON map of
+// tile numbers. Both write each tileset as a PNG next to the map, tiles in rows.
+
+// Tiled keeps the flips in the top bits of each tile id
+const TILED_FLIP_X: u32 = 0x8000_0000;
+const TILED_FLIP_Y: u32 = 0x4000_0000;
+const TILED_FLIP_DIAGONAL: u32 = 0x2000_0000;
+
+// Tiles per row in exported tileset images
+const TILESET_COLUMNS: usize = 16;
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Turn a name into something safe to put in a file name
+fn file_stem(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect()
+}
+
+/// File beside `filepath` named after it and a tileset, e.g. map_Tileset_1.tsx
+fn companion_path(filepath: &Path, tileset: &Tileset, extension: &str) -> PathBuf {
+    let stem = filepath.file_stem().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    filepath.with_file_name(format!("{}_{}.{}", stem, file_stem(&tileset.name), extension))
+}
+
+fn tileset_columns(tileset: &Tileset) -> usize {
+    tileset.tile_count().clamp(1, TILESET_COLUMNS)
+}
+
+/// Tiled's number for a placed tile; 0 is an empty cell
+fn tiled_gid(cell: Option<TileRef>, first_gid: u32) -> u32 {
+    let Some(tile) = cell else {
+        return 0;
+    };
+    let mut gid = first_gid + tile.tile as u32;
+    if tile.flip_x {
+        gid |= TILED_FLIP_X;
+    }
+    if tile.flip_y {
+        gid |= TILED_FLIP_Y;
+    }
+    if tile.flip_diagonal {
+        gid |= TILED_FLIP_DIAGONAL;
+    }
+    gid
+}
+
+impl PixelArtEditor {
+    /// Tilemap layers of the current frame, bottom first
+    fn export_tilemap_layers(&self) -> Result<Vec<&Layer>, Box<dyn std::error::Error>> {
+        let layers: Vec<&Layer> = self.frames[self.current_frame].layers.iter()
+            .filter(|layer| layer.tilemap.as_ref().is_some_and(|tilemap| tilemap.tileset < self.tilesets.len()))
+            .collect();
+        if layers.is_empty() {
+            return Err("The current frame has no tilemap layers".into());
+        }
+        Ok(layers)
+    }
+
+    /// Save a tileset's image and return its file name
+    fn save_tileset_image(&self, image_path: &Path, tileset: usize) -> Result<String, Box<dyn std::error::Error>> {
+        let tileset = &self.tilesets[tileset];
+        let sheet = tileset.sheet(tileset_columns(tileset));
+        sheet.into_rgba_image().save_with_format(image_path, image::ImageFormat::Png)?;
+        Ok(image_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default())
+    }
+
+    /// Save a tileset as a Tiled .tsx file with its image beside it
+    pub fn save_tileset_tsx(&self, filepath: &Path, tileset: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let image_name = self.save_tileset_image(&filepath.with_extension("png"), tileset)?;
+        let set = &self.tilesets[tileset];
+        let image = set.sheet(tileset_columns(set));
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        writeln!(
+            xml,
+            "<tileset version=\"1.10\" name=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" tilecount=\"{}\" columns=\"{}\">",
+            xml_escape(&set.name), set.tile_width, set.tile_height, set.tile_count(), tileset_columns(set),
+        )?;
+        writeln!(xml, " <image source=\"{}\" width=\"{}\" height=\"{}\"/>", xml_escape(&image_name), image.width(), image.height())?;
+        xml.push_str("</tileset>\n");
+        std::fs::write(filepath, xml)?;
+        Ok(())
+    }
+
+    /// Save the current frame's tilemap layers as a Tiled .tmx map. Each tileset goes in
+    /// its own .tsx file beside the map. The map uses the tile size of the bottom
+    /// tilemap layer; layers with other tile sizes are left out.
+    pub fn save_tilemap_tmx(&self, filepath: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let layers = self.export_tilemap_layers()?;
+        let first = &self.tilesets[layers[0].tilemap.as_ref().map_or(0, |tilemap| tilemap.tileset)];
+        let (tile_width, tile_height) = (first.tile_width, first.tile_height);
+        let layers: Vec<&Layer> = layers.into_iter().filter(|layer| {
+            let tileset = &self.tilesets[layer.tilemap.as_ref().map_or(0, |tilemap| tilemap.tileset)];
+            tileset.tile_width == tile_width && tileset.tile_height == tile_height
+        }).collect();
+        let canvas = &self.frames[self.current_frame].layers[0];
+        let (columns, rows) = (canvas.width().div_ceil(tile_width), canvas.height().div_ceil(tile_height));
+
+        // Tilesets used, each with the first tile id Tiled gives it
+        let mut used: Vec<(usize, u32)> = Vec::new();
+        let mut next_gid = 1;
+        for layer in &layers {
+            let tileset = layer.tilemap.as_ref().map_or(0, |tilemap| tilemap.tileset);
+            if !used.iter().any(|&(t, _)| t == tileset) {
+                used.push((tileset, next_gid));
+                next_gid += self.tilesets[tileset].tile_count().max(1) as u32;
+            }
+        }
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        writeln!(
+            xml,
+            "<map version=\"1.10\" orientation=\"orthogonal\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" infinite=\"0\" nextlayerid=\"{}\" nextobjectid=\"1\">",
+            columns, rows, tile_width, tile_height, layers.len() + 1,
+        )?;
+        for &(tileset, first_gid) in &used {
+            let tsx_path = companion_path(filepath, &self.tilesets[tileset], "tsx");
+            self.save_tileset_tsx(&tsx_path, tileset)?;
+            let tsx_name = tsx_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            writeln!(xml, " <tileset firstgid=\"{}\" source=\"{}\"/>", first_gid, xml_escape(&tsx_name))?;
+        }
+
+        for (id, layer) in layers.iter().enumerate() {
+            let Some(tilemap) = &layer.tilemap else {
+                continue;
+            };
+            let first_gid = used.iter().find(|&&(t, _)| t == tilemap.tileset).map_or(1, |&(_, gid)| gid);
+            write!(xml, " <layer id=\"{}\" name=\"{}\" width=\"{}\" height=\"{}\"", id + 1, xml_escape(&layer.name), columns, rows)?;
+            if !layer.visible {
+                xml.push_str(" visible=\"0\"");
+            }
+            if layer.opacity < 1.0 {
+                write!(xml, " opacity=\"{}\"", layer.opacity)?;
+            }
+            xml.push_str(">\n  <data encoding=\"csv\">\n");
+            let lines: Vec<String> = (0..rows).map(|row| {
+                (0..columns).map(|column| tiled_gid(tilemap.get(column, row), first_gid).to_string()).collect::<Vec<_>>().join(",")
+            }).collect();
+            xml.push_str(&lines.join(",\n"));
+            xml.push_str("\n</data>\n </layer>\n");
+        }
+        xml.push_str("</map>\n");
+        std::fs::write(filepath, xml)?;
+        println!("Tilemap saved as: {:?}", filepath);
+        Ok(())
+    }
+
+    /// Save the current frame's tilemap layers as a JSON map of tile numbers (-1 for an
+    /// empty cell) with a parallel list of flip bits: 1 horizontal, 2 vertical, 4 diagonal
+    pub fn save_tilemap_json(&self, filepath: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let layers = self.export_tilemap_layers()?;
+        let mut tilesets: Vec<usize> = layers.iter().filter_map(|layer| layer.tilemap.as_ref().map(|tilemap| tilemap.tileset)).collect();
+        tilesets.sort_unstable();
+        tilesets.dedup();
+
+        let tileset_values = tilesets.iter().map(|&index| {
+            let tileset = &self.tilesets[index];
+            Ok(json!({
+                "name": tileset.name,
+                "image": self.save_tileset_image(&companion_path(filepath, tileset, "png"), index)?,
+                "tilewidth": tileset.tile_width,
+                "tileheight": tileset.tile_height,
+                "tilecount": tileset.tile_count(),
+                "columns": tileset_columns(tileset),
+            }))
+        }).collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+        let layer_values: Vec<serde_json::Value> = layers.iter().filter_map(|layer| {
+            let tilemap = layer.tilemap.as_ref()?;
+            let data: Vec<i64> = tilemap.cells.iter().map(|cell| cell.map_or(-1, |tile| tile.tile as i64)).collect();
+            let flips: Vec<u8> = tilemap.cells.iter().map(|cell| cell.map_or(0, |tile| {
+                tile.flip_x as u8 | (tile.flip_y as u8) << 1 | (tile.flip_diagonal as u8) << 2
+            })).collect();
+            Some(json!({
+                "name": layer.name,
+                "visible": layer.visible,
+                "opacity": layer.opacity,
+                "tileset": tilesets.iter().position(|&t| t == tilemap.tileset),
+                "width": tilemap.columns,
+                "height": tilemap.rows,
+                "data": data,
+                "flips": flips,
+            }))
+        }).collect();
+
+        let document = json!({ "tilesets": tileset_values, "layers": layer_values });
+        std::fs::write(filepath, serde_json::to_string_pretty(&document)?)?;
+        println!("Tilemap saved as: {:?}", filepath);
+        Ok(())
+    }
+}
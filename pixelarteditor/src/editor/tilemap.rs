@@ -0,0 +1,390 @@
+use crate::editor::core::PixelArtEditor;
+use crate::pixel_buffer::PixelBuffer;
+use crate::tiled_buffer::TiledBuffer;
+use crate::types::{DirtyRect, Layer, LayerKind, TileRef, Tilemap, Tileset};
+use std::collections::{HashMap, HashSet};
+
+// Tilemap layers. A tilemap layer keeps the pixels of its placed tiles in its grid like
+// any other layer, so every tool can draw on it. Before each redraw, cells that were drawn
+// on are written back to their tile and every other placement of that tile is redrawn.
+
+/// The flips a tile can be placed with; turning needs square tiles
+fn placements(tileset: &Tileset) -> Vec<TileRef> {
+    let square = tileset.tile_width == tileset.tile_height;
+    let mut all = Vec::new();
+    for flip_diagonal in [false, true] {
+        if flip_diagonal && !square {
+            continue;
+        }
+        for flip_x in [false, true] {
+            for flip_y in [false, true] {
+                all.push(TileRef { tile: 0, flip_x, flip_y, flip_diagonal });
+            }
+        }
+    }
+    all
+}
+
+/// The tile image that shows as `pixels` when placed as `tile`
+fn unplaced_tile(pixels: &PixelBuffer, tile: TileRef, width: usize, height: usize) -> PixelBuffer {
+    let mut out = PixelBuffer::new(width, height);
+    for (x, y, color) in pixels.pixels() {
+        let (sx, sy) = tile.source_pixel(x, y, width, height);
+        out.set(sx, sy, color);
+    }
+    out
+}
+
+/// Size of a cell's pixels as placed
+fn placed_size(tileset: &Tileset, tile: Option<TileRef>) -> (usize, usize) {
+    if tile.is_some_and(|tile| tile.flip_diagonal) {
+        (tileset.tile_height, tileset.tile_width)
+    } else {
+        (tileset.tile_width, tileset.tile_height)
+    }
+}
+
+/// Redraw one cell of a tilemap layer from its tileset
+fn render_cell(layer: &mut Layer, tileset: &Tileset, column: usize, row: usize) {
+    let Some(tilemap) = &layer.tilemap else {
+        return;
+    };
+    let pixels = match tilemap.get(column, row) {
+        Some(tile) if tile.tile < tileset.tile_count() => tileset.placed_tile(tile),
+        _ => PixelBuffer::new(tileset.tile_width, tileset.tile_height),
+    };
+    let (x, y) = (column * tileset.tile_width, row * tileset.tile_height);
+    layer.grid.paste(&pixels, x as isize, y as isize);
+    layer.mark_dirty(DirtyRect {
+        min_x: x,
+        min_y: y,
+        max_x: x + pixels.width() - 1,
+        max_y: y + pixels.height() - 1,
+    });
+}
+
+/// Redraw every cell of a tilemap layer
+pub fn render_tilemap(layer: &mut Layer, tileset: &Tileset) {
+    let Some(tilemap) = &layer.tilemap else {
+        return;
+    };
+    let (columns, rows) = (tilemap.columns, tilemap.rows);
+    layer.set_grid(TiledBuffer::new(layer.width(), layer.height()));
+    for row in 0..rows {
+        for column in 0..columns {
+            render_cell(layer, tileset, column, row);
+        }
+    }
+}
+
+impl PixelArtEditor {
+    /// Tileset used by the current layer, if it is a tilemap layer
+    pub fn active_tileset(&self) -> Option<usize> {
+        self.get_active_layer().tilemap.as_ref()
+            .map(|tilemap| tilemap.tileset)
+            .filter(|&index| index < self.tilesets.len())
+    }
+
+    /// Tileset with the given tile size, created if there is none yet
+    fn tileset_for_size(&mut self, tile_width: usize, tile_height: usize) -> usize {
+        if let Some(index) = self.tilesets.iter().position(|t| t.tile_width == tile_width && t.tile_height == tile_height) {
+            return index;
+        }
+        let name = format!("Tileset {}", self.tilesets.len() + 1);
+        self.tilesets.push(Tileset::new(name, tile_width, tile_height));
+        self.tilesets.len() - 1
+    }
+
+    /// Add an empty tilemap layer above the current layer, using `new_tile_size` tiles
+    pub fn add_tilemap_layer(&mut self) {
+        self.push_undo();
+        let (tile_width, tile_height) = self.new_tile_size;
+        let tileset = self.tileset_for_size(tile_width, tile_height);
+        let frame = &mut self.frames[self.current_frame];
+        let (width, height) = (frame.layers[0].width(), frame.layers[0].height());
+        let name = format!("Tilemap {}", frame.layers.iter().filter(|l| l.kind == LayerKind::Tilemap).count() + 1);
+        frame.layers.push(Layer::new_tilemap(name, width, height, tileset, tile_width, tile_height));
+        self.current_layer = frame.layers.len() - 1;
+        self.invalidate_cache();
+    }
+
+    /// Turn the current image layer into a tilemap layer in every frame. Repeated
+    /// tiles, flipped or turned copies included, share one tile.
+    pub fn convert_layer_to_tilemap(&mut self) {
+        let layer_index = self.current_layer;
+        let layer = self.get_active_layer();
+        if layer.kind != LayerKind::Image {
+            return;
+        }
+        self.push_undo();
+        let (tile_width, tile_height) = self.new_tile_size;
+        let tileset_index = self.tileset_for_size(tile_width, tile_height);
+        let tileset = &mut self.tilesets[tileset_index];
+        let flips = placements(tileset);
+
+        // Look up tiles by how they look in each placement
+        let mut known: HashMap<Vec<u8>, TileRef> = HashMap::new();
+        for tile in 0..tileset.tile_count() {
+            for flip in &flips {
+                let tile = TileRef { tile, ..*flip };
+                known.entry(tileset.placed_tile(tile).as_bytes().to_vec()).or_insert(tile);
+            }
+        }
+
+        for frame in &mut self.frames {
+            let Some(layer) = frame.layers.get_mut(layer_index).filter(|layer| layer.kind == LayerKind::Image) else {
+                continue;
+            };
+            let mut tilemap = Tilemap::new(tileset_index, layer.width().div_ceil(tile_width), layer.height().div_ceil(tile_height));
+            for row in 0..tilemap.rows {
+                for column in 0..tilemap.columns {
+                    let pixels = layer.grid.read_region((column * tile_width) as isize, (row * tile_height) as isize, tile_width, tile_height);
+                    if pixels.pixels().all(|(_, _, color)| color.a() == 0) {
+                        continue;
+                    }
+                    let tile = match known.get(pixels.as_bytes()) {
+                        Some(&tile) => tile,
+                        None => {
+                            let tile = tileset.push_tile(&pixels);
+                            for flip in &flips {
+                                let placed = TileRef { tile, ..*flip };
+                                known.entry(tileset.placed_tile(placed).as_bytes().to_vec()).or_insert(placed);
+                            }
+                            TileRef::new(tile)
+                        }
+                    };
+                    tilemap.set(column, row, Some(tile));
+                }
+            }
+            layer.kind = LayerKind::Tilemap;
+            layer.link = None;
+            layer.tilemap = Some(tilemap);
+            render_tilemap(layer, tileset);
+        }
+        self.invalidate_cache();
+    }
+
+    /// Cell of the current tilemap layer under a canvas pixel
+    pub fn tile_cell_at(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let tileset = &self.tilesets[self.active_tileset()?];
+        let (column, row) = (x / tileset.tile_width, y / tileset.tile_height);
+        let tilemap = self.get_active_layer().tilemap.as_ref()?;
+        (column < tilemap.columns && row < tilemap.rows).then_some((column, row))
+    }
+
+    /// Place a tile, or clear the cell with `None`, under a canvas pixel
+    pub fn set_tile_cell(&mut self, x: usize, y: usize, tile: Option<TileRef>) {
+        let (Some(tileset), Some((column, row))) = (self.active_tileset(), self.tile_cell_at(x, y)) else {
+            return;
+        };
+        let layer = &mut self.frames[self.current_frame].layers[self.current_layer];
+        if layer.lock_pixels {
+            return;
+        }
+        let Some(tilemap) = layer.tilemap.as_mut() else {
+            return;
+        };
+        if tilemap.get(column, row) == tile {
+            return;
+        }
+        tilemap.set(column, row, tile);
+        render_cell(layer, &self.tilesets[tileset], column, row);
+    }
+
+    /// Tile tool: stamp the selected tile under a canvas pixel
+    pub fn stamp_tile(&mut self, x: usize, y: usize) {
+        if let Some(tile) = self.selected_tile {
+            self.set_tile_cell(x, y, Some(tile));
+        }
+    }
+
+    /// Pick the tile placed under a canvas pixel, flips included
+    pub fn pick_tile(&mut self, x: usize, y: usize) {
+        let Some((column, row)) = self.tile_cell_at(x, y) else {
+            return;
+        };
+        if let Some(tile) = self.get_active_layer().tilemap.as_ref().and_then(|tilemap| tilemap.get(column, row)) {
+            self.selected_tile = Some(tile);
+        }
+    }
+
+    /// Add a blank tile to the current tileset and select it
+    pub fn add_blank_tile(&mut self) {
+        let Some(index) = self.active_tileset() else {
+            return;
+        };
+        self.push_undo();
+        let tileset = &mut self.tilesets[index];
+        let blank = PixelBuffer::new(tileset.tile_width, tileset.tile_height);
+        self.selected_tile = Some(TileRef::new(tileset.push_tile(&blank)));
+    }
+
+    /// Change tile numbers in every tilemap using a tileset; `map` returns the new
+    /// number, or `None` to clear the placement
+    fn remap_tiles(&mut self, tileset: usize, map: impl Fn(usize) -> Option<usize>) {
+        for layer in self.frames.iter_mut().flat_map(|frame| frame.layers.iter_mut()) {
+            let Some(tilemap) = layer.tilemap.as_mut().filter(|tilemap| tilemap.tileset == tileset) else {
+                continue;
+            };
+            let mut cleared = Vec::new();
+            for (i, cell) in tilemap.cells.iter_mut().enumerate() {
+                if let Some(tile) = cell {
+                    match map(tile.tile) {
+                        Some(new) => tile.tile = new,
+                        None => {
+                            *cell = None;
+                            cleared.push((i % tilemap.columns, i / tilemap.columns));
+                        }
+                    }
+                }
+            }
+            for (column, row) in cleared {
+                render_cell(layer, &self.tilesets[tileset], column, row);
+            }
+        }
+        if let Some(selected) = self.selected_tile {
+            self.selected_tile = map(selected.tile).map(|tile| TileRef { tile, ..selected });
+        }
+        for terrain in self.terrains.iter_mut().filter(|terrain| terrain.tileset == tileset) {
+            terrain.rules.retain_mut(|rule| match map(rule.tile.tile) {
+                Some(tile) => {
+                    rule.tile.tile = tile;
+                    true
+                }
+                None => false,
+            });
+        }
+    }
+
+    /// Move a tile to another position in the current tileset; placements keep their tile
+    pub fn move_tile(&mut self, from: usize, to: usize) {
+        let Some(index) = self.active_tileset() else {
+            return;
+        };
+        let count = self.tilesets[index].tile_count();
+        if from >= count || to >= count || from == to {
+            return;
+        }
+        self.push_undo();
+        let tileset = &mut self.tilesets[index];
+        let mut tiles: Vec<PixelBuffer> = (0..count).map(|i| tileset.tile(i)).collect();
+        let moved = tiles.remove(from);
+        tiles.insert(to, moved);
+        for (i, tile) in tiles.iter().enumerate() {
+            tileset.set_tile(i, tile);
+        }
+        self.remap_tiles(index, |tile| Some(match tile {
+            t if t == from => to,
+            t if from < to && t > from && t <= to => t - 1,
+            t if to < from && t >= to && t < from => t + 1,
+            t => t,
+        }));
+    }
+
+    /// Remove a tile from the current tileset, clearing every cell that placed it
+    pub fn remove_tile(&mut self, tile: usize) {
+        let Some(index) = self.active_tileset() else {
+            return;
+        };
+        let count = self.tilesets[index].tile_count();
+        if tile >= count {
+            return;
+        }
+        self.push_undo();
+        let tileset = &mut self.tilesets[index];
+        for i in tile..count - 1 {
+            let next = tileset.tile(i + 1);
+            tileset.set_tile(i, &next);
+        }
+        let kept = tileset.image.read_region(0, 0, tileset.tile_width, (count - 1) * tileset.tile_height);
+        tileset.image = TiledBuffer::from_pixel_buffer(&kept);
+        self.remap_tiles(index, |t| match t {
+            t if t == tile => None,
+            t if t > tile => Some(t - 1),
+            t => Some(t),
+        });
+    }
+
+    /// Keep tilemap cells in step with a canvas resize that moved pixels by the given offset
+    pub fn resize_tilemaps(&mut self, offset_x: usize, offset_y: usize) {
+        for layer in self.frames.iter_mut().flat_map(|frame| frame.layers.iter_mut()) {
+            let Some(tileset) = layer.tilemap.as_ref().and_then(|tilemap| self.tilesets.get(tilemap.tileset)) else {
+                continue;
+            };
+            let (columns, rows) = (layer.width().div_ceil(tileset.tile_width), layer.height().div_ceil(tileset.tile_height));
+            // Cells move by whole tiles, so the map stays on the tile grid
+            let (dx, dy) = ((offset_x / tileset.tile_width) as isize, (offset_y / tileset.tile_height) as isize);
+            if let Some(tilemap) = layer.tilemap.as_mut() {
+                tilemap.resize(columns, rows, dx, dy);
+            }
+            render_tilemap(layer, tileset);
+        }
+    }
+
+    /// Write drawing done on tilemap layers since the last redraw back to the tiles,
+    /// then redraw every placement of the tiles that changed
+    pub fn sync_tilemaps(&mut self) {
+        // Tiles as they were before this pass, so strokes over several placements of
+        // one tile all land in it
+        let mut originals: HashMap<(usize, usize), PixelBuffer> = HashMap::new();
+        let mut changed: HashSet<(usize, usize)> = HashSet::new();
+
+        for layer in self.frames.iter_mut().flat_map(|frame| frame.layers.iter_mut()) {
+            let (Some(rect), Some(tilemap)) = (layer.dirty, layer.tilemap.as_mut()) else {
+                continue;
+            };
+            let Some(tileset) = self.tilesets.get_mut(tilemap.tileset) else {
+                continue;
+            };
+            let (tile_width, tile_height) = (tileset.tile_width, tileset.tile_height);
+            let last_column = (rect.max_x / tile_width).min(tilemap.columns.saturating_sub(1));
+            let last_row = (rect.max_y / tile_height).min(tilemap.rows.saturating_sub(1));
+            for row in rect.min_y / tile_height..=last_row {
+                for column in rect.min_x / tile_width..=last_column {
+                    let cell = tilemap.get(column, row).filter(|tile| tile.tile < tileset.tile_count());
+                    let (width, height) = placed_size(tileset, cell);
+                    let pixels = layer.grid.read_region((column * tile_width) as isize, (row * tile_height) as isize, width, height);
+                    let Some(tile) = cell else {
+                        // Drawing on an empty cell makes a new tile
+                        if pixels.pixels().any(|(_, _, color)| color.a() > 0) {
+                            tilemap.set(column, row, Some(TileRef::new(tileset.push_tile(&pixels))));
+                        }
+                        continue;
+                    };
+
+                    let drawn = unplaced_tile(&pixels, tile, tile_width, tile_height);
+                    let original = originals.entry((tilemap.tileset, tile.tile)).or_insert_with(|| tileset.tile(tile.tile));
+                    let mut merged = tileset.tile(tile.tile);
+                    for (x, y, color) in drawn.pixels() {
+                        if original.get_pixel(x, y) != color {
+                            merged.set(x, y, color);
+                        }
+                    }
+                    if merged != tileset.tile(tile.tile) {
+                        tileset.set_tile(tile.tile, &merged);
+                        changed.insert((tilemap.tileset, tile.tile));
+                    }
+                }
+            }
+        }
+        if changed.is_empty() {
+            return;
+        }
+
+        for layer in self.frames.iter_mut().flat_map(|frame| frame.layers.iter_mut()) {
+            let Some(tilemap) = &layer.tilemap else {
+                continue;
+            };
+            let Some(tileset) = self.tilesets.get(tilemap.tileset) else {
+                continue;
+            };
+            let cells: Vec<(usize, usize)> = tilemap.cells.iter().enumerate()
+                .filter(|(_, cell)| cell.is_some_and(|tile| changed.contains(&(tilemap.tileset, tile.tile))))
+                .map(|(i, _)| (i % tilemap.columns, i / tilemap.columns))
+                .collect();
+            for (column, row) in cells {
+                render_cell(layer, tileset, column, row);
+            }
+        }
+    }
+}
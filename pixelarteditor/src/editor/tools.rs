@@ -16,10 +16,18 @@ impl PixelArtEditor {
             Tool::Line => "╱".to_owned(),
             Tool::Rectangle => "▭".to_owned(),
             Tool::Circle => "◯".to_owned(),
+            Tool::Ellipse => "⬭".to_owned(),
+            Tool::Polygon => "⬟".to_owned(),
+            Tool::Curve => "〰".to_owned(),
             Tool::Select => "⬚".to_owned(),
+            Tool::EllipseSelect => "⭕".to_owned(),
             Tool::Lasso => "➰".to_owned(),
+            Tool::MagicWand => "✨".to_owned(),
             Tool::Spray => "💨".to_owned(),
             Tool::Dither => "▒".to_owned(),
+            Tool::Gradient => "🌈".to_owned(),
+            Tool::Slice => "✂".to_owned(),
+            Tool::Tile => "▦".to_owned(),
         }
     }
 
@@ -33,89 +41,43 @@ impl PixelArtEditor {
             Tool::Line => "Line",
             Tool::Rectangle => "Rectangle",
             Tool::Circle => "Circle",
+            Tool::Ellipse => "Ellipse",
+            Tool::Polygon => "Polygon",
+            Tool::Curve => "Curve",
             Tool::Select => "Select",
+            Tool::EllipseSelect => "Ellipse Select",
             Tool::Lasso => "Lasso",
+            Tool::MagicWand => "Magic Wand",
             Tool::Spray => "Spray",
             Tool::Dither => "Dither",
+            Tool::Gradient => "Gradient",
+            Tool::Slice => "Slice",
+            Tool::Tile => "Tile",
         }
     }
 
-    /// Applies a simple 2x2 Bayer dither pattern at the given pixel location.
+    /// Paint the dither pattern over one brush dab at the given pixel location. The
+    /// pattern is lined up with the canvas, so dabs next to each other join seamlessly.
+    /// Pixels the pattern leaves out keep what they had, or take the secondary colour in
+    /// two-colour mode.
     pub fn apply_dither(&mut self, x: usize, y: usize, color: eframe::egui::Color32) {
-        let layer = self.get_active_layer_mut();
-        let width = layer.width();
-        let height = layer.height();
-
-        // 2x2 Bayer matrix
-        let bayer = [[0, 2], [3, 1]];
-        for dy in 0..2 {
-            for dx in 0..2 {
-                let px = x + dx;
-                let py = y + dy;
-                if px < width && py < height {
-                    // Apply color based on threshold
-                    if bayer[dy][dx] < 2 {
-                        layer.grid[py][px] = color;
-                    }
+        let map = self.dither_pattern.map(&self.custom_dither);
+        let pixels: Vec<_> = self.dab_pixels(x as isize, y as isize, color).into_iter()
+            .filter_map(|(px, py, _)| {
+                if self.dither_pattern.covers(px, py, self.dither_density, map.as_ref()) {
+                    Some((px, py, color))
+                } else {
+                    self.dither_two_color.then_some((px, py, self.secondary_color))
                 }
-            }
-        }
+            })
+            .collect();
+        self.put_pixels(&pixels);
     }
 
     /// Paint with symmetry if enabled
     pub fn paint_with_symmetry(&mut self, x: usize, y: usize, color: eframe::egui::Color32) {
-        let width = self.get_active_layer().width();
-        let height = self.get_active_layer().height();
-        let symmetry_mode = self.symmetry_mode;
-        let symmetry_axis = self.symmetry_axis;
-        
-        let layer = self.get_active_layer_mut();
-        
-        // Paint the main pixel
-        if x < width && y < height {
-            layer.grid[y][x] = color;
-        }
-        
-        // Paint symmetry pixels if enabled
-        if symmetry_mode {
-            if symmetry_axis.0 { // Horizontal symmetry
-                let sym_x = width - 1 - x;
-                if sym_x < width && y < height {
-                    layer.grid[y][sym_x] = color;
-                }
-            }
-            
-            if symmetry_axis.1 { // Vertical symmetry
-                let sym_y = height - 1 - y;
-                if x < width && sym_y < height {
-                    layer.grid[sym_y][x] = color;
-                }
-            }
-            
-            if symmetry_axis.0 && symmetry_axis.1 { // Both axes
-                let sym_x = width - 1 - x;
-                let sym_y = height - 1 - y;
-                if sym_x < width && sym_y < height {
-                    layer.grid[sym_y][sym_x] = color;
-                }
-            }
-        }
-    }
-
-    pub fn shift_layer_grid(grid: &Vec<Vec<eframe::egui::Color32>>, dx: isize, dy: isize) -> Vec<Vec<eframe::egui::Color32>> {
-        let height = grid.len();
-        let width = if height > 0 { grid[0].len() } else { 0 };
-        let mut new_grid = vec![vec![eframe::egui::Color32::TRANSPARENT; width]; height];
-        for y in 0..height {
-            for x in 0..width {
-                let nx = x as isize - dx;
-                let ny = y as isize - dy;
-                if nx >= 0 && nx < width as isize && ny >= 0 && ny < height as isize {
-                    new_grid[y][x] = grid[ny as usize][nx as usize];
-                }
-            }
-        }
-        new_grid
+        let pixels = self.with_symmetry(vec![(x as isize, y as isize, color)]);
+        self.put_pixels(&pixels);
     }
 }
 
@@ -147,9 +109,10 @@ impl PixelArtEditor {
             }
             Tool::Eyedropper => {
                 if let Some(layer) = self.frames.get(self.current_frame)
-                    .and_then(|frame| frame.layers.get(self.current_layer)) {
+                    .and_then(|frame| frame.layers.get(self.current_layer))
+                    .filter(|layer| layer.is_pickable()) {
                     if x < layer.width() && y < layer.height() {
-                        self.selected_color = layer.grid[y][x];
+                        self.selected_color = layer.get_pixel(x, y);
                     }
                 }
             }
@@ -202,7 +165,7 @@ impl PixelArtEditor {
             return;
         }
         
-        let target_color = layer.grid[start_y][start_x];
+        let target_color = layer.get_pixel(start_x, start_y);
         
         // Don't fill if colors are the same
         if target_color == new_color {
@@ -214,17 +177,18 @@ impl PixelArtEditor {
         let mut visited = vec![vec![false; width]; height];
         
         while let Some((x, y)) = stack.pop() {
-            if x >= width || y >= height || visited[y][x] {
+            // Unselected pixels wall the fill in like a different colour would
+            if x >= width || y >= height || visited[y][x] || !self.is_selected(x as isize, y as isize) {
                 continue;
             }
             
             let layer = self.get_active_layer_mut();
-            if layer.grid[y][x] != target_color {
+            if layer.get_pixel(x, y) != target_color {
                 continue;
             }
             
             visited[y][x] = true;
-            layer.grid[y][x] = new_color;
+            layer.set_pixel(x, y, new_color);
             
             // Add neighboring pixels to stack
             if x > 0 {
@@ -1,9 +1,63 @@
 use crate::editor::core::PixelArtEditor;
+use crate::types::{AnimationTag, ColorMode, Frame, Slice, Terrain, Tileset};
+use eframe::egui;
+
+/// Everything an undo step puts back
+#[derive(Clone)]
+pub struct UndoState {
+    pub frames: Vec<Frame>,
+    pub tags: Vec<AnimationTag>, // Tag ranges follow frames being added and removed
+    pub slices: Vec<Slice>,      // So do slice keys
+    pub tilesets: Vec<Tileset>,
+    pub terrains: Vec<Terrain>, // Their rules point at tiles by index
+    pub color_mode: ColorMode,
+    pub color_palette: Vec<egui::Color32>,
+    pub transparent_index: u8,
+    pub current_frame: usize,
+    pub current_layer: usize,
+}
 
 impl PixelArtEditor {
+    fn undo_state(&self) -> UndoState {
+        UndoState {
+            frames: self.frames.clone(),
+            tags: self.tags.clone(),
+            slices: self.slices.clone(),
+            tilesets: self.tilesets.clone(),
+            terrains: self.terrains.clone(),
+            color_mode: self.color_mode,
+            color_palette: self.color_palette.clone(),
+            transparent_index: self.transparent_index,
+            current_frame: self.current_frame,
+            current_layer: self.current_layer,
+        }
+    }
+
+    fn restore_state(&mut self, state: UndoState) {
+        self.frames = state.frames;
+        self.tags = state.tags;
+        self.playback_tag = self.playback_tag.filter(|&i| i < self.tags.len());
+        self.slices = state.slices;
+        self.selected_slice = self.selected_slice.filter(|&i| i < self.slices.len());
+        self.tilesets = state.tilesets;
+        self.terrains = state.terrains;
+        self.active_terrain = self.active_terrain.filter(|&i| i < self.terrains.len());
+        self.color_mode = state.color_mode;
+        self.color_palette = state.color_palette;
+        self.transparent_index = state.transparent_index;
+        self.current_frame = state.current_frame;
+        self.current_layer = state.current_layer;
+        self.palette_undo_entry = None;
+        self.drop_stale_selection();
+
+        // Invalidate cache
+        self.invalidate_cache();
+    }
+
     pub fn push_undo(&mut self) {
         // Store current state in undo stack
-        self.undo_stack.push((self.frames.clone(), self.current_frame, self.current_layer));
+        self.undo_stack.push(self.undo_state());
+        self.palette_undo_entry = None;
         
         // Clear redo stack when making a new change
         self.redo_stack.clear();
@@ -18,32 +72,24 @@ impl PixelArtEditor {
     }
 
     pub fn undo(&mut self) {
-        if let Some((frames, cf, cl)) = self.undo_stack.pop() {
+        // Undoing while pixels float takes back the lift or paste
+        if self.floating.is_some() {
+            self.cancel_floating();
+            return;
+        }
+        if let Some(state) = self.undo_stack.pop() {
             // Push current state to redo stack
-            self.redo_stack.push((self.frames.clone(), self.current_frame, self.current_layer));
-            
-            // Restore previous state
-            self.frames = frames;
-            self.current_frame = cf;
-            self.current_layer = cl;
-            
-            // Invalidate cache
-            self.invalidate_cache();
+            self.redo_stack.push(self.undo_state());
+            self.restore_state(state);
         }
     }
 
     pub fn redo(&mut self) {
-        if let Some((frames, cf, cl)) = self.redo_stack.pop() {
+        self.commit_floating();
+        if let Some(state) = self.redo_stack.pop() {
             // Push current state to undo stack
-            self.undo_stack.push((self.frames.clone(), self.current_frame, self.current_layer));
-            
-            // Restore redo state
-            self.frames = frames;
-            self.current_frame = cf;
-            self.current_layer = cl;
-            
-            // Invalidate cache
-            self.invalidate_cache();
+            self.undo_stack.push(self.undo_state());
+            self.restore_state(state);
         }
     }
 
@@ -0,0 +1,109 @@
+use eframe::egui::Color32;
+use crate::dither::ThresholdMap;
+
+/// How the gradient tool spreads colour from the start of the drag to its end
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GradientShape {
+    Linear,  // Bands across the drag direction
+    Radial,  // Rings around the start
+    Angular, // A sweep around the start, beginning in the drag direction
+}
+
+impl GradientShape {
+    pub const ALL: [GradientShape; 3] = [GradientShape::Linear, GradientShape::Radial, GradientShape::Angular];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GradientShape::Linear => "Linear",
+            GradientShape::Radial => "Radial",
+            GradientShape::Angular => "Angular",
+        }
+    }
+
+    /// Position of a point along the gradient, from 0 at the start to 1 at the end
+    pub fn position(&self, start: (f32, f32), end: (f32, f32), point: (f32, f32)) -> f32 {
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let (px, py) = (point.0 - start.0, point.1 - start.1);
+        let length = dx * dx + dy * dy;
+        if length == 0.0 {
+            return 0.0;
+        }
+        match self {
+            GradientShape::Linear => ((px * dx + py * dy) / length).clamp(0.0, 1.0),
+            GradientShape::Radial => ((px * px + py * py) / length).sqrt().min(1.0),
+            GradientShape::Angular => {
+                let turn = py.atan2(px) - dy.atan2(dx);
+                turn.rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU
+            }
+        }
+    }
+}
+
+/// How positions between two colour stops become colours
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GradientDither {
+    Smooth, // Blend the RGB values
+    Steps,  // Hard bands of the stop colours
+    Bayer2,
+    Bayer4,
+    Bayer8,
+    Custom, // The user's own threshold map
+}
+
+impl GradientDither {
+    pub const ALL: [GradientDither; 6] = [
+        GradientDither::Smooth,
+        GradientDither::Steps,
+        GradientDither::Bayer2,
+        GradientDither::Bayer4,
+        GradientDither::Bayer8,
+        GradientDither::Custom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GradientDither::Smooth => "Smooth RGB",
+            GradientDither::Steps => "Hard Steps",
+            GradientDither::Bayer2 => "Bayer 2×2",
+            GradientDither::Bayer4 => "Bayer 4×4",
+            GradientDither::Bayer8 => "Bayer 8×8",
+            GradientDither::Custom => "Custom Pattern",
+        }
+    }
+
+    /// Threshold map the dither uses; None for smooth blending and hard steps
+    pub fn map(&self, custom: &ThresholdMap) -> Option<ThresholdMap> {
+        match self {
+            GradientDither::Bayer2 => Some(ThresholdMap::bayer(2)),
+            GradientDither::Bayer4 => Some(ThresholdMap::bayer(4)),
+            GradientDither::Bayer8 => Some(ThresholdMap::bayer(8)),
+            GradientDither::Custom => Some(custom.clone()),
+            GradientDither::Smooth | GradientDither::Steps => None,
+        }
+    }
+}
+
+/// Colour at position `t` across evenly spaced stops. Without a threshold the two
+/// nearest stops are blended; with one, the pixel takes the nearer stop's colour when
+/// its share of the way across falls short of the threshold, and the farther one's
+/// otherwise, so only stop colours are ever used.
+pub fn gradient_color(stops: &[Color32], t: f32, threshold: Option<f32>) -> Color32 {
+    let Some(&first) = stops.first() else {
+        return Color32::TRANSPARENT;
+    };
+    if stops.len() == 1 {
+        return first;
+    }
+    let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let index = (position.floor() as usize).min(stops.len() - 2);
+    let share = position - index as f32;
+    let (a, b) = (stops[index], stops[index + 1]);
+    match threshold {
+        Some(threshold) => if share > threshold { b } else { a },
+        None => {
+            let (a, b) = (a.to_srgba_unmultiplied(), b.to_srgba_unmultiplied());
+            let mix = |i: usize| (a[i] as f32 + (b[i] as f32 - a[i] as f32) * share).round() as u8;
+            Color32::from_rgba_unmultiplied(mix(0), mix(1), mix(2), mix(3))
+        }
+    }
+}
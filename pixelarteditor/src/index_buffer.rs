@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::tiled_buffer::TILE_SIZE;
+
+/// Sparse palette indices of a layer in indexed colour mode.
+///
+/// Laid out in the same tiles as the layer's `TiledBuffer`; `None` marks a pixel
+/// that holds no colour at all. Tiles are shared between clones like layer tiles.
+#[derive(Clone, Default)]
+pub struct IndexBuffer {
+    tiles: HashMap<(i32, i32), Arc<Vec<Option<u8>>>>,
+}
+
+fn tile_coords(x: isize, y: isize) -> ((i32, i32), usize) {
+    let size = TILE_SIZE as isize;
+    let key = (x.div_euclid(size) as i32, y.div_euclid(size) as i32);
+    (key, y.rem_euclid(size) as usize * TILE_SIZE + x.rem_euclid(size) as usize)
+}
+
+impl IndexBuffer {
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+    }
+
+    pub fn get(&self, x: isize, y: isize) -> Option<u8> {
+        let (key, i) = tile_coords(x, y);
+        self.tiles.get(&key).and_then(|tile| tile[i])
+    }
+
+    /// Store the index of a pixel; returns true if it changed
+    pub fn set(&mut self, x: isize, y: isize, index: Option<u8>) -> bool {
+        let (key, i) = tile_coords(x, y);
+        if index.is_none() && !self.tiles.contains_key(&key) {
+            return false;
+        }
+        let tile = self.tiles.entry(key).or_insert_with(|| Arc::new(vec![None; TILE_SIZE * TILE_SIZE]));
+        if tile[i] == index {
+            return false;
+        }
+        Arc::make_mut(tile)[i] = index;
+        true
+    }
+
+    /// Tiles holding any indices, keyed by tile coordinates
+    pub fn tiles(&self) -> impl Iterator<Item = ((i32, i32), &[Option<u8>])> {
+        self.tiles.iter().map(|(&key, tile)| (key, tile.as_slice()))
+    }
+
+    /// Put a whole tile of `TILE_SIZE * TILE_SIZE` indices in place
+    pub fn insert_tile(&mut self, key: (i32, i32), indices: Vec<Option<u8>>) {
+        debug_assert_eq!(indices.len(), TILE_SIZE * TILE_SIZE);
+        self.tiles.insert(key, Arc::new(indices));
+    }
+
+    /// Call `f` for every pixel that holds an index
+    pub fn for_each_index(&self, mut f: impl FnMut(isize, isize, u8)) {
+        for (&(tx, ty), tile) in &self.tiles {
+            let (ox, oy) = (tx as isize * TILE_SIZE as isize, ty as isize * TILE_SIZE as isize);
+            for (i, index) in tile.iter().enumerate() {
+                if let Some(index) = index {
+                    f(ox + (i % TILE_SIZE) as isize, oy + (i / TILE_SIZE) as isize, *index);
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,29 @@
 // Re-export main types and editor for public use
 pub use editor::PixelArtEditor;
-pub use types::{Tool, Layer, Frame};
+pub use types::{Tool, Layer, LayerKind, Frame, DirtyRect, BlendMode, ColorMode, AnimationTag, AnimationDirection, Slice, SliceKey, SliceRect, Tileset, Tilemap, TileRef, ReferenceImage, Terrain, TerrainRule, AutotileKind};
+pub use pixel_buffer::PixelBuffer;
+pub use tiled_buffer::TiledBuffer;
+pub use selection_mask::{SelectionMask, SelectionMode};
+pub use brush::{BrushShape, CustomBrush, Ink};
+pub use dither::{DitherPattern, ThresholdMap};
+pub use gradient::{GradientShape, GradientDither};
+pub use shapes::CurveKind;
 pub use constants::*;
 
 // Module declarations
 mod constants;
 mod types;
+mod pixel_buffer;
+mod tiled_buffer;
+mod selection_mask;
+mod transform;
+mod brush;
+mod dither;
+mod gradient;
+mod shapes;
+mod index_buffer;
+mod palette;
+mod compositor;
 mod editor;
 mod app;
 mod ui;
@@ -0,0 +1,82 @@
+use eframe::egui::Color32;
+
+// Colour helpers for the indexed and grayscale document modes.
+
+/// Colour a palette index is shown as; the transparent index shows nothing
+pub fn index_color(palette: &[Color32], index: u8, transparent: u8) -> Color32 {
+    if index == transparent {
+        return Color32::TRANSPARENT;
+    }
+    palette.get(index as usize).copied().unwrap_or(Color32::TRANSPARENT)
+}
+
+/// Palette entry closest to `color`, never the transparent index
+pub fn nearest_index(palette: &[Color32], color: Color32, transparent: u8) -> Option<u8> {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    palette.iter()
+        .enumerate()
+        .take(256)
+        .filter(|(i, _)| *i != transparent as usize)
+        .min_by_key(|(_, entry)| {
+            let [er, eg, eb, ea] = entry.to_srgba_unmultiplied();
+            let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2);
+            // Weighted towards green, which the eye is most sensitive to
+            2 * d(r, er) + 4 * d(g, eg) + 3 * d(b, eb) + 4 * d(a, ea)
+        })
+        .map(|(i, _)| i as u8)
+}
+
+/// Grey with the same perceived brightness, keeping alpha
+pub fn to_grayscale(color: Color32) -> Color32 {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let luma = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32).round() as u8;
+    Color32::from_rgba_unmultiplied(luma, luma, luma, a)
+}
+
+/// The colour one step lighter or darker than `color` along a ramp of colours, which
+/// may run either way. Colours not on the ramp have no shade; the ends stay put.
+pub fn shade_along(ramp: &[Color32], color: Color32, lighter: bool) -> Option<Color32> {
+    let index = ramp.iter().position(|&c| c == color)?;
+    let luma = |c: Color32| to_grayscale(c).r();
+    let (first, last) = (ramp[0], ramp[ramp.len() - 1]);
+    // Step towards whichever end is lighter, or away from it
+    let forward = (luma(last) >= luma(first)) == lighter;
+    let index = if forward { (index + 1).min(ramp.len() - 1) } else { index.saturating_sub(1) };
+    Some(ramp[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_index_picks_the_closest_entry() {
+        let palette = [Color32::BLACK, Color32::from_rgb(250, 0, 0), Color32::from_rgb(0, 0, 250), Color32::WHITE];
+        assert_eq!(nearest_index(&palette, Color32::from_rgb(200, 20, 10), 255), Some(1));
+        assert_eq!(nearest_index(&palette, Color32::from_rgb(10, 10, 10), 255), Some(0));
+        assert_eq!(nearest_index(&palette, Color32::WHITE, 255), Some(3));
+    }
+
+    #[test]
+    fn nearest_index_never_picks_the_transparent_entry() {
+        let palette = [Color32::BLACK, Color32::from_rgb(100, 100, 100)];
+        assert_eq!(nearest_index(&palette, Color32::BLACK, 0), Some(1));
+        assert_eq!(nearest_index(&[Color32::BLACK], Color32::BLACK, 0), None);
+        assert_eq!(nearest_index(&[], Color32::BLACK, 0), None);
+    }
+
+    #[test]
+    fn nearest_index_weighs_green_over_red() {
+        // A small miss in green counts for more than a larger one in red
+        let palette = [Color32::from_rgb(120, 100, 100), Color32::from_rgb(100, 115, 100)];
+        assert_eq!(nearest_index(&palette, Color32::from_rgb(100, 100, 100), 255), Some(0));
+    }
+
+    #[test]
+    fn index_colors_hide_the_transparent_index() {
+        let palette = [Color32::RED, Color32::GREEN];
+        assert_eq!(index_color(&palette, 1, 0), Color32::GREEN);
+        assert_eq!(index_color(&palette, 0, 0), Color32::TRANSPARENT);
+        assert_eq!(index_color(&palette, 5, 0), Color32::TRANSPARENT);
+    }
+}
@@ -0,0 +1,296 @@
+use eframe::egui::Color32;
+use crate::types::DirtyRect;
+
+/// Bytes per pixel (straight RGBA8)
+pub const BYTES_PER_PIXEL: usize = 4;
+
+/// A contiguous, row-major RGBA8 pixel buffer.
+///
+/// Pixels are stored with straight (unmultiplied) alpha, the same layout `image::RgbaImage`
+/// uses, so a buffer can be handed to the `image` crate without copying.
+#[derive(Clone, PartialEq, Default)]
+pub struct PixelBuffer {
+    width: usize,
+    height: usize,
+    stride: usize, // Bytes per row
+    data: Vec<u8>,
+}
+
+impl PixelBuffer {
+    /// Create a fully transparent buffer
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            stride: width * BYTES_PER_PIXEL,
+            data: vec![0; width * height * BYTES_PER_PIXEL],
+        }
+    }
+
+    /// Create a buffer filled with one colour
+    pub fn filled(width: usize, height: usize, color: Color32) -> Self {
+        let mut buffer = Self::new(width, height);
+        buffer.fill(color);
+        buffer
+    }
+
+    /// Wrap raw RGBA8 bytes; returns None if the length doesn't match the size
+    pub fn from_raw(width: usize, height: usize, data: Vec<u8>) -> Option<Self> {
+        if data.len() != width * height * BYTES_PER_PIXEL {
+            return None;
+        }
+        Some(Self { width, height, stride: width * BYTES_PER_PIXEL, data })
+    }
+
+    /// Take ownership of an image's pixel storage without copying
+    pub fn from_rgba_image(image: image::RgbaImage) -> Self {
+        let (width, height) = image.dimensions();
+        let (width, height) = (width as usize, height as usize);
+        Self { width, height, stride: width * BYTES_PER_PIXEL, data: image.into_raw() }
+    }
+
+    /// Hand the pixel storage to the `image` crate without copying
+    pub fn into_rgba_image(self) -> image::RgbaImage {
+        image::RgbaImage::from_raw(self.width as u32, self.height as u32, self.data)
+            .expect("pixel buffer size always matches its dimensions")
+    }
+
+    /// Borrow the buffer as an `image` view, e.g. for encoding
+    pub fn as_image(&self) -> image::ImageBuffer<image::Rgba<u8>, &[u8]> {
+        image::ImageBuffer::from_raw(self.width as u32, self.height as u32, self.data.as_slice())
+            .expect("pixel buffer size always matches its dimensions")
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Bytes between the starts of two consecutive rows
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    fn offset(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y * self.stride + x * BYTES_PER_PIXEL)
+    }
+
+    /// Read a pixel, or None when out of bounds
+    pub fn get(&self, x: usize, y: usize) -> Option<Color32> {
+        let i = self.offset(x, y)?;
+        let p = &self.data[i..i + BYTES_PER_PIXEL];
+        Some(Color32::from_rgba_unmultiplied(p[0], p[1], p[2], p[3]))
+    }
+
+    /// Read a pixel; out-of-bounds reads are transparent
+    pub fn get_pixel(&self, x: usize, y: usize) -> Color32 {
+        self.get(x, y).unwrap_or(Color32::TRANSPARENT)
+    }
+
+    /// Read a pixel with signed coordinates, handy for neighbourhood filters
+    pub fn get_signed(&self, x: isize, y: isize) -> Option<Color32> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.get(x as usize, y as usize)
+    }
+
+    /// Write a pixel; returns true if the stored value changed
+    pub fn set(&mut self, x: usize, y: usize, color: Color32) -> bool {
+        let Some(i) = self.offset(x, y) else {
+            return false;
+        };
+        let rgba = color.to_srgba_unmultiplied();
+        let p = &mut self.data[i..i + BYTES_PER_PIXEL];
+        if *p == rgba {
+            return false;
+        }
+        p.copy_from_slice(&rgba);
+        true
+    }
+
+    pub fn fill(&mut self, color: Color32) {
+        let rgba = color.to_srgba_unmultiplied();
+        for pixel in self.data.chunks_exact_mut(BYTES_PER_PIXEL) {
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    /// Fill the pixels of a region with one colour
+    pub fn fill_rect(&mut self, rect: DirtyRect, color: Color32) {
+        let Some(rect) = rect.clamp_to(self.width, self.height) else {
+            return;
+        };
+        let rgba = color.to_srgba_unmultiplied();
+        for y in rect.min_y..=rect.max_y {
+            let row = &mut self.row_mut(y)[rect.min_x * BYTES_PER_PIXEL..(rect.max_x + 1) * BYTES_PER_PIXEL];
+            for pixel in row.chunks_exact_mut(BYTES_PER_PIXEL) {
+                pixel.copy_from_slice(&rgba);
+            }
+        }
+    }
+
+    /// Raw RGBA bytes of one row
+    pub fn row(&self, y: usize) -> &[u8] {
+        let start = y * self.stride;
+        &self.data[start..start + self.width * BYTES_PER_PIXEL]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        let start = y * self.stride;
+        let len = self.width * BYTES_PER_PIXEL;
+        &mut self.data[start..start + len]
+    }
+
+    /// Iterate over all rows as raw RGBA bytes
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks_exact(self.stride.max(1)).map(move |row| &row[..self.width * BYTES_PER_PIXEL])
+    }
+
+    /// Raw bytes of the rows of a region, each clipped to the region's columns.
+    /// Yields nothing if the region lies outside the buffer.
+    pub fn region(&self, rect: DirtyRect) -> impl Iterator<Item = (usize, &[u8])> {
+        let clamped = rect.clamp_to(self.width, self.height);
+        let (rows, start, end) = match clamped {
+            Some(r) => (r.min_y..r.max_y + 1, r.min_x * BYTES_PER_PIXEL, (r.max_x + 1) * BYTES_PER_PIXEL),
+            None => (0..0, 0, 0),
+        };
+        rows.map(move |y| (y, &self.row(y)[start..end]))
+    }
+
+    /// Copy a region out into its own buffer
+    pub fn crop(&self, rect: DirtyRect) -> PixelBuffer {
+        let Some(rect) = rect.clamp_to(self.width, self.height) else {
+            return PixelBuffer::new(0, 0);
+        };
+        let mut out = PixelBuffer::new(rect.width(), rect.height());
+        for (y, row) in self.region(rect) {
+            out.row_mut(y - rect.min_y).copy_from_slice(row);
+        }
+        out
+    }
+
+    /// Copy of this buffer moved by (dx, dy); pixels shifted off the edge are dropped
+    pub fn shifted(&self, dx: isize, dy: isize) -> PixelBuffer {
+        let mut out = PixelBuffer::new(self.width, self.height);
+        out.blit(self, dx, dy);
+        out
+    }
+
+    /// Copy of this buffer with a new size, anchored at the top-left corner
+    pub fn resized(&self, width: usize, height: usize) -> PixelBuffer {
+        let mut out = PixelBuffer::new(width, height);
+        out.blit(self, 0, 0);
+        out
+    }
+
+    /// Overwrite pixels with `src` placed at (dx, dy), clipping to both buffers
+    pub fn blit(&mut self, src: &PixelBuffer, dx: isize, dy: isize) {
+        let x0 = dx.max(0) as usize;
+        let x1 = (dx + src.width as isize).clamp(0, self.width as isize) as usize;
+        if x0 >= x1 {
+            return;
+        }
+        let src_x0 = (x0 as isize - dx) as usize;
+        let len = (x1 - x0) * BYTES_PER_PIXEL;
+        for sy in 0..src.height {
+            let y = sy as isize + dy;
+            if y < 0 || y >= self.height as isize {
+                continue;
+            }
+            let src_row = &src.row(sy)[src_x0 * BYTES_PER_PIXEL..src_x0 * BYTES_PER_PIXEL + len];
+            self.row_mut(y as usize)[x0 * BYTES_PER_PIXEL..x0 * BYTES_PER_PIXEL + len].copy_from_slice(src_row);
+        }
+    }
+
+    /// Split the rows of a region into disjoint mutable bands of up to `band_height` rows,
+    /// so each band can be processed on its own thread
+    pub fn row_bands_mut(&mut self, rect: DirtyRect, band_height: usize) -> Vec<RowBand<'_>> {
+        let Some(rect) = rect.clamp_to(self.width, self.height) else {
+            return Vec::new();
+        };
+        let (width, stride) = (self.width, self.stride);
+        let rows = &mut self.data[rect.min_y * stride..(rect.max_y + 1) * stride];
+        rows.chunks_mut(band_height.max(1) * stride)
+            .enumerate()
+            .map(|(i, data)| RowBand {
+                width,
+                stride,
+                first_row: rect.min_y + i * band_height.max(1),
+                data,
+            })
+            .collect()
+    }
+
+    /// Iterate over every pixel as (x, y, colour)
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, Color32)> + '_ {
+        let width = self.width.max(1);
+        self.data.chunks_exact(BYTES_PER_PIXEL).enumerate().map(move |(i, p)| {
+            (i % width, i / width, Color32::from_rgba_unmultiplied(p[0], p[1], p[2], p[3]))
+        })
+    }
+}
+
+/// A run of whole rows borrowed mutably from a `PixelBuffer`; coordinates are the buffer's own
+pub struct RowBand<'a> {
+    width: usize,
+    stride: usize,
+    first_row: usize,
+    data: &'a mut [u8],
+}
+
+impl RowBand<'_> {
+    /// Rows covered by this band, clipped to `rect`; None if they don't overlap
+    pub fn clip(&self, rect: DirtyRect) -> Option<DirtyRect> {
+        let last_row = self.first_row + self.data.len() / self.stride.max(1) - 1;
+        if rect.max_y < self.first_row || rect.min_y > last_row {
+            return None;
+        }
+        Some(DirtyRect {
+            min_y: rect.min_y.max(self.first_row),
+            max_y: rect.max_y.min(last_row),
+            ..rect
+        })
+    }
+
+    fn offset(&self, x: usize, y: usize) -> Option<usize> {
+        let row = y.checked_sub(self.first_row)?;
+        let i = row * self.stride + x * BYTES_PER_PIXEL;
+        (x < self.width && i + BYTES_PER_PIXEL <= self.data.len()).then_some(i)
+    }
+
+    /// Read a pixel; pixels outside the band are transparent
+    pub fn get_pixel(&self, x: usize, y: usize) -> Color32 {
+        self.offset(x, y).map_or(Color32::TRANSPARENT, |i| {
+            let p = &self.data[i..i + BYTES_PER_PIXEL];
+            Color32::from_rgba_unmultiplied(p[0], p[1], p[2], p[3])
+        })
+    }
+
+    /// Write a pixel; writes outside the band are ignored
+    pub fn set(&mut self, x: usize, y: usize, color: Color32) {
+        if let Some(i) = self.offset(x, y) {
+            self.data[i..i + BYTES_PER_PIXEL].copy_from_slice(&color.to_srgba_unmultiplied());
+        }
+    }
+}
+
+impl std::fmt::Debug for PixelBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PixelBuffer")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
@@ -2,6 +2,11 @@ use eframe::egui;
 use crate::plugins::{Plugin, PluginMetadata, PluginCategory, PluginCommand, PluginResult, PluginContext, PluginParameter};
 use std::collections::HashMap;
 use rand::Rng;
+use rayon::prelude::*;
+use crate::types::DirtyRect;
+
+// Rows handed to each worker thread by the filters
+const FILTER_BAND_ROWS: usize = 16;
 
 /// Blur filter plugin
 pub struct BlurPlugin {
@@ -24,47 +29,53 @@ impl BlurPlugin {
     }
     
     pub fn apply_blur(&self, layer: &mut crate::types::Layer, radius: f32) {
-        let width = layer.width();
-        let height = layer.height();
-        let mut new_grid = layer.grid.clone();
-        
-        for y in 0..height {
-            for x in 0..width {
-                let mut r_sum = 0.0;
-                let mut g_sum = 0.0;
-                let mut b_sum = 0.0;
-                let mut a_sum = 0.0;
-                let mut count = 0;
-                
-                let radius_i = radius as i32;
-                for dy in -radius_i..=radius_i {
-                    for dx in -radius_i..=radius_i {
-                        let nx = x as i32 + dx;
-                        let ny = y as i32 + dy;
-                        
-                        if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
-                            let pixel = layer.grid[ny as usize][nx as usize];
-                            r_sum += pixel.r() as f32;
-                            g_sum += pixel.g() as f32;
-                            b_sum += pixel.b() as f32;
-                            a_sum += pixel.a() as f32;
-                            count += 1;
+        let source = layer.to_pixel_buffer();
+        let width = source.width();
+        let height = source.height();
+        let mut result = source.clone();
+        let full = DirtyRect::full(width, height);
+        
+        // Bands only read from the untouched source, so they can run in parallel
+        result.row_bands_mut(full, FILTER_BAND_ROWS).into_par_iter().for_each(|mut band| {
+            let Some(rows) = band.clip(full) else { return };
+            for y in rows.min_y..=rows.max_y {
+                for x in 0..width {
+                    let mut r_sum = 0.0;
+                    let mut g_sum = 0.0;
+                    let mut b_sum = 0.0;
+                    let mut a_sum = 0.0;
+                    let mut count = 0;
+                    
+                    let radius_i = radius as i32;
+                    for dy in -radius_i..=radius_i {
+                        for dx in -radius_i..=radius_i {
+                            let nx = x as i32 + dx;
+                            let ny = y as i32 + dy;
+                            
+                            if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
+                                let pixel = source.get_pixel(nx as usize, ny as usize);
+                                r_sum += pixel.r() as f32;
+                                g_sum += pixel.g() as f32;
+                                b_sum += pixel.b() as f32;
+                                a_sum += pixel.a() as f32;
+                                count += 1;
+                            }
                         }
                     }
-                }
-                
-                if count > 0 {
-                    new_grid[y][x] = egui::Color32::from_rgba_unmultiplied(
-                        (r_sum / count as f32) as u8,
-                        (g_sum / count as f32) as u8,
-                        (b_sum / count as f32) as u8,
-                        (a_sum / count as f32) as u8,
-                    );
+                    
+                    if count > 0 {
+                        band.set(x, y, egui::Color32::from_rgba_unmultiplied(
+                            (r_sum / count as f32) as u8,
+                            (g_sum / count as f32) as u8,
+                            (b_sum / count as f32) as u8,
+                            (a_sum / count as f32) as u8,
+                        ));
+                    }
                 }
             }
-        }
+        });
         
-        layer.grid = new_grid;
+        layer.set_pixels(&result);
     }
 }
 
@@ -163,23 +174,28 @@ impl NoisePlugin {
     }
     
     pub fn apply_noise(&self, layer: &mut crate::types::Layer, intensity: f32) {
-        let width = layer.width();
-        let height = layer.height();
-        let mut rng = rand::thread_rng();
-        
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = layer.grid[y][x];
-                if pixel.a() > 0 {
-                    let noise = rng.gen_range(-intensity..=intensity);
-                    let r = (pixel.r() as f32 + noise).clamp(0.0, 255.0) as u8;
-                    let g = (pixel.g() as f32 + noise).clamp(0.0, 255.0) as u8;
-                    let b = (pixel.b() as f32 + noise).clamp(0.0, 255.0) as u8;
-                    
-                    layer.grid[y][x] = egui::Color32::from_rgba_unmultiplied(r, g, b, pixel.a());
+        let mut pixels = layer.to_pixel_buffer();
+        let full = DirtyRect::full(pixels.width(), pixels.height());
+        
+        pixels.row_bands_mut(full, FILTER_BAND_ROWS).into_par_iter().for_each(|mut band| {
+            let Some(rows) = band.clip(full) else { return };
+            let mut rng = rand::thread_rng();
+            for y in rows.min_y..=rows.max_y {
+                for x in rows.min_x..=rows.max_x {
+                    let pixel = band.get_pixel(x, y);
+                    if pixel.a() > 0 {
+                        let noise = rng.gen_range(-intensity..=intensity);
+                        let r = (pixel.r() as f32 + noise).clamp(0.0, 255.0) as u8;
+                        let g = (pixel.g() as f32 + noise).clamp(0.0, 255.0) as u8;
+                        let b = (pixel.b() as f32 + noise).clamp(0.0, 255.0) as u8;
+                        
+                        band.set(x, y, egui::Color32::from_rgba_unmultiplied(r, g, b, pixel.a()));
+                    }
                 }
             }
-        }
+        });
+        
+        layer.set_pixels(&pixels);
     }
 }
 
@@ -278,40 +294,51 @@ impl OutlinePlugin {
     }
     
     pub fn apply_outline(&self, layer: &mut crate::types::Layer, color: egui::Color32, thickness: usize) {
-        let width = layer.width();
-        let height = layer.height();
-        let mut new_grid = layer.grid.clone();
-        
-        for y in 0..height {
-            for x in 0..width {
-                if layer.grid[y][x].a() > 0 {
-                    // Check surrounding pixels
-                    for dy in -(thickness as i32)..=(thickness as i32) {
-                        for dx in -(thickness as i32)..=(thickness as i32) {
+        let source = layer.to_pixel_buffer();
+        let width = source.width();
+        let height = source.height();
+        let mut result = source.clone();
+        let full = DirtyRect::full(width, height);
+        let reach = thickness as i32;
+        
+        // Each transparent pixel checks whether a filled pixel lies within the outline
+        // distance, so every band writes only its own rows
+        result.row_bands_mut(full, FILTER_BAND_ROWS).into_par_iter().for_each(|mut band| {
+            let Some(rows) = band.clip(full) else { return };
+            for y in rows.min_y..=rows.max_y {
+                for x in 0..width {
+                    if source.get_pixel(x, y).a() > 0 {
+                        continue;
+                    }
+                    
+                    let mut near_filled = false;
+                    'search: for dy in -reach..=reach {
+                        for dx in -reach..=reach {
                             if dx == 0 && dy == 0 { continue; }
                             
                             let nx = x as i32 + dx;
                             let ny = y as i32 + dy;
                             
-                            if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32 {
-                                let nx = nx as usize;
-                                let ny = ny as usize;
-                                
-                                if layer.grid[ny][nx].a() == 0 {
-                                    // Distance check for circular outline
-                                    let dist = ((dx * dx + dy * dy) as f32).sqrt();
-                                    if dist <= thickness as f32 {
-                                        new_grid[ny][nx] = color;
-                                    }
+                            if nx >= 0 && nx < width as i32 && ny >= 0 && ny < height as i32
+                                && source.get_pixel(nx as usize, ny as usize).a() > 0 {
+                                // Distance check for circular outline
+                                let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                                if dist <= thickness as f32 {
+                                    near_filled = true;
+                                    break 'search;
                                 }
                             }
                         }
                     }
+                    
+                    if near_filled {
+                        band.set(x, y, color);
+                    }
                 }
             }
-        }
+        });
         
-        layer.grid = new_grid;
+        layer.set_pixels(&result);
     }
 }
 
@@ -426,11 +453,14 @@ impl PixelatePlugin {
     }
     
     pub fn apply_pixelate(&self, layer: &mut crate::types::Layer, block_size: usize) {
-        let width = layer.width();
-        let height = layer.height();
-        let mut new_grid = layer.grid.clone();
-        
-        for y in (0..height).step_by(block_size) {
+        let mut pixels = layer.to_pixel_buffer();
+        let width = pixels.width();
+        let full = DirtyRect::full(width, pixels.height());
+        let block_size = block_size.max(1);
+        
+        // One band per row of blocks
+        pixels.row_bands_mut(full, block_size).into_par_iter().for_each(|mut band| {
+            let Some(rows) = band.clip(full) else { return };
             for x in (0..width).step_by(block_size) {
                 // Calculate average color of the block
                 let mut r_sum = 0;
@@ -439,9 +469,9 @@ impl PixelatePlugin {
                 let mut a_sum = 0;
                 let mut count = 0;
                 
-                for by in y..std::cmp::min(y + block_size, height) {
+                for by in rows.min_y..=rows.max_y {
                     for bx in x..std::cmp::min(x + block_size, width) {
-                        let pixel = layer.grid[by][bx];
+                        let pixel = band.get_pixel(bx, by);
                         r_sum += pixel.r() as u32;
                         g_sum += pixel.g() as u32;
                         b_sum += pixel.b() as u32;
@@ -459,16 +489,16 @@ impl PixelatePlugin {
                     );
                     
                     // Apply average color to the entire block
-                    for by in y..std::cmp::min(y + block_size, height) {
+                    for by in rows.min_y..=rows.max_y {
                         for bx in x..std::cmp::min(x + block_size, width) {
-                            new_grid[by][bx] = avg_color;
+                            band.set(bx, by, avg_color);
                         }
                     }
                 }
             }
-        }
+        });
         
-        layer.grid = new_grid;
+        layer.set_pixels(&pixels);
     }
 }
 
@@ -567,25 +597,30 @@ impl ColorReplacementPlugin {
     }
     
     pub fn replace_color(&self, layer: &mut crate::types::Layer, from_color: egui::Color32, to_color: egui::Color32, tolerance: u8) {
-        let width = layer.width();
-        let height = layer.height();
-        
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = layer.grid[y][x];
-                
-                // Check if pixel matches the from_color within tolerance
-                let r_diff = (pixel.r() as i32 - from_color.r() as i32).abs();
-                let g_diff = (pixel.g() as i32 - from_color.g() as i32).abs();
-                let b_diff = (pixel.b() as i32 - from_color.b() as i32).abs();
-                let a_diff = (pixel.a() as i32 - from_color.a() as i32).abs();
-                
-                if r_diff <= tolerance as i32 && g_diff <= tolerance as i32 && 
-                   b_diff <= tolerance as i32 && a_diff <= tolerance as i32 {
-                    layer.grid[y][x] = to_color;
+        let mut pixels = layer.to_pixel_buffer();
+        let full = DirtyRect::full(pixels.width(), pixels.height());
+        
+        pixels.row_bands_mut(full, FILTER_BAND_ROWS).into_par_iter().for_each(|mut band| {
+            let Some(rows) = band.clip(full) else { return };
+            for y in rows.min_y..=rows.max_y {
+                for x in rows.min_x..=rows.max_x {
+                    let pixel = band.get_pixel(x, y);
+                    
+                    // Check if pixel matches the from_color within tolerance
+                    let r_diff = (pixel.r() as i32 - from_color.r() as i32).abs();
+                    let g_diff = (pixel.g() as i32 - from_color.g() as i32).abs();
+                    let b_diff = (pixel.b() as i32 - from_color.b() as i32).abs();
+                    let a_diff = (pixel.a() as i32 - from_color.a() as i32).abs();
+                    
+                    if r_diff <= tolerance as i32 && g_diff <= tolerance as i32 && 
+                       b_diff <= tolerance as i32 && a_diff <= tolerance as i32 {
+                        band.set(x, y, to_color);
+                    }
                 }
             }
-        }
+        });
+        
+        layer.set_pixels(&pixels);
     }
 }
 
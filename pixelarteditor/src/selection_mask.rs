@@ -0,0 +1,387 @@
+use eframe::egui::Color32;
+use crate::editor::stroke::line_points;
+use crate::pixel_buffer::PixelBuffer;
+use crate::types::DirtyRect;
+use std::sync::OnceLock;
+
+/// How a new selection shape combines with the current selection
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SelectionMode {
+    Replace,
+    Add,
+    Subtract,
+    Intersect,
+}
+
+impl SelectionMode {
+    pub const ALL: [SelectionMode; 4] = [SelectionMode::Replace, SelectionMode::Add, SelectionMode::Subtract, SelectionMode::Intersect];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SelectionMode::Replace => "Replace",
+            SelectionMode::Add => "Add",
+            SelectionMode::Subtract => "Subtract",
+            SelectionMode::Intersect => "Intersect",
+        }
+    }
+
+    /// Mode picked by held modifiers: Shift adds, Alt subtracts and both intersect
+    pub fn from_modifiers(shift: bool, alt: bool) -> Option<Self> {
+        match (shift, alt) {
+            (true, true) => Some(SelectionMode::Intersect),
+            (true, false) => Some(SelectionMode::Add),
+            (false, true) => Some(SelectionMode::Subtract),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Per-pixel selection over the canvas.
+///
+/// Pixels off the canvas are never selected. The edit operations treat the area past
+/// the canvas edge as selected when shrinking, so a full selection stays full.
+#[derive(Clone, Debug)]
+pub struct SelectionMask {
+    width: usize,
+    height: usize,
+    selected: Vec<bool>,
+    edges: OnceLock<Vec<[(usize, usize); 2]>>, // Worked out when first drawn, dropped on any change
+}
+
+impl PartialEq for SelectionMask {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width && self.height == other.height && self.selected == other.selected
+    }
+}
+
+impl Eq for SelectionMask {}
+
+impl SelectionMask {
+    /// Create a mask with nothing selected
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, selected: vec![false; width * height], edges: OnceLock::new() }
+    }
+
+    /// Create a mask with the whole canvas selected
+    pub fn all(width: usize, height: usize) -> Self {
+        Self { width, height, selected: vec![true; width * height], edges: OnceLock::new() }
+    }
+
+    /// Select the rectangle between two corner pixels, both included
+    pub fn rect(width: usize, height: usize, a: (isize, isize), b: (isize, isize)) -> Self {
+        let mut mask = Self::new(width, height);
+        for y in a.1.min(b.1)..=a.1.max(b.1) {
+            for x in a.0.min(b.0)..=a.0.max(b.0) {
+                mask.set(x, y, true);
+            }
+        }
+        mask
+    }
+
+    /// Select the ellipse that fills the rectangle between two corner pixels, so even
+    /// widths and heights come out symmetric
+    pub fn ellipse(width: usize, height: usize, a: (isize, isize), b: (isize, isize)) -> Self {
+        let (min_x, max_x) = (a.0.min(b.0), a.0.max(b.0));
+        let (min_y, max_y) = (a.1.min(b.1), a.1.max(b.1));
+        let (rx, ry) = ((max_x - min_x + 1) as f32 / 2.0, (max_y - min_y + 1) as f32 / 2.0);
+        let (cx, cy) = (min_x as f32 + rx, min_y as f32 + ry);
+        let mut mask = Self::new(width, height);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let dx = (x as f32 + 0.5 - cx) / rx;
+                let dy = (y as f32 + 0.5 - cy) / ry;
+                if dx * dx + dy * dy <= 1.0 {
+                    mask.set(x, y, true);
+                }
+            }
+        }
+        mask
+    }
+
+    /// Select the inside of a closed polygon through pixel centres, its outline included
+    pub fn polygon(width: usize, height: usize, points: &[(isize, isize)]) -> Self {
+        let mut mask = Self::new(width, height);
+        let Some(&first) = points.first() else {
+            return mask;
+        };
+        let edges: Vec<_> = points.iter().copied().zip(points.iter().copied().skip(1).chain([first])).collect();
+        let min_y = points.iter().map(|p| p.1).min().unwrap_or(0).max(0);
+        let max_y = points.iter().map(|p| p.1).max().unwrap_or(0).min(height as isize - 1);
+        for y in min_y..=max_y {
+            let mut crossings: Vec<f32> = edges.iter()
+                .filter(|(a, b)| (a.1 > y) != (b.1 > y))
+                .map(|(a, b)| a.0 as f32 + (y - a.1) as f32 * (b.0 - a.0) as f32 / (b.1 - a.1) as f32)
+                .collect();
+            crossings.sort_by(f32::total_cmp);
+            for span in crossings.chunks_exact(2) {
+                for x in span[0].ceil() as isize..=span[1].floor() as isize {
+                    mask.set(x, y, true);
+                }
+            }
+        }
+        for &(a, b) in &edges {
+            for (x, y) in line_points(a, b) {
+                mask.set(x, y, true);
+            }
+        }
+        mask
+    }
+
+    /// Select pixels whose colour is within `tolerance` of the pixel at (x, y) on every
+    /// channel, either only those connected to it or anywhere in the image
+    pub fn matching(pixels: &PixelBuffer, x: usize, y: usize, tolerance: u8, contiguous: bool) -> Self {
+        let (width, height) = (pixels.width(), pixels.height());
+        let mut mask = Self::new(width, height);
+        let Some(target) = pixels.get(x, y) else {
+            return mask;
+        };
+        let matches = |color: Color32| colors_match(color, target, tolerance);
+        if !contiguous {
+            for (px, py, color) in pixels.pixels() {
+                if matches(color) {
+                    mask.selected[py * width + px] = true;
+                }
+            }
+            return mask;
+        }
+        let mut stack = vec![(x, y)];
+        while let Some((x, y)) = stack.pop() {
+            if mask.selected[y * width + x] || !matches(pixels.get_pixel(x, y)) {
+                continue;
+            }
+            mask.selected[y * width + x] = true;
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if x + 1 < width {
+                stack.push((x + 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+            if y + 1 < height {
+                stack.push((x, y + 1));
+            }
+        }
+        mask
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether a pixel is selected; pixels off the canvas never are
+    pub fn contains(&self, x: isize, y: isize) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height
+            && self.selected[y as usize * self.width + x as usize]
+    }
+
+    /// Select or deselect a pixel; pixels off the canvas are ignored
+    pub fn set(&mut self, x: isize, y: isize, selected: bool) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.selected[y as usize * self.width + x as usize] = selected;
+            self.edges.take();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.selected.contains(&true)
+    }
+
+    /// Smallest rectangle holding every selected pixel
+    pub fn bounds(&self) -> Option<DirtyRect> {
+        let mut bounds: Option<DirtyRect> = None;
+        for (i, _) in self.selected.iter().enumerate().filter(|(_, s)| **s) {
+            let pixel = DirtyRect::pixel(i % self.width, i / self.width);
+            bounds = Some(bounds.map_or(pixel, |b| b.union(pixel)));
+        }
+        bounds
+    }
+
+    /// Merge another mask of the same size into this one
+    pub fn combine(&mut self, other: &SelectionMask, mode: SelectionMode) {
+        self.edges.take();
+        for (selected, &other) in self.selected.iter_mut().zip(&other.selected) {
+            *selected = match mode {
+                SelectionMode::Replace => other,
+                SelectionMode::Add => *selected || other,
+                SelectionMode::Subtract => *selected && !other,
+                SelectionMode::Intersect => *selected && other,
+            };
+        }
+    }
+
+    pub fn inverted(&self) -> Self {
+        Self { width: self.width, height: self.height, selected: self.selected.iter().map(|s| !s).collect(), edges: OnceLock::new() }
+    }
+
+    /// Select every pixel within `amount` pixels of the selection, diagonals included
+    pub fn grown(&self, amount: usize) -> Self {
+        let (width, height) = (self.width, self.height);
+        let mut rows = Self::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let from = x.saturating_sub(amount);
+                let to = (x + amount).min(width - 1);
+                rows.selected[y * width + x] = self.selected[y * width + from..=y * width + to].contains(&true);
+            }
+        }
+        let mut grown = Self::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let from = y.saturating_sub(amount);
+                let to = (y + amount).min(height - 1);
+                grown.selected[y * width + x] = (from..=to).any(|y| rows.selected[y * width + x]);
+            }
+        }
+        grown
+    }
+
+    /// Drop every pixel within `amount` pixels of an unselected one
+    pub fn shrunk(&self, amount: usize) -> Self {
+        self.inverted().grown(amount).inverted()
+    }
+
+    /// Select the band `amount` pixels wide just inside the selection's edge; here the
+    /// canvas edge counts as an edge too
+    pub fn border(&self, amount: usize) -> Self {
+        let mut inner = self.shrunk(amount);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let near_edge = x < amount || y < amount || x + amount >= self.width || y + amount >= self.height;
+                if near_edge {
+                    inner.selected[y * self.width + x] = false;
+                }
+            }
+        }
+        let mut border = self.clone();
+        border.combine(&inner, SelectionMode::Subtract);
+        border
+    }
+
+    /// Boundary between selected and unselected pixels, as horizontal and vertical runs
+    /// between pixel corners. They are only worked out again after the mask changes.
+    pub fn edges(&self) -> &[[(usize, usize); 2]] {
+        self.edges.get_or_init(|| self.find_edges())
+    }
+
+    fn find_edges(&self) -> Vec<[(usize, usize); 2]> {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let mut edges = Vec::new();
+        for y in 0..=height {
+            let mut start = None;
+            for x in 0..=width {
+                let edge = x < width && self.contains(x, y - 1) != self.contains(x, y);
+                match (edge, start) {
+                    (true, None) => start = Some(x),
+                    (false, Some(from)) => {
+                        edges.push([(from as usize, y as usize), (x as usize, y as usize)]);
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for x in 0..=width {
+            let mut start = None;
+            for y in 0..=height {
+                let edge = y < height && self.contains(x - 1, y) != self.contains(x, y);
+                match (edge, start) {
+                    (true, None) => start = Some(y),
+                    (false, Some(from)) => {
+                        edges.push([(x as usize, from as usize), (x as usize, y as usize)]);
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        edges
+    }
+}
+
+/// Whether two colours differ by at most `tolerance` on every channel; all fully
+/// transparent pixels match each other
+fn colors_match(a: Color32, b: Color32, tolerance: u8) -> bool {
+    if a.a() == 0 && b.a() == 0 {
+        return true;
+    }
+    let (a, b) = (a.to_srgba_unmultiplied(), b.to_srgba_unmultiplied());
+    a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) <= tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(mask: &SelectionMask) -> Vec<(isize, isize)> {
+        let mut pixels = Vec::new();
+        for y in 0..mask.height() as isize {
+            for x in 0..mask.width() as isize {
+                if mask.contains(x, y) {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn combine_modes_act_as_set_operations() {
+        let a = SelectionMask::rect(4, 1, (0, 0), (1, 0));
+        let b = SelectionMask::rect(4, 1, (1, 0), (2, 0));
+        let combined = |mode| {
+            let mut mask = a.clone();
+            mask.combine(&b, mode);
+            selected(&mask)
+        };
+        assert_eq!(combined(SelectionMode::Replace), vec![(1, 0), (2, 0)]);
+        assert_eq!(combined(SelectionMode::Add), vec![(0, 0), (1, 0), (2, 0)]);
+        assert_eq!(combined(SelectionMode::Subtract), vec![(0, 0)]);
+        assert_eq!(combined(SelectionMode::Intersect), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn modifiers_pick_the_mode() {
+        assert_eq!(SelectionMode::from_modifiers(false, false), None);
+        assert_eq!(SelectionMode::from_modifiers(true, false), Some(SelectionMode::Add));
+        assert_eq!(SelectionMode::from_modifiers(false, true), Some(SelectionMode::Subtract));
+        assert_eq!(SelectionMode::from_modifiers(true, true), Some(SelectionMode::Intersect));
+    }
+
+    #[test]
+    fn growing_reaches_diagonals_and_stops_at_the_canvas() {
+        let mut mask = SelectionMask::new(5, 5);
+        mask.set(2, 2, true);
+        assert_eq!(mask.grown(1), SelectionMask::rect(5, 5, (1, 1), (3, 3)));
+        assert_eq!(mask.grown(4), SelectionMask::all(5, 5));
+        assert_eq!(mask.grown(0), mask);
+    }
+
+    #[test]
+    fn shrinking_undoes_growing_away_from_the_canvas_edge() {
+        let mask = SelectionMask::rect(7, 7, (2, 2), (4, 4));
+        assert_eq!(mask.grown(1).shrunk(1), mask);
+        assert_eq!(selected(&mask.shrunk(1)), vec![(3, 3)]);
+        assert!(mask.shrunk(2).is_empty());
+    }
+
+    #[test]
+    fn a_full_selection_stays_full_when_shrunk() {
+        assert_eq!(SelectionMask::all(4, 3).shrunk(1), SelectionMask::all(4, 3));
+    }
+
+    #[test]
+    fn edges_are_dropped_when_the_mask_changes() {
+        let mut mask = SelectionMask::new(3, 3);
+        assert!(mask.edges().is_empty());
+        mask.set(1, 1, true);
+        assert_eq!(mask.edges().len(), 4);
+        mask.combine(&SelectionMask::new(3, 3), SelectionMode::Intersect);
+        assert!(mask.edges().is_empty());
+    }
+}
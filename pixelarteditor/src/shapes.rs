@@ -0,0 +1,309 @@
+use crate::editor::stroke::{is_corner, line_points};
+
+/// Kind of bezier the curve tool draws
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CurveKind {
+    Quadratic, // One control point
+    Cubic,     // Two control points
+}
+
+impl CurveKind {
+    pub const ALL: [CurveKind; 2] = [CurveKind::Quadratic, CurveKind::Cubic];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CurveKind::Quadratic => "Quadratic",
+            CurveKind::Cubic => "Cubic",
+        }
+    }
+
+    /// Points of a straight curve between two ends, control points evenly spaced between them
+    pub fn straight(&self, start: (isize, isize), end: (isize, isize)) -> Vec<(isize, isize)> {
+        let segments = match self {
+            CurveKind::Quadratic => 2,
+            CurveKind::Cubic => 3,
+        };
+        let along = |i: isize, a: isize, b: isize| a + ((b - a) as f32 * i as f32 / segments as f32).round() as isize;
+        (0..=segments).map(|i| (along(i, start.0, end.0), along(i, start.1, end.1))).collect()
+    }
+}
+
+/// Slopes Shift snaps lines to, as steps across and down: orthogonals, 1:1, 2:1 and 3:1
+pub const PIXEL_ART_SLOPES: [(isize, isize); 7] = [(1, 0), (0, 1), (1, 1), (2, 1), (1, 2), (3, 1), (1, 3)];
+
+/// Slopes isometric lines keep to: the 2:1 tile edges and the orthogonals
+pub const ISOMETRIC_SLOPES: [(isize, isize); 3] = [(1, 0), (0, 1), (2, 1)];
+
+/// Corners of the box dragged from `start` to `end`. Square boxes take the longer side,
+/// and centred ones grow from `start` the same distance each way.
+pub fn drag_box(start: (isize, isize), end: (isize, isize), square: bool, centered: bool) -> ((isize, isize), (isize, isize)) {
+    let (mut dx, mut dy) = (end.0 - start.0, end.1 - start.1);
+    if square {
+        let side = dx.abs().max(dy.abs());
+        dx = if dx < 0 { -side } else { side };
+        dy = if dy < 0 { -side } else { side };
+    }
+    if centered {
+        ((start.0 - dx, start.1 - dy), (start.0 + dx, start.1 + dy))
+    } else {
+        (start, (start.0 + dx, start.1 + dy))
+    }
+}
+
+/// Line from `start` towards `end` at whichever of the slopes is nearest the drag. Sloped
+/// lines are laid out in whole runs, e.g. pairs of pixels for 2:1, so every step matches.
+/// Centred lines carry on the same way back past `start`.
+pub fn snapped_line(start: (isize, isize), end: (isize, isize), slopes: &[(isize, isize)], centered: bool) -> Vec<(isize, isize)> {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let angle = (dy.abs() as f32).atan2(dx.abs() as f32);
+    let Some(&(across, down)) = slopes.iter()
+        .min_by(|a, b| {
+            let off = |&(across, down): &(isize, isize)| ((down as f32).atan2(across as f32) - angle).abs();
+            off(a).total_cmp(&off(b))
+        }) else {
+        return line_points(start, end);
+    };
+    let (sx, sy) = (if dx < 0 { -1 } else { 1 }, if dy < 0 { -1 } else { 1 });
+    // Walk the longer axis; the shorter one steps once per run
+    let (long, short, length) = if across >= down { (across, down, dx.abs()) } else { (down, across, dy.abs()) };
+    let run = long / short.max(1);
+    let count = if short == 0 {
+        length + 1
+    } else {
+        ((length + 1) as f32 / run as f32).round().max(1.0) as isize * run
+    };
+    let first = if centered { -count } else { 0 };
+    (first..count)
+        .map(|i| {
+            let step = if short == 0 { 0 } else { i.div_euclid(run) };
+            if across >= down { (start.0 + sx * i, start.1 + sy * step) } else { (start.0 + sx * step, start.1 + sy * i) }
+        })
+        .collect()
+}
+
+/// Drop repeated pixels and the corners of L-shaped steps from a path of touching pixels,
+/// leaving a line one pixel thin
+pub fn pixel_perfect(path: &[(isize, isize)]) -> Vec<(isize, isize)> {
+    let mut points: Vec<(isize, isize)> = Vec::with_capacity(path.len());
+    for &point in path {
+        if points.last() == Some(&point) {
+            continue;
+        }
+        if let [.., a, b] = points[..] && is_corner(a, b, point) {
+            points.pop();
+        }
+        points.push(point);
+    }
+    points
+}
+
+/// Pixels of straight lines through a list of points, joined back to the first when closed
+pub fn polyline(points: &[(isize, isize)], closed: bool) -> Vec<(isize, isize)> {
+    let mut path = Vec::new();
+    let ends = points.iter().chain(points.first().filter(|_| closed && points.len() > 2));
+    for (&from, &to) in points.iter().zip(ends.skip(1)) {
+        path.extend(line_points(from, to));
+    }
+    if path.is_empty() {
+        path.extend(points.first());
+    }
+    let mut path = pixel_perfect(&path);
+    // A closed outline comes back to where it began, which may leave a corner at the join
+    if closed && path.len() > 2 && path.first() == path.last() {
+        path.pop();
+        if let [second, .., last] = path[1..] && is_corner(last, path[0], second) {
+            path.remove(0);
+        }
+    }
+    path
+}
+
+/// Outline of the ellipse that fills the rectangle between two corner pixels. Even widths
+/// and heights get a flat middle of two pixels, so the outline stays symmetric.
+pub fn ellipse_outline(a: (isize, isize), b: (isize, isize)) -> Vec<(isize, isize)> {
+    // Bresenham's ellipse worked from the bounding box, after Alois Zingl
+    let (mut x0, mut x1) = (a.0.min(b.0), a.0.max(b.0));
+    let (mut y0, mut y1) = (a.1.min(b.1), a.1.max(b.1));
+    let (width, height) = ((x1 - x0) as i64, (y1 - y0) as i64);
+    let odd = height & 1;
+    let mut dx = 4 * (1 - width) * height * height;
+    let mut dy = 4 * (odd + 1) * width * width;
+    let mut err = dx + dy + odd * width * width;
+    y0 += (height as isize + 1) / 2;
+    y1 = y0 - odd as isize;
+    let (step_x, step_y) = (8 * height * height, 8 * width * width);
+    let mut points = Vec::new();
+    loop {
+        points.extend([(x1, y0), (x0, y0), (x0, y1), (x1, y1)]);
+        let e2 = 2 * err;
+        if e2 <= dy {
+            y0 += 1;
+            y1 -= 1;
+            dy += step_y;
+            err += dy;
+        }
+        if e2 >= dx || 2 * err > dy {
+            x0 += 1;
+            x1 -= 1;
+            dx += step_x;
+            err += dx;
+        }
+        if x0 > x1 {
+            break;
+        }
+    }
+    // Flat ellipses stop early; finish the tips at the left and right
+    while (y0 - y1) as i64 <= height {
+        points.extend([(x0 - 1, y0), (x1 + 1, y0), (x0 - 1, y1), (x1 + 1, y1)]);
+        y0 += 1;
+        y1 -= 1;
+    }
+    points.sort_unstable_by_key(|&(x, y)| (y, x));
+    points.dedup();
+    points
+}
+
+/// The ellipse between two corner pixels, filled to its outline
+pub fn ellipse_filled(a: (isize, isize), b: (isize, isize)) -> Vec<(isize, isize)> {
+    let outline = ellipse_outline(a, b);
+    let mut points = Vec::new();
+    // The outline is sorted by row, so each row's run starts and ends at its outer pixels
+    for row in outline.chunk_by(|p, q| p.1 == q.1) {
+        let (first, last) = (row[0], row[row.len() - 1]);
+        points.extend((first.0..=last.0).map(|x| (x, first.1)));
+    }
+    points
+}
+
+/// Pixels of the bezier curve through its end points, pulled towards the control points
+/// between them: three points make a quadratic curve and four a cubic one
+pub fn bezier_points(points: &[(isize, isize)]) -> Vec<(isize, isize)> {
+    if points.len() < 2 {
+        return points.to_vec();
+    }
+    // Sample often enough that neighbouring samples are never more than a pixel apart
+    let reach: isize = points.windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0).abs().max((pair[1].1 - pair[0].1).abs()))
+        .sum();
+    let samples = 2 * reach.max(1) as usize;
+    let mut path = Vec::with_capacity(samples + 1);
+    for i in 0..=samples {
+        let t = i as f32 / samples as f32;
+        // De Casteljau: blend neighbouring points until one is left
+        let mut blend: Vec<(f32, f32)> = points.iter().map(|&(x, y)| (x as f32, y as f32)).collect();
+        while blend.len() > 1 {
+            blend = blend.windows(2)
+                .map(|pair| (pair[0].0 + (pair[1].0 - pair[0].0) * t, pair[0].1 + (pair[1].1 - pair[0].1) * t))
+                .collect();
+        }
+        let point = (blend[0].0.round() as isize, blend[0].1.round() as isize);
+        match path.last() {
+            Some(&last) if last != point => path.extend(line_points(last, point).into_iter().skip(1)),
+            Some(_) => {}
+            None => path.push(point),
+        }
+    }
+    pixel_perfect(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_perfect_drops_repeats_and_l_corners() {
+        let path = [(0, 0), (0, 0), (1, 0), (1, 1), (2, 1), (3, 1)];
+        assert_eq!(pixel_perfect(&path), vec![(0, 0), (1, 1), (2, 1), (3, 1)]);
+        // Straight runs and diagonals are already thin
+        let diagonal = [(0, 0), (1, 1), (2, 2)];
+        assert_eq!(pixel_perfect(&diagonal), diagonal.to_vec());
+    }
+
+    #[test]
+    fn ellipse_outlines_are_symmetric_and_touch_their_box() {
+        for (a, b) in [((0, 0), (6, 4)), ((2, 1), (9, 8)), ((0, 0), (5, 5)), ((3, 3), (0, 0))] {
+            let points = ellipse_outline(a, b);
+            let (min_x, max_x) = (a.0.min(b.0), a.0.max(b.0));
+            let (min_y, max_y) = (a.1.min(b.1), a.1.max(b.1));
+            assert_eq!(points.iter().map(|p| p.0).min(), Some(min_x));
+            assert_eq!(points.iter().map(|p| p.0).max(), Some(max_x));
+            assert_eq!(points.iter().map(|p| p.1).min(), Some(min_y));
+            assert_eq!(points.iter().map(|p| p.1).max(), Some(max_y));
+            for &(x, y) in &points {
+                assert!(points.contains(&(min_x + max_x - x, y)));
+                assert!(points.contains(&(x, min_y + max_y - y)));
+            }
+        }
+    }
+
+    #[test]
+    fn tiny_ellipses_fill_their_box() {
+        assert_eq!(ellipse_outline((0, 0), (0, 0)), vec![(0, 0)]);
+        assert_eq!(ellipse_outline((0, 0), (2, 0)), vec![(0, 0), (1, 0), (2, 0)]);
+        assert_eq!(ellipse_outline((0, 0), (1, 1)), vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn filled_ellipses_cover_their_outline() {
+        let outline = ellipse_outline((0, 0), (7, 5));
+        let filled = ellipse_filled((0, 0), (7, 5));
+        assert!(outline.iter().all(|p| filled.contains(p)));
+        assert!(filled.contains(&(3, 2)) && filled.len() > outline.len());
+    }
+
+    #[test]
+    fn straight_beziers_are_lines() {
+        let points = CurveKind::Quadratic.straight((0, 0), (6, 0));
+        assert_eq!(bezier_points(&points), (0..=6).map(|x| (x, 0)).collect::<Vec<_>>());
+        let points = CurveKind::Cubic.straight((0, 0), (4, 4));
+        assert_eq!(bezier_points(&points), (0..=4).map(|i| (i, i)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bent_beziers_run_end_to_end_one_pixel_thin() {
+        let path = bezier_points(&[(0, 0), (5, 10), (10, 0)]);
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert_eq!(path.last(), Some(&(10, 0)));
+        assert!(path.iter().any(|&(_, y)| y >= 4));
+        for pair in path.windows(2) {
+            let (dx, dy) = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+            assert!(dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0));
+        }
+        assert_eq!(pixel_perfect(&path), path);
+    }
+
+    #[test]
+    fn snapped_lines_step_in_whole_runs() {
+        let line = snapped_line((0, 0), (5, 3), &PIXEL_ART_SLOPES, false);
+        assert_eq!(line, vec![(0, 0), (1, 0), (2, 1), (3, 1), (4, 2), (5, 2)]);
+        // Nearly vertical drags snap straight up
+        assert_eq!(snapped_line((0, 0), (1, -8), &PIXEL_ART_SLOPES, false), (0..=8).map(|y| (0, -y)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn centred_snapped_lines_reach_back_past_the_start() {
+        let line = snapped_line((0, 0), (5, 3), &PIXEL_ART_SLOPES, true);
+        assert_eq!(line.len(), 12);
+        assert_eq!(line.first(), Some(&(-6, -3)));
+        assert_eq!(line.last(), Some(&(5, 2)));
+    }
+
+    #[test]
+    fn isometric_lines_keep_to_two_to_one() {
+        let line = snapped_line((0, 0), (-8, -3), &ISOMETRIC_SLOPES, false);
+        for (i, &(x, y)) in line.iter().enumerate() {
+            assert_eq!((x, y), (-(i as isize), -(i as isize / 2)));
+        }
+        assert_eq!(line.len() % 2, 0);
+        // Shallow drags stay flat rather than taking a 1:1 slope, which isometric lines lack
+        assert!(snapped_line((0, 0), (8, 1), &ISOMETRIC_SLOPES, false).iter().all(|&(_, y)| y == 0));
+    }
+
+    #[test]
+    fn drag_boxes_square_up_and_centre() {
+        assert_eq!(drag_box((2, 2), (5, 0), false, false), ((2, 2), (5, 0)));
+        assert_eq!(drag_box((2, 2), (5, 0), true, false), ((2, 2), (5, -1)));
+        assert_eq!(drag_box((2, 2), (5, 0), false, true), ((-1, 4), (5, 0)));
+        assert_eq!(drag_box((2, 2), (5, 0), true, true), ((-1, 5), (5, -1)));
+    }
+}
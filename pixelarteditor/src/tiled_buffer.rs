@@ -0,0 +1,253 @@
+use eframe::egui::Color32;
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::pixel_buffer::{PixelBuffer, BYTES_PER_PIXEL};
+use crate::types::DirtyRect;
+
+/// Edge length of a storage tile in pixels
+pub const TILE_SIZE: usize = 64;
+
+/// Sparse layer storage made of fixed-size tiles.
+///
+/// Tiles that were never painted are not allocated, and tiles may lie outside
+/// the canvas, so content moved off the edge is kept. Tiles are shared between
+/// clones (undo snapshots, duplicated frames) and copied on first write.
+#[derive(Clone, Default)]
+pub struct TiledBuffer {
+    width: usize, // Canvas size the layer is shown at
+    height: usize,
+    tiles: HashMap<(i32, i32), Arc<PixelBuffer>>,
+}
+
+/// Tile holding a pixel coordinate, and the pixel's position inside it
+fn tile_coords(x: isize, y: isize) -> ((i32, i32), usize, usize) {
+    let size = TILE_SIZE as isize;
+    let key = (x.div_euclid(size) as i32, y.div_euclid(size) as i32);
+    (key, x.rem_euclid(size) as usize, y.rem_euclid(size) as usize)
+}
+
+fn tile_origin(key: (i32, i32)) -> (isize, isize) {
+    (key.0 as isize * TILE_SIZE as isize, key.1 as isize * TILE_SIZE as isize)
+}
+
+fn is_empty_tile(tile: &PixelBuffer) -> bool {
+    tile.as_bytes().chunks_exact(BYTES_PER_PIXEL).all(|p| p[3] == 0)
+}
+
+impl TiledBuffer {
+    /// Create an empty layer; no tiles are allocated
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, tiles: HashMap::new() }
+    }
+
+    /// Create a layer whose canvas area is filled with one colour
+    pub fn filled(width: usize, height: usize, color: Color32) -> Self {
+        let mut buffer = Self::new(width, height);
+        if color.a() > 0 {
+            buffer.fill_rect(0, 0, width, height, color);
+        }
+        buffer
+    }
+
+    /// Convert a flat buffer into tiles, skipping the empty ones
+    pub fn from_pixel_buffer(pixels: &PixelBuffer) -> Self {
+        let mut buffer = Self::new(pixels.width(), pixels.height());
+        buffer.paste(pixels, 0, 0);
+        buffer
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Change the canvas size without touching any pixels
+    pub fn set_size(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Number of allocated tiles
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Allocated tiles with their tile coordinates
+    pub fn tiles(&self) -> impl Iterator<Item = ((i32, i32), &PixelBuffer)> {
+        self.tiles.iter().map(|(key, tile)| (*key, tile.as_ref()))
+    }
+
+    /// Store a whole tile; empty tiles are dropped instead
+    pub fn insert_tile(&mut self, key: (i32, i32), tile: PixelBuffer) {
+        if tile.width() != TILE_SIZE || tile.height() != TILE_SIZE || is_empty_tile(&tile) {
+            self.tiles.remove(&key);
+        } else {
+            self.tiles.insert(key, Arc::new(tile));
+        }
+    }
+
+    fn tile_mut(&mut self, key: (i32, i32)) -> &mut PixelBuffer {
+        let tile = self.tiles.entry(key).or_insert_with(|| Arc::new(PixelBuffer::new(TILE_SIZE, TILE_SIZE)));
+        Arc::make_mut(tile)
+    }
+
+    /// Read a pixel anywhere on the layer; unallocated areas are transparent
+    pub fn get(&self, x: isize, y: isize) -> Color32 {
+        let (key, tx, ty) = tile_coords(x, y);
+        self.tiles.get(&key).map_or(Color32::TRANSPARENT, |tile| tile.get_pixel(tx, ty))
+    }
+
+    /// Write a pixel anywhere on the layer; returns true if the stored value changed
+    pub fn set(&mut self, x: isize, y: isize, color: Color32) -> bool {
+        let (key, tx, ty) = tile_coords(x, y);
+        if color.a() == 0 && !self.tiles.contains_key(&key) {
+            return false;
+        }
+        self.tile_mut(key).set(tx, ty, color)
+    }
+
+    /// Fill a rectangle with one colour
+    pub fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Color32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (first, _, _) = tile_coords(x, y);
+        let (last, _, _) = tile_coords(x + width as isize - 1, y + height as isize - 1);
+        for ty in first.1..=last.1 {
+            for tx in first.0..=last.0 {
+                let (ox, oy) = tile_origin((tx, ty));
+                let x0 = (x - ox).max(0) as usize;
+                let y0 = (y - oy).max(0) as usize;
+                let x1 = ((x + width as isize - ox) as usize).min(TILE_SIZE);
+                let y1 = ((y + height as isize - oy) as usize).min(TILE_SIZE);
+                let rect = DirtyRect { min_x: x0, min_y: y0, max_x: x1 - 1, max_y: y1 - 1 };
+                if color.a() == 0 && !self.tiles.contains_key(&(tx, ty)) {
+                    continue;
+                }
+                self.tile_mut((tx, ty)).fill_rect(rect, color);
+            }
+        }
+    }
+
+    /// Overwrite pixels with a flat buffer placed at (x, y)
+    pub fn paste(&mut self, pixels: &PixelBuffer, x: isize, y: isize) {
+        if pixels.width() == 0 || pixels.height() == 0 {
+            return;
+        }
+        let (first, _, _) = tile_coords(x, y);
+        let (last, _, _) = tile_coords(x + pixels.width() as isize - 1, y + pixels.height() as isize - 1);
+        for ty in first.1..=last.1 {
+            for tx in first.0..=last.0 {
+                let (ox, oy) = tile_origin((tx, ty));
+                let mut tile = self.tiles.get(&(tx, ty))
+                    .map_or_else(|| PixelBuffer::new(TILE_SIZE, TILE_SIZE), |tile| tile.as_ref().clone());
+                tile.blit(pixels, x - ox, y - oy);
+                self.insert_tile((tx, ty), tile);
+            }
+        }
+    }
+
+    /// Copy any rectangle of the layer (inside or outside the canvas) into a flat buffer
+    pub fn read_region(&self, x: isize, y: isize, width: usize, height: usize) -> PixelBuffer {
+        let mut out = PixelBuffer::new(width, height);
+        if width == 0 || height == 0 {
+            return out;
+        }
+        let (first, _, _) = tile_coords(x, y);
+        let (last, _, _) = tile_coords(x + width as isize - 1, y + height as isize - 1);
+        for ty in first.1..=last.1 {
+            for tx in first.0..=last.0 {
+                if let Some(tile) = self.tiles.get(&(tx, ty)) {
+                    let (ox, oy) = tile_origin((tx, ty));
+                    out.blit(tile, ox - x, oy - y);
+                }
+            }
+        }
+        out
+    }
+
+    /// Flatten the canvas area into a flat buffer, e.g. for export
+    pub fn to_pixel_buffer(&self) -> PixelBuffer {
+        self.read_region(0, 0, self.width, self.height)
+    }
+
+    /// Smallest rectangle (x, y, width, height) holding every visible pixel, on or off the canvas
+    pub fn content_bounds(&self) -> Option<(isize, isize, usize, usize)> {
+        let mut bounds: Option<(isize, isize, isize, isize)> = None;
+        for (&key, tile) in &self.tiles {
+            let (ox, oy) = tile_origin(key);
+            for (ty, row) in tile.rows().enumerate() {
+                for (tx, pixel) in row.chunks_exact(BYTES_PER_PIXEL).enumerate() {
+                    if pixel[3] == 0 {
+                        continue;
+                    }
+                    let (x, y) = (ox + tx as isize, oy + ty as isize);
+                    bounds = Some(match bounds {
+                        Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                        None => (x, y, x, y),
+                    });
+                }
+            }
+        }
+        bounds.map(|(x0, y0, x1, y1)| (x0, y0, (x1 - x0 + 1) as usize, (y1 - y0 + 1) as usize))
+    }
+
+    /// Call `f` for every non-transparent pixel inside a canvas region
+    pub fn for_each_visible_pixel(&self, rect: DirtyRect, mut f: impl FnMut(usize, usize, Color32)) {
+        let (first, _, _) = tile_coords(rect.min_x as isize, rect.min_y as isize);
+        let (last, _, _) = tile_coords(rect.max_x as isize, rect.max_y as isize);
+        for ty in first.1..=last.1 {
+            for tx in first.0..=last.0 {
+                let Some(tile) = self.tiles.get(&(tx, ty)) else {
+                    continue;
+                };
+                let (ox, oy) = tile_origin((tx, ty));
+                let (ox, oy) = (ox as usize, oy as usize);
+                let x0 = rect.min_x.max(ox);
+                let x1 = rect.max_x.min(ox + TILE_SIZE - 1);
+                for y in rect.min_y.max(oy)..=rect.max_y.min(oy + TILE_SIZE - 1) {
+                    let row = tile.row(y - oy);
+                    for x in x0..=x1 {
+                        let i = (x - ox) * BYTES_PER_PIXEL;
+                        if row[i + 3] > 0 {
+                            f(x, y, Color32::from_rgba_unmultiplied(row[i], row[i + 1], row[i + 2], row[i + 3]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copy of this layer moved by (dx, dy); nothing is lost at the canvas edges
+    pub fn shifted(&self, dx: isize, dy: isize) -> TiledBuffer {
+        let mut out = TiledBuffer::new(self.width, self.height);
+        let tile_size = TILE_SIZE as isize;
+        if dx.rem_euclid(tile_size) == 0 && dy.rem_euclid(tile_size) == 0 {
+            // Whole-tile moves just relabel the tiles
+            let (kx, ky) = ((dx / tile_size) as i32, (dy / tile_size) as i32);
+            out.tiles = self.tiles.iter().map(|(&(tx, ty), tile)| ((tx + kx, ty + ky), Arc::clone(tile))).collect();
+            return out;
+        }
+        for (&key, tile) in &self.tiles {
+            let (ox, oy) = tile_origin(key);
+            out.paste(tile, ox + dx, oy + dy);
+        }
+        out
+    }
+
+    /// Drop tiles touching a canvas region that no longer hold any visible pixel
+    pub fn prune(&mut self, rect: DirtyRect) {
+        let (first, _, _) = tile_coords(rect.min_x as isize, rect.min_y as isize);
+        let (last, _, _) = tile_coords(rect.max_x as isize, rect.max_y as isize);
+        for ty in first.1..=last.1 {
+            for tx in first.0..=last.0 {
+                if self.tiles.get(&(tx, ty)).is_some_and(|tile| is_empty_tile(tile)) {
+                    self.tiles.remove(&(tx, ty));
+                }
+            }
+        }
+    }
+}
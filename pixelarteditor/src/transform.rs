@@ -0,0 +1,109 @@
+use crate::pixel_buffer::PixelBuffer;
+
+// Pixel-art friendly transforms. Nothing here blends colours: every output pixel is a
+// copy of some input pixel, so palettes and hard edges survive.
+
+/// Mirror left-right and/or top-bottom
+pub fn flip(src: &PixelBuffer, horizontal: bool, vertical: bool) -> PixelBuffer {
+    let (width, height) = (src.width(), src.height());
+    let mut out = PixelBuffer::new(width, height);
+    for (x, y, color) in src.pixels() {
+        let x = if horizontal { width - 1 - x } else { x };
+        let y = if vertical { height - 1 - y } else { y };
+        out.set(x, y, color);
+    }
+    out
+}
+
+/// Scale to any size with nearest-neighbour sampling
+pub fn scale_nearest(src: &PixelBuffer, width: usize, height: usize) -> PixelBuffer {
+    let mut out = PixelBuffer::new(width, height);
+    if src.width() == 0 || src.height() == 0 {
+        return out;
+    }
+    for y in 0..height {
+        for x in 0..width {
+            out.set(x, y, src.get_pixel(x * src.width() / width, y * src.height() / height));
+        }
+    }
+    out
+}
+
+/// Rotate clockwise by a number of quarter turns
+pub fn rotate_quarter(src: &PixelBuffer, turns: usize) -> PixelBuffer {
+    let (width, height) = (src.width(), src.height());
+    let turns = turns % 4;
+    let mut out = match turns {
+        1 | 3 => PixelBuffer::new(height, width),
+        _ => PixelBuffer::new(width, height),
+    };
+    for (x, y, color) in src.pixels() {
+        let (tx, ty) = match turns {
+            1 => (height - 1 - y, x),
+            2 => (width - 1 - x, height - 1 - y),
+            3 => (y, width - 1 - x),
+            _ => (x, y),
+        };
+        out.set(tx, ty, color);
+    }
+    out
+}
+
+/// Double the size with Scale2x, which rounds off diagonal steps instead of making blocks
+pub fn scale2x(src: &PixelBuffer) -> PixelBuffer {
+    let (width, height) = (src.width(), src.height());
+    let mut out = PixelBuffer::new(width * 2, height * 2);
+    for (x, y, p) in src.pixels() {
+        // Past the edge a pixel's neighbour is itself
+        let at = |dx: isize, dy: isize| src.get_signed(x as isize + dx, y as isize + dy).unwrap_or(p);
+        let (a, b, c, d) = (at(0, -1), at(1, 0), at(-1, 0), at(0, 1));
+        let (x2, y2) = (x * 2, y * 2);
+        out.set(x2, y2, if c == a && c != d && a != b { a } else { p });
+        out.set(x2 + 1, y2, if a == b && a != c && b != d { b } else { p });
+        out.set(x2, y2 + 1, if d == c && d != b && c != a { c } else { p });
+        out.set(x2 + 1, y2 + 1, if b == d && b != a && d != c { d } else { p });
+    }
+    out
+}
+
+/// Rotate clockwise by any angle the way RotSprite does: upscale 8x with Scale2x,
+/// rotate that with nearest-neighbour sampling and sample it back down. The result is
+/// just big enough to hold the rotated image, centred on the same point.
+pub fn rotsprite(src: &PixelBuffer, degrees: f32) -> PixelBuffer {
+    if src.width() == 0 || src.height() == 0 {
+        return src.clone();
+    }
+    rotate_sampled(src, &scale2x(&scale2x(&scale2x(src))), 8.0, degrees)
+}
+
+/// Rotate clockwise by any angle with plain nearest-neighbour sampling: rougher than
+/// RotSprite but quick enough to redraw while a rotation is being dragged
+pub fn rotate_nearest(src: &PixelBuffer, degrees: f32) -> PixelBuffer {
+    if src.width() == 0 || src.height() == 0 {
+        return src.clone();
+    }
+    rotate_sampled(src, src, 1.0, degrees)
+}
+
+/// Rotate `src` by sampling `big`, a copy of it upscaled `upscale` times
+fn rotate_sampled(src: &PixelBuffer, big: &PixelBuffer, upscale: f32, degrees: f32) -> PixelBuffer {
+    let (width, height) = (src.width() as f32, src.height() as f32);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    // Trim float error so exact multiples of 90 degrees don't grow by a pixel
+    let out_width = (width * cos.abs() + height * sin.abs() - 1e-3).ceil().max(1.0) as usize;
+    let out_height = (width * sin.abs() + height * cos.abs() - 1e-3).ceil().max(1.0) as usize;
+    let mut out = PixelBuffer::new(out_width, out_height);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            // Offset of this pixel's centre from the middle, turned back to the source
+            let px = x as f32 + 0.5 - out_width as f32 / 2.0;
+            let py = y as f32 + 0.5 - out_height as f32 / 2.0;
+            let sx = px * cos + py * sin + width / 2.0;
+            let sy = -px * sin + py * cos + height / 2.0;
+            if let Some(color) = big.get_signed((sx * upscale).floor() as isize, (sy * upscale).floor() as isize) {
+                out.set(x, y, color);
+            }
+        }
+    }
+    out
+}
@@ -1,6 +1,10 @@
 use eframe::egui;
 use std::time::Instant;
 use rand::Rng;
+use crate::pixel_buffer::PixelBuffer;
+use crate::tiled_buffer::TiledBuffer;
+use crate::index_buffer::IndexBuffer;
+use crate::palette;
 
 // Custom serialization for egui::Color32
 mod color32_serde {
@@ -24,34 +28,249 @@ mod color32_serde {
     }
 }
 
-// Custom serialization for Vec<Vec<Color32>>
-mod grid_serde {
+// Custom serialization for palettes, as straight RGBA arrays
+mod palette_serde {
     use serde::{Deserialize, Deserializer, Serialize, Serializer};
     use eframe::egui::Color32;
 
-    pub fn serialize<S>(grid: &Vec<Vec<Color32>>, serializer: S) -> Result<S::Ok, S::Error>
+    pub fn serialize<S>(palette: &[Color32], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let entries: Vec<[u8; 4]> = palette.iter().map(|c| c.to_srgba_unmultiplied()).collect();
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Color32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let entries: Vec<[u8; 4]> = Vec::deserialize(deserializer)?;
+        Ok(entries.iter().map(|c| Color32::from_rgba_unmultiplied(c[0], c[1], c[2], c[3])).collect())
+    }
+}
+
+// Custom serialization for tiled layers: only allocated tiles are written, each as
+// run-length encoded RGBA hex ("3*ff0000ff 00000000 ..."). Older project files stored
+// the whole canvas as rows of RGBA arrays; those still load.
+mod grid_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use crate::pixel_buffer::PixelBuffer;
+    use crate::tiled_buffer::{TiledBuffer, TILE_SIZE};
+    use std::fmt::Write;
+
+    #[derive(Serialize, Deserialize)]
+    struct StoredTile {
+        x: i32,
+        y: i32,
+        runs: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct StoredLayer {
+        width: usize,
+        height: usize,
+        tile_size: usize,
+        tiles: Vec<StoredTile>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum AnyLayer {
+        Tiled(StoredLayer),
+        Legacy(Vec<Vec<[u8; 4]>>),
+    }
+
+    fn encode_runs(tile: &PixelBuffer) -> String {
+        let mut runs = String::new();
+        let mut pixels = tile.as_bytes().chunks_exact(4).peekable();
+        while let Some(pixel) = pixels.next() {
+            let mut count = 1;
+            while pixels.next_if(|next| *next == pixel).is_some() {
+                count += 1;
+            }
+            if !runs.is_empty() {
+                runs.push(' ');
+            }
+            if count > 1 {
+                let _ = write!(runs, "{}*", count);
+            }
+            let _ = write!(runs, "{:02x}{:02x}{:02x}{:02x}", pixel[0], pixel[1], pixel[2], pixel[3]);
+        }
+        runs
+    }
+
+    fn decode_runs(runs: &str) -> Option<PixelBuffer> {
+        let mut data = Vec::with_capacity(TILE_SIZE * TILE_SIZE * 4);
+        for token in runs.split_whitespace() {
+            let (count, hex) = match token.split_once('*') {
+                Some((count, hex)) => (count.parse::<usize>().ok()?, hex),
+                None => (1, token),
+            };
+            let rgba = u32::from_str_radix(hex, 16).ok()?.to_be_bytes();
+            if hex.len() != 8 || data.len() + count * 4 > TILE_SIZE * TILE_SIZE * 4 {
+                return None;
+            }
+            for _ in 0..count {
+                data.extend_from_slice(&rgba);
+            }
+        }
+        PixelBuffer::from_raw(TILE_SIZE, TILE_SIZE, data)
+    }
+
+    pub fn serialize<S>(grid: &TiledBuffer, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let rgba_grid: Vec<Vec<[u8; 4]>> = grid.iter()
-            .map(|row| row.iter()
-                .map(|color| [color.r(), color.g(), color.b(), color.a()])
-                .collect())
+        let mut tiles: Vec<StoredTile> = grid.tiles()
+            .map(|((x, y), tile)| StoredTile { x, y, runs: encode_runs(tile) })
             .collect();
-        rgba_grid.serialize(serializer)
+        // Keep the output stable between saves
+        tiles.sort_by_key(|tile| (tile.y, tile.x));
+        StoredLayer { width: grid.width(), height: grid.height(), tile_size: TILE_SIZE, tiles }
+            .serialize(serializer)
     }
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Vec<Color32>>, D::Error>
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<TiledBuffer, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let rgba_grid: Vec<Vec<[u8; 4]>> = Vec::deserialize(deserializer)?;
-        let grid = rgba_grid.into_iter()
-            .map(|row| row.into_iter()
-                .map(|rgba| Color32::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3]))
-                .collect())
+        match AnyLayer::deserialize(deserializer)? {
+            AnyLayer::Tiled(stored) => {
+                if stored.tile_size != TILE_SIZE {
+                    return Err(serde::de::Error::custom(format!("unsupported tile size {}", stored.tile_size)));
+                }
+                let mut grid = TiledBuffer::new(stored.width, stored.height);
+                for tile in stored.tiles {
+                    let pixels = decode_runs(&tile.runs)
+                        .ok_or_else(|| serde::de::Error::custom("invalid tile data"))?;
+                    grid.insert_tile((tile.x, tile.y), pixels);
+                }
+                Ok(grid)
+            }
+            AnyLayer::Legacy(rgba_grid) => {
+                let height = rgba_grid.len();
+                let width = rgba_grid.first().map_or(0, |row| row.len());
+                if rgba_grid.iter().any(|row| row.len() != width) {
+                    return Err(serde::de::Error::custom("layer rows have different lengths"));
+                }
+                let data = rgba_grid.into_iter().flatten().flatten().collect();
+                let pixels = PixelBuffer::from_raw(width, height, data)
+                    .ok_or_else(|| serde::de::Error::custom("invalid layer size"))?;
+                Ok(TiledBuffer::from_pixel_buffer(&pixels))
+            }
+        }
+    }
+
+    /// The same format for grids that may be missing, e.g. layer masks
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use crate::tiled_buffer::TiledBuffer;
+
+        pub fn serialize<S>(grid: &Option<TiledBuffer>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            struct Grid<'a>(&'a TiledBuffer);
+            impl Serialize for Grid<'_> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    super::serialize(self.0, serializer)
+                }
+            }
+            grid.as_ref().map(Grid).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<TiledBuffer>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            #[derive(Deserialize)]
+            struct Grid(#[serde(deserialize_with = "super::deserialize")] TiledBuffer);
+            Ok(Option::<Grid>::deserialize(deserializer)?.map(|grid| grid.0))
+        }
+    }
+}
+
+// Palette indices of indexed layers, as tiles in the same layout as `grid_serde`. Each
+// run is an index in hex, or "-" for pixels without one ("5*03 -"). Projects saved
+// before indices were stored have none, and rebuild them from the colours.
+mod index_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use crate::index_buffer::IndexBuffer;
+    use crate::tiled_buffer::TILE_SIZE;
+    use std::fmt::Write;
+
+    #[derive(Serialize, Deserialize)]
+    struct StoredTile {
+        x: i32,
+        y: i32,
+        runs: String,
+    }
+
+    fn encode_runs(tile: &[Option<u8>]) -> String {
+        let mut runs = String::new();
+        let mut indices = tile.iter().peekable();
+        while let Some(index) = indices.next() {
+            let mut count = 1;
+            while indices.next_if(|next| *next == index).is_some() {
+                count += 1;
+            }
+            if !runs.is_empty() {
+                runs.push(' ');
+            }
+            if count > 1 {
+                let _ = write!(runs, "{}*", count);
+            }
+            match index {
+                Some(index) => { let _ = write!(runs, "{:02x}", index); }
+                None => runs.push('-'),
+            }
+        }
+        runs
+    }
+
+    fn decode_runs(runs: &str) -> Option<Vec<Option<u8>>> {
+        let mut indices = Vec::with_capacity(TILE_SIZE * TILE_SIZE);
+        for token in runs.split_whitespace() {
+            let (count, value) = match token.split_once('*') {
+                Some((count, value)) => (count.parse::<usize>().ok()?, value),
+                None => (1, token),
+            };
+            let index = match value {
+                "-" => None,
+                hex if hex.len() == 2 => Some(u8::from_str_radix(hex, 16).ok()?),
+                _ => return None,
+            };
+            if indices.len() + count > TILE_SIZE * TILE_SIZE {
+                return None;
+            }
+            indices.extend(std::iter::repeat_n(index, count));
+        }
+        (indices.len() == TILE_SIZE * TILE_SIZE).then_some(indices)
+    }
+
+    pub fn serialize<S>(buffer: &IndexBuffer, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut tiles: Vec<StoredTile> = buffer.tiles()
+            .map(|((x, y), tile)| StoredTile { x, y, runs: encode_runs(tile) })
             .collect();
-        Ok(grid)
+        tiles.sort_by_key(|tile| (tile.y, tile.x));
+        tiles.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<IndexBuffer, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut buffer = IndexBuffer::default();
+        for tile in Vec::<StoredTile>::deserialize(deserializer)? {
+            let indices = decode_runs(&tile.runs)
+                .ok_or_else(|| serde::de::Error::custom("invalid index tile data"))?;
+            buffer.insert_tile((tile.x, tile.y), indices);
+        }
+        Ok(buffer)
     }
 }
 
@@ -65,10 +284,18 @@ pub enum Tool {
     Line,
     Rectangle,
     Circle,
+    Ellipse,
+    Polygon,
+    Curve,
     Select,
+    EllipseSelect,
     Lasso,
+    MagicWand,
     Spray,
     Dither,
+    Gradient,
+    Slice,
+    Tile,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -224,13 +451,211 @@ pub enum ExportFormat {
     FLI,
 }
 
+/// Inclusive bounding box of pixels that changed since the last composite
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DirtyRect {
+    pub min_x: usize,
+    pub min_y: usize,
+    pub max_x: usize,
+    pub max_y: usize,
+}
+
+impl DirtyRect {
+    pub fn pixel(x: usize, y: usize) -> Self {
+        Self { min_x: x, min_y: y, max_x: x, max_y: y }
+    }
+
+    /// Rectangle covering a whole `width` x `height` image
+    pub fn full(width: usize, height: usize) -> Self {
+        Self {
+            min_x: 0,
+            min_y: 0,
+            max_x: width.saturating_sub(1),
+            max_y: height.saturating_sub(1),
+        }
+    }
+
+    pub fn union(self, other: DirtyRect) -> Self {
+        Self {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.max_x - self.min_x + 1
+    }
+
+    pub fn height(&self) -> usize {
+        self.max_y - self.min_y + 1
+    }
+
+    /// Clamp to a `width` x `height` image, or `None` if nothing is left
+    pub fn clamp_to(self, width: usize, height: usize) -> Option<Self> {
+        if width == 0 || height == 0 || self.min_x >= width || self.min_y >= height {
+            return None;
+        }
+        Some(Self {
+            min_x: self.min_x,
+            min_y: self.min_y,
+            max_x: self.max_x.min(width - 1),
+            max_y: self.max_y.min(height - 1),
+        })
+    }
+}
+
+/// Merge an optional region with another one
+pub fn union_dirty(region: Option<DirtyRect>, other: Option<DirtyRect>) -> Option<DirtyRect> {
+    match (region, other) {
+        (Some(a), Some(b)) => Some(a.union(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// How the pixels of a document are stored and limited
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum ColorMode {
+    #[default]
+    Rgba,
+    // Every pixel is an entry of the document palette
+    Indexed,
+    Grayscale,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 3] = [ColorMode::Rgba, ColorMode::Indexed, ColorMode::Grayscale];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorMode::Rgba => "RGBA",
+            ColorMode::Indexed => "Indexed",
+            ColorMode::Grayscale => "Grayscale",
+        }
+    }
+}
+
+/// How a layer's pixels are combined with the layers below it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+    Addition,
+    Subtract,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 18] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Darken,
+        BlendMode::Lighten,
+        BlendMode::ColorDodge,
+        BlendMode::ColorBurn,
+        BlendMode::HardLight,
+        BlendMode::SoftLight,
+        BlendMode::Difference,
+        BlendMode::Exclusion,
+        BlendMode::Hue,
+        BlendMode::Saturation,
+        BlendMode::Color,
+        BlendMode::Luminosity,
+        BlendMode::Addition,
+        BlendMode::Subtract,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Multiply => "Multiply",
+            BlendMode::Screen => "Screen",
+            BlendMode::Overlay => "Overlay",
+            BlendMode::Darken => "Darken",
+            BlendMode::Lighten => "Lighten",
+            BlendMode::ColorDodge => "Color Dodge",
+            BlendMode::ColorBurn => "Color Burn",
+            BlendMode::HardLight => "Hard Light",
+            BlendMode::SoftLight => "Soft Light",
+            BlendMode::Difference => "Difference",
+            BlendMode::Exclusion => "Exclusion",
+            BlendMode::Hue => "Hue",
+            BlendMode::Saturation => "Saturation",
+            BlendMode::Color => "Color",
+            BlendMode::Luminosity => "Luminosity",
+            BlendMode::Addition => "Addition",
+            BlendMode::Subtract => "Subtract",
+        }
+    }
+}
+
+/// What a layer holds
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum LayerKind {
+    #[default]
+    Image,
+    // Holds no pixels; the layers directly below it in the list with a greater depth are its children
+    Group,
+    // Cells that place tiles from a tileset; the pixels show the placed tiles
+    Tilemap,
+    // An imported image to work from; the pixels show it placed on the canvas and can't be painted
+    Reference,
+}
+
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Layer {
     pub name: String,
     pub visible: bool,
     pub opacity: f32,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+    #[serde(default)]
+    pub kind: LayerKind,
+    #[serde(default)]
+    pub depth: usize, // Nesting level, 0 = top level
+    #[serde(default)]
+    pub collapsed: bool, // Group children hidden in the layers panel
+    #[serde(default)]
+    pub link: Option<u64>, // Cels with the same link id share one image across frames
+    #[serde(default)]
+    pub tilemap: Option<Tilemap>,
+    #[serde(default)]
+    pub reference: Option<ReferenceImage>,
+    #[serde(default)]
+    pub lock_pixels: bool, // No tool may change the layer
+    #[serde(default)]
+    pub lock_alpha: bool, // Painting only recolours pixels that are already there
+    // How much of each pixel the mask hides, in the alpha channel; missing pixels hide nothing
+    #[serde(default, with = "grid_serde::option")]
+    pub mask: Option<TiledBuffer>,
+    // Tools paint on the mask instead of the pixels
+    #[serde(skip)]
+    pub editing_mask: bool,
     #[serde(with = "grid_serde")]
-    pub grid: Vec<Vec<egui::Color32>>,
+    pub grid: TiledBuffer,
+    // Palette index of each pixel in indexed colour mode; rebuilt from the colours when missing
+    #[serde(default, with = "index_serde")]
+    pub indices: IndexBuffer,
+    // Pixels changed since the compositor last looked at this layer
+    #[serde(skip)]
+    pub dirty: Option<DirtyRect>,
 }
 
 impl Layer {
@@ -239,19 +664,346 @@ impl Layer {
             name,
             visible: true,
             opacity: 1.0,
-            grid: vec![vec![color; width]; height],
+            blend_mode: BlendMode::Normal,
+            kind: LayerKind::Image,
+            depth: 0,
+            collapsed: false,
+            link: None,
+            tilemap: None,
+            reference: None,
+            lock_pixels: false,
+            lock_alpha: false,
+            mask: None,
+            editing_mask: false,
+            grid: TiledBuffer::filled(width, height, color),
+            indices: IndexBuffer::default(),
+            dirty: Some(DirtyRect::full(width, height)),
+        }
+    }
+
+    /// Create an empty group layer
+    pub fn new_group(name: String, width: usize, height: usize) -> Self {
+        Self {
+            kind: LayerKind::Group,
+            ..Self::new(name, width, height, egui::Color32::TRANSPARENT)
+        }
+    }
+
+    /// Create an empty tilemap layer covering the canvas with tiles of the given size
+    pub fn new_tilemap(name: String, width: usize, height: usize, tileset: usize, tile_width: usize, tile_height: usize) -> Self {
+        Self {
+            kind: LayerKind::Tilemap,
+            tilemap: Some(Tilemap::new(tileset, width.div_ceil(tile_width), height.div_ceil(tile_height))),
+            ..Self::new(name, width, height, egui::Color32::TRANSPARENT)
+        }
+    }
+
+    /// Create a half-transparent reference layer showing an imported image
+    pub fn new_reference(name: String, width: usize, height: usize, reference: ReferenceImage) -> Self {
+        let mut layer = Self {
+            kind: LayerKind::Reference,
+            opacity: 0.5,
+            reference: Some(reference),
+            ..Self::new(name, width, height, egui::Color32::TRANSPARENT)
+        };
+        layer.render_reference();
+        layer
+    }
+
+    pub fn is_group(&self) -> bool {
+        self.kind == LayerKind::Group
+    }
+
+    pub fn is_reference(&self) -> bool {
+        self.kind == LayerKind::Reference
+    }
+
+    /// Whether the layer shows up in exported and saved images
+    pub fn is_exported(&self) -> bool {
+        !self.is_reference() || self.reference.as_ref().is_some_and(|reference| reference.export)
+    }
+
+    /// Whether the eyedropper sees the layer
+    pub fn is_pickable(&self) -> bool {
+        !self.is_reference() || self.reference.as_ref().is_some_and(|reference| reference.pickable)
+    }
+
+    /// Redraw a reference layer's pixels after its image was moved or scaled
+    pub fn render_reference(&mut self) {
+        if let Some(reference) = &self.reference {
+            let pixels = reference.render(self.width(), self.height());
+            self.set_grid(TiledBuffer::from_pixel_buffer(&pixels));
         }
     }
     
     pub fn width(&self) -> usize {
-        self.grid.first().map_or(0, |row| row.len())
+        self.grid.width()
     }
     
     pub fn height(&self) -> usize {
-        self.grid.len()
+        self.grid.height()
+    }
+
+    /// Colour of a pixel as tools see it; while the mask is edited that is the mask,
+    /// white where it shows the layer and black where it hides it
+    pub fn get_pixel(&self, x: usize, y: usize) -> egui::Color32 {
+        match self.edited_mask() {
+            Some(mask) => mask_to_gray(mask.get(x as isize, y as isize)),
+            None => self.grid.get(x as isize, y as isize),
+        }
+    }
+
+    /// Write a pixel and record it in the layer's dirty region; writes outside the canvas are ignored
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: egui::Color32) {
+        if !self.is_editable() || x >= self.width() || y >= self.height() {
+            return;
+        }
+        let changed = if let Some(mask) = self.edited_mask_mut() {
+            mask.set(x as isize, y as isize, gray_to_mask(color))
+        } else if self.lock_alpha {
+            let old = self.grid.get(x as isize, y as isize);
+            alpha_locked(old, color).is_some_and(|color| self.grid.set(x as isize, y as isize, color))
+        } else {
+            self.grid.set(x as isize, y as isize, color)
+        };
+        if changed {
+            self.mark_dirty(DirtyRect::pixel(x, y));
+        }
+    }
+
+    /// Whether tools may change the layer at all. Groups hold no pixels of their own,
+    /// and references only show their image.
+    pub fn is_editable(&self) -> bool {
+        !self.is_group() && !self.is_reference() && !self.lock_pixels
+    }
+
+    /// The mask, if tools are painting on it
+    pub fn edited_mask(&self) -> Option<&TiledBuffer> {
+        self.mask.as_ref().filter(|_| self.editing_mask)
+    }
+
+    fn edited_mask_mut(&mut self) -> Option<&mut TiledBuffer> {
+        self.mask.as_mut().filter(|_| self.editing_mask)
+    }
+
+    /// The grid tools work on: the mask while it is edited, else the pixels
+    pub fn target_grid(&self) -> &TiledBuffer {
+        self.edited_mask().unwrap_or(&self.grid)
+    }
+
+    /// Replace what tools work on, e.g. after moving it. With the alpha locked only the
+    /// canvas area changes, and only in colour.
+    pub fn set_target_grid(&mut self, grid: TiledBuffer) {
+        if !self.is_editable() {
+            return;
+        }
+        let lock_alpha = self.lock_alpha;
+        match self.edited_mask_mut() {
+            Some(mask) => {
+                *mask = grid;
+                self.mark_all_dirty();
+            }
+            None if lock_alpha => self.set_pixels(&grid.to_pixel_buffer()),
+            None => self.set_grid(grid),
+        }
+    }
+
+    /// A pixel's colour with the part the mask hides taken away
+    pub fn masked(&self, x: usize, y: usize, color: egui::Color32) -> egui::Color32 {
+        let Some(mask) = &self.mask else {
+            return color;
+        };
+        let hidden = mask.get(x as isize, y as isize).a();
+        if hidden == 0 {
+            return color;
+        }
+        let [r, g, b, a] = color.to_srgba_unmultiplied();
+        let alpha = a as u32 * (255 - hidden as u32) / 255;
+        egui::Color32::from_rgba_unmultiplied(r, g, b, alpha as u8)
+    }
+
+    /// Give the layer a mask that hides nothing yet
+    pub fn add_mask(&mut self) {
+        if self.mask.is_none() && !self.is_reference() {
+            self.mask = Some(TiledBuffer::new(self.width(), self.height()));
+        }
+    }
+
+    pub fn remove_mask(&mut self) {
+        if self.mask.take().is_some() {
+            self.editing_mask = false;
+            self.mark_all_dirty();
+        }
+    }
+
+    /// Bake the mask into the pixels and drop it
+    pub fn apply_mask(&mut self) {
+        if self.mask.is_none() || self.is_group() {
+            return;
+        }
+        let pixels = self.masked_pixels();
+        self.mask = None;
+        self.editing_mask = false;
+        let mut grid = self.grid.clone();
+        grid.paste(&pixels, 0, 0);
+        self.set_grid(grid);
+    }
+
+    /// Replace the whole pixel storage
+    pub fn set_grid(&mut self, grid: TiledBuffer) {
+        self.grid = grid;
+        self.indices.clear();
+        self.mark_all_dirty();
+    }
+
+    /// Replace the canvas area with a flat buffer of the same size
+    pub fn set_pixels(&mut self, pixels: &PixelBuffer) {
+        if !self.is_editable() {
+            return;
+        }
+        if let Some(mask) = self.edited_mask_mut() {
+            let mut values = PixelBuffer::new(pixels.width(), pixels.height());
+            for (x, y, color) in pixels.pixels() {
+                values.set(x, y, gray_to_mask(color));
+            }
+            mask.paste(&values, 0, 0);
+        } else if self.lock_alpha {
+            let mut locked = self.grid.to_pixel_buffer();
+            for (x, y, color) in pixels.pixels() {
+                if let Some(color) = alpha_locked(locked.get_pixel(x, y), color) {
+                    locked.set(x, y, color);
+                }
+            }
+            self.grid.paste(&locked, 0, 0);
+        } else {
+            self.grid.paste(pixels, 0, 0);
+        }
+        self.mark_all_dirty();
+    }
+
+    /// Flatten the canvas area of what tools work on: the mask as greys while it is
+    /// edited, else the pixels
+    pub fn to_pixel_buffer(&self) -> PixelBuffer {
+        match self.edited_mask() {
+            Some(mask) => {
+                let mask = mask.to_pixel_buffer();
+                let mut values = PixelBuffer::new(mask.width(), mask.height());
+                for (x, y, value) in mask.pixels() {
+                    values.set(x, y, mask_to_gray(value));
+                }
+                values
+            }
+            None => self.grid.to_pixel_buffer(),
+        }
+    }
+
+    /// Flatten the canvas area of the layer as it looks, with the mask applied
+    pub fn masked_pixels(&self) -> PixelBuffer {
+        let pixels = self.grid.to_pixel_buffer();
+        if self.mask.is_none() {
+            return pixels;
+        }
+        let mut masked = PixelBuffer::new(pixels.width(), pixels.height());
+        for (x, y, color) in pixels.pixels() {
+            masked.set(x, y, self.masked(x, y, color));
+        }
+        masked
+    }
+
+    /// Snap the pixels of a canvas region to palette entries and record their indices.
+    /// Pixels whose stored index still matches their colour keep it, so duplicate
+    /// palette entries stay distinct.
+    pub fn conform_to_palette(&mut self, rect: DirtyRect, palette: &[egui::Color32], transparent: u8) {
+        let Some(rect) = rect.clamp_to(self.width(), self.height()) else {
+            return;
+        };
+        if self.is_group() {
+            return;
+        }
+        let pixels = self.grid.read_region(rect.min_x as isize, rect.min_y as isize, rect.width(), rect.height());
+        for (px, py, color) in pixels.pixels() {
+            let (x, y) = ((rect.min_x + px) as isize, (rect.min_y + py) as isize);
+            let index = match self.indices.get(x, y) {
+                Some(index) if palette::index_color(palette, index, transparent) == color => Some(index),
+                _ if color.a() == 0 => None,
+                _ => palette::nearest_index(palette, color, transparent),
+            };
+            self.indices.set(x, y, index);
+            let snapped = index.map_or(egui::Color32::TRANSPARENT, |i| palette::index_color(palette, i, transparent));
+            if snapped != color {
+                self.grid.set(x, y, snapped);
+            }
+        }
+    }
+
+    /// Turn the pixels of a canvas region grey
+    pub fn conform_to_grayscale(&mut self, rect: DirtyRect) {
+        let Some(rect) = rect.clamp_to(self.width(), self.height()) else {
+            return;
+        };
+        let pixels = self.grid.read_region(rect.min_x as isize, rect.min_y as isize, rect.width(), rect.height());
+        for (px, py, color) in pixels.pixels() {
+            let gray = palette::to_grayscale(color);
+            if gray != color {
+                self.grid.set((rect.min_x + px) as isize, (rect.min_y + py) as isize, gray);
+            }
+        }
+    }
+
+    /// Repaint every indexed pixel with its palette entry's current colour
+    pub fn recolor_from_palette(&mut self, palette: &[egui::Color32], transparent: u8) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let mut updates = Vec::new();
+        self.indices.for_each_index(|x, y, index| {
+            if (0..width).contains(&x) && (0..height).contains(&y) {
+                updates.push((x, y, palette::index_color(palette, index, transparent)));
+            }
+        });
+        for (x, y, color) in updates {
+            self.grid.set(x, y, color);
+        }
+        self.mark_all_dirty();
+    }
+
+    pub fn mark_dirty(&mut self, rect: DirtyRect) {
+        self.dirty = union_dirty(self.dirty, Some(rect));
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        self.mark_dirty(DirtyRect::full(self.width(), self.height()));
+    }
+
+    /// Hand the accumulated dirty region to the compositor
+    pub fn take_dirty(&mut self) -> Option<DirtyRect> {
+        // Free tiles that were erased completely
+        if let Some(rect) = self.dirty {
+            self.grid.prune(rect);
+        }
+        self.dirty.take()
     }
 }
 
+/// Mask value for a colour painted on it: white shows the layer, black or nothing hides it
+fn gray_to_mask(color: egui::Color32) -> egui::Color32 {
+    let [gray, _, _, alpha] = palette::to_grayscale(color).to_srgba_unmultiplied();
+    let shown = gray as u32 * alpha as u32 / 255;
+    egui::Color32::from_rgba_unmultiplied(0, 0, 0, 255 - shown as u8)
+}
+
+/// How a mask value looks to tools
+fn mask_to_gray(value: egui::Color32) -> egui::Color32 {
+    egui::Color32::from_gray(255 - value.a())
+}
+
+/// What painting `color` over `old` leaves with the alpha locked: the new colour at the
+/// old opacity, or nothing for empty pixels and erasing
+fn alpha_locked(old: egui::Color32, color: egui::Color32) -> Option<egui::Color32> {
+    (old.a() > 0 && color.a() > 0).then(|| {
+        let [r, g, b, _] = color.to_srgba_unmultiplied();
+        egui::Color32::from_rgba_unmultiplied(r, g, b, old.a())
+    })
+}
+
 impl Default for Layer {
     fn default() -> Self {
         Self::new("Layer 1".to_string(), 16, 16, egui::Color32::TRANSPARENT)
@@ -261,12 +1013,719 @@ impl Default for Layer {
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Frame {
     pub layers: Vec<Layer>,
+    // How long the frame is shown during playback and in animated exports
+    #[serde(default = "default_frame_duration")]
+    pub duration_ms: u32,
+}
+
+fn default_frame_duration() -> u32 {
+    crate::constants::DEFAULT_FRAME_DURATION_MS
+}
+
+// Layers are kept bottom to top with each group directly above its children, so the
+// panel can list them top to bottom as a tree by walking the list backwards.
+impl Frame {
+    /// First index of the subtree that ends at layer `i` (just `i` for image layers)
+    pub fn subtree_start(&self, i: usize) -> usize {
+        let depth = self.layers[i].depth;
+        let mut start = i;
+        while start > 0 && self.layers[start - 1].depth > depth {
+            start -= 1;
+        }
+        start
+    }
+
+    /// Group that directly contains layer `i`
+    pub fn parent_of(&self, i: usize) -> Option<usize> {
+        let depth = self.layers[i].depth;
+        (i + 1..self.layers.len()).find(|&j| self.layers[j].depth < depth)
+    }
+
+    /// Whether `ancestor` is `i` or one of the groups containing it
+    pub fn is_within(&self, i: usize, ancestor: usize) -> bool {
+        i <= ancestor && self.subtree_start(ancestor) <= i
+    }
+
+    /// Visible itself and inside visible groups only
+    pub fn is_layer_visible(&self, i: usize) -> bool {
+        let mut current = Some(i);
+        while let Some(j) = current {
+            if !self.layers[j].visible {
+                return false;
+            }
+            current = self.parent_of(j);
+        }
+        true
+    }
+
+    /// Hidden in the layers panel because a containing group is collapsed
+    pub fn is_layer_folded(&self, i: usize) -> bool {
+        let mut current = self.parent_of(i);
+        while let Some(j) = current {
+            if self.layers[j].collapsed {
+                return true;
+            }
+            current = self.parent_of(j);
+        }
+        false
+    }
+
+    /// Top-level entries of `range` as (first index, last index) subtrees, bottom to top
+    pub fn top_level(&self, range: std::ops::Range<usize>) -> Vec<(usize, usize)> {
+        let mut entries = Vec::new();
+        let mut end = range.end;
+        while end > range.start {
+            let start = self.subtree_start(end - 1).max(range.start);
+            entries.push((start, end - 1));
+            end = start;
+        }
+        entries.reverse();
+        entries
+    }
+
+    /// Move layer `i` (with its children) so it sits directly above layer `target`, or
+    /// inside it as the topmost child when `target` is a group and `into` is set.
+    /// Returns the layer's new index.
+    pub fn move_subtree(&mut self, i: usize, target: usize, into: bool) -> usize {
+        if self.is_within(target, i) {
+            return i;
+        }
+        let start = self.subtree_start(i);
+        let mut moved: Vec<Layer> = self.layers.drain(start..=i).collect();
+        let target = if target > i { target - moved.len() } else { target };
+
+        let (insert_at, depth) = if into && self.layers[target].is_group() {
+            (target, self.layers[target].depth + 1)
+        } else {
+            (target + 1, self.layers[target].depth)
+        };
+        let shift = depth as isize - moved.last().map_or(0, |l| l.depth) as isize;
+        for layer in &mut moved {
+            layer.depth = (layer.depth as isize + shift).max(0) as usize;
+        }
+        let count = moved.len();
+        self.layers.splice(insert_at..insert_at, moved);
+        insert_at + count - 1
+    }
+
+    /// Swap layer `i` (with its children) with the sibling above it; returns its new index
+    pub fn move_up(&mut self, i: usize) -> usize {
+        let depth = self.layers[i].depth;
+        match (i + 1..self.layers.len()).find(|&j| self.layers[j].depth <= depth) {
+            Some(sibling) if self.layers[sibling].depth == depth => self.move_subtree(i, sibling, false),
+            _ => i,
+        }
+    }
+
+    /// Swap layer `i` (with its children) with the sibling below it; returns its new index
+    pub fn move_down(&mut self, i: usize) -> usize {
+        let start = self.subtree_start(i);
+        if start == 0 || self.layers[start - 1].depth != self.layers[i].depth {
+            return i;
+        }
+        let sibling = start - 1;
+        let sibling_len = sibling - self.subtree_start(sibling) + 1;
+        self.move_subtree(sibling, i, false);
+        i - sibling_len
+    }
+
+    /// Remove layer `i` together with its children
+    pub fn remove_subtree(&mut self, i: usize) {
+        let start = self.subtree_start(i);
+        self.layers.drain(start..=i);
+    }
 }
 
 impl Default for Frame {
     fn default() -> Self {
         Self {
             layers: vec![Layer::default()],
+            duration_ms: default_frame_duration(),
+        }
+    }
+}
+
+/// Order in which an animation tag plays its frames
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum AnimationDirection {
+    #[default]
+    Forward,
+    Reverse,
+    // Back and forth, turning at each end without showing the end frame twice
+    PingPong,
+    PingPongReverse,
+}
+
+impl AnimationDirection {
+    pub const ALL: [AnimationDirection; 4] = [
+        AnimationDirection::Forward,
+        AnimationDirection::Reverse,
+        AnimationDirection::PingPong,
+        AnimationDirection::PingPongReverse,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnimationDirection::Forward => "Forward",
+            AnimationDirection::Reverse => "Reverse",
+            AnimationDirection::PingPong => "Ping-pong",
+            AnimationDirection::PingPongReverse => "Ping-pong Reverse",
+        }
+    }
+}
+
+/// A named range of frames, such as "walk" or "attack", played on its own
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AnimationTag {
+    pub name: String,
+    // First and last frame, inclusive
+    pub from: usize,
+    pub to: usize,
+    #[serde(with = "color32_serde")]
+    pub color: egui::Color32,
+    #[serde(default)]
+    pub direction: AnimationDirection,
+    // How many times the tag plays (each sweep for ping-pong); 0 loops forever
+    #[serde(default)]
+    pub repeat: u32,
+}
+
+impl AnimationTag {
+    pub fn new(name: String, from: usize, to: usize) -> Self {
+        Self {
+            name,
+            from: from.min(to),
+            to: from.max(to),
+            color: egui::Color32::from_rgb(0, 0, 0),
+            direction: AnimationDirection::Forward,
+            repeat: 0,
+        }
+    }
+
+    pub fn contains(&self, frame: usize) -> bool {
+        (self.from..=self.to).contains(&frame)
+    }
+
+    /// Frames in the order they are shown. With a repeat count this is the whole
+    /// playback; when looping forever it is one cycle of the loop.
+    pub fn play_order(&self, frame_count: usize) -> Vec<usize> {
+        if frame_count == 0 {
+            return Vec::new();
+        }
+        let to = self.to.min(frame_count - 1);
+        let from = self.from.min(to);
+        let forward: Vec<usize> = (from..=to).collect();
+        let backward: Vec<usize> = forward.iter().rev().copied().collect();
+
+        let (first, second) = match self.direction {
+            AnimationDirection::Forward => return forward.repeat(self.repeat.max(1) as usize),
+            AnimationDirection::Reverse => return backward.repeat(self.repeat.max(1) as usize),
+            AnimationDirection::PingPong => (forward, backward),
+            AnimationDirection::PingPongReverse => (backward, forward),
+        };
+        if first.len() == 1 {
+            return first;
+        }
+
+        // A loop ends just before the starting frame comes round again
+        let sweeps = if self.repeat == 0 { 2 } else { self.repeat as usize };
+        let mut order = first.clone();
+        for sweep in 1..sweeps {
+            let pass = if sweep % 2 == 1 { &second } else { &first };
+            order.extend_from_slice(&pass[1..]);
+        }
+        if self.repeat == 0 {
+            order.pop();
+        }
+        order
+    }
+}
+
+/// A rectangle in canvas pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SliceRect {
+    pub x: isize,
+    pub y: isize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl SliceRect {
+    /// Rectangle spanning two corner pixels, inclusive
+    pub fn from_corners(a: (isize, isize), b: (isize, isize)) -> Self {
+        Self {
+            x: a.0.min(b.0),
+            y: a.1.min(b.1),
+            width: a.0.abs_diff(b.0) + 1,
+            height: a.1.abs_diff(b.1) + 1,
+        }
+    }
+
+    pub fn contains(&self, x: isize, y: isize) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width as isize && y < self.y + self.height as isize
+    }
+}
+
+/// Where a slice is from one frame on, until the slice's next key
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SliceKey {
+    pub frame: usize,
+    pub bounds: SliceRect,
+    // 9-slice centre, relative to the bounds; the parts around it are the fixed borders
+    #[serde(default)]
+    pub center: Option<SliceRect>,
+    // Pivot point, relative to the bounds
+    #[serde(default)]
+    pub pivot: Option<(isize, isize)>,
+}
+
+/// A named region of the sprite, e.g. a UI panel or a hit box
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Slice {
+    pub name: String,
+    #[serde(with = "color32_serde")]
+    pub color: egui::Color32,
+    // Sorted by frame
+    pub keys: Vec<SliceKey>,
+}
+
+impl Slice {
+    /// The key in effect on `frame`; none before the slice's first key
+    pub fn key_at(&self, frame: usize) -> Option<&SliceKey> {
+        self.keys.iter().rev().find(|key| key.frame <= frame)
+    }
+
+    /// Add or replace the key on `key.frame`
+    pub fn set_key(&mut self, key: SliceKey) {
+        match self.keys.binary_search_by_key(&key.frame, |k| k.frame) {
+            Ok(i) => self.keys[i] = key,
+            Err(i) => self.keys.insert(i, key),
+        }
+    }
+}
+
+/// A tile placed in a tilemap cell. The flips are Tiled's: the diagonal flip swaps x
+/// and y and is applied first, then the horizontal and vertical flips.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TileRef {
+    pub tile: usize,
+    #[serde(default)]
+    pub flip_x: bool,
+    #[serde(default)]
+    pub flip_y: bool,
+    #[serde(default)]
+    pub flip_diagonal: bool,
+}
+
+impl TileRef {
+    pub fn new(tile: usize) -> Self {
+        Self { tile, ..Default::default() }
+    }
+
+    /// The same placement turned 90° clockwise; only square tiles can be turned
+    pub fn rotated_cw(self) -> Self {
+        Self { flip_x: !self.flip_y, flip_y: self.flip_x, flip_diagonal: !self.flip_diagonal, ..self }
+    }
+
+    /// The same placement turned 90° counter-clockwise
+    pub fn rotated_ccw(self) -> Self {
+        self.rotated_cw().rotated_cw().rotated_cw()
+    }
+
+    /// Pixel of a `width` × `height` tile image shown at (x, y) in the cell
+    pub fn source_pixel(&self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        let (w, h) = if self.flip_diagonal { (height, width) } else { (width, height) };
+        let x = if self.flip_x { w - 1 - x } else { x };
+        let y = if self.flip_y { h - 1 - y } else { y };
+        if self.flip_diagonal { (y, x) } else { (x, y) }
+    }
+}
+
+/// Tiles of one size shared by every tilemap layer that uses them
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Tileset {
+    pub name: String,
+    pub tile_width: usize,
+    pub tile_height: usize,
+    // Tiles stacked top to bottom
+    #[serde(with = "grid_serde")]
+    pub image: TiledBuffer,
+}
+
+impl Tileset {
+    pub fn new(name: String, tile_width: usize, tile_height: usize) -> Self {
+        Self { name, tile_width, tile_height, image: TiledBuffer::new(tile_width, 0) }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.image.height() / self.tile_height.max(1)
+    }
+
+    pub fn tile(&self, index: usize) -> PixelBuffer {
+        self.image.read_region(0, (index * self.tile_height) as isize, self.tile_width, self.tile_height)
+    }
+
+    pub fn set_tile(&mut self, index: usize, pixels: &PixelBuffer) {
+        self.image.paste(pixels, 0, (index * self.tile_height) as isize);
+    }
+
+    /// Append a tile and return its index
+    pub fn push_tile(&mut self, pixels: &PixelBuffer) -> usize {
+        let index = self.tile_count();
+        self.image.set_size(self.tile_width, (index + 1) * self.tile_height);
+        self.set_tile(index, pixels);
+        index
+    }
+
+    /// All tiles laid out in rows of `columns`
+    pub fn sheet(&self, columns: usize) -> PixelBuffer {
+        let columns = columns.max(1);
+        let rows = self.tile_count().div_ceil(columns).max(1);
+        let mut sheet = PixelBuffer::new(columns * self.tile_width, rows * self.tile_height);
+        for i in 0..self.tile_count() {
+            let (x, y) = ((i % columns) * self.tile_width, (i / columns) * self.tile_height);
+            sheet.blit(&self.tile(i), x as isize, y as isize);
+        }
+        sheet
+    }
+
+    /// The tile as it looks when placed with the given flips
+    pub fn placed_tile(&self, tile: TileRef) -> PixelBuffer {
+        let source = self.tile(tile.tile);
+        let (width, height) = if tile.flip_diagonal {
+            (self.tile_height, self.tile_width)
+        } else {
+            (self.tile_width, self.tile_height)
+        };
+        let mut out = PixelBuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = tile.source_pixel(x, y, self.tile_width, self.tile_height);
+                out.set(x, y, source.get_pixel(sx, sy));
+            }
+        }
+        out
+    }
+}
+
+/// Image on a reference layer, kept at its own size and placed on the canvas
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReferenceImage {
+    #[serde(with = "grid_serde")]
+    pub image: TiledBuffer,
+    pub x: f32, // Canvas position of the image's top left corner
+    pub y: f32,
+    pub scale: f32, // Canvas pixels per image pixel
+    #[serde(default)]
+    pub export: bool, // Include the layer in exported and saved images
+    #[serde(default)]
+    pub pickable: bool, // Let the eyedropper pick colours from it
+}
+
+impl ReferenceImage {
+    /// Place an image centred on the canvas, shrunk to fit if it is larger
+    pub fn fitted(image: TiledBuffer, width: usize, height: usize) -> Self {
+        let mut reference = Self { image, x: 0.0, y: 0.0, scale: 1.0, export: false, pickable: false };
+        reference.fit(width, height);
+        reference
+    }
+
+    /// Scale and centre the image to fit inside a canvas
+    pub fn fit(&mut self, width: usize, height: usize) {
+        let (image_width, image_height) = (self.image.width().max(1) as f32, self.image.height().max(1) as f32);
+        self.scale = (width as f32 / image_width).min(height as f32 / image_height).min(1.0);
+        self.x = (width as f32 - image_width * self.scale) / 2.0;
+        self.y = (height as f32 - image_height * self.scale) / 2.0;
+    }
+
+    /// The image as it covers a canvas of the given size. Shrunk images average the
+    /// image pixels under each canvas pixel so photos don't break up.
+    pub fn render(&self, width: usize, height: usize) -> PixelBuffer {
+        let mut out = PixelBuffer::new(width, height);
+        if self.scale <= 0.0 || self.image.width() == 0 || self.image.height() == 0 {
+            return out;
+        }
+        let image = self.image.to_pixel_buffer();
+        let (image_width, image_height) = (image.width() as isize, image.height() as isize);
+        // Samples per canvas pixel along each axis
+        let samples = (1.0 / self.scale).ceil().clamp(1.0, 8.0) as usize;
+        for y in 0..height {
+            for x in 0..width {
+                let (mut sum, mut count) = ([0u32; 4], 0u32);
+                for sy in 0..samples {
+                    for sx in 0..samples {
+                        let cx = x as f32 + (sx as f32 + 0.5) / samples as f32;
+                        let cy = y as f32 + (sy as f32 + 0.5) / samples as f32;
+                        let ix = ((cx - self.x) / self.scale).floor() as isize;
+                        let iy = ((cy - self.y) / self.scale).floor() as isize;
+                        count += 1;
+                        if (0..image_width).contains(&ix) && (0..image_height).contains(&iy) {
+                            let [r, g, b, a] = image.get_pixel(ix as usize, iy as usize).to_srgba_unmultiplied();
+                            // Weight by alpha so transparent pixels don't darken the edges
+                            let a = a as u32;
+                            sum[0] += r as u32 * a;
+                            sum[1] += g as u32 * a;
+                            sum[2] += b as u32 * a;
+                            sum[3] += a;
+                        }
+                    }
+                }
+                let average = |channel: u32| channel.checked_div(sum[3]).map(|value| value as u8);
+                if let (Some(r), Some(g), Some(b)) = (average(sum[0]), average(sum[1]), average(sum[2])) {
+                    out.set(x, y, egui::Color32::from_rgba_unmultiplied(r, g, b, (sum[3] / count) as u8));
+                }
+            }
         }
+        out
+    }
+}
+
+/// Tile placements of a tilemap layer, row by row
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Tilemap {
+    pub tileset: usize,
+    pub columns: usize,
+    pub rows: usize,
+    pub cells: Vec<Option<TileRef>>,
+}
+
+impl Tilemap {
+    pub fn new(tileset: usize, columns: usize, rows: usize) -> Self {
+        Self { tileset, columns, rows, cells: vec![None; columns * rows] }
+    }
+
+    pub fn get(&self, column: usize, row: usize) -> Option<TileRef> {
+        if column < self.columns && row < self.rows {
+            self.cells[row * self.columns + column]
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&mut self, column: usize, row: usize, cell: Option<TileRef>) {
+        if column < self.columns && row < self.rows {
+            self.cells[row * self.columns + column] = cell;
+        }
+    }
+
+    /// Change the grid size, moving existing cells by whole cells
+    pub fn resize(&mut self, columns: usize, rows: usize, offset_x: isize, offset_y: isize) {
+        let mut cells = vec![None; columns * rows];
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let (x, y) = (column as isize + offset_x, row as isize + offset_y);
+                if x >= 0 && y >= 0 && (x as usize) < columns && (y as usize) < rows {
+                    cells[y as usize * columns + x as usize] = self.get(column, row);
+                }
+            }
+        }
+        *self = Self { tileset: self.tileset, columns, rows, cells };
+    }
+}
+
+/// How an auto-tiling terrain picks each cell's tile from its surroundings
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum AutotileKind {
+    // 16 tiles, by which of the four edge neighbours are the same terrain
+    #[default]
+    Edge4,
+    // 47 tiles, by all eight neighbours; a corner only counts when both edges beside it do
+    Blob8,
+    // 16 tiles, by which of the cell's four corners are in the terrain
+    WangCorner,
+}
+
+impl AutotileKind {
+    pub const ALL: [AutotileKind; 3] = [AutotileKind::Edge4, AutotileKind::Blob8, AutotileKind::WangCorner];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AutotileKind::Edge4 => "4-bit Edges (16)",
+            AutotileKind::Blob8 => "8-bit Blob (47)",
+            AutotileKind::WangCorner => "Wang Corners (16)",
+        }
+    }
+
+    /// Drop blob corner bits whose edges aren't both set; other kinds use every mask as is
+    pub fn normalize(&self, mask: u8) -> u8 {
+        if *self != AutotileKind::Blob8 {
+            return mask;
+        }
+        // Bits clockwise from north: N, NE, E, SE, S, SW, W, NW
+        let mut normalized = mask & 0b0101_0101;
+        for corner in [1, 3, 5, 7] {
+            let (before, after) = (corner - 1, (corner + 1) % 8);
+            if mask & (1 << corner) != 0 && mask & (1 << before) != 0 && mask & (1 << after) != 0 {
+                normalized |= 1 << corner;
+            }
+        }
+        normalized
+    }
+
+    /// Every mask a complete set has a tile for
+    pub fn masks(&self) -> Vec<u8> {
+        match self {
+            AutotileKind::Edge4 | AutotileKind::WangCorner => (0..16).collect(),
+            AutotileKind::Blob8 => (0..=255).filter(|&mask| self.normalize(mask) == mask).collect(),
+        }
+    }
+}
+
+/// The tile a terrain uses for one neighbour mask
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TerrainRule {
+    pub mask: u8,
+    pub tile: TileRef,
+}
+
+/// An auto-tiling terrain: the tiles of one tileset that draw it, keyed by neighbour mask
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Terrain {
+    pub name: String,
+    #[serde(with = "color32_serde")]
+    pub color: egui::Color32,
+    pub kind: AutotileKind,
+    pub tileset: usize,
+    #[serde(default)]
+    pub rules: Vec<TerrainRule>,
+}
+
+impl Terrain {
+    pub fn new(name: String, kind: AutotileKind, tileset: usize) -> Self {
+        Self { name, color: egui::Color32::from_rgb(110, 200, 90), kind, tileset, rules: Vec::new() }
+    }
+
+    pub fn rule(&self, mask: u8) -> Option<TileRef> {
+        self.rules.iter().find(|rule| rule.mask == mask).map(|rule| rule.tile)
+    }
+
+    pub fn set_rule(&mut self, mask: u8, tile: Option<TileRef>) {
+        self.rules.retain(|rule| rule.mask != mask);
+        if let Some(tile) = tile {
+            self.rules.push(TerrainRule { mask, tile });
+            self.rules.sort_by_key(|rule| rule.mask);
+        }
+    }
+
+    /// Tile for a mask; an incomplete set falls back to the rule sharing the most bits
+    pub fn tile_for(&self, mask: u8) -> Option<TileRef> {
+        let mask = self.kind.normalize(mask);
+        self.rule(mask).or_else(|| {
+            self.rules.iter()
+                .min_by_key(|rule| (rule.mask ^ mask).count_ones())
+                .map(|rule| rule.tile)
+        })
+    }
+
+    /// Mask of a placed tile, if the tile belongs to this terrain
+    pub fn mask_of(&self, tile: TileRef) -> Option<u8> {
+        self.rules.iter().find(|rule| rule.tile == tile).map(|rule| rule.mask)
+    }
+}
+
+/// Everything a project file stores
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Project {
+    pub frames: Vec<Frame>,
+    #[serde(default)]
+    pub color_mode: ColorMode,
+    #[serde(default, with = "palette_serde")]
+    pub palette: Vec<egui::Color32>,
+    #[serde(default)]
+    pub transparent_index: u8,
+    #[serde(default)]
+    pub tags: Vec<AnimationTag>,
+    #[serde(default)]
+    pub slices: Vec<Slice>,
+    #[serde(default)]
+    pub tilesets: Vec<Tileset>,
+    #[serde(default)]
+    pub terrains: Vec<Terrain>,
+}
+
+impl Project {
+    /// Parse a project file; files that only hold the frame list still load
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum AnyProject {
+            Project(Project),
+            Frames(Vec<Frame>),
+        }
+
+        Ok(match serde_json::from_str(json)? {
+            AnyProject::Project(project) => project,
+            AnyProject::Frames(frames) => Project {
+                frames,
+                color_mode: ColorMode::Rgba,
+                palette: Vec::new(),
+                transparent_index: 0,
+                tags: Vec::new(),
+                slices: Vec::new(),
+                tilesets: Vec::new(),
+                terrains: Vec::new(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(from: usize, to: usize, direction: AnimationDirection, repeat: u32) -> AnimationTag {
+        AnimationTag { direction, repeat, ..AnimationTag::new("tag".to_owned(), from, to) }
+    }
+
+    #[test]
+    fn forward_and_reverse_play_the_range_once_per_repeat() {
+        assert_eq!(tag(1, 3, AnimationDirection::Forward, 0).play_order(5), vec![1, 2, 3]);
+        assert_eq!(tag(1, 3, AnimationDirection::Forward, 2).play_order(5), vec![1, 2, 3, 1, 2, 3]);
+        assert_eq!(tag(1, 3, AnimationDirection::Reverse, 1).play_order(5), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn looping_ping_pong_stops_before_the_first_frame_comes_round() {
+        assert_eq!(tag(0, 3, AnimationDirection::PingPong, 0).play_order(4), vec![0, 1, 2, 3, 2, 1]);
+        assert_eq!(tag(0, 2, AnimationDirection::PingPongReverse, 0).play_order(4), vec![2, 1, 0, 1]);
+    }
+
+    #[test]
+    fn ping_pong_repeats_count_sweeps() {
+        assert_eq!(tag(0, 2, AnimationDirection::PingPong, 1).play_order(3), vec![0, 1, 2]);
+        assert_eq!(tag(0, 2, AnimationDirection::PingPong, 2).play_order(3), vec![0, 1, 2, 1, 0]);
+        assert_eq!(tag(0, 2, AnimationDirection::PingPong, 3).play_order(3), vec![0, 1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn palette_indices_are_saved_with_the_layer() {
+        let mut layer = Layer::new("Indexed".to_owned(), 40, 3, egui::Color32::TRANSPARENT);
+        // Two entries of the same colour stay apart
+        layer.indices.set(0, 0, Some(3));
+        layer.indices.set(1, 0, Some(7));
+        layer.indices.set(39, 2, Some(255));
+        let json = serde_json::to_string(&layer).unwrap();
+        let loaded: Layer = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.indices.get(0, 0), Some(3));
+        assert_eq!(loaded.indices.get(1, 0), Some(7));
+        assert_eq!(loaded.indices.get(39, 2), Some(255));
+        assert_eq!(loaded.indices.get(2, 0), None);
+    }
+
+    #[test]
+    fn layers_saved_without_indices_still_load() {
+        let layer = Layer::new("Old".to_owned(), 2, 2, egui::Color32::RED);
+        let mut json = serde_json::to_value(&layer).unwrap();
+        json.as_object_mut().unwrap().remove("indices");
+        let loaded: Layer = serde_json::from_value(json).unwrap();
+        assert!(loaded.indices.is_empty());
+    }
+
+    #[test]
+    fn tags_are_clamped_to_the_frames_there_are() {
+        assert_eq!(tag(2, 8, AnimationDirection::Forward, 0).play_order(4), vec![2, 3]);
+        assert_eq!(tag(6, 8, AnimationDirection::Forward, 0).play_order(4), vec![3]);
+        assert_eq!(tag(0, 3, AnimationDirection::Forward, 0).play_order(0), Vec::<usize>::new());
+        assert_eq!(tag(1, 1, AnimationDirection::PingPong, 0).play_order(4), vec![1]);
     }
 }
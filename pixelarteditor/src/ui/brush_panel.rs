@@ -0,0 +1,87 @@
+use eframe::egui;
+use crate::brush::BrushShape;
+use crate::editor::PixelArtEditor;
+
+// Largest size a brush is shown at in the brush library
+const BRUSH_BUTTON_SIZE: f32 = 32.0;
+
+impl PixelArtEditor {
+    /// Brush tip settings and the brush library: capture brushes from the selection,
+    /// pick one to paint with, or delete it
+    pub fn show_brush_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Brushes");
+        ui.horizontal_wrapped(|ui| {
+            for shape in BrushShape::ALL {
+                ui.selectable_value(&mut self.brush_shape, shape, shape.name());
+            }
+        });
+        if self.brush_shape == BrushShape::Custom {
+            ui.checkbox(&mut self.brush_own_colors, "Own Colours")
+                .on_hover_text("Paint the brush's colours; off paints its shape in the foreground colour");
+            ui.checkbox(&mut self.brush_aligned, "Align to Grid")
+                .on_hover_text("Tile the brush pattern from the canvas corner so dabs join up seamlessly");
+        } else {
+            ui.horizontal(|ui| {
+                ui.label("Size:");
+                ui.add(egui::DragValue::new(&mut self.brush_size).range(1..=100));
+                if self.brush_shape.has_angle() {
+                    ui.label("Angle:");
+                    ui.add(egui::DragValue::new(&mut self.brush_angle).range(-180.0..=180.0).speed(1.0).suffix("°"));
+                }
+            });
+        }
+
+        if self.brush_textures.len() != self.brushes.len() {
+            self.brush_textures = self.brushes.iter().enumerate().map(|(i, brush)| {
+                let image = egui::ColorImage::from_rgba_unmultiplied([brush.pixels.width(), brush.pixels.height()], brush.pixels.as_bytes());
+                ui.ctx().load_texture(format!("brush_{}", i), image, egui::TextureOptions::NEAREST)
+            }).collect();
+        }
+        let mut picked = None;
+        egui::ScrollArea::vertical()
+            .id_salt("brush_scroll")
+            .max_height(120.0)
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    ui.spacing_mut().item_spacing = egui::vec2(4.0, 4.0);
+                    for (i, (brush, texture)) in self.brushes.iter().zip(&self.brush_textures).enumerate() {
+                        let (width, height) = (brush.pixels.width() as f32, brush.pixels.height() as f32);
+                        let scale = (BRUSH_BUTTON_SIZE / width.max(height)).min(4.0);
+                        let (rect, response) = ui.allocate_exact_size(egui::vec2(BRUSH_BUTTON_SIZE, BRUSH_BUTTON_SIZE), egui::Sense::click());
+                        ui.painter().rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+                        let image_rect = egui::Rect::from_center_size(rect.center(), egui::vec2(width * scale, height * scale));
+                        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                        ui.painter().image(texture.id(), image_rect, uv, egui::Color32::WHITE);
+                        let selected = self.brush_shape == BrushShape::Custom && self.active_brush == Some(i);
+                        let stroke = if selected {
+                            egui::Stroke::new(2.0, ui.visuals().selection.stroke.color)
+                        } else {
+                            egui::Stroke::new(1.0, ui.visuals().widgets.noninteractive.bg_stroke.color)
+                        };
+                        ui.painter().rect_stroke(rect, 0.0, stroke, egui::StrokeKind::Outside);
+                        if response.on_hover_text(format!("{} ({}×{})", brush.name, width, height)).clicked() {
+                            picked = Some(i);
+                        }
+                    }
+                });
+            });
+        if let Some(i) = picked {
+            self.active_brush = Some(i);
+            self.brush_shape = BrushShape::Custom;
+        }
+
+        ui.horizontal(|ui| {
+            let has_pixels = self.selection.is_some() || self.floating.is_some();
+            if ui.add_enabled(has_pixels, egui::Button::new("+ From Selection"))
+                .on_hover_text("Make a brush from the selected pixels")
+                .clicked() {
+                self.capture_brush();
+            }
+            if ui.add_enabled(self.active_brush.is_some(), egui::Button::new("Del"))
+                .on_hover_text("Delete the brush from the library")
+                .clicked() && let Some(i) = self.active_brush {
+                self.remove_brush(i);
+            }
+        });
+    }
+}
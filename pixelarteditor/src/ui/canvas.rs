@@ -1,13 +1,25 @@
 use eframe::egui;
 use crate::editor::PixelArtEditor;
 use crate::types::Tool;
+use crate::brush::Ink;
+use crate::selection_mask::{SelectionMask, SelectionMode};
+use crate::shapes::drag_box;
+use crate::editor::floating::{FloatingSelection, FloatHandle};
 use crate::constants::PIXEL_SIZE;
 
+/// Screen size of a floating selection's handles
+const FLOAT_HANDLE_SIZE: f32 = 8.0;
+/// How far above a floating selection its rotation handle sits, in screen pixels
+const FLOAT_ROTATE_REACH: f32 = 24.0;
+/// Screen radius of the curve tool's point handles
+const CURVE_HANDLE_SIZE: f32 = 5.0;
+
 impl PixelArtEditor {
     pub fn show_canvas(&mut self, ui: &mut egui::Ui) {
+        self.settle_floating();
+        self.update_render_cache();
         let composed = self.get_composed_grid();
-        let height = composed.len();
-        let width = if height > 0 { composed[0].len() } else { 0 };
+        let (width, height) = (composed.width(), composed.height());
 
         let pixel_size = PIXEL_SIZE * self.zoom;
         let canvas_size = egui::vec2(width as f32 * pixel_size, height as f32 * pixel_size);
@@ -44,7 +56,7 @@ impl PixelArtEditor {
                 ui.allocate_space(egui::vec2(padding_x, 0.0));
                 
                 // Allocate the canvas area
-                let (canvas_rect, _response) = ui.allocate_exact_size(canvas_size, egui::Sense::click_and_drag());
+                let (canvas_rect, response) = ui.allocate_exact_size(canvas_size, egui::Sense::click_and_drag());
                 
                 // Handle canvas centering on first load
                 if self.canvas_center_on_start {
@@ -60,62 +72,74 @@ impl PixelArtEditor {
                     }
                 }
 
-                // Performance optimization: Only draw visible pixels
-                let visible_rect = ui.clip_rect();
+                // Checkerboard background: one repeating 2x2 texture, one texel per canvas pixel
+                let checker_id = self.checker_texture_id(ui.ctx());
+                ui.painter().image(
+                    checker_id,
+                    canvas_rect,
+                    egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(width as f32 / 2.0, height as f32 / 2.0)),
+                    egui::Color32::WHITE,
+                );
+
+                // The composed image itself, sampled with nearest neighbour
+                let texture_id = self.sync_canvas_texture(ui.ctx(), &composed);
+                ui.painter().image(
+                    texture_id,
+                    canvas_rect,
+                    egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                    egui::Color32::WHITE,
+                );
+
+                // Only the visible part of the canvas needs grid lines
+                let visible_rect = ui.clip_rect().intersect(canvas_rect);
                 let min_x = ((visible_rect.min.x - canvas_rect.min.x) / pixel_size).floor().max(0.0) as usize;
                 let max_x = ((visible_rect.max.x - canvas_rect.min.x) / pixel_size).ceil().min(width as f32) as usize;
                 let min_y = ((visible_rect.min.y - canvas_rect.min.y) / pixel_size).floor().max(0.0) as usize;
                 let max_y = ((visible_rect.max.y - canvas_rect.min.y) / pixel_size).ceil().min(height as f32) as usize;
 
-                // Draw the canvas background and pixels (only visible ones)
-                for y in min_y..max_y {
+                // Draw grid lines if enabled (only at certain zoom levels)
+                if self.show_grid && pixel_size > 2.0 {
+                    let grid_color = egui::Color32::from_gray(128);
                     for x in min_x..max_x {
-                        let pixel_rect = egui::Rect::from_min_size(
-                            canvas_rect.min + egui::vec2(x as f32 * pixel_size, y as f32 * pixel_size),
-                            egui::vec2(pixel_size, pixel_size),
+                        let line_x = canvas_rect.min.x + x as f32 * pixel_size;
+                        ui.painter().rect_filled(
+                            egui::Rect::from_min_max(
+                                egui::pos2(line_x, visible_rect.min.y),
+                                egui::pos2(line_x + 1.0, visible_rect.max.y),
+                            ),
+                            0.0,
+                            grid_color,
                         );
+                    }
+                    for y in min_y..max_y {
+                        let line_y = canvas_rect.min.y + y as f32 * pixel_size;
+                        ui.painter().rect_filled(
+                            egui::Rect::from_min_max(
+                                egui::pos2(visible_rect.min.x, line_y),
+                                egui::pos2(visible_rect.max.x, line_y + 1.0),
+                            ),
+                            0.0,
+                            grid_color,
+                        );
+                    }
+                }
 
-                        // Draw checkerboard background for transparent pixels
-                        let bg_color = if (x + y) % 2 == 0 {
-                            egui::Color32::from_gray(220)
-                        } else {
-                            egui::Color32::from_gray(240)
-                        };
-
-                        ui.painter().rect_filled(pixel_rect, 0.0, bg_color);
-
-                        // Draw the pixel on top if not transparent
-                        let pixel_color = composed[y][x];
-                        if pixel_color.a() > 0 {
-                            ui.painter().rect_filled(pixel_rect, 0.0, pixel_color);
-                        }
+                // Draw animation effects
+                self.draw_pixel_animation_effects(ui, &canvas_rect, pixel_size);
 
-                        // Draw animation effects
-                        self.draw_pixel_animation_effects(ui, pixel_rect, x, y, pixel_size);
+                // Handle pointer interaction for the whole canvas
+                self.handle_pixel_interaction(ui, &response, &canvas_rect, pixel_size, width, height);
 
-                        // Draw grid lines if enabled (only at certain zoom levels)
-                        if self.show_grid && pixel_size > 2.0 {
-                            ui.painter().rect_filled(
-                                egui::Rect::from_min_size(
-                                    pixel_rect.min,
-                                    egui::vec2(pixel_size, 1.0),
-                                ),
-                                0.0,
-                                egui::Color32::from_gray(128),
-                            );
-                            ui.painter().rect_filled(
-                                egui::Rect::from_min_size(
-                                    pixel_rect.min,
-                                    egui::vec2(1.0, pixel_size),
-                                ),
-                                0.0,
-                                egui::Color32::from_gray(128),
-                            );
-                        }
-
-                        // Handle pixel interaction
-                        self.handle_pixel_interaction(ui, pixel_rect, x, y, width, height, &composed);
-                    }
+                // Pixels a tool is about to paint
+                if let Some((preview_id, bounds)) = self.sync_preview_texture(ui.ctx()) {
+                    let min = canvas_rect.min + egui::vec2(bounds.min_x as f32, bounds.min_y as f32) * pixel_size;
+                    let size = egui::vec2(bounds.width() as f32, bounds.height() as f32) * pixel_size;
+                    ui.painter().image(
+                        preview_id,
+                        egui::Rect::from_min_size(min, size),
+                        egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                        egui::Color32::WHITE,
+                    );
                 }
 
                 // Draw tool overlays
@@ -134,37 +158,43 @@ impl PixelArtEditor {
         self.canvas_scroll_offset = scroll_output.state.offset;
     }
 
-    fn draw_pixel_animation_effects(&self, ui: &mut egui::Ui, pixel_rect: egui::Rect, x: usize, y: usize, pixel_size: f32) {
+    fn draw_pixel_animation_effects(&self, ui: &mut egui::Ui, canvas_rect: &egui::Rect, pixel_size: f32) {
         // Draw sparkle effects for specific tools
         for tool in [Tool::Spray, Tool::Circle, Tool::Dither].iter() {
             let effects = self.get_tool_effects(*tool);
             for effect_pos in effects {
                 let effect_x = effect_pos.x as usize;
                 let effect_y = effect_pos.y as usize;
-                
-                if effect_x == x && effect_y == y {
-                    // Draw sparkle animation
-                    let time = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs_f32();
-                    
-                    let sparkle_alpha = (time * 5.0).sin().abs();
-                    let sparkle_color = egui::Color32::from_rgba_unmultiplied(255, 255, 255, (sparkle_alpha * 255.0) as u8);
-                    
-                    // Draw sparkle cross
-                    let center = pixel_rect.center();
-                    let size = pixel_size * 0.8;
-                    
-                    ui.painter().line_segment(
-                        [center - egui::vec2(size/2.0, 0.0), center + egui::vec2(size/2.0, 0.0)],
-                        egui::Stroke::new(2.0, sparkle_color)
-                    );
-                    ui.painter().line_segment(
-                        [center - egui::vec2(0.0, size/2.0), center + egui::vec2(0.0, size/2.0)],
-                        egui::Stroke::new(2.0, sparkle_color)
-                    );
+                let pixel_rect = egui::Rect::from_min_size(
+                    canvas_rect.min + egui::vec2(effect_x as f32 * pixel_size, effect_y as f32 * pixel_size),
+                    egui::vec2(pixel_size, pixel_size),
+                );
+
+                if !ui.clip_rect().intersects(pixel_rect) {
+                    continue;
                 }
+
+                // Draw sparkle animation
+                let time = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs_f32();
+                
+                let sparkle_alpha = (time * 5.0).sin().abs();
+                let sparkle_color = egui::Color32::from_rgba_unmultiplied(255, 255, 255, (sparkle_alpha * 255.0) as u8);
+                
+                // Draw sparkle cross
+                let center = pixel_rect.center();
+                let size = pixel_size * 0.8;
+                
+                ui.painter().line_segment(
+                    [center - egui::vec2(size/2.0, 0.0), center + egui::vec2(size/2.0, 0.0)],
+                    egui::Stroke::new(2.0, sparkle_color)
+                );
+                ui.painter().line_segment(
+                    [center - egui::vec2(0.0, size/2.0), center + egui::vec2(0.0, size/2.0)],
+                    egui::Stroke::new(2.0, sparkle_color)
+                );
             }
         }
     }
@@ -393,167 +423,356 @@ impl PixelArtEditor {
     }
 
     fn draw_tool_overlays(&self, ui: &mut egui::Ui, canvas_rect: &egui::Rect, width: usize, height: usize, pixel_size: f32) {
-        // Draw line preview
-        if let Some((start_x, start_y)) = self.line_start {
-            if let Some(hover_pos) = ui.input(|i| i.pointer.hover_pos()) {
-                if canvas_rect.contains(hover_pos) {
-                    let rel_pos = hover_pos - canvas_rect.min;
-                    let hover_x = (rel_pos.x / pixel_size) as usize;
-                    let hover_y = (rel_pos.y / pixel_size) as usize;
-                    
-                    if hover_x < width && hover_y < height {
-                        // Draw preview line
-                        let start_pos = canvas_rect.min + egui::vec2(start_x as f32 * pixel_size, start_y as f32 * pixel_size);
-                        let end_pos = canvas_rect.min + egui::vec2(hover_x as f32 * pixel_size, hover_y as f32 * pixel_size);
-                        ui.painter().line_segment([start_pos, end_pos], egui::Stroke::new(2.0, egui::Color32::WHITE));
-                    }
-                }
+        // Draw the gradient's direction while it is dragged
+        if let (Some(start), Some(hover_pos)) = (self.gradient_start, ui.input(|i| i.pointer.hover_pos())) {
+            let start_pos = canvas_rect.min + egui::vec2((start.0 as f32 + 0.5) * pixel_size, (start.1 as f32 + 0.5) * pixel_size);
+            ui.painter().line_segment([start_pos, hover_pos], egui::Stroke::new(2.0, egui::Color32::WHITE));
+            ui.painter().circle_filled(start_pos, 3.0, egui::Color32::WHITE);
+        }
+
+        // Draw the curve's handles, each control point tied to the end it pulls on
+        if let [first, .., last] = self.curve_points[..] {
+            let at = |point| Self::pixel_center(point, canvas_rect, pixel_size);
+            let guide = egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(255, 255, 255, 160));
+            let controls = &self.curve_points[1..self.curve_points.len() - 1];
+            if let (Some(&near_first), Some(&near_last)) = (controls.first(), controls.last()) {
+                ui.painter().line_segment([at(first), at(near_first)], guide);
+                ui.painter().line_segment([at(last), at(near_last)], guide);
+            }
+            for &point in controls {
+                ui.painter().circle(at(point), CURVE_HANDLE_SIZE, egui::Color32::BLACK, egui::Stroke::new(1.5, egui::Color32::WHITE));
+            }
+            for point in [first, last] {
+                ui.painter().circle(at(point), CURVE_HANDLE_SIZE, egui::Color32::WHITE, egui::Stroke::new(1.5, egui::Color32::BLACK));
             }
         }
 
-        // Draw rectangle preview
-        if let Some((start_x, start_y)) = self.rectangle_start {
+        // Draw brush preview: the outline of the pixels one dab would cover
+        if matches!(self.tool, Tool::Pencil | Tool::Eraser) {
             if let Some(hover_pos) = ui.input(|i| i.pointer.hover_pos()) {
                 if canvas_rect.contains(hover_pos) {
-                    let rel_pos = hover_pos - canvas_rect.min;
-                    let hover_x = (rel_pos.x / pixel_size) as usize;
-                    let hover_y = (rel_pos.y / pixel_size) as usize;
-                    
-                    if hover_x < width && hover_y < height {
-                        let min_x = start_x.min(hover_x);
-                        let min_y = start_y.min(hover_y);
-                        let max_x = start_x.max(hover_x);
-                        let max_y = start_y.max(hover_y);
-                        
-                        let rect = egui::Rect::from_min_max(
-                            canvas_rect.min + egui::vec2(min_x as f32 * pixel_size, min_y as f32 * pixel_size),
-                            canvas_rect.min + egui::vec2((max_x + 1) as f32 * pixel_size, (max_y + 1) as f32 * pixel_size)
-                        );
-                        ui.painter().rect_stroke(rect, 0.0, egui::Stroke::new(2.0, egui::Color32::WHITE), egui::epaint::StrokeKind::Middle);
+                    let (hover_x, hover_y) = Self::canvas_position_at(hover_pos, canvas_rect, pixel_size);
+                    let dab = self.brush_dab(hover_x, hover_y, self.selected_color);
+                    if dab.len() > 1 {
+                        // Outline the dab over its own bounds, then mirror the outline for symmetry
+                        let (min_x, max_x) = dab.iter().fold((isize::MAX, isize::MIN), |(lo, hi), p| (lo.min(p.0), hi.max(p.0)));
+                        let (min_y, max_y) = dab.iter().fold((isize::MAX, isize::MIN), |(lo, hi), p| (lo.min(p.1), hi.max(p.1)));
+                        let mut outline = SelectionMask::new((max_x - min_x + 1) as usize, (max_y - min_y + 1) as usize);
+                        for (x, y, _) in dab {
+                            outline.set(x - min_x, y - min_y, true);
+                        }
+                        let (horizontal, vertical) = if self.symmetry_mode { self.symmetry_axis } else { (false, false) };
+                        let copies = [(false, false), (true, false), (false, true), (true, true)]
+                            .into_iter()
+                            .filter(|&(flip_x, flip_y)| (!flip_x || horizontal) && (!flip_y || vertical));
+                        let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(255, 255, 255, 128));
+                        let edges = outline.edges();
+                        for (flip_x, flip_y) in copies {
+                            let corner = |(x, y): (usize, usize)| {
+                                let x = if flip_x { width as isize - (x as isize + min_x) } else { x as isize + min_x };
+                                let y = if flip_y { height as isize - (y as isize + min_y) } else { y as isize + min_y };
+                                canvas_rect.min + egui::vec2(x as f32 * pixel_size, y as f32 * pixel_size)
+                            };
+                            ui.painter().extend(edges.iter().map(|&[a, b]| egui::Shape::line_segment([corner(a), corner(b)], stroke)));
+                        }
                     }
                 }
             }
         }
 
-        // Draw circle preview
-        if let Some((start_x, start_y)) = self.circle_start {
-            if let Some(hover_pos) = ui.input(|i| i.pointer.hover_pos()) {
-                if canvas_rect.contains(hover_pos) {
-                    let rel_pos = hover_pos - canvas_rect.min;
-                    let hover_x = (rel_pos.x / pixel_size) as usize;
-                    let hover_y = (rel_pos.y / pixel_size) as usize;
-                    
-                    if hover_x < width && hover_y < height {
-                        let center = canvas_rect.min + egui::vec2(start_x as f32 * pixel_size + pixel_size/2.0, start_y as f32 * pixel_size + pixel_size/2.0);
-                        let radius = ((hover_x as f32 - start_x as f32).powi(2) + (hover_y as f32 - start_y as f32).powi(2)).sqrt() * pixel_size;
-                        ui.painter().circle_stroke(center, radius, egui::Stroke::new(2.0, egui::Color32::WHITE));
-                    }
+        self.draw_selection_overlays(ui, canvas_rect, pixel_size);
+        if self.show_slices || self.tool == Tool::Slice {
+            self.draw_slice_overlays(ui, canvas_rect, pixel_size);
+        }
+        if self.show_tile_grid || self.tool == Tool::Tile {
+            self.draw_tile_grid(ui, canvas_rect, pixel_size);
+        }
+    }
+
+    fn draw_tile_grid(&self, ui: &mut egui::Ui, canvas_rect: &egui::Rect, pixel_size: f32) {
+        let (Some(tileset), Some(tilemap)) = (self.active_tileset(), self.get_active_layer().tilemap.as_ref()) else {
+            return;
+        };
+        let tileset = &self.tilesets[tileset];
+        let cell = egui::vec2(tileset.tile_width as f32 * pixel_size, tileset.tile_height as f32 * pixel_size);
+        let painter = ui.painter();
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(80, 160, 255, 110));
+        let bottom = canvas_rect.min.y + cell.y * tilemap.rows as f32;
+        let right = canvas_rect.min.x + cell.x * tilemap.columns as f32;
+        for column in 0..=tilemap.columns {
+            let x = canvas_rect.min.x + cell.x * column as f32;
+            painter.line_segment([egui::pos2(x, canvas_rect.min.y), egui::pos2(x, bottom)], stroke);
+        }
+        for row in 0..=tilemap.rows {
+            let y = canvas_rect.min.y + cell.y * row as f32;
+            painter.line_segment([egui::pos2(canvas_rect.min.x, y), egui::pos2(right, y)], stroke);
+        }
+
+        // Cell the tile tool would stamp
+        if self.tool != Tool::Tile {
+            return;
+        }
+        let hovered = ui.input(|i| i.pointer.hover_pos())
+            .and_then(|pos| Self::canvas_pixel_at(pos, canvas_rect, pixel_size, self.get_active_layer().width(), self.get_active_layer().height()))
+            .and_then(|(x, y)| self.tile_cell_at(x, y));
+        if let Some((column, row)) = hovered {
+            let min = canvas_rect.min + egui::vec2(cell.x * column as f32, cell.y * row as f32);
+            painter.rect_stroke(egui::Rect::from_min_size(min, cell), 0.0, egui::Stroke::new(2.0, egui::Color32::WHITE), egui::epaint::StrokeKind::Inside);
+        }
+    }
+
+    /// Screen positions of a floating selection's scale handles and rotation handle
+    fn floating_handles(floating: &FloatingSelection, canvas_rect: &egui::Rect, pixel_size: f32) -> Vec<(FloatHandle, egui::Pos2)> {
+        let min = canvas_rect.min + egui::vec2(floating.x as f32 * pixel_size, floating.y as f32 * pixel_size);
+        let size = egui::vec2(floating.width as f32 * pixel_size, floating.height as f32 * pixel_size);
+        let mut handles = Vec::new();
+        for sy in -1..=1i8 {
+            for sx in -1..=1i8 {
+                if (sx, sy) != (0, 0) {
+                    let offset = egui::vec2((sx + 1) as f32 / 2.0 * size.x, (sy + 1) as f32 / 2.0 * size.y);
+                    handles.push((FloatHandle::Scale(sx, sy), min + offset));
                 }
             }
         }
+        handles.push((FloatHandle::Rotate, min + egui::vec2(size.x / 2.0, -FLOAT_ROTATE_REACH)));
+        handles
+    }
 
-        // Draw selection rectangle
-        if let Some((min_x, min_y, max_x, max_y)) = self.selection_rect {
-            let rect = egui::Rect::from_min_max(
-                canvas_rect.min + egui::vec2(min_x as f32 * pixel_size, min_y as f32 * pixel_size),
-                canvas_rect.min + egui::vec2((max_x + 1) as f32 * pixel_size, (max_y + 1) as f32 * pixel_size)
-            );
-            ui.painter().rect_stroke(rect, 0.0, egui::Stroke::new(1.0, egui::Color32::from_rgb(255, 255, 255)), egui::epaint::StrokeKind::Middle);
-            ui.painter().rect_stroke(rect, 0.0, egui::Stroke::new(1.0, egui::Color32::from_rgb(0, 0, 0)), egui::epaint::StrokeKind::Middle);
+    /// Screen position of the centre of a canvas pixel
+    fn pixel_center(point: (isize, isize), canvas_rect: &egui::Rect, pixel_size: f32) -> egui::Pos2 {
+        canvas_rect.min + egui::vec2((point.0 as f32 + 0.5) * pixel_size, (point.1 as f32 + 0.5) * pixel_size)
+    }
+
+    /// Curve point whose handle is under a screen position, the nearest if several are
+    fn curve_handle_at(points: &[(isize, isize)], pos: egui::Pos2, canvas_rect: &egui::Rect, pixel_size: f32) -> Option<usize> {
+        let reach = (pixel_size / 2.0).max(CURVE_HANDLE_SIZE + 2.0);
+        points.iter().enumerate()
+            .map(|(i, &point)| (i, Self::pixel_center(point, canvas_rect, pixel_size).distance(pos)))
+            .filter(|&(_, distance)| distance <= reach)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Part of the floating selection under a screen position: a handle, or its box to move it
+    fn floating_handle_at(floating: Option<&FloatingSelection>, pos: egui::Pos2, canvas_rect: &egui::Rect, pixel_size: f32) -> Option<FloatHandle> {
+        let floating = floating?;
+        let handle = Self::floating_handles(floating, canvas_rect, pixel_size).into_iter()
+            .find(|(_, at)| at.distance(pos) <= FLOAT_HANDLE_SIZE)
+            .map(|(handle, _)| handle);
+        let (pixels, x, y) = floating.render();
+        let bounds = egui::Rect::from_min_size(
+            canvas_rect.min + egui::vec2(x.min(floating.x) as f32 * pixel_size, y.min(floating.y) as f32 * pixel_size),
+            egui::vec2(pixels.width().max(floating.width) as f32 * pixel_size, pixels.height().max(floating.height) as f32 * pixel_size),
+        );
+        handle.or_else(|| bounds.contains(pos).then_some(FloatHandle::Move))
+    }
+
+    /// Marching ants around the selection, and the outline of a selection being made
+    fn draw_selection_overlays(&self, ui: &mut egui::Ui, canvas_rect: &egui::Rect, pixel_size: f32) {
+        const DASH: f32 = 4.0;
+        let painter = ui.painter();
+        let corner = |(x, y): (usize, usize)| canvas_rect.min + egui::vec2(x as f32 * pixel_size, y as f32 * pixel_size);
+        let center = |(x, y): (isize, isize)| canvas_rect.min + egui::vec2((x as f32 + 0.5) * pixel_size, (y as f32 + 0.5) * pixel_size);
+        let hovered = ui.input(|i| i.pointer.hover_pos()).map(|pos| Self::canvas_position_at(pos, canvas_rect, pixel_size));
+
+        if let Some(selection) = &self.selection {
+            let time = ui.input(|i| i.time) as f32;
+            let mut shapes = Vec::new();
+            for &[a, b] in selection.edges() {
+                let path = [corner(a), corner(b)];
+                shapes.push(egui::Shape::line_segment(path, egui::Stroke::new(1.0, egui::Color32::BLACK)));
+                // Each run starts its dashes at its distance along the diagonal, so the
+                // pattern lines up where runs meet
+                let offset = ((a.0 + a.1) as f32 * pixel_size - time * 8.0).rem_euclid(DASH * 2.0);
+                shapes.extend(egui::Shape::dashed_line_with_offset(&path, egui::Stroke::new(1.0, egui::Color32::WHITE), &[DASH], &[DASH], offset));
+            }
+            painter.extend(shapes);
+            ui.ctx().request_repaint_after(std::time::Duration::from_millis(50));
         }
 
-        // Draw lasso selection
-        if let Some(ref points) = self.lasso_selection {
-            if points.len() > 1 {
-                let path_points: Vec<egui::Pos2> = points.iter()
-                    .map(|(x, y)| canvas_rect.min + egui::vec2(*x as f32 * pixel_size, *y as f32 * pixel_size))
-                    .collect();
-                
-                for i in 0..path_points.len() {
-                    let start = path_points[i];
-                    let end = path_points[(i + 1) % path_points.len()];
-                    ui.painter().line_segment([start, end], egui::Stroke::new(1.0, egui::Color32::WHITE));
+        // Floating selection box with its handles
+        if let Some(floating) = &self.floating {
+            let min = canvas_rect.min + egui::vec2(floating.x as f32 * pixel_size, floating.y as f32 * pixel_size);
+            let size = egui::vec2(floating.width as f32 * pixel_size, floating.height as f32 * pixel_size);
+            let frame = egui::Rect::from_min_size(min, size);
+            painter.rect_stroke(frame, 0.0, egui::Stroke::new(1.0, egui::Color32::BLACK), egui::epaint::StrokeKind::Middle);
+            painter.extend(egui::Shape::dashed_line(&[frame.left_top(), frame.right_top(), frame.right_bottom(), frame.left_bottom(), frame.left_top()], egui::Stroke::new(1.0, egui::Color32::WHITE), DASH, DASH));
+            painter.line_segment([frame.center_top(), frame.center_top() - egui::vec2(0.0, FLOAT_ROTATE_REACH)], egui::Stroke::new(1.0, egui::Color32::WHITE));
+            for (handle, at) in Self::floating_handles(floating, canvas_rect, pixel_size) {
+                if handle == FloatHandle::Rotate {
+                    painter.circle(at, FLOAT_HANDLE_SIZE / 2.0, egui::Color32::WHITE, egui::Stroke::new(1.0, egui::Color32::BLACK));
+                } else {
+                    let square = egui::Rect::from_center_size(at, egui::vec2(FLOAT_HANDLE_SIZE, FLOAT_HANDLE_SIZE));
+                    painter.rect(square, 0.0, egui::Color32::WHITE, egui::Stroke::new(1.0, egui::Color32::BLACK), egui::epaint::StrokeKind::Middle);
                 }
             }
         }
 
-        // Draw active lasso path
-        if self.lasso_active && self.lasso_points.len() > 1 {
-            let path_points: Vec<egui::Pos2> = self.lasso_points.iter()
-                .map(|(x, y)| canvas_rect.min + egui::vec2(*x as f32 * pixel_size, *y as f32 * pixel_size))
-                .collect();
-            
-            for i in 0..path_points.len() - 1 {
-                let start = path_points[i];
-                let end = path_points[i + 1];
-                ui.painter().line_segment([start, end], egui::Stroke::new(1.0, egui::Color32::YELLOW));
+        // Rectangle or ellipse being dragged out
+        if let (Some(start), Some(end)) = (self.selection_start, hovered) {
+            for &[a, b] in self.selection_shape(start, end).edges() {
+                painter.line_segment([corner(a), corner(b)], egui::Stroke::new(1.0, egui::Color32::YELLOW));
             }
         }
 
-        // Draw brush preview
-        if matches!(self.tool, Tool::Pencil | Tool::Eraser) && self.brush_size > 1 {
-            if let Some(hover_pos) = ui.input(|i| i.pointer.hover_pos()) {
-                if canvas_rect.contains(hover_pos) {
-                    let rel_pos = hover_pos - canvas_rect.min;
-                    let hover_x = (rel_pos.x / pixel_size) as usize;
-                    let hover_y = (rel_pos.y / pixel_size) as usize;
-                    
-                    if hover_x < width && hover_y < height {
-                        let brush_radius = (self.brush_size / 2) as f32;
-                        let center = canvas_rect.min + egui::vec2(
-                            hover_x as f32 * pixel_size + pixel_size / 2.0,
-                            hover_y as f32 * pixel_size + pixel_size / 2.0
-                        );
-                        
-                        // Draw brush preview circle
-                        ui.painter().circle_stroke(
-                            center,
-                            brush_radius * pixel_size,
-                            egui::Stroke::new(1.0, egui::Color32::from_rgba_unmultiplied(255, 255, 255, 128))
-                        );
-                    }
+        // Lasso outline so far, with the side to the pointer it would add next
+        if self.lasso_active && !self.lasso_points.is_empty() {
+            let mut path: Vec<egui::Pos2> = self.lasso_points.iter().map(|&point| center(point)).collect();
+            path.extend(hovered.map(center));
+            painter.add(egui::Shape::line(path, egui::Stroke::new(1.0, egui::Color32::YELLOW)));
+        }
+    }
+
+    fn draw_slice_overlays(&self, ui: &mut egui::Ui, canvas_rect: &egui::Rect, pixel_size: f32) {
+        let painter = ui.painter();
+        let to_screen = |x: isize, y: isize| canvas_rect.min + egui::vec2(x as f32 * pixel_size, y as f32 * pixel_size);
+        let screen_rect = |rect: crate::types::SliceRect| egui::Rect::from_min_max(
+            to_screen(rect.x, rect.y),
+            to_screen(rect.x + rect.width as isize, rect.y + rect.height as isize),
+        );
+
+        let frame = self.composite_frame_index();
+        for (i, slice) in self.slices.iter().enumerate() {
+            let Some(key) = slice.key_at(frame) else {
+                continue;
+            };
+            let selected = self.selected_slice == Some(i);
+            let bounds = screen_rect(key.bounds);
+            let stroke = egui::Stroke::new(if selected { 2.0 } else { 1.0 }, slice.color);
+            painter.rect_stroke(bounds, 0.0, stroke, egui::epaint::StrokeKind::Inside);
+            painter.text(
+                bounds.left_top() + egui::vec2(2.0, 1.0),
+                egui::Align2::LEFT_TOP,
+                &slice.name,
+                egui::FontId::proportional(11.0),
+                slice.color,
+            );
+
+            // 9-slice centre as dashed lines across the whole slice
+            if let Some(center) = key.center {
+                let inner = screen_rect(crate::types::SliceRect {
+                    x: key.bounds.x + center.x,
+                    y: key.bounds.y + center.y,
+                    ..center
+                });
+                let dashed = egui::Stroke::new(1.0, slice.color);
+                for x in [inner.left(), inner.right()] {
+                    painter.extend(egui::Shape::dashed_line(&[egui::pos2(x, bounds.top()), egui::pos2(x, bounds.bottom())], dashed, 4.0, 3.0));
                 }
+                for y in [inner.top(), inner.bottom()] {
+                    painter.extend(egui::Shape::dashed_line(&[egui::pos2(bounds.left(), y), egui::pos2(bounds.right(), y)], dashed, 4.0, 3.0));
+                }
+            }
+
+            // Pivot as a cross on the centre of its pixel
+            if let Some((px, py)) = key.pivot {
+                let center = to_screen(key.bounds.x + px, key.bounds.y + py) + egui::vec2(pixel_size, pixel_size) / 2.0;
+                let arm = (pixel_size / 2.0).max(4.0);
+                let pivot_stroke = egui::Stroke::new(1.5, slice.color);
+                painter.line_segment([center - egui::vec2(arm, 0.0), center + egui::vec2(arm, 0.0)], pivot_stroke);
+                painter.line_segment([center - egui::vec2(0.0, arm), center + egui::vec2(0.0, arm)], pivot_stroke);
             }
         }
+
+        // Slice being drawn
+        if let (Some(start), Some(end), None) = (self.slice_drag_start, self.slice_drag_end, self.slice_drag_original) {
+            let rect = screen_rect(crate::types::SliceRect::from_corners(start, end));
+            painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, egui::Color32::WHITE), egui::epaint::StrokeKind::Inside);
+        }
+    }
+
+    /// Map a screen position to the canvas pixel under it, as signed pixel coordinates
+    fn canvas_position_at(pos: egui::Pos2, canvas_rect: &egui::Rect, pixel_size: f32) -> (isize, isize) {
+        let rel_pos = pos - canvas_rect.min;
+        ((rel_pos.x / pixel_size).floor() as isize, (rel_pos.y / pixel_size).floor() as isize)
+    }
+
+    /// Map a screen position to the canvas pixel under it, if it lies on the canvas
+    fn canvas_pixel_at(pos: egui::Pos2, canvas_rect: &egui::Rect, pixel_size: f32, width: usize, height: usize) -> Option<(usize, usize)> {
+        let (x, y) = Self::canvas_position_at(pos, canvas_rect, pixel_size);
+        if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+            Some((x as usize, y as usize))
+        } else {
+            None
+        }
     }
 
     fn handle_pixel_interaction(
         &mut self,
         ui: &mut egui::Ui,
-        pixel_rect: egui::Rect,
-        x: usize,
-        y: usize,
-        _width: usize,
-        _height: usize,
-        composed: &Vec<Vec<egui::Color32>>,
+        response: &egui::Response,
+        canvas_rect: &egui::Rect,
+        pixel_size: f32,
+        width: usize,
+        height: usize,
     ) {
-        let interact_rect = ui.allocate_rect(pixel_rect, egui::Sense::click_and_drag());
         let pointer = ui.input(|i| i.pointer.clone());
         let alt = ui.input(|i| i.modifiers.alt);
+        // Shapes: Shift keeps to pixel-art angles, squares and circles; Ctrl or Alt grows them from the centre
+        let constrain = ui.input(|i| i.modifiers.shift);
+        let centered = ui.input(|i| i.modifiers.ctrl) || alt;
 
-        // Move tool drag logic
-        if self.tool == Tool::Move {
-            if interact_rect.drag_started() {
-                self.move_drag_start = Some((x, y));
-                self.move_layer_snapshot = Some(self.get_active_layer().grid.clone());
-                self.move_last_offset = Some((0, 0));
-                self.push_undo();
+        // Move tool on a floating selection: drag its handles to transform it, or click
+        // away from it to drop it. Dragging a selection lifts its pixels first.
+        let lifts = self.selection.is_some() && !self.get_active_layer().is_reference();
+        if self.tool == Tool::Move && (self.floating.is_some() || lifts) {
+            let canvas_point = |pos: egui::Pos2| ((pos.x - canvas_rect.min.x) / pixel_size, (pos.y - canvas_rect.min.y) / pixel_size);
+            if response.drag_started() && let Some(origin) = pointer.press_origin() {
+                let handle = if self.floating.is_some() {
+                    Self::floating_handle_at(self.floating.as_ref(), origin, canvas_rect, pixel_size)
+                } else {
+                    self.lift_selection();
+                    Some(FloatHandle::Move)
+                };
+                match (handle, &self.floating) {
+                    (Some(handle), Some(floating)) => self.floating_drag = Some((handle, floating.clone(), canvas_point(origin))),
+                    _ => self.commit_floating(),
+                }
+            }
+            if response.dragged() && let (Some((handle, start, from)), Some(pos)) = (self.floating_drag.clone(), pointer.interact_pos()) {
+                let snap = ui.input(|i| i.modifiers.shift);
+                self.drag_floating(handle, &start, from, canvas_point(pos), snap);
+            }
+            if response.drag_stopped() {
+                self.end_floating_drag();
+            }
+            if response.clicked() && let Some(pos) = pointer.interact_pos()
+                && Self::floating_handle_at(self.floating.as_ref(), pos, canvas_rect, pixel_size).is_none() {
+                self.commit_floating();
+            }
+        } else if self.tool == Tool::Move {
+            if response.drag_started() {
+                if let Some(origin) = pointer.press_origin() {
+                    let (start_x, start_y) = Self::canvas_position_at(origin, canvas_rect, pixel_size);
+                    self.move_drag_start = Some((start_x.max(0) as usize, start_y.max(0) as usize));
+                    self.move_layer_snapshot = Some(self.get_active_layer().target_grid().clone());
+                    self.move_last_offset = Some((0, 0));
+                    self.push_undo();
+                }
             }
             if let (Some((start_x, start_y)), Some(snapshot), Some((last_dx, last_dy))) = (
                 self.move_drag_start,
                 &self.move_layer_snapshot,
                 self.move_last_offset,
             ) {
-                if interact_rect.dragged() {
-                    let dx = x as isize - start_x as isize;
-                    let dy = y as isize - start_y as isize;
-                    if dx != last_dx || dy != last_dy {
-                        let shifted = Self::shift_layer_grid(snapshot, dx, dy);
-                        self.get_active_layer_mut().grid = shifted;
-                        self.move_last_offset = Some((dx, dy));
+                if response.dragged() {
+                    if let Some(pos) = pointer.interact_pos() {
+                        let (x, y) = Self::canvas_position_at(pos, canvas_rect, pixel_size);
+                        let dx = x - start_x as isize;
+                        let dy = y - start_y as isize;
+                        if dx != last_dx || dy != last_dy {
+                            // A reference layer moves its image, which may reach past the canvas
+                            if self.get_active_layer().is_reference() {
+                                self.move_reference(dx - last_dx, dy - last_dy);
+                            } else {
+                                let shifted = snapshot.shifted(dx, dy);
+                                self.get_active_layer_mut().set_target_grid(shifted);
+                            }
+                            self.move_last_offset = Some((dx, dy));
+                        }
                     }
                 }
-                if interact_rect.drag_stopped() {
+                if response.drag_stopped() {
                     self.move_drag_start = None;
                     self.move_layer_snapshot = None;
                     self.move_last_offset = None;
@@ -561,18 +780,238 @@ impl PixelArtEditor {
             }
         }
 
-        // Handle clicks
-        if interact_rect.clicked() {
-            match self.tool {
-                Tool::Pencil => {
-                    self.push_undo();
-                    let selected_color = self.selected_color;
-                    self.use_tool_with_animation(Tool::Pencil, x, y, selected_color);
+        // Slice tool: drag out a new slice, or drag an existing one to move it on this frame
+        if self.tool == Tool::Slice {
+            if response.drag_started() && let Some(origin) = pointer.press_origin() {
+                let (x, y) = Self::canvas_position_at(origin, canvas_rect, pixel_size);
+                self.begin_slice_drag(x, y);
+            }
+            if response.dragged() && let Some(pos) = pointer.interact_pos() {
+                let (x, y) = Self::canvas_position_at(pos, canvas_rect, pixel_size);
+                self.update_slice_drag(x, y);
+            }
+            if response.drag_stopped() {
+                self.end_slice_drag();
+            }
+        }
+
+        // Selection tools: drag out a rectangle or ellipse, or draw a lasso. The lasso also
+        // takes polygon corners one click at a time and closes on a double-click.
+        if matches!(self.tool, Tool::Select | Tool::EllipseSelect | Tool::Lasso) {
+            let mode = self.selection_mode_for(ui.input(|i| i.modifiers));
+            let position = pointer.interact_pos().map(|pos| Self::canvas_position_at(pos, canvas_rect, pixel_size));
+            if response.drag_started() && let Some(origin) = pointer.press_origin() {
+                let start = Self::canvas_position_at(origin, canvas_rect, pixel_size);
+                if self.tool != Tool::Lasso {
+                    self.selection_start = Some(start);
+                } else if !self.lasso_active {
+                    self.lasso_points = vec![start];
+                    self.lasso_active = true;
                 }
-                Tool::Eraser => {
-                    self.push_undo();
-                    self.use_tool_with_animation(Tool::Eraser, x, y, eframe::egui::Color32::TRANSPARENT);
+            }
+            if response.dragged() && self.tool == Tool::Lasso && let Some(point) = position
+                && self.lasso_points.last() != Some(&point) {
+                self.lasso_points.push(point);
+            }
+            if response.drag_stopped() {
+                match (self.selection_start.take(), position) {
+                    (Some(start), Some(end)) => self.apply_selection(self.selection_shape(start, end), mode),
+                    _ => self.close_lasso(mode),
+                }
+            }
+            if response.clicked() {
+                if self.tool == Tool::Lasso && let Some(point) = position {
+                    if !self.lasso_active {
+                        self.lasso_points.clear();
+                        self.lasso_active = true;
+                    }
+                    if self.lasso_points.last() != Some(&point) {
+                        self.lasso_points.push(point);
+                    }
+                } else if self.tool != Tool::Lasso && mode == SelectionMode::Replace {
+                    self.deselect();
+                }
+            }
+            if response.double_clicked() {
+                self.close_lasso(mode);
+            }
+            if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.selection_start = None;
+                self.lasso_points.clear();
+                self.lasso_active = false;
+            }
+        }
+
+        // Gradient tool: drag from where the gradient starts to where it ends
+        if self.tool == Tool::Gradient {
+            let position = pointer.interact_pos().map(|pos| Self::canvas_position_at(pos, canvas_rect, pixel_size));
+            if response.drag_started() && let Some(origin) = pointer.press_origin() {
+                self.gradient_start = Some(Self::canvas_position_at(origin, canvas_rect, pixel_size));
+            }
+            if response.dragged() && let (Some(start), Some(end)) = (self.gradient_start, position) {
+                self.preview_gradient(start, end);
+            }
+            if response.drag_stopped() && let Some(start) = self.gradient_start.take() {
+                match position {
+                    Some(end) => {
+                        self.start_tool_animation(Tool::Gradient);
+                        self.apply_gradient(start, end);
+                    }
+                    None => self.preview_overlay = None,
+                }
+            }
+            if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.gradient_start = None;
+                self.preview_overlay = None;
+            }
+        }
+
+        // Unfinished lines, rectangles, circles and polygons are dropped when another tool is picked, and a curve is drawn
+        if self.tool != Tool::Line && self.line_start.is_some()
+            || self.tool != Tool::Rectangle && self.rectangle_start.is_some()
+            || self.tool != Tool::Circle && self.circle_start.is_some()
+            || self.tool != Tool::Polygon && !self.polygon_points.is_empty() {
+            self.line_start = None;
+            self.rectangle_start = None;
+            self.circle_start = None;
+            self.polygon_points.clear();
+            self.preview_overlay = None;
+        }
+        if self.tool != Tool::Curve && !self.curve_points.is_empty() {
+            self.commit_curve();
+        }
+
+        // Ellipse tool: drag from one corner of the ellipse's bounding box to the other
+        if self.tool == Tool::Ellipse {
+            let position = pointer.interact_pos().map(|pos| Self::canvas_position_at(pos, canvas_rect, pixel_size));
+            if response.drag_started() && let Some(origin) = pointer.press_origin() {
+                self.ellipse_start = Some(Self::canvas_position_at(origin, canvas_rect, pixel_size));
+            }
+            if response.dragged() && let (Some(start), Some(end)) = (self.ellipse_start, position) {
+                let (a, b) = drag_box(start, end, constrain, centered);
+                self.preview_shape(&self.ellipse_pixels(a, b));
+            }
+            if response.drag_stopped() && let Some(start) = self.ellipse_start.take() {
+                match position {
+                    Some(end) => {
+                        self.start_tool_animation(Tool::Ellipse);
+                        let (a, b) = drag_box(start, end, constrain, centered);
+                        self.draw_shape(&self.ellipse_pixels(a, b));
+                    }
+                    None => self.preview_overlay = None,
+                }
+            }
+        }
+
+        // Polygon tool: click each corner, then double-click to close the polygon
+        if self.tool == Tool::Polygon {
+            let position = pointer.interact_pos().map(|pos| Self::canvas_position_at(pos, canvas_rect, pixel_size));
+            if response.clicked() && let Some(point) = position && self.polygon_points.last() != Some(&point) {
+                self.polygon_points.push(point);
+            }
+            if response.double_clicked() {
+                self.start_tool_animation(Tool::Polygon);
+                self.close_polygon();
+            } else if !self.polygon_points.is_empty() {
+                // Show the sides so far, with one more running to the pointer
+                let mut corners = self.polygon_points.clone();
+                corners.extend(pointer.hover_pos().map(|pos| Self::canvas_position_at(pos, canvas_rect, pixel_size)));
+                self.preview_shape(&self.polygon_pixels(&corners, false));
+            }
+        }
+
+        // Curve tool: drag out a line, then drag its ends or control points to bend it.
+        // Enter or a click away from the handles draws it, as does dragging out the next one.
+        if self.tool == Tool::Curve {
+            let position = pointer.interact_pos().map(|pos| Self::canvas_position_at(pos, canvas_rect, pixel_size));
+            if response.drag_started() && let Some(origin) = pointer.press_origin() {
+                self.curve_handle = Self::curve_handle_at(&self.curve_points, origin, canvas_rect, pixel_size);
+                if self.curve_handle.is_none() {
+                    self.commit_curve();
+                    self.curve_start = Some(Self::canvas_position_at(origin, canvas_rect, pixel_size));
+                }
+            }
+            if response.dragged() && let Some(point) = position {
+                match (self.curve_handle, self.curve_start) {
+                    (Some(index), _) => self.move_curve_point(index, point),
+                    (None, Some(start)) => self.begin_curve(start, point),
+                    _ => {}
+                }
+            }
+            if response.drag_stopped() {
+                self.curve_start = None;
+                self.curve_handle = None;
+            }
+            let clicked_away = response.clicked() && pointer.interact_pos()
+                .is_some_and(|pos| Self::curve_handle_at(&self.curve_points, pos, canvas_rect, pixel_size).is_none());
+            if (clicked_away || ui.input(|i| i.key_pressed(egui::Key::Enter))) && !self.curve_points.is_empty() {
+                self.start_tool_animation(Tool::Curve);
+                self.commit_curve();
+            }
+        }
+
+        // Lines, rectangles and circles show what the second click would draw
+        let hover = pointer.hover_pos().map(|pos| Self::canvas_position_at(pos, canvas_rect, pixel_size));
+        match (self.tool, self.line_start, self.rectangle_start, self.circle_start, hover) {
+            (Tool::Line, Some((start_x, start_y)), _, _, Some(end)) => {
+                self.preview_shape(&self.line_pixels((start_x as isize, start_y as isize), end, constrain, centered));
+            }
+            (Tool::Rectangle, _, Some((start_x, start_y)), _, Some(end)) => {
+                let (a, b) = drag_box((start_x as isize, start_y as isize), end, constrain, centered);
+                self.preview_shape(&self.rectangle_pixels(a, b));
+            }
+            (Tool::Circle, _, _, Some((start_x, start_y)), Some(edge)) => {
+                self.preview_shape(&self.circle_pixels((start_x as isize, start_y as isize), edge));
+            }
+            _ => {}
+        }
+
+        let shaping = matches!(self.tool, Tool::Line | Tool::Rectangle | Tool::Circle | Tool::Ellipse | Tool::Polygon | Tool::Curve);
+        if shaping && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.cancel_shape();
+        }
+
+        // Pencil and eraser strokes, and right-button erasing with other tools, follow the
+        // pointer past the canvas edges so strokes that leave and come back join up.
+        // Shading ink steps lighter with the left button and darker with the right.
+        let shading = self.tool == Tool::Pencil && self.ink == Ink::Shading && !alt;
+        let stroke_color = if shading && (pointer.primary_down() || pointer.secondary_down()) {
+            Some(self.selected_color)
+        } else if pointer.primary_down() && !alt && matches!(self.tool, Tool::Pencil | Tool::Eraser) {
+            Some(if self.tool == Tool::Pencil { self.selected_color } else { egui::Color32::TRANSPARENT })
+        } else if pointer.secondary_down() && self.tool != Tool::Tile {
+            Some(egui::Color32::TRANSPARENT)
+        } else {
+            None
+        };
+        match (stroke_color, pointer.interact_pos()) {
+            (Some(color), Some(pos)) if response.is_pointer_button_down_on() => {
+                let (x, y) = Self::canvas_position_at(pos, canvas_rect, pixel_size);
+                if self.stroke.is_some() {
+                    self.continue_stroke(x, y);
+                } else {
+                    let tool = if color == egui::Color32::TRANSPARENT { Tool::Eraser } else { Tool::Pencil };
+                    self.start_tool_animation(tool);
+                    self.create_tool_effect(tool, egui::Vec2::new(x as f32, y as f32));
+                    if shading {
+                        self.begin_shading_stroke(x, y, pointer.primary_down());
+                    } else {
+                        self.begin_stroke(x, y, color);
+                    }
                 }
+            }
+            _ => self.end_stroke(),
+        }
+
+        // Everything below acts on the pixel under the pointer
+        let Some((x, y)) = pointer.interact_pos()
+            .and_then(|pos| Self::canvas_pixel_at(pos, canvas_rect, pixel_size, width, height)) else {
+            return;
+        };
+
+        // Handle clicks
+        if response.clicked() {
+            match self.tool {
                 Tool::Bucket => {
                     self.push_undo();
                     let selected_color = self.selected_color;
@@ -584,235 +1023,101 @@ impl PixelArtEditor {
                     self.use_tool_with_animation(Tool::Spray, x, y, selected_color);
                 }
                 Tool::Dither => {
-                    self.push_undo();
+                    // The press already took the undo step and painted
                     let selected_color = self.selected_color;
                     self.use_tool_with_animation(Tool::Dither, x, y, selected_color);
                 }
                 Tool::Eyedropper => {
-                    self.selected_color = composed[y][x];
+                    self.selected_color = self.pick_color(x, y);
                 }
-                Tool::Line => {
-                    if self.line_start.is_none() {
-                        self.line_start = Some((x, y));
-                    } else {
-                        self.push_undo();
-                        if let Some((start_x, start_y)) = self.line_start {
-                            // Simple line drawing using Bresenham's algorithm
-                            let selected_color = self.selected_color;
-                            let layer = self.get_active_layer_mut();
-                            let mut x0 = start_x as i32;
-                            let mut y0 = start_y as i32;
-                            let x1 = x as i32;
-                            let y1 = y as i32;
-                            
-                            let dx = (x1 - x0).abs();
-                            let dy = (y1 - y0).abs();
-                            let sx = if x0 < x1 { 1 } else { -1 };
-                            let sy = if y0 < y1 { 1 } else { -1 };
-                            let mut err = dx - dy;
-                            
-                            loop {
-                                if x0 >= 0 && x0 < layer.width() as i32 && y0 >= 0 && y0 < layer.height() as i32 {
-                                    layer.grid[y0 as usize][x0 as usize] = selected_color;
-                                }
-                                if x0 == x1 && y0 == y1 {
-                                    break;
-                                }
-                                let e2 = 2 * err;
-                                if e2 > -dy {
-                                    err -= dy;
-                                    x0 += sx;
-                                }
-                                if e2 < dx {
-                                    err += dx;
-                                    y0 += sy;
-                                }
-                            }
-                        }
-                        self.line_start = None;
+                Tool::Line => match self.line_start.take() {
+                    None => self.line_start = Some((x, y)),
+                    Some((start_x, start_y)) => {
+                        self.draw_shape(&self.line_pixels((start_x as isize, start_y as isize), (x as isize, y as isize), constrain, centered));
                     }
-                }
-                Tool::Rectangle => {
-                    if self.rectangle_start.is_none() {
-                        self.rectangle_start = Some((x, y));
-                    } else {
-                        self.push_undo();
-                        if let Some((start_x, start_y)) = self.rectangle_start {
-                            let selected_color = self.selected_color;
-                            let fill_shape = self.fill_shape;
-                            let layer = self.get_active_layer_mut();
-                            let min_x = start_x.min(x);
-                            let max_x = start_x.max(x);
-                            let min_y = start_y.min(y);
-                            let max_y = start_y.max(y);
-                            
-                            if fill_shape {
-                                // Fill rectangle
-                                for py in min_y..=max_y {
-                                    for px in min_x..=max_x {
-                                        if px < layer.width() && py < layer.height() {
-                                            layer.grid[py][px] = selected_color;
-                                        }
-                                    }
-                                }
-                            } else {
-                                // Draw rectangle outline
-                                for px in min_x..=max_x {
-                                    if px < layer.width() && min_y < layer.height() {
-                                        layer.grid[min_y][px] = selected_color;
-                                    }
-                                    if px < layer.width() && max_y < layer.height() {
-                                        layer.grid[max_y][px] = selected_color;
-                                    }
-                                }
-                                for py in min_y..=max_y {
-                                    if min_x < layer.width() && py < layer.height() {
-                                        layer.grid[py][min_x] = selected_color;
-                                    }
-                                    if max_x < layer.width() && py < layer.height() {
-                                        layer.grid[py][max_x] = selected_color;
-                                    }
-                                }
-                            }
-                        }
-                        self.rectangle_start = None;
+                },
+                Tool::Rectangle => match self.rectangle_start.take() {
+                    None => self.rectangle_start = Some((x, y)),
+                    Some((start_x, start_y)) => {
+                        let (a, b) = drag_box((start_x as isize, start_y as isize), (x as isize, y as isize), constrain, centered);
+                        self.draw_shape(&self.rectangle_pixels(a, b));
                     }
-                }
-                Tool::Circle => {
-                    if self.circle_start.is_none() {
-                        self.circle_start = Some((x, y));
-                    } else {
-                        self.push_undo();
-                        if let Some((start_x, start_y)) = self.circle_start {
-                            let selected_color = self.selected_color;
-                            let layer = self.get_active_layer_mut();
-                            let radius = ((x as f32 - start_x as f32).powi(2) + (y as f32 - start_y as f32).powi(2)).sqrt() as i32;
-                            
-                            // Simple circle drawing using midpoint algorithm
-                            let mut cx = 0;
-                            let mut cy = radius;
-                            let mut d = 1 - radius;
-                            
-                            while cx <= cy {
-                                // Plot 8 octants
-                                let points = [
-                                    (start_x as i32 + cx, start_y as i32 + cy),
-                                    (start_x as i32 - cx, start_y as i32 + cy),
-                                    (start_x as i32 + cx, start_y as i32 - cy),
-                                    (start_x as i32 - cx, start_y as i32 - cy),
-                                    (start_x as i32 + cy, start_y as i32 + cx),
-                                    (start_x as i32 - cy, start_y as i32 + cx),
-                                    (start_x as i32 + cy, start_y as i32 - cx),
-                                    (start_x as i32 - cy, start_y as i32 - cx),
-                                ];
-                                
-                                for (px, py) in points {
-                                    if px >= 0 && px < layer.width() as i32 && py >= 0 && py < layer.height() as i32 {
-                                        layer.grid[py as usize][px as usize] = selected_color;
-                                    }
-                                }
-                                
-                                if d < 0 {
-                                    d += 2 * cx + 3;
-                                } else {
-                                    d += 2 * (cx - cy) + 5;
-                                    cy -= 1;
-                                }
-                                cx += 1;
-                            }
-                        }
-                        self.circle_start = None;
+                },
+                Tool::Circle => match self.circle_start.take() {
+                    None => self.circle_start = Some((x, y)),
+                    Some((start_x, start_y)) => {
+                        self.draw_shape(&self.circle_pixels((start_x as isize, start_y as isize), (x as isize, y as isize)));
                     }
+                },
+                Tool::MagicWand => {
+                    let mode = self.selection_mode_for(ui.input(|i| i.modifiers));
+                    self.magic_wand(x, y, mode);
                 }
-                Tool::Select => {
-                    if self.selection_start.is_none() {
-                        self.selection_start = Some((x, y));
-                    } else {
-                        if let Some((start_x, start_y)) = self.selection_start {
-                            self.selection_rect = Some((
-                                start_x.min(x),
-                                start_y.min(y),
-                                start_x.max(x),
-                                start_y.max(y),
-                            ));
-                        }
-                        self.selection_start = None;
-                    }
+                Tool::Slice => {
+                    self.selected_slice = self.slice_at(self.current_frame, x as isize, y as isize);
                 }
-                Tool::Lasso => {
-                    if !self.lasso_active {
-                        self.lasso_points.clear();
-                        self.lasso_active = true;
+                Tool::Tile => {
+                    if alt {
+                        self.pick_tile(x, y);
+                    } else {
+                        self.push_undo();
+                        self.use_tile_tool(x, y, false);
                     }
-                    self.lasso_points.push((x, y));
                 }
                 _ => {}
             }
-        } else if interact_rect.secondary_clicked() {
+        } else if response.secondary_clicked() && self.tool == Tool::Tile {
             self.push_undo();
-            self.erase_brush(x, y);
+            self.use_tile_tool(x, y, true);
         }
 
         // Handle dragging
-        if interact_rect.hovered() && pointer.primary_down() && !alt {
+        if response.is_pointer_button_down_on() && pointer.primary_down() && !alt {
             match self.tool {
-                Tool::Pencil => {
-                    let selected_color = self.selected_color;
-                    self.paint_brush(x, y, selected_color);
-                }
-                Tool::Eraser => {
-                    self.erase_brush(x, y);
-                }
                 Tool::Spray => {
                     // Simple spray paint implementation
                     let size = self.spray_size;
                     let selected_color = self.selected_color;
-                    let layer = self.get_active_layer_mut();
+                    let mut pixels = Vec::new();
                     for _ in 0..size {
                         let offset_x = (rand::random::<f32>() - 0.5) * size as f32;
                         let offset_y = (rand::random::<f32>() - 0.5) * size as f32;
-                        let spray_x = (x as f32 + offset_x) as i32;
-                        let spray_y = (y as f32 + offset_y) as i32;
+                        let spray_x = (x as f32 + offset_x) as isize;
+                        let spray_y = (y as f32 + offset_y) as isize;
                         
-                        if spray_x >= 0 && spray_x < layer.width() as i32 && spray_y >= 0 && spray_y < layer.height() as i32 {
-                            if rand::random::<f32>() < 0.3 {
-                                layer.grid[spray_y as usize][spray_x as usize] = selected_color;
-                            }
+                        if rand::random::<f32>() < 0.3 {
+                            pixels.push((spray_x, spray_y, selected_color));
                         }
                     }
+                    self.put_pixels(&pixels);
                 }
                 Tool::Dither => {
+                    if pointer.primary_pressed() {
+                        self.push_undo();
+                    }
                     self.apply_dither(x, y, self.selected_color);
                 }
-                Tool::Lasso => {
-                    if self.lasso_active {
-                        self.lasso_points.push((x, y));
+                Tool::Tile => {
+                    if response.drag_started() {
+                        self.push_undo();
                     }
+                    self.use_tile_tool(x, y, false);
                 }
                 _ => {}
             }
         }
 
-        // Handle right-click erasing on drag
-        if interact_rect.hovered() && pointer.secondary_down() {
-            let layer = self.get_active_layer_mut();
-            if x < layer.width() && y < layer.height() {
-                layer.grid[y][x] = egui::Color32::TRANSPARENT;
-            }
+        // Right-dragging with the tile tool erases tiles or terrain; other tools erase pixels with a stroke
+        if response.is_pointer_button_down_on() && pointer.secondary_down() && self.tool == Tool::Tile {
+            self.use_tile_tool(x, y, true);
         }
 
-        // Handle Alt+Click for Eyedropper
-        if interact_rect.clicked() && alt {
-            self.selected_color = composed[y][x];
+        // Handle Alt+Click for Eyedropper; selection tools subtract with Alt, shapes draw from the centre
+        // and the tile tool picks a tile instead
+        let selecting = matches!(self.tool, Tool::Select | Tool::EllipseSelect | Tool::Lasso | Tool::MagicWand);
+        if response.clicked() && alt && !selecting && !shaping && self.tool != Tool::Tile {
+            self.selected_color = self.pick_color(x, y);
         }
 
-        // Handle mouse release for lasso tool
-        if interact_rect.drag_stopped() && self.tool == Tool::Lasso && self.lasso_active {
-            if !self.lasso_points.is_empty() {
-                // Close the lasso path and create selection
-                self.lasso_selection = Some(self.lasso_points.clone());
-                self.lasso_active = false;
-            }
-        }
     }
 }
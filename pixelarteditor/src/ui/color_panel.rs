@@ -1,5 +1,6 @@
 use eframe::egui;
 use crate::editor::PixelArtEditor;
+use crate::types::ColorMode;
 
 impl PixelArtEditor {
     pub fn show_color_panel(&mut self, ui: &mut egui::Ui) {
@@ -52,54 +53,105 @@ impl PixelArtEditor {
             });
 
         ui.add_space(10.0);
+        ui.horizontal(|ui| {
+            ui.label("Mode:");
+            let mut mode = self.color_mode;
+            egui::ComboBox::from_id_salt("color_mode")
+                .selected_text(mode.name())
+                .show_ui(ui, |ui| {
+                    for m in ColorMode::ALL {
+                        ui.selectable_value(&mut mode, m, m.name());
+                    }
+                });
+            if mode != self.color_mode {
+                self.set_color_mode(mode);
+            }
+        });
         ui.label("Color Palette:");
 
         let palette_size = 30.0;
         let columns = 4;
+        let indexed = self.color_mode == ColorMode::Indexed;
 
         egui::ScrollArea::vertical()
             .id_salt("color_palette_scroll")
             .show(ui, |ui| {
-                let mut row = Vec::new();
+                for (row_start, row) in self.color_palette.clone().chunks(columns).enumerate() {
+                    ui.horizontal(|ui| {
+                        for (j, color) in row.iter().enumerate() {
+                            let index = row_start * columns + j;
+                            let (rect, response) = ui.allocate_exact_size(
+                                egui::vec2(palette_size, palette_size),
+                                egui::Sense::click(),
+                            );
+
+                            if response.clicked() {
+                                self.selected_color = *color;
+                                self.selected_palette_index = Some(index);
+                            } else if response.secondary_clicked() {
+                                self.secondary_color = *color;
+                            }
 
-                for (i, color) in self.color_palette.iter().enumerate() {
-                    row.push(*color);
+                            ui.painter().rect_filled(rect, 4.0, *color);
 
-                    if (i + 1) % columns == 0 || i == self.color_palette.len() - 1 {
-                        ui.horizontal(|ui| {
-                            for color in &row {
-                                let (rect, response) = ui.allocate_exact_size(
-                                    egui::vec2(palette_size, palette_size),
-                                    egui::Sense::click(),
+                            if *color == self.selected_color {
+                                ui.painter().rect_filled(
+                                    rect.shrink(2.0),
+                                    4.0,
+                                    egui::Color32::WHITE,
                                 );
+                            } else if *color == self.secondary_color {
+                                ui.painter().rect_filled(
+                                    rect.shrink(2.0),
+                                    4.0,
+                                    egui::Color32::LIGHT_GRAY,
+                                );
+                            }
 
-                                if response.clicked() {
-                                    self.selected_color = *color;
-                                } else if response.secondary_clicked() {
-                                    self.secondary_color = *color;
-                                }
-
-                                ui.painter().rect_filled(rect, 4.0, *color);
-
-                                if *color == self.selected_color {
-                                    ui.painter().rect_filled(
-                                        rect.shrink(2.0),
-                                        4.0,
-                                        egui::Color32::WHITE,
-                                    );
-                                } else if *color == self.secondary_color {
-                                    ui.painter().rect_filled(
-                                        rect.shrink(2.0),
-                                        4.0,
-                                        egui::Color32::LIGHT_GRAY,
-                                    );
-                                }
+                            // Cross out the entry that stands for transparency
+                            if indexed && index == self.transparent_index as usize {
+                                let stroke = egui::Stroke::new(2.0, egui::Color32::RED);
+                                ui.painter().line_segment([rect.left_top(), rect.right_bottom()], stroke);
                             }
-                        });
-                        row.clear();
+                        }
+                    });
+                }
+            });
+
+        if indexed {
+            // Editing an entry recolours every pixel that uses it
+            if let Some(index) = self.selected_palette_index.filter(|&i| i < self.color_palette.len()) {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Index {}:", index));
+                    let mut color = self.color_palette[index];
+                    let response = egui::color_picker::color_edit_button_srgba(
+                        ui,
+                        &mut color,
+                        egui::color_picker::Alpha::BlendOrAdditive,
+                    );
+                    if response.changed() {
+                        self.set_palette_color(index, color);
+                        self.selected_color = color;
                     }
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.label("Transparent index:");
+                let mut transparent = self.transparent_index;
+                let max = self.color_palette.len().clamp(1, 256) - 1;
+                let response = ui.add(egui::DragValue::new(&mut transparent).range(0..=max as u8));
+                if response.drag_started() || (response.changed() && !response.dragged()) {
+                    self.push_undo();
                 }
+                self.set_transparent_index(transparent);
             });
+            ui.horizontal(|ui| {
+                if self.color_palette.len() < 256 && ui.button("+ Add to Palette").clicked() && !self.color_palette.contains(&self.selected_color) {
+                    self.color_palette.push(self.selected_color);
+                }
+                ui.label(format!("{}/256", self.color_palette.len()));
+            });
+        }
 
         ui.separator();
         
@@ -1,6 +1,7 @@
 use eframe::egui;
 use crate::editor::PixelArtEditor;
-use crate::types::Layer;
+use crate::types::{Layer, SliceRect};
+use crate::constants::MAX_CANVAS_SIZE;
 
 impl PixelArtEditor {
     pub fn show_new_sprite_dialog(&mut self, ctx: &egui::Context) {
@@ -13,32 +14,16 @@ impl PixelArtEditor {
                     ui.label("Canvas Size:");
                     ui.add(
                         egui::DragValue::new(&mut self.new_sprite_width)
-                            .range(1..=1024)
+                            .range(1..=MAX_CANVAS_SIZE)
                             .prefix("W: "),
                     );
                     ui.add(
                         egui::DragValue::new(&mut self.new_sprite_height)
-                            .range(1..=1024)
+                            .range(1..=MAX_CANVAS_SIZE)
                             .prefix("H: "),
                     );
                 });
 
-                // Warning for large canvases
-                if self.new_sprite_width > 200 || self.new_sprite_height > 200 {
-                    ui.colored_label(
-                        egui::Color32::from_rgb(255, 165, 0),
-                        format!("WARNING: Large canvas ({}x{}) may affect performance", 
-                               self.new_sprite_width, self.new_sprite_height)
-                    );
-                }
-
-                if self.new_sprite_width > 500 || self.new_sprite_height > 500 {
-                    ui.colored_label(
-                        egui::Color32::from_rgb(255, 80, 80),
-                        "ERROR: Very large canvas - may cause lag"
-                    );
-                }
-
                 ui.horizontal(|ui| {
                     ui.label("Background:");
                     egui::color_picker::color_edit_button_srgba(
@@ -55,18 +40,23 @@ impl PixelArtEditor {
                         let h = self.new_sprite_height;
                         let bg = self.new_sprite_bg;
 
-                        let layer = Layer {
-                            name: "Background".to_string(),
-                            visible: true,
-                            opacity: 1.0,
-                            grid: vec![vec![bg; w]; h],
-                        };
+                        let layer = Layer::new("Background".to_string(), w, h, bg);
 
                         self.frames = vec![crate::types::Frame {
                             layers: vec![layer],
+                            ..Default::default()
                         }];
+                        self.tags.clear();
+                        self.slices.clear();
+                        self.selected_slice = None;
+                        self.tilesets.clear();
+                        self.selected_tile = None;
+                        self.terrains.clear();
+                        self.active_terrain = None;
+                        self.deselect();
                         self.current_frame = 0;
                         self.current_layer = 0;
+                        self.invalidate_cache();
                         self.show_new_sprite_dialog = false;
                     }
                     if ui.button("Cancel").clicked() {
@@ -86,32 +76,16 @@ impl PixelArtEditor {
                     ui.label("New Size:");
                     ui.add(
                         egui::DragValue::new(&mut self.resize_width)
-                            .range(1..=1024)
+                            .range(1..=MAX_CANVAS_SIZE)
                             .prefix("W: "),
                     );
                     ui.add(
                         egui::DragValue::new(&mut self.resize_height)
-                            .range(1..=1024)
+                            .range(1..=MAX_CANVAS_SIZE)
                             .prefix("H: "),
                     );
                 });
 
-                // Warning for large canvases
-                if self.resize_width > 200 || self.resize_height > 200 {
-                    ui.colored_label(
-                        egui::Color32::from_rgb(255, 165, 0),
-                        format!("Warning: Large canvas ({}x{}) may affect performance", 
-                               self.resize_width, self.resize_height)
-                    );
-                }
-
-                if self.resize_width > 500 || self.resize_height > 500 {
-                    ui.colored_label(
-                        egui::Color32::from_rgb(255, 80, 80),
-                        "Error: Very large canvas - may cause lag"
-                    );
-                }
-
                 ui.horizontal(|ui| {
                     ui.label("Anchor:");
                     ui.radio_value(&mut self.resize_anchor, 0, "Top-Left");
@@ -131,4 +105,57 @@ impl PixelArtEditor {
                 });
             });
     }
+
+    pub fn show_nine_slice_preview_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_nine_slice_preview;
+        egui::Window::new("9-Slice Preview")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let frame = self.current_frame;
+                let key = self.selected_slice
+                    .and_then(|i| self.slices.get(i))
+                    .and_then(|slice| slice.key_at(frame))
+                    .copied();
+                let (Some(index), Some(key)) = (self.selected_slice, key) else {
+                    ui.label("Select a slice on this frame to preview it");
+                    return;
+                };
+
+                ui.horizontal(|ui| {
+                    ui.label("Size:");
+                    ui.add(egui::DragValue::new(&mut self.nine_slice_preview_size.0).range(1..=256).prefix("W: "));
+                    ui.add(egui::DragValue::new(&mut self.nine_slice_preview_size.1).range(1..=256).prefix("H: "));
+                });
+                if key.center.is_none() {
+                    ui.label("This slice has no 9-slice centre, so it stretches evenly");
+                }
+
+                let Some(source) = self.slice_image(index, frame) else {
+                    return;
+                };
+                // Without a centre the whole slice stretches
+                let center = key.center.unwrap_or(SliceRect { x: 0, y: 0, width: key.bounds.width, height: key.bounds.height });
+                let (width, height) = self.nine_slice_preview_size;
+                let preview = crate::editor::slices::nine_slice_stretch(&source, center, width, height);
+
+                let scale = (256.0 / width.max(height) as f32).clamp(1.0, 8.0);
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(width as f32 * scale, height as f32 * scale), egui::Sense::hover());
+                let painter = ui.painter_at(rect);
+                painter.rect_filled(rect, 0.0, egui::Color32::from_gray(40));
+                for y in 0..height {
+                    for x in 0..width {
+                        let Some(color) = preview.get(x, y) else {
+                            continue;
+                        };
+                        if color.a() == 0 {
+                            continue;
+                        }
+                        let min = rect.min + egui::vec2(x as f32 * scale, y as f32 * scale);
+                        painter.rect_filled(egui::Rect::from_min_size(min, egui::vec2(scale, scale)), 0.0, color);
+                    }
+                }
+            });
+        self.show_nine_slice_preview = open;
+    }
 }
@@ -1,6 +1,11 @@
 use eframe::egui;
 use crate::editor::PixelArtEditor;
 use crate::types::Tool;
+use crate::selection_mask::SelectionMode;
+use crate::brush::{BrushShape, Ink};
+use crate::dither::{DitherPattern, ThresholdMap};
+use crate::gradient::{GradientDither, GradientShape};
+use crate::shapes::CurveKind;
 
 impl PixelArtEditor {
     pub fn show_menu_bar(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
@@ -49,6 +54,60 @@ impl PixelArtEditor {
                     self.show_export_dialog = true;
                     ui.close_menu();
                 }
+                if ui
+                    .button("Export Sprite Sheet...")
+                    .on_hover_text("Save all frames in one PNG with a JSON file of frames, tags and slices")
+                    .clicked()
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .set_title("Export Sprite Sheet")
+                        .add_filter("PNG", &["png"])
+                        .save_file() {
+                        if let Err(e) = self.save_sprite_sheet(&path) {
+                            eprintln!("Failed to export sprite sheet: {}", e);
+                        }
+                    }
+                    ui.close_menu();
+                }
+                if ui
+                    .button("Export Tilemap (TMX)...")
+                    .on_hover_text("Save the tilemap layers as a Tiled map with .tsx tilesets")
+                    .clicked()
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .set_title("Export Tilemap")
+                        .add_filter("Tiled Map", &["tmx"])
+                        .save_file() {
+                        if let Err(e) = self.save_tilemap_tmx(&path) {
+                            eprintln!("Failed to export tilemap: {}", e);
+                        }
+                    }
+                    ui.close_menu();
+                }
+                if ui
+                    .button("Export Tilemap (JSON)...")
+                    .on_hover_text("Save the tilemap layers as a JSON map of tile numbers")
+                    .clicked()
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .set_title("Export Tilemap")
+                        .add_filter("JSON", &["json"])
+                        .save_file() {
+                        if let Err(e) = self.save_tilemap_json(&path) {
+                            eprintln!("Failed to export tilemap: {}", e);
+                        }
+                    }
+                    ui.close_menu();
+                }
+                ui.separator();
+                if ui
+                    .button("Import Reference Image...")
+                    .on_hover_text("Add an image to work from as a reference layer")
+                    .clicked()
+                {
+                    self.import_reference_dialog();
+                    ui.close_menu();
+                }
                 ui.separator();
                 if ui
                     .button("Save Project...")
@@ -80,6 +139,21 @@ impl PixelArtEditor {
                     }
                     ui.close_menu();
                 }
+                if ui
+                    .button("Open Aseprite...")
+                    .on_hover_text("Open an Aseprite file (.aseprite, .ase)")
+                    .clicked()
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .set_title("Open Aseprite")
+                        .add_filter("Aseprite", &["aseprite", "ase"])
+                        .pick_file() {
+                        if let Err(e) = self.load_aseprite_file(&path.to_string_lossy()) {
+                            eprintln!("Failed to open Aseprite file: {}", e);
+                        }
+                    }
+                    ui.close_menu();
+                }
                 ui.separator();
                 if ui
                     .button("Undo")
@@ -137,8 +211,11 @@ impl PixelArtEditor {
                 ui.checkbox(&mut self.show_layers_panel, "Show Layers");
                 ui.checkbox(&mut self.show_frames_panel, "Show Frames");
                 ui.checkbox(&mut self.show_color_panel, "Show Colors");
+                ui.checkbox(&mut self.show_tileset_panel, "Show Tileset");
+                ui.checkbox(&mut self.show_brush_panel, "Show Brushes");
                 ui.separator();
                 ui.checkbox(&mut self.show_grid, "Show Grid");
+                ui.checkbox(&mut self.show_slices, "Show Slices");
                 
                 // Animation controls
                 ui.separator();
@@ -193,6 +270,77 @@ impl PixelArtEditor {
                         ui.add(egui::Slider::new(&mut self.onion_opacity, 0.1..=1.0));
                     });
                 }
+
+                // Worker threads for compositing, filters and export
+                ui.separator();
+                ui.label("Performance:");
+                ui.horizontal(|ui| {
+                    ui.label("Threads:");
+                    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+                    let mut threads = self.thread_count;
+                    let changed = ui.add(egui::DragValue::new(&mut threads)
+                        .range(0..=max_threads)
+                        .custom_formatter(|n, _| if n == 0.0 { "Auto".to_string() } else { format!("{}", n) }))
+                        .on_hover_text("0 = one thread per CPU core")
+                        .changed();
+                    if changed {
+                        self.set_thread_count(threads);
+                    }
+                });
+            });
+
+            ui.menu_button("Select", |ui| {
+                if ui.button("Copy (Ctrl+C)").clicked() {
+                    self.copy();
+                    ui.close_menu();
+                }
+                if ui.button("Cut (Ctrl+X)").clicked() {
+                    self.cut();
+                    ui.close_menu();
+                }
+                if ui.button("Paste (Ctrl+V)").clicked() {
+                    self.paste();
+                    ui.close_menu();
+                }
+                if ui.button("Paste as New Layer (Ctrl+Shift+V)").clicked() {
+                    self.paste_as_new_layer();
+                    ui.close_menu();
+                }
+                ui.separator();
+                if ui.button("Select All (Ctrl+A)").clicked() {
+                    self.select_all();
+                    ui.close_menu();
+                }
+                if ui.button("Deselect (Ctrl+D)").clicked() {
+                    self.deselect();
+                    ui.close_menu();
+                }
+                if ui.button("Invert (Ctrl+Shift+I)").clicked() {
+                    self.invert_selection();
+                    ui.close_menu();
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Modify by:");
+                    ui.add(egui::DragValue::new(&mut self.selection_modify_amount).range(1..=64).suffix(" px"));
+                });
+                if ui.button("Grow").clicked() {
+                    self.grow_selection();
+                    ui.close_menu();
+                }
+                if ui.button("Shrink").clicked() {
+                    self.shrink_selection();
+                    ui.close_menu();
+                }
+                if ui.button("Border").clicked() {
+                    self.border_selection();
+                    ui.close_menu();
+                }
+                ui.separator();
+                if ui.add_enabled(self.selection.is_some(), egui::Button::new("Clear Selected Pixels (Delete)")).clicked() {
+                    self.clear_selection();
+                    ui.close_menu();
+                }
             });
 
             ui.menu_button("Tools", |ui| {
@@ -281,6 +429,12 @@ impl PixelArtEditor {
                 ui.label("Ctrl+ -: Zoom Out");
                 ui.label("Ctrl+0: Reset Zoom");
                 ui.label("Ctrl+Home: Center Canvas");
+                ui.label("Ctrl+A / Ctrl+D: Select All / Deselect");
+                ui.label("Ctrl+Shift+I: Invert Selection");
+                ui.label("Delete: Clear Selected Pixels");
+                ui.label("Ctrl+C / Ctrl+X / Ctrl+V: Copy / Cut / Paste as a floating selection, through the system clipboard");
+                ui.label("Ctrl+Shift+V: Paste onto a new layer");
+                ui.label("Enter / Esc: Drop / Cancel a floating selection");
                 ui.separator();
                 ui.label("Alt+Click: Pick color (eyedropper)");
 
@@ -295,14 +449,25 @@ impl PixelArtEditor {
                 ui.label(format!("{} Eraser: Make pixels transparent", self.tool_icon_safe(Tool::Eraser)));
                 ui.label(format!("{} Bucket: Fill connected area with color", self.tool_icon_safe(Tool::Bucket)));
                 ui.label(format!("{} Eyedropper: Pick color from canvas", self.tool_icon_safe(Tool::Eyedropper)));
-                ui.label(format!("{} Move: Move layer content", self.tool_icon_safe(Tool::Move)));
-                ui.label(format!("{} Line: Draw straight lines", self.tool_icon_safe(Tool::Line)));
-                ui.label(format!("{} Rectangle: Draw rectangles", self.tool_icon_safe(Tool::Rectangle)));
+                ui.label(format!("{} Move: Move layer content or the selected pixels, or a reference layer's image; drag handles to scale and rotate pasted pixels", self.tool_icon_safe(Tool::Move)));
+                ui.label(format!("{} Line: Draw straight lines; Shift snaps to 1:1, 2:1 and 3:1 slopes, or set Isometric for 2:1", self.tool_icon_safe(Tool::Line)));
+                ui.label(format!("{} Rectangle: Draw rectangles; Shift makes squares", self.tool_icon_safe(Tool::Rectangle)));
                 ui.label(format!("{} Circle: Draw circles", self.tool_icon_safe(Tool::Circle)));
+                ui.label(format!("{} Ellipse: Drag an ellipse of any width and height; Shift makes circles", self.tool_icon_safe(Tool::Ellipse)));
+                ui.label("Shapes grow from the centre with Ctrl or Alt held");
+                ui.label(format!("{} Polygon: Click corners and double-click to close", self.tool_icon_safe(Tool::Polygon)));
+                ui.label(format!("{} Curve: Drag a line, then drag its control points to bend it (Enter draws)", self.tool_icon_safe(Tool::Curve)));
                 ui.label(format!("{} Select: Select rectangular area", self.tool_icon_safe(Tool::Select)));
-                ui.label(format!("{} Lasso: Select freehand area", self.tool_icon_safe(Tool::Lasso)));
+                ui.label(format!("{} Ellipse Select: Select elliptical area", self.tool_icon_safe(Tool::EllipseSelect)));
+                ui.label(format!("{} Lasso: Select freehand area, or click polygon corners and double-click", self.tool_icon_safe(Tool::Lasso)));
+                ui.label(format!("{} Magic Wand: Select by colour, connected or everywhere", self.tool_icon_safe(Tool::MagicWand)));
+                ui.label("Selecting with Shift adds, Alt subtracts, Shift+Alt intersects; painting stays inside the selection");
+                ui.label(format!("{} Slice: Drag out or move named slices", self.tool_icon_safe(Tool::Slice)));
+                ui.label(format!("{} Tile: Stamp tiles on a tilemap layer or paint a terrain (Alt+Click picks)", self.tool_icon_safe(Tool::Tile)));
                 ui.label(format!("{} Spray: Spray paint effect", self.tool_icon_safe(Tool::Spray)));
-                ui.label(format!("{} Dither: Dithering patterns", self.tool_icon_safe(Tool::Dither)));
+                ui.label(format!("{} Dither: Paint Bayer, line, crosshatch or custom patterns, in one or two colours", self.tool_icon_safe(Tool::Dither)));
+                ui.label(format!("{} Gradient: Drag a linear, radial or angular gradient, smooth or dithered", self.tool_icon_safe(Tool::Gradient)));
+                ui.label("Shading ink (Pencil): Left click steps pixels lighter, right click darker along the palette ramp");
             });
     }
 
@@ -371,7 +536,11 @@ impl PixelArtEditor {
                     let text = if self.animation_playing { "Pause" } else { "Play" };
                     
                     if ui.button(icon).on_hover_text(text).clicked() {
-                        self.animation_playing = !self.animation_playing;
+                        if self.animation_playing {
+                            self.stop_playback();
+                        } else {
+                            self.start_playback(ctx.input(|i| i.time));
+                        }
                     }
                     
                     ui.label(format!("Frame {}/{}", self.current_frame + 1, self.frames.len()));
@@ -414,16 +583,33 @@ impl PixelArtEditor {
                             .on_hover_text("Play/Pause Animation")
                             .clicked()
                         {
-                            self.animation_playing = !self.animation_playing;
                             if self.animation_playing {
-                                self.animation_frame = 0;
-                                self.last_animation_time = ctx.input(|i| i.time);
+                                self.stop_playback();
+                            } else {
+                                self.start_playback(ctx.input(|i| i.time));
+                            }
+                        }
+
+                        // Which frames play; switching restarts playback
+                        let mut playback_tag = self.playback_tag;
+                        let selected = playback_tag
+                            .and_then(|i| self.tags.get(i))
+                            .map_or("All frames".to_string(), |tag| tag.name.clone());
+                        egui::ComboBox::from_id_salt("playback_tag")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut playback_tag, None, "All frames");
+                                for (i, tag) in self.tags.iter().enumerate() {
+                                    ui.selectable_value(&mut playback_tag, Some(i), &tag.name);
+                                }
+                            });
+                        if playback_tag != self.playback_tag {
+                            self.playback_tag = playback_tag;
+                            if self.animation_playing {
+                                self.start_playback(ctx.input(|i| i.time));
                             }
                         }
-                        ui.add(
-                            egui::Slider::new(&mut self.animation_speed, 1.0..=30.0)
-                                .text("FPS"),
-                        );
+
                         if self.animation_playing {
                             ui.label(format!(
                                 "Frame: {}/{}",
@@ -452,37 +638,74 @@ impl PixelArtEditor {
                             ui.add(egui::Slider::new(&mut self.spray_intensity, 0.1..=1.0).text(""));
                         },
                         Tool::Dither => {
-                            ui.label("Dither Pattern");
+                            self.show_dither_settings(ui);
+                        },
+                        Tool::Pencil | Tool::Eraser => {
+                            let brush = match self.active_brush.and_then(|i| self.brushes.get(i)) {
+                                Some(brush) if self.brush_shape == BrushShape::Custom => brush.name.clone(),
+                                _ => format!("{} {}px", self.brush_shape.name(), self.brush_size),
+                            };
+                            ui.label(format!("Brush: {}", brush));
+                            ui.label("Pick tips and custom brushes in the Brushes panel");
+                            if self.tool == Tool::Pencil {
+                                self.show_ink_settings(ui);
+                            }
+                        },
+                        Tool::Gradient => {
+                            self.show_gradient_settings(ui);
+                        },
+                        Tool::Rectangle | Tool::Circle | Tool::Ellipse | Tool::Polygon => {
+                            ui.checkbox(&mut self.fill_shape, "Fill Shape");
+                            match self.tool {
+                                Tool::Polygon => ui.label("Click corners, double-click to close"),
+                                Tool::Circle => ui.label("Click the centre, then a point on the edge"),
+                                _ => ui.label("Shift: Square or circle, Ctrl/Alt: From the centre"),
+                            };
+                        },
+                        Tool::Curve => {
                             ui.horizontal(|ui| {
-                                for i in 0..4 {
-                                    let selected = self.dither_pattern == i;
-                                    if ui.selectable_label(selected, format!("P{}", i + 1)).clicked() {
-                                        self.dither_pattern = i;
+                                for kind in CurveKind::ALL {
+                                    if ui.selectable_label(self.curve_kind == kind, kind.name()).clicked() {
+                                        self.set_curve_kind(kind);
                                     }
                                 }
                             });
-                        },
-                        Tool::Rectangle | Tool::Circle => {
-                            ui.checkbox(&mut self.fill_outline, "Fill Shape");
+                            ui.label("Drag a line, then drag its handles; Enter or click away to draw");
                         },
                         Tool::Line => {
-                            ui.label("Line tool: Click and drag to draw");
+                            ui.checkbox(&mut self.isometric_lines, "Isometric (2:1)")
+                                .on_hover_text("Keep lines to the 2:1 slopes of isometric tiles, or straight across and down");
+                            ui.label("Click the start, then the end");
+                            ui.label("Shift: Pixel-art angles, Ctrl/Alt: From the centre");
+                        },
+                        Tool::Move => {
+                            if self.floating.is_some() {
+                                self.show_floating_settings(ui);
+                            } else {
+                                ui.label("Drag to move the layer, or the selected pixels if there is a selection");
+                            }
                         },
-                        Tool::Select | Tool::Lasso => {
-                            ui.label("Selection tool: Click and drag to select");
-                            if self.selection_area.is_some() {
+                        Tool::Select | Tool::EllipseSelect | Tool::Lasso | Tool::MagicWand => {
+                            ui.label(match self.tool {
+                                Tool::Lasso => "Drag a freehand outline, or click corners and double-click to close",
+                                Tool::MagicWand => "Click a colour to select it",
+                                _ => "Click and drag to select",
+                            });
+                            ui.horizontal(|ui| {
+                                for mode in SelectionMode::ALL {
+                                    ui.selectable_value(&mut self.selection_mode, mode, mode.name());
+                                }
+                            });
+                            ui.label("Shift: Add, Alt: Subtract, Shift+Alt: Intersect");
+                            if self.tool == Tool::MagicWand {
                                 ui.horizontal(|ui| {
-                                    if ui.button("Copy").clicked() {
-                                        self.copy_selection();
-                                    }
-                                    if ui.button("Cut").clicked() {
-                                        self.cut_selection();
-                                    }
-                                    if ui.button("Clear").clicked() {
-                                        self.clear_selection();
-                                    }
+                                    ui.label("Tolerance:");
+                                    ui.add(egui::Slider::new(&mut self.wand_tolerance, 0..=255));
                                 });
+                                ui.checkbox(&mut self.wand_contiguous, "Contiguous");
                             }
+                            ui.separator();
+                            self.show_selection_actions(ui);
                         },
                         _ => {
                             ui.label("Standard tool settings");
@@ -492,41 +715,213 @@ impl PixelArtEditor {
             });
     }
 
-    fn copy_selection(&mut self) {
-        // Implementation for copying selection
-        if let Some((x, y, w, h)) = self.selection_area {
-            let layer = self.get_active_layer();
-            let mut selected_pixels = vec![vec![egui::Color32::TRANSPARENT; w]; h];
-            
-            for dy in 0..h {
-                for dx in 0..w {
-                    if x + dx < layer.width() && y + dy < layer.height() {
-                        selected_pixels[dy][dx] = layer.grid[y + dy][x + dx];
+    fn show_ink_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Ink:");
+            for ink in Ink::ALL {
+                ui.selectable_value(&mut self.ink, ink, ink.name());
+            }
+        });
+        if self.ink != Ink::Shading {
+            return;
+        }
+        ui.label("Left click shades lighter, right click darker");
+        ui.label("Ramp: click colours to shade through only those");
+        const SWATCH_SIZE: f32 = 14.0;
+        let palette = self.custom_palettes[self.active_palette].clone();
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing = egui::vec2(2.0, 2.0);
+            for color in palette {
+                let (rect, response) = ui.allocate_exact_size(egui::vec2(SWATCH_SIZE, SWATCH_SIZE), egui::Sense::click());
+                ui.painter().rect_filled(rect, 0.0, color);
+                let picked = self.shading_colors.contains(&color);
+                if picked {
+                    ui.painter().rect_stroke(rect, 0.0, egui::Stroke::new(2.0, ui.visuals().selection.stroke.color), egui::StrokeKind::Outside);
+                }
+                if response.clicked() {
+                    if picked {
+                        self.shading_colors.retain(|&c| c != color);
+                    } else {
+                        self.shading_colors.push(color);
                     }
                 }
             }
-            
-            self.selection_pixels = Some(selected_pixels);
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Whole Palette").clicked() {
+                self.shading_colors.clear();
+            }
+            let ramp = self.shading_ramp();
+            // The ramp as shading walks it
+            for color in ramp.iter().take(32) {
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(SWATCH_SIZE / 2.0, SWATCH_SIZE), egui::Sense::hover());
+                ui.painter().rect_filled(rect, 0.0, *color);
+            }
+        });
+    }
+
+    fn show_gradient_settings(&mut self, ui: &mut egui::Ui) {
+        ui.label("Drag from where the gradient starts to where it ends");
+        ui.horizontal(|ui| {
+            for shape in GradientShape::ALL {
+                ui.selectable_value(&mut self.gradient_shape, shape, shape.name());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.gradient_palette_ramp, false, "Primary → Secondary");
+            ui.selectable_value(&mut self.gradient_palette_ramp, true, "Palette Ramp");
+        });
+        egui::ComboBox::from_label("Dither")
+            .selected_text(self.gradient_dither.name())
+            .show_ui(ui, |ui| {
+                for dither in GradientDither::ALL {
+                    ui.selectable_value(&mut self.gradient_dither, dither, dither.name());
+                }
+            });
+        if self.gradient_dither == GradientDither::Custom {
+            self.show_custom_dither_editor(ui);
         }
     }
 
-    fn cut_selection(&mut self) {
-        self.push_undo();
-        self.copy_selection();
-        self.clear_selection();
+    fn show_dither_settings(&mut self, ui: &mut egui::Ui) {
+        ui.label("Paints the pattern in the brush's shape, lined up with the canvas");
+        egui::ComboBox::from_label("Pattern")
+            .selected_text(self.dither_pattern.name())
+            .show_ui(ui, |ui| {
+                for pattern in DitherPattern::ALL {
+                    ui.selectable_value(&mut self.dither_pattern, pattern, pattern.name());
+                }
+            });
+        if self.dither_pattern.has_density() {
+            ui.horizontal(|ui| {
+                ui.label("Density:");
+                ui.add(egui::Slider::new(&mut self.dither_density, 0.0..=1.0).custom_formatter(|n, _| format!("{:.0}%", n * 100.0)));
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Brush Size:");
+            ui.add(egui::DragValue::new(&mut self.brush_size).range(1..=100));
+        });
+        ui.checkbox(&mut self.dither_two_color, "Two Colours")
+            .on_hover_text("Fill the gaps in the pattern with the secondary colour");
+        if self.dither_pattern == DitherPattern::Custom {
+            self.show_custom_dither_editor(ui);
+        }
     }
 
-    fn clear_selection(&mut self) {
-        if let Some((x, y, w, h)) = self.selection_area {
-            let layer = self.get_active_layer_mut();
-            
-            for dy in 0..h {
-                for dx in 0..w {
-                    if x + dx < layer.width() && y + dy < layer.height() {
-                        layer.grid[y + dy][x + dx] = egui::Color32::TRANSPARENT;
+    /// Edit the user-defined threshold map shared by the dither and gradient tools
+    fn show_custom_dither_editor(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Start from:");
+            for size in [2, 4, 8] {
+                if ui.button(format!("Bayer {}×{}", size, size)).clicked() {
+                    self.custom_dither = ThresholdMap::bayer(size);
+                }
+            }
+        });
+        ui.label("Lower numbers switch to the next colour first");
+        let max_level = (self.custom_dither.count() - 1) as u8;
+        egui::Grid::new("custom_dither_grid").spacing(egui::vec2(2.0, 2.0)).show(ui, |ui| {
+            for y in 0..self.custom_dither.height() {
+                for x in 0..self.custom_dither.width() {
+                    let mut level = self.custom_dither.level(x, y);
+                    if ui.add(egui::DragValue::new(&mut level).range(0..=max_level)).changed() {
+                        self.custom_dither.set_level(x, y, level);
                     }
                 }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn show_floating_settings(&mut self, ui: &mut egui::Ui) {
+        ui.label("Drag the box to move, its handles to scale and the round handle to rotate (Shift snaps)");
+        ui.horizontal(|ui| {
+            if ui.button("Flip H").clicked() {
+                self.transform_floating(|floating| floating.flip_x = !floating.flip_x);
+            }
+            if ui.button("Flip V").clicked() {
+                self.transform_floating(|floating| floating.flip_y = !floating.flip_y);
+            }
+            if ui.button("⟲ 90°").clicked() {
+                self.transform_floating(|floating| floating.angle = (floating.angle - 90.0).rem_euclid(360.0));
+            }
+            if ui.button("⟳ 90°").clicked() {
+                self.transform_floating(|floating| floating.angle = (floating.angle + 90.0).rem_euclid(360.0));
+            }
+        });
+        let Some(mut edited) = self.floating.clone() else {
+            return;
+        };
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Angle:");
+            changed |= ui.add(egui::DragValue::new(&mut edited.angle).range(-360.0..=360.0).speed(1.0).suffix("°")).changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Size:");
+            changed |= ui.add(egui::DragValue::new(&mut edited.width).range(1..=4096)).changed();
+            ui.label("×");
+            changed |= ui.add(egui::DragValue::new(&mut edited.height).range(1..=4096)).changed();
+            if ui.button("1:1").on_hover_text("Back to the original size").clicked() {
+                edited.width = edited.source.width();
+                edited.height = edited.source.height();
+                changed = true;
+            }
+        });
+        if changed {
+            self.transform_floating(|floating| {
+                floating.angle = edited.angle;
+                floating.width = edited.width;
+                floating.height = edited.height;
+            });
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Commit (Enter)").clicked() {
+                self.commit_floating();
+            }
+            if ui.button("Cancel (Esc)").clicked() {
+                self.cancel_floating();
+            }
+        });
+    }
+
+    fn show_selection_actions(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("All").clicked() {
+                self.select_all();
+            }
+            if ui.button("None").clicked() {
+                self.deselect();
+            }
+            if ui.button("Invert").clicked() {
+                self.invert_selection();
             }
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.selection_modify_amount).range(1..=64).suffix(" px"));
+            if ui.button("Grow").clicked() {
+                self.grow_selection();
+            }
+            if ui.button("Shrink").clicked() {
+                self.shrink_selection();
+            }
+            if ui.button("Border").clicked() {
+                self.border_selection();
+            }
+        });
+        if self.selection.is_some() {
+            ui.horizontal(|ui| {
+                if ui.button("Copy").clicked() {
+                    self.copy();
+                }
+                if ui.button("Cut").clicked() {
+                    self.cut();
+                }
+                if ui.button("Clear").clicked() {
+                    self.clear_selection();
+                }
+            });
         }
     }
 }
@@ -3,3 +3,5 @@ pub mod panels;
 pub mod color_panel;
 pub mod dialogs;
 pub mod canvas;
+pub mod tileset_panel;
+pub mod brush_panel;
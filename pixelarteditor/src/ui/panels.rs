@@ -1,6 +1,13 @@
 use eframe::egui;
 use crate::editor::PixelArtEditor;
-use crate::types::{Layer, Frame, Tool};
+use crate::types::{Layer, LayerKind, Frame, Tool, BlendMode, SliceKey, SliceRect};
+use crate::tiled_buffer::TiledBuffer;
+
+/// Drag-and-drop payload for reordering layers
+struct DraggedLayer(usize);
+
+/// Change to a layer's mask, made once the layer list has been drawn
+type MaskEdit = fn(&mut Layer);
 
 impl PixelArtEditor {
     pub fn show_layers_panel(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
@@ -10,25 +17,36 @@ impl PixelArtEditor {
 
         let mut layer_to_remove = None;
         let mut add_layer = false;
+        let mut add_group = false;
         let mut layer_to_duplicate = None;
         let mut layer_to_clear = None;
+        let mut layer_drop: Option<(usize, usize, bool)> = None; // (dragged, target, into group)
         let mut should_move_up = false;
         let mut should_move_down = false;
+        let mut layers_changed = false;
+        let mut add_reference = false;
+        let mut mask_edit: Option<(usize, MaskEdit)> = None;
 
         let current_frame = self.current_frame;
         let frame = &mut self.frames[current_frame];
         let layers_len = frame.layers.len();
         let current_layer = self.current_layer;
+        let folded: Vec<bool> = (0..layers_len).map(|i| frame.is_layer_folded(i)).collect();
+        let subtree_sizes: Vec<usize> = (0..layers_len).map(|i| i - frame.subtree_start(i) + 1).collect();
 
         egui::ScrollArea::vertical()
             .id_salt("layers_panel_scroll")
             .max_height(150.0)
             .show(ui, |ui| {
                 for (i, layer) in frame.layers.iter_mut().enumerate().rev() {
+                    if folded[i] {
+                        continue;
+                    }
                     let is_current = current_layer == i;
                     let is_renaming = self.renaming_layer == Some(i);
+                    let indent = (2 + layer.depth * 12).min(i8::MAX as usize) as i8;
 
-                    egui::Frame::group(ui.style())
+                    let row = egui::Frame::group(ui.style())
                         .fill(if is_current {
                             ui.visuals().selection.bg_fill
                         } else {
@@ -36,12 +54,24 @@ impl PixelArtEditor {
                         })
                         .corner_radius(4.0)
                         .inner_margin(8.0)
-                        .outer_margin(2.0)
+                        .outer_margin(egui::Margin { left: indent, right: 2, top: 2, bottom: 2 })
                         .show(ui, |ui| {
                             ui.horizontal(|ui| {
+                                ui.dnd_drag_source(egui::Id::new(("layer_drag", i)), DraggedLayer(i), |ui| {
+                                    ui.label("≡");
+                                }).response.on_hover_text("Drag onto a layer to move it, or onto a group to put it inside");
+
+                                if layer.is_group() {
+                                    let arrow = if layer.collapsed { "▶" } else { "▼" };
+                                    if ui.small_button(arrow).on_hover_text("Expand/collapse group").clicked() {
+                                        layer.collapsed = !layer.collapsed;
+                                    }
+                                }
+
                                 if ui.checkbox(&mut layer.visible, "")
                                     .on_hover_text("Toggle visibility")
                                     .changed() { 
+                                    layers_changed = true;
                                     ctx.request_repaint(); 
                                 }
 
@@ -66,17 +96,26 @@ impl PixelArtEditor {
                                     if ui.selectable_label(is_current, &layer.name).clicked() { 
                                         self.current_layer = i; 
                                     }
+                                    if layer.link.is_some() {
+                                        ui.label("🔗").on_hover_text("Linked cel: shares its image with other frames");
+                                    }
+                                    if layer.tilemap.is_some() {
+                                        ui.label("▦").on_hover_text("Tilemap layer: drawing on a tile changes every placement of it");
+                                    }
+                                    if layer.is_reference() {
+                                        ui.label("🖼").on_hover_text("Reference layer: can't be painted on, and left out of exports unless marked");
+                                    }
                                 }
 
                                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                                     let btn_size = egui::vec2(20.0, 18.0);
-                                    if ui.add(egui::Button::new("Clear").min_size(btn_size)).on_hover_text("Clear Layer").clicked() { 
+                                    if layer.is_editable() && ui.add(egui::Button::new("Clear").min_size(btn_size)).on_hover_text("Clear Layer").clicked() { 
                                         layer_to_clear = Some(i);
                                     }
                                     if ui.add(egui::Button::new("Copy").min_size(btn_size)).on_hover_text("Duplicate").clicked() { 
                                         layer_to_duplicate = Some(i);
                                     }
-                                    if layers_len > 1 && ui.add(egui::Button::new("Del").min_size(btn_size)).on_hover_text("Delete").clicked() { 
+                                    if subtree_sizes[i] < layers_len && ui.add(egui::Button::new("Del").min_size(btn_size)).on_hover_text("Delete").clicked() { 
                                         layer_to_remove = Some(i);
                                     }
                                     if !is_renaming && ui.add(egui::Button::new("Edit").min_size(btn_size)).on_hover_text("Rename").clicked() {
@@ -89,10 +128,92 @@ impl PixelArtEditor {
                             ui.horizontal(|ui| {
                                 ui.label("Opacity:");
                                 if ui.add(egui::Slider::new(&mut layer.opacity, 0.0..=1.0).show_value(true).text("")).changed() { 
+                                    layers_changed = true;
                                     ctx.request_repaint(); 
                                 }
                             });
-                        });
+
+                            ui.horizontal(|ui| {
+                                ui.label("Blend:");
+                                egui::ComboBox::from_id_salt(("layer_blend_mode", i))
+                                    .selected_text(layer.blend_mode.name())
+                                    .show_ui(ui, |ui| {
+                                        for mode in BlendMode::ALL {
+                                            if ui.selectable_value(&mut layer.blend_mode, mode, mode.name()).changed() {
+                                                layers_changed = true;
+                                                ctx.request_repaint();
+                                            }
+                                        }
+                                    });
+                            });
+
+                            if !layer.is_group() && !layer.is_reference() {
+                                ui.horizontal(|ui| {
+                                    ui.toggle_value(&mut layer.lock_pixels, "🔒")
+                                        .on_hover_text("Lock pixels: no tool can change this layer");
+                                    ui.toggle_value(&mut layer.lock_alpha, "α")
+                                        .on_hover_text("Lock alpha: painting only recolours pixels that are already there");
+                                    if layer.mask.is_some() {
+                                        ui.toggle_value(&mut layer.editing_mask, "Mask")
+                                            .on_hover_text("Paint on the mask instead of the layer: black hides, white shows");
+                                        if ui.small_button("Apply").on_hover_text("Bake the mask into the layer").clicked() {
+                                            mask_edit = Some((i, Layer::apply_mask));
+                                        }
+                                        if ui.small_button("✖").on_hover_text("Delete Mask").clicked() {
+                                            mask_edit = Some((i, Layer::remove_mask));
+                                        }
+                                    } else if ui.small_button("+ Mask").on_hover_text("Add a mask that hides parts of the layer without erasing them").clicked() {
+                                        mask_edit = Some((i, |layer| {
+                                            layer.add_mask();
+                                            layer.editing_mask = true;
+                                        }));
+                                    }
+                                });
+                            }
+
+                            // Placement of a reference image; the Move tool drags it too
+                            let (width, height) = (layer.width(), layer.height());
+                            if let Some(reference) = &mut layer.reference {
+                                let mut placed = false;
+                                ui.horizontal(|ui| {
+                                    ui.label("Position:");
+                                    placed |= ui.add(egui::DragValue::new(&mut reference.x).speed(0.5)).changed();
+                                    placed |= ui.add(egui::DragValue::new(&mut reference.y).speed(0.5)).changed();
+                                    ui.label("Scale:");
+                                    placed |= ui.add(egui::DragValue::new(&mut reference.scale).speed(0.01).range(0.01..=64.0)).changed();
+                                    if ui.small_button("Fit").on_hover_text("Fit the image inside the canvas").clicked() {
+                                        reference.fit(width, height);
+                                        placed = true;
+                                    }
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.checkbox(&mut reference.export, "Export")
+                                        .on_hover_text("Include this layer in saved and exported images");
+                                    ui.checkbox(&mut reference.pickable, "Eyedropper")
+                                        .on_hover_text("Let the eyedropper pick colours from this layer");
+                                });
+                                if placed {
+                                    layer.render_reference();
+                                    ctx.request_repaint();
+                                }
+                            }
+                        })
+                        .response;
+
+                    // Dropping on the lower half of a group row puts the layer inside the group
+                    let into = layer.is_group() && ui.ctx().pointer_interact_pos()
+                        .is_some_and(|pos| pos.y > row.rect.center().y);
+                    if row.dnd_hover_payload::<DraggedLayer>().is_some() {
+                        let stroke = ui.visuals().selection.stroke;
+                        if into {
+                            ui.painter().rect_stroke(row.rect, 4.0, stroke, egui::StrokeKind::Inside);
+                        } else {
+                            ui.painter().hline(row.rect.x_range(), row.rect.top(), stroke);
+                        }
+                    }
+                    if let Some(dragged) = row.dnd_release_payload::<DraggedLayer>() {
+                        layer_drop = Some((dragged.0, i, into));
+                    }
 
                     ui.add_space(2.0);
                 }
@@ -100,21 +221,21 @@ impl PixelArtEditor {
 
         ui.separator();        
         ui.horizontal(|ui| {
-            let canvas_width = if !self.frames[self.current_frame].layers.is_empty() { 
-                self.frames[self.current_frame].layers[0].width() 
-            } else { 0 };
-            let canvas_height = if !self.frames[self.current_frame].layers.is_empty() { 
-                self.frames[self.current_frame].layers[0].height() 
-            } else { 0 };
-            let max_layers = crate::constants::get_max_layers_for_size(canvas_width, canvas_height);
+            let max_layers = crate::constants::MAX_LAYERS;
             let current_layer_count = self.frames[self.current_frame].layers.len();
             
             if current_layer_count < max_layers && ui.button("+ Add Layer").clicked() { 
                 add_layer = true;
             }
+            if current_layer_count < max_layers && ui.button("+ Group").on_hover_text("Add Group").clicked() { 
+                add_group = true;
+            }
+            if current_layer_count < max_layers && ui.button("+ Reference").on_hover_text("Import an image to work from").clicked() {
+                add_reference = true;
+            }
             if current_layer_count >= max_layers {
                 ui.colored_label(egui::Color32::from_rgb(255, 165, 0), 
-                    format!("Max layers ({}) reached for {}x{} canvas", max_layers, canvas_width, canvas_height));
+                    format!("Max layers ({}) reached", max_layers));
             }
             if ui.button("▲").on_hover_text("Move Layer Up").clicked() && self.current_layer < current_layer_count - 1 { 
                 should_move_up = true;
@@ -124,60 +245,117 @@ impl PixelArtEditor {
             }
         });
 
+        let mut add_tilemap = false;
+        let mut convert_to_tilemap = false;
+        ui.horizontal(|ui| {
+            ui.label("Tiles:");
+            ui.add(egui::DragValue::new(&mut self.new_tile_size.0).range(1..=256));
+            ui.label("×");
+            ui.add(egui::DragValue::new(&mut self.new_tile_size.1).range(1..=256));
+            let current_layer_count = self.frames[self.current_frame].layers.len();
+            if current_layer_count < crate::constants::MAX_LAYERS && ui.button("+ Tilemap").on_hover_text("Add Tilemap Layer").clicked() {
+                add_tilemap = true;
+            }
+            if ui.add_enabled(self.get_active_layer().kind == LayerKind::Image, egui::Button::new("To Tilemap"))
+                .on_hover_text("Convert this layer to a tilemap, sharing repeated tiles")
+                .clicked() {
+                convert_to_tilemap = true;
+            }
+        });
+        if add_tilemap {
+            self.add_tilemap_layer();
+        }
+        if add_reference {
+            self.import_reference_dialog();
+        }
+        if convert_to_tilemap {
+            self.convert_layer_to_tilemap();
+        }
+
         // Handle layer operations
-        if layer_to_remove.is_some() || add_layer || layer_to_duplicate.is_some() || layer_to_clear.is_some() { 
+        if layer_to_remove.is_some() || add_layer || add_group || layer_to_duplicate.is_some() || layer_to_clear.is_some() || layer_drop.is_some() || mask_edit.is_some() { 
             self.push_undo(); 
         }
 
+        // Layer order, visibility and opacity all affect every composed pixel
+        if layers_changed || layer_to_remove.is_some() || add_layer || add_group || layer_to_duplicate.is_some() || layer_drop.is_some() || should_move_up || should_move_down {
+            self.invalidate_cache();
+        }
+
         let frame = &mut self.frames[self.current_frame];
         
         if let Some(i) = layer_to_remove { 
-            frame.layers.remove(i); 
+            frame.remove_subtree(i); 
             if self.current_layer >= frame.layers.len() { 
                 self.current_layer = frame.layers.len().saturating_sub(1); 
             } 
         }
         
         if let Some(i) = layer_to_duplicate { 
-            let mut duplicated_layer = frame.layers[i].clone(); 
-            duplicated_layer.name = format!("{} Copy", duplicated_layer.name); 
-            frame.layers.insert(i + 1, duplicated_layer); 
-            self.current_layer = i + 1; 
+            // Groups are duplicated together with their children
+            let start = frame.subtree_start(i);
+            let mut duplicated: Vec<Layer> = frame.layers[start..=i].to_vec();
+            for layer in &mut duplicated {
+                // Copies get their own image instead of joining the original's links
+                layer.link = None;
+            }
+            if let Some(root) = duplicated.last_mut() {
+                root.name = format!("{} Copy", root.name);
+            }
+            let count = duplicated.len();
+            frame.layers.splice(i + 1..i + 1, duplicated);
+            self.current_layer = i + count; 
         }
         
+        if let Some((i, edit)) = mask_edit {
+            edit(&mut frame.layers[i]);
+        }
+
         if let Some(i) = layer_to_clear { 
             let w = frame.layers[i].width(); 
             let h = frame.layers[i].height(); 
-            frame.layers[i].grid = vec![vec![egui::Color32::TRANSPARENT; w]; h]; 
+            // Tilemaps lose their placements, not the tiles themselves
+            if let Some(tilemap) = frame.layers[i].tilemap.as_mut() {
+                tilemap.cells.fill(None);
+            }
+            frame.layers[i].set_grid(TiledBuffer::new(w, h)); 
         }
         
         if add_layer { 
-            let layer = Layer { 
-                name: format!("Layer {}", frame.layers.len() + 1), 
-                visible: true, 
-                opacity: 1.0, 
-                grid: { 
-                    let w = frame.layers[0].width(); 
-                    let h = frame.layers[0].height(); 
-                    vec![vec![egui::Color32::TRANSPARENT; w]; h] 
-                }, 
-            }; 
+            let layer = Layer::new(
+                format!("Layer {}", frame.layers.len() + 1),
+                frame.layers[0].width(),
+                frame.layers[0].height(),
+                egui::Color32::TRANSPARENT,
+            );
             frame.layers.push(layer); 
             self.current_layer = frame.layers.len() - 1; 
         }
 
+        if add_group { 
+            let group = Layer::new_group(
+                format!("Group {}", frame.layers.iter().filter(|l| l.is_group()).count() + 1),
+                frame.layers[0].width(),
+                frame.layers[0].height(),
+            );
+            frame.layers.push(group); 
+            self.current_layer = frame.layers.len() - 1; 
+        }
+
+        if let Some((dragged, target, into)) = layer_drop {
+            self.current_layer = frame.move_subtree(dragged, target, into);
+        }
+
         if should_move_up { 
             self.push_undo(); 
             let frame = &mut self.frames[self.current_frame]; 
-            frame.layers.swap(self.current_layer, self.current_layer + 1); 
-            self.current_layer += 1; 
+            self.current_layer = frame.move_up(self.current_layer); 
         }
         
         if should_move_down { 
             self.push_undo(); 
             let frame = &mut self.frames[self.current_frame]; 
-            frame.layers.swap(self.current_layer, self.current_layer - 1); 
-            self.current_layer -= 1; 
+            self.current_layer = frame.move_down(self.current_layer); 
         }
     }
 
@@ -228,36 +406,20 @@ impl PixelArtEditor {
                                             }
                                         });
 
+                                        // Tags covering this frame, as coloured strips
+                                        for tag in self.tags.iter().filter(|tag| tag.contains(i)) {
+                                            let (strip, _) = ui.allocate_exact_size(egui::vec2(preview_size, 4.0), egui::Sense::hover());
+                                            ui.painter().rect_filled(strip, 1.0, tag.color);
+                                        }
+
                                         let (rect, _) = ui.allocate_exact_size(
                                             egui::vec2(width as f32 * scale, height as f32 * scale),
                                             egui::Sense::hover(),
                                         );
 
-                                        let mut composed = vec![vec![egui::Color32::TRANSPARENT; width]; height];
-                                        for layer in &frame.layers { 
-                                            if !layer.visible { continue; } 
-                                            for y in 0..height { 
-                                                for x in 0..width { 
-                                                    let c = layer.grid[y][x]; 
-                                                    if c.a() > 0 { 
-                                                        composed[y][x] = c; 
-                                                    } 
-                                                } 
-                                            } 
-                                        }
-
-                                        for y in 0..height { 
-                                            for x in 0..width { 
-                                                let pixel_color = composed[y][x]; 
-                                                if pixel_color.a() > 0 { 
-                                                    let pixel_rect = egui::Rect::from_min_size( 
-                                                        rect.min + egui::vec2(x as f32 * scale, y as f32 * scale), 
-                                                        egui::vec2(scale, scale), 
-                                                    ); 
-                                                    ui.painter().rect_filled(pixel_rect, 0.0, pixel_color); 
-                                                } 
-                                            } 
-                                        }
+                                        let thumbnail = self.frame_thumbnail(ui.ctx(), i, preview_size as usize);
+                                        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                                        ui.painter().image(thumbnail, rect, uv, egui::Color32::WHITE);
 
                                         // Draw a simple frame around the preview
                                         ui.painter().rect_filled(
@@ -275,7 +437,11 @@ impl PixelArtEditor {
                                     let btn_size = egui::vec2(20.0, 18.0);
                                     if self.frames.len() > 1 && ui.add(egui::Button::new("Del").min_size(btn_size)).on_hover_text("Delete Frame").clicked() { 
                                         self.push_undo(); 
+                                        self.stop_playback();
                                         self.frames.remove(i); 
+                                        self.shift_tags_for_removed_frame(i);
+                                        self.shift_slices_for_removed_frame(i);
+                                        self.invalidate_cache(); 
                                         if self.current_frame >= self.frames.len() { 
                                             self.current_frame = self.frames.len() - 1; 
                                         } 
@@ -285,10 +451,23 @@ impl PixelArtEditor {
                                         self.push_undo(); 
                                         let new_frame = self.frames[i].clone(); 
                                         self.frames.insert(i + 1, new_frame); 
+                                        self.shift_tags_for_inserted_frame(i + 1);
+                                        self.shift_slices_for_inserted_frame(i + 1);
                                         self.current_frame = i + 1; 
                                         self.current_layer = 0; 
                                     }
                                 });
+
+                                let mut duration = self.frames[i].duration_ms;
+                                let response = ui.add(
+                                    egui::DragValue::new(&mut duration)
+                                        .range(1..=crate::constants::MAX_FRAME_DURATION_MS)
+                                        .suffix(" ms"),
+                                ).on_hover_text("Frame duration");
+                                if response.drag_started() || (response.changed() && !response.dragged()) {
+                                    self.push_undo();
+                                }
+                                self.frames[i].duration_ms = duration;
                             });
 
                         ui.add_space(4.0);
@@ -298,13 +477,7 @@ impl PixelArtEditor {
 
         ui.separator();
         ui.horizontal(|ui| {
-            let canvas_width = if !self.frames[self.current_frame].layers.is_empty() { 
-                self.frames[self.current_frame].layers[0].width() 
-            } else { 0 };
-            let canvas_height = if !self.frames[self.current_frame].layers.is_empty() { 
-                self.frames[self.current_frame].layers[0].height() 
-            } else { 0 };
-            let max_frames = crate::constants::get_max_frames_for_size(canvas_width, canvas_height);
+            let max_frames = crate::constants::MAX_FRAMES;
             let current_frame_count = self.frames.len();
             
             if current_frame_count < max_frames && ui.button("+ Add Frame").clicked() { 
@@ -315,12 +488,14 @@ impl PixelArtEditor {
                     let new_frame = self.frames[self.current_frame].clone(); 
                     self.frames.insert(self.current_frame + 1, new_frame); 
                     self.current_frame += 1; 
+                    self.shift_tags_for_inserted_frame(self.current_frame);
+                    self.shift_slices_for_inserted_frame(self.current_frame);
                 } 
                 self.current_layer = 0; 
             }
             if current_frame_count >= max_frames {
                 ui.colored_label(egui::Color32::from_rgb(255, 165, 0), 
-                    format!("Max frames ({}) reached for {}x{} canvas", max_frames, canvas_width, canvas_height));
+                    format!("Max frames ({}) reached", max_frames));
             }
             if ui.button("⏮").on_hover_text("First Frame").clicked() && !self.frames.is_empty() { 
                 self.current_frame = 0; 
@@ -336,6 +511,157 @@ impl PixelArtEditor {
                 self.show_animation_controls(ctx, ui);
             }
         });
+
+        ui.horizontal(|ui| {
+            ui.label("Cel:");
+            if ui.add_enabled(self.can_link_cel_to_previous(), egui::Button::new("Link ◀"))
+                .on_hover_text("Link the current layer's cel to the previous frame")
+                .clicked() {
+                self.link_cel_to_previous();
+            }
+            if ui.add_enabled(self.frames.len() > 1, egui::Button::new("Link All"))
+                .on_hover_text("Link the current layer's cel in every frame")
+                .clicked() {
+                self.link_cel_across_frames();
+            }
+            if ui.add_enabled(self.get_active_layer().link.is_some(), egui::Button::new("Unlink"))
+                .on_hover_text("Give this cel its own copy of the image")
+                .clicked() {
+                self.unlink_cel();
+            }
+        });
+
+        self.show_tags_section(ui);
+        self.show_slices_section(ui);
+    }
+
+    fn show_tags_section(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(format!("Tags ({})", self.tags.len()))
+            .id_salt("animation_tags")
+            .show(ui, |ui| {
+                let last_frame = self.frames.len().saturating_sub(1);
+                let mut remove = None;
+                for (i, tag) in self.tags.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        egui::color_picker::color_edit_button_srgba(ui, &mut tag.color, egui::color_picker::Alpha::Opaque);
+                        ui.add(egui::TextEdit::singleline(&mut tag.name).desired_width(70.0));
+
+                        // Frames are shown numbered from 1, as in the frame list
+                        let mut from = tag.from + 1;
+                        let mut to = tag.to + 1;
+                        ui.add(egui::DragValue::new(&mut from).range(1..=last_frame + 1).prefix("from "));
+                        ui.add(egui::DragValue::new(&mut to).range(1..=last_frame + 1).prefix("to "));
+                        tag.from = from - 1;
+                        tag.to = (to - 1).max(tag.from);
+
+                        egui::ComboBox::from_id_salt(("tag_direction", i))
+                            .selected_text(tag.direction.name())
+                            .show_ui(ui, |ui| {
+                                for direction in crate::types::AnimationDirection::ALL {
+                                    ui.selectable_value(&mut tag.direction, direction, direction.name());
+                                }
+                            });
+                        ui.add(
+                            egui::DragValue::new(&mut tag.repeat)
+                                .range(0..=u16::MAX as u32)
+                                .custom_formatter(|n, _| if n == 0.0 { "∞".to_string() } else { format!("×{}", n) }),
+                        ).on_hover_text("Times to play (∞ loops forever)");
+
+                        if ui.small_button("✖").on_hover_text("Delete Tag").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.remove_tag(i);
+                }
+
+                if ui.button("+ Tag").on_hover_text("Tag the current frame").clicked() {
+                    self.add_tag(self.current_frame, self.current_frame);
+                }
+            });
+    }
+
+    fn show_slices_section(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(format!("Slices ({})", self.slices.len()))
+            .id_salt("slices")
+            .show(ui, |ui| {
+                let mut remove = None;
+                for (i, slice) in self.slices.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(self.selected_slice == Some(i), "◻").on_hover_text("Select Slice").clicked() {
+                            self.selected_slice = Some(i);
+                        }
+                        egui::color_picker::color_edit_button_srgba(ui, &mut slice.color, egui::color_picker::Alpha::Opaque);
+                        ui.add(egui::TextEdit::singleline(&mut slice.name).desired_width(90.0));
+                        if ui.small_button("✖").on_hover_text("Delete Slice").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.remove_slice(i);
+                }
+
+                let frame = self.current_frame;
+                let Some(index) = self.selected_slice else {
+                    ui.label("Drag with the Slice tool to add a slice");
+                    return;
+                };
+                let Some(&key) = self.slices.get(index).and_then(|slice| slice.key_at(frame)) else {
+                    ui.label("The selected slice is not on this frame");
+                    return;
+                };
+
+                // Changes made here key the slice on the current frame
+                let mut edited = key;
+                ui.separator();
+                ui.label(format!("Frame {}{}", frame + 1, if key.frame == frame { " (key)" } else { "" }));
+                ui.horizontal(|ui| {
+                    ui.label("Bounds:");
+                    ui.add(egui::DragValue::new(&mut edited.bounds.x).prefix("x "));
+                    ui.add(egui::DragValue::new(&mut edited.bounds.y).prefix("y "));
+                    ui.add(egui::DragValue::new(&mut edited.bounds.width).range(1..=crate::constants::MAX_CANVAS_SIZE).prefix("w "));
+                    ui.add(egui::DragValue::new(&mut edited.bounds.height).range(1..=crate::constants::MAX_CANVAS_SIZE).prefix("h "));
+                });
+
+                let mut has_center = edited.center.is_some();
+                if ui.checkbox(&mut has_center, "9-Slice Centre").changed() {
+                    edited.center = has_center.then(|| SliceRect {
+                        x: 1,
+                        y: 1,
+                        width: edited.bounds.width.saturating_sub(2).max(1),
+                        height: edited.bounds.height.saturating_sub(2).max(1),
+                    });
+                }
+                if let Some(center) = edited.center.as_mut() {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut center.x).range(0..=edited.bounds.width as isize).prefix("x "));
+                        ui.add(egui::DragValue::new(&mut center.y).range(0..=edited.bounds.height as isize).prefix("y "));
+                        ui.add(egui::DragValue::new(&mut center.width).range(1..=edited.bounds.width).prefix("w "));
+                        ui.add(egui::DragValue::new(&mut center.height).range(1..=edited.bounds.height).prefix("h "));
+                    });
+                }
+
+                let mut has_pivot = edited.pivot.is_some();
+                if ui.checkbox(&mut has_pivot, "Pivot").changed() {
+                    edited.pivot = has_pivot.then_some(((edited.bounds.width / 2) as isize, (edited.bounds.height / 2) as isize));
+                }
+                if let Some((x, y)) = edited.pivot.as_mut() {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(x).prefix("x "));
+                        ui.add(egui::DragValue::new(y).prefix("y "));
+                    });
+                }
+
+                if edited != key {
+                    self.edit_slice_key(index, frame, |key| *key = SliceKey { frame, ..edited });
+                }
+
+                if ui.button("9-Slice Preview").on_hover_text("Preview the slice stretched to another size").clicked() {
+                    self.show_nine_slice_preview = true;
+                }
+            });
     }
 
     pub fn show_tools_panel(&mut self, ui: &mut egui::Ui) {
@@ -376,7 +702,7 @@ impl PixelArtEditor {
         ui.group(|ui| {
             ui.label("Shape Tools:");
             ui.horizontal_wrapped(|ui| {
-                for &tool in &[Tool::Line, Tool::Rectangle, Tool::Circle] {
+                for &tool in &[Tool::Line, Tool::Rectangle, Tool::Circle, Tool::Ellipse, Tool::Polygon, Tool::Curve] {
                     let (icon, scale, _rotation, alpha) = self.tool_icon_animated(tool);
                     let is_selected = self.tool == tool;
                     
@@ -405,7 +731,7 @@ impl PixelArtEditor {
         ui.group(|ui| {
             ui.label("Selection Tools:");
             ui.horizontal_wrapped(|ui| {
-                for &tool in &[Tool::Select, Tool::Lasso, Tool::Move] {
+                for &tool in &[Tool::Select, Tool::EllipseSelect, Tool::Lasso, Tool::MagicWand, Tool::Move, Tool::Slice, Tool::Tile] {
                     let (icon, scale, _rotation, alpha) = self.tool_icon_animated(tool);
                     let is_selected = self.tool == tool;
                     
@@ -434,7 +760,7 @@ impl PixelArtEditor {
         ui.group(|ui| {
             ui.label("Special Tools:");
             ui.horizontal_wrapped(|ui| {
-                for &tool in &[Tool::Spray, Tool::Dither] {
+                for &tool in &[Tool::Spray, Tool::Dither, Tool::Gradient] {
                     let (icon, scale, _rotation, alpha) = self.tool_icon_animated(tool);
                     let is_selected = self.tool == tool;
                     
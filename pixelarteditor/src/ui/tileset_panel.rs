@@ -0,0 +1,272 @@
+use eframe::egui;
+use crate::editor::PixelArtEditor;
+use crate::editor::autotile::{BLOB_NEIGHBOURS, CORNERS, EDGES};
+use crate::types::{AutotileKind, TileRef, Tool};
+
+// Largest size a tile is shown at in the tileset panel
+const TILE_BUTTON_SIZE: f32 = 32.0;
+
+// Size of the mask diagram and tile in a terrain rule slot
+const RULE_SLOT_SIZE: f32 = 24.0;
+
+/// Draw a tile from the tileset texture, flipped and turned as placed
+fn paint_tile(painter: &egui::Painter, texture: egui::TextureId, rect: egui::Rect, uv: egui::Rect, tile: TileRef) {
+    let mut mesh = egui::Mesh::with_texture(texture);
+    // Corners clockwise from the top left, each showing the tile corner the flips put there
+    for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+        let sx = if tile.flip_x { 1.0 - x } else { x };
+        let sy = if tile.flip_y { 1.0 - y } else { y };
+        let (u, v) = if tile.flip_diagonal { (sy, sx) } else { (sx, sy) };
+        mesh.vertices.push(egui::epaint::Vertex {
+            pos: rect.lerp_inside(egui::vec2(x, y)),
+            uv: uv.lerp_inside(egui::vec2(u, v)),
+            color: egui::Color32::WHITE,
+        });
+    }
+    mesh.add_triangle(0, 1, 2);
+    mesh.add_triangle(0, 2, 3);
+    painter.add(mesh);
+}
+
+/// Draw which neighbours or corners a terrain mask covers
+fn paint_mask(painter: &egui::Painter, rect: egui::Rect, kind: AutotileKind, mask: u8, color: egui::Color32) {
+    painter.rect_filled(rect, 0.0, egui::Color32::from_gray(40));
+    let fill = |x: isize, y: isize, cells: f32| {
+        let size = rect.width() / cells;
+        let min = rect.min + egui::vec2(x as f32 * size, y as f32 * size);
+        painter.rect_filled(egui::Rect::from_min_size(min, egui::vec2(size, size)).shrink(0.5), 0.0, color);
+    };
+    match kind {
+        AutotileKind::Edge4 => {
+            fill(1, 1, 3.0);
+            for (bit, (dx, dy)) in EDGES {
+                if mask & bit != 0 {
+                    fill(1 + dx, 1 + dy, 3.0);
+                }
+            }
+        }
+        AutotileKind::Blob8 => {
+            fill(1, 1, 3.0);
+            for (bit, (dx, dy)) in BLOB_NEIGHBOURS.iter().enumerate() {
+                if mask & (1 << bit) != 0 {
+                    fill(1 + dx, 1 + dy, 3.0);
+                }
+            }
+        }
+        AutotileKind::WangCorner => {
+            for (bit, (x, y)) in CORNERS {
+                if mask & bit != 0 {
+                    fill(x, y, 2.0);
+                }
+            }
+        }
+    }
+}
+
+impl PixelArtEditor {
+    /// Tiles of the current tilemap layer's tileset: pick one to stamp, flip or turn
+    /// it, and reorder or remove tiles
+    pub fn show_tileset_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(index) = self.active_tileset() else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.heading("Tileset");
+            ui.add(egui::TextEdit::singleline(&mut self.tilesets[index].name).desired_width(90.0));
+        });
+        let tileset = &self.tilesets[index];
+        let (tile_width, tile_height) = (tileset.tile_width, tileset.tile_height);
+        let count = tileset.tile_count();
+        ui.label(format!("{} tiles of {}×{}", count, tile_width, tile_height));
+
+        // The panel shows the tiles from one texture of the whole set
+        let columns = ((ui.available_width() / (TILE_BUTTON_SIZE + 4.0)) as usize).max(1);
+        let sheet = tileset.sheet(columns);
+        let image = egui::ColorImage::from_rgba_unmultiplied([sheet.width(), sheet.height()], sheet.as_bytes());
+        match &mut self.tileset_texture {
+            Some(texture) => texture.set(image, egui::TextureOptions::NEAREST),
+            None => self.tileset_texture = Some(ui.ctx().load_texture("tileset", image, egui::TextureOptions::NEAREST)),
+        }
+        let Some(texture_id) = self.tileset_texture.as_ref().map(|texture| texture.id()) else {
+            return;
+        };
+
+        let scale = TILE_BUTTON_SIZE / tile_width.max(tile_height) as f32;
+        let button_size = egui::vec2(tile_width as f32 * scale, tile_height as f32 * scale);
+        let sheet_size = egui::vec2(sheet.width() as f32, sheet.height() as f32);
+        let tile_uv = |i: usize| egui::Rect::from_min_size(
+            egui::pos2(((i % columns) * tile_width) as f32 / sheet_size.x, ((i / columns) * tile_height) as f32 / sheet_size.y),
+            egui::vec2(tile_width as f32 / sheet_size.x, tile_height as f32 / sheet_size.y),
+        );
+        let mut picked = None;
+        egui::ScrollArea::vertical()
+            .id_salt("tileset_scroll")
+            .max_height(160.0)
+            .show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    ui.spacing_mut().item_spacing = egui::vec2(4.0, 4.0);
+                    for i in 0..count {
+                        let (rect, response) = ui.allocate_exact_size(button_size, egui::Sense::click());
+                        ui.painter().rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+                        ui.painter().image(texture_id, rect, tile_uv(i), egui::Color32::WHITE);
+                        let selected = self.selected_tile.is_some_and(|tile| tile.tile == i);
+                        let stroke = if selected {
+                            egui::Stroke::new(2.0, ui.visuals().selection.stroke.color)
+                        } else {
+                            egui::Stroke::new(1.0, ui.visuals().widgets.noninteractive.bg_stroke.color)
+                        };
+                        ui.painter().rect_stroke(rect, 0.0, stroke, egui::StrokeKind::Outside);
+                        if response.on_hover_text(format!("Tile {}", i)).clicked() {
+                            picked = Some(i);
+                        }
+                    }
+                });
+            });
+        if let Some(i) = picked {
+            // Keep the flips when switching tiles, so a turned stamp stays turned
+            self.selected_tile = Some(TileRef { tile: i, ..self.selected_tile.unwrap_or_default() });
+            self.tool = Tool::Tile;
+        }
+
+        let square = tile_width == tile_height;
+        ui.horizontal(|ui| {
+            let Some(tile) = self.selected_tile.as_mut().filter(|tile| tile.tile < count) else {
+                ui.label("Pick a tile to stamp it with the Tile tool");
+                return;
+            };
+            // The stamp as it will be placed
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(RULE_SLOT_SIZE, RULE_SLOT_SIZE), egui::Sense::hover());
+            paint_tile(ui.painter(), texture_id, rect, tile_uv(tile.tile), *tile);
+            if ui.button("↔").on_hover_text("Flip Horizontally").clicked() {
+                tile.flip_x = !tile.flip_x;
+            }
+            if ui.button("↕").on_hover_text("Flip Vertically").clicked() {
+                tile.flip_y = !tile.flip_y;
+            }
+            if ui.add_enabled(square, egui::Button::new("⟲")).on_hover_text("Rotate 90° Counter-Clockwise").clicked() {
+                *tile = tile.rotated_ccw();
+            }
+            if ui.add_enabled(square, egui::Button::new("⟳")).on_hover_text("Rotate 90° Clockwise").clicked() {
+                *tile = tile.rotated_cw();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let selected = self.selected_tile.map(|tile| tile.tile).filter(|&tile| tile < count);
+            if ui.add_enabled(selected.is_some_and(|tile| tile > 0), egui::Button::new("◀"))
+                .on_hover_text("Move Tile Earlier")
+                .clicked() && let Some(tile) = selected {
+                self.move_tile(tile, tile - 1);
+            }
+            if ui.add_enabled(selected.is_some_and(|tile| tile + 1 < count), egui::Button::new("▶"))
+                .on_hover_text("Move Tile Later")
+                .clicked() && let Some(tile) = selected {
+                self.move_tile(tile, tile + 1);
+            }
+            if ui.button("+ Tile").on_hover_text("Add a blank tile").clicked() {
+                self.add_blank_tile();
+            }
+            if ui.add_enabled(selected.is_some(), egui::Button::new("Del"))
+                .on_hover_text("Delete the tile and clear its placements")
+                .clicked() && let Some(tile) = selected {
+                self.remove_tile(tile);
+            }
+        });
+        ui.checkbox(&mut self.show_tile_grid, "Show Tile Grid");
+
+        self.show_terrain_section(ui, index, texture_id, &tile_uv);
+    }
+
+    fn show_terrain_section(&mut self, ui: &mut egui::Ui, tileset: usize, texture_id: egui::TextureId, tile_uv: &dyn Fn(usize) -> egui::Rect) {
+        let count = self.terrains.iter().filter(|terrain| terrain.tileset == tileset).count();
+        egui::CollapsingHeader::new(format!("Terrains ({})", count))
+            .id_salt("terrains")
+            .show(ui, |ui| {
+                let mut remove = None;
+                for (i, terrain) in self.terrains.iter_mut().enumerate().filter(|(_, terrain)| terrain.tileset == tileset) {
+                    ui.horizontal(|ui| {
+                        let active = self.active_terrain == Some(i);
+                        if ui.selectable_label(active, "🖌").on_hover_text("Paint this terrain with the Tile tool").clicked() {
+                            self.active_terrain = if active { None } else { Some(i) };
+                            self.tool = Tool::Tile;
+                        }
+                        egui::color_picker::color_edit_button_srgba(ui, &mut terrain.color, egui::color_picker::Alpha::Opaque);
+                        ui.add(egui::TextEdit::singleline(&mut terrain.name).desired_width(80.0));
+                        ui.label(format!("{}/{}", terrain.rules.len(), terrain.kind.masks().len()))
+                            .on_hover_text(terrain.kind.name());
+                        if ui.small_button("✖").on_hover_text("Delete Terrain").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.remove_terrain(i);
+                }
+
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("new_terrain_kind")
+                        .selected_text(self.new_terrain_kind.name())
+                        .show_ui(ui, |ui| {
+                            for kind in AutotileKind::ALL {
+                                ui.selectable_value(&mut self.new_terrain_kind, kind, kind.name());
+                            }
+                        });
+                    if ui.button("+ Terrain").clicked() {
+                        self.add_terrain(self.new_terrain_kind);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(self.active_tileset().is_some(), egui::Button::new("Import..."))
+                        .on_hover_text("Add terrains from a JSON rule file for this layer's tileset")
+                        .clicked()
+                        && let Some(path) = rfd::FileDialog::new()
+                            .set_title("Import Terrains")
+                            .add_filter("JSON", &["json"])
+                            .pick_file()
+                        && let Err(e) = self.load_terrains_json(&path.to_string_lossy()) {
+                        eprintln!("Failed to import terrains: {}", e);
+                    }
+                    if ui.add_enabled(!self.terrains.is_empty(), egui::Button::new("Export..."))
+                        .on_hover_text("Save the terrain rules as JSON")
+                        .clicked()
+                        && let Some(path) = rfd::FileDialog::new()
+                            .set_title("Export Terrains")
+                            .add_filter("JSON", &["json"])
+                            .save_file()
+                        && let Err(e) = self.save_terrains_json(&path.to_string_lossy()) {
+                        eprintln!("Failed to export terrains: {}", e);
+                    }
+                });
+
+                // Rules of the terrain being painted
+                let Some(active) = self.active_terrain.filter(|&i| self.terrains.get(i).is_some_and(|t| t.tileset == tileset)) else {
+                    return;
+                };
+                ui.label("Click a slot to give it the picked tile, right-click to clear it");
+                let terrain = &mut self.terrains[active];
+                egui::ScrollArea::vertical()
+                    .id_salt("terrain_rules_scroll")
+                    .max_height(180.0)
+                    .show(ui, |ui| {
+                        ui.horizontal_wrapped(|ui| {
+                            for mask in terrain.kind.masks() {
+                                let size = egui::vec2(RULE_SLOT_SIZE * 2.0 + 2.0, RULE_SLOT_SIZE);
+                                let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+                                let mask_rect = egui::Rect::from_min_size(rect.min, egui::vec2(RULE_SLOT_SIZE, RULE_SLOT_SIZE));
+                                let tile_rect = mask_rect.translate(egui::vec2(RULE_SLOT_SIZE + 2.0, 0.0));
+                                paint_mask(ui.painter(), mask_rect, terrain.kind, mask, terrain.color);
+                                ui.painter().rect_filled(tile_rect, 0.0, ui.visuals().extreme_bg_color);
+                                if let Some(tile) = terrain.rule(mask) {
+                                    paint_tile(ui.painter(), texture_id, tile_rect, tile_uv(tile.tile), tile);
+                                }
+                                if response.clicked() {
+                                    terrain.set_rule(mask, self.selected_tile);
+                                } else if response.secondary_clicked() {
+                                    terrain.set_rule(mask, None);
+                                }
+                            }
+                        });
+                    });
+            });
+    }
+}